@@ -0,0 +1,542 @@
+use std::{
+    cmp::min,
+    collections::{BTreeMap, HashMap, HashSet},
+    io::Write,
+};
+
+use anyhow::Result;
+use itertools::Itertools;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::{
+    analysis::cfa::SectionAddress,
+    obj::{
+        ObjDataKind, ObjInfo, ObjSection, ObjSectionKind, ObjSplit, ObjSymbol, ObjSymbolKind,
+        SectionIndex,
+    },
+    util::{
+        config::write_split_sections,
+        split::{create_gap_splits, split_ctors_dtors_sections, split_extabindex_section},
+    },
+};
+
+/// A suggested translation unit boundary.
+#[derive(Debug, Clone)]
+pub struct SplitSuggestion {
+    /// Start of the suggested unit.
+    pub address: SectionAddress,
+    /// End of the suggested unit, if known.
+    pub end: Option<u32>,
+    /// Suggested unit name, if known.
+    pub unit: Option<String>,
+    /// Confidence from 0.0 to 1.0.
+    pub confidence: f32,
+    pub reasons: Vec<String>,
+}
+
+/// Matches source file names in assertion strings, e.g. `"foo.c"` or `"src/bar.cpp"`.
+static SOURCE_FILE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(?:[A-Za-z0-9_.\-]+[/\\])*[A-Za-z0-9_\-]+\.(?:c|cp|cpp|cxx|cc)$").unwrap()
+});
+
+struct Suggestions<'a> {
+    obj: &'a ObjInfo,
+    out: BTreeMap<SectionAddress, SplitSuggestion>,
+}
+
+impl Suggestions<'_> {
+    fn add(&mut self, address: SectionAddress, confidence: f32, reason: String) {
+        self.add_unit(address, None, None, confidence, reason);
+    }
+
+    fn add_unit(
+        &mut self,
+        address: SectionAddress,
+        end: Option<u32>,
+        unit: Option<String>,
+        confidence: f32,
+        reason: String,
+    ) {
+        let section = &self.obj.sections[address.section];
+        if address.address <= section.address as u32
+            || address.address >= (section.address + section.size) as u32
+        {
+            return;
+        }
+        // Don't suggest boundaries inside of user-defined splits
+        if let Some((start, split)) = section.splits.for_address(address.address) {
+            if !split.autogenerated && start != address.address {
+                return;
+            }
+        }
+        let suggestion = self.out.entry(address).or_insert_with(|| SplitSuggestion {
+            address,
+            end: None,
+            unit: None,
+            confidence: 0.0,
+            reasons: vec![],
+        });
+        // Combine independent evidence
+        suggestion.confidence = 1.0 - (1.0 - suggestion.confidence) * (1.0 - confidence);
+        if suggestion.end.is_none() {
+            suggestion.end = end;
+        }
+        if suggestion.unit.is_none() {
+            suggestion.unit = unit;
+        }
+        suggestion.reasons.push(reason);
+    }
+}
+
+/// Returns the first sized symbol of the given kind starting at or after the address.
+fn next_symbol(
+    obj: &ObjInfo,
+    section_index: SectionIndex,
+    address: u32,
+    kind: ObjSymbolKind,
+) -> Option<&ObjSymbol> {
+    obj.symbols
+        .for_section_range(section_index, address..)
+        .map(|(_, s)| s)
+        .find(|s| s.kind == kind && s.size > 0)
+}
+
+/// Derives a unit name from a static initializer name, e.g. `__sinit_foo_cpp` -> `foo.cpp`.
+fn unit_for_sinit(name: &str) -> Option<String> {
+    let (base, ext) = name.strip_prefix("__sinit_")?.rsplit_once('_')?;
+    Some(format!("{}.{}", base, ext))
+}
+
+/// Whether the address is covered by a user-defined split.
+fn in_user_split(obj: &ObjInfo, address: SectionAddress) -> bool {
+    obj.sections[address.section]
+        .splits
+        .for_address(address.address)
+        .is_some_and(|(_, split)| !split.autogenerated)
+}
+
+/// Clones the module, keeping only the user-defined splits.
+fn user_splits_only(obj: &ObjInfo) -> ObjInfo {
+    let mut obj = obj.clone();
+    for (_, section) in obj.sections.iter_mut() {
+        let addresses = section
+            .splits
+            .iter()
+            .filter(|(_, split)| split.autogenerated)
+            .map(|(address, _)| address)
+            .dedup()
+            .collect_vec();
+        for address in addresses {
+            let Some(splits) = section.splits.remove(address) else { continue };
+            for split in splits.into_iter().filter(|split| !split.autogenerated) {
+                section.splits.push(address, split);
+            }
+        }
+    }
+    obj.link_order.retain(|unit| !unit.autogenerated);
+    obj
+}
+
+fn split_starts(obj: &ObjInfo) -> HashSet<SectionAddress> {
+    obj.sections
+        .all_splits()
+        .map(|(section_index, _, address, _)| SectionAddress::new(section_index, address))
+        .collect()
+}
+
+/// Splits added since `before` was taken.
+fn new_splits<'a>(
+    obj: &'a ObjInfo,
+    before: &'a HashSet<SectionAddress>,
+) -> impl Iterator<Item = (SectionAddress, &'a ObjSection, &'a ObjSplit)> {
+    obj.sections
+        .all_splits()
+        .map(|(section_index, section, address, split)| {
+            (SectionAddress::new(section_index, address), section, split)
+        })
+        .filter(|(address, _, _)| !before.contains(address))
+}
+
+/// Runs the automatic split steps of [`update_splits`](crate::util::split::update_splits) on
+/// the user-defined splits only, and suggests boundaries from the splits they create.
+fn suggest_auto_splits(obj: &ObjInfo, out: &mut Suggestions) {
+    let mut auto_obj = user_splits_only(obj);
+
+    let before = split_starts(&auto_obj);
+    match split_extabindex_section(&mut auto_obj) {
+        Ok(()) => suggest_extabindex(&auto_obj, &before, out),
+        Err(e) => log::warn!("Skipping extabindex suggestions: {:#}", e),
+    }
+
+    let before = split_starts(&auto_obj);
+    match split_ctors_dtors_sections(&mut auto_obj) {
+        Ok(()) => suggest_ctors_dtors(&auto_obj, &before, out),
+        Err(e) => log::warn!("Skipping .ctors/.dtors suggestions: {:#}", e),
+    }
+
+    let before = split_starts(&auto_obj);
+    // Gap splits that don't start at a section start or the end of a split were split off
+    // because of a duplicate symbol name or an alignment change
+    let mut ends = auto_obj
+        .sections
+        .all_splits()
+        .map(|(section_index, _, _, split)| SectionAddress::new(section_index, split.end))
+        .collect::<HashSet<_>>();
+    ends.extend(auto_obj.sections.iter().map(|(section_index, section)| {
+        SectionAddress::new(section_index, section.address as u32)
+    }));
+    match create_gap_splits(&mut auto_obj) {
+        Ok(()) => {
+            for (address, _, _) in new_splits(&auto_obj, &before) {
+                if ends.contains(&address) {
+                    continue;
+                }
+                let reason = match auto_obj
+                    .symbols
+                    .for_section_range(address.section, address.address..=address.address)
+                    .next()
+                {
+                    Some((_, symbol)) => format!(
+                        "gap split: duplicate symbol name or alignment change at {}",
+                        symbol.name
+                    ),
+                    None => "gap split: duplicate symbol name or alignment change".to_string(),
+                };
+                out.add(address, 0.6, reason);
+            }
+        }
+        Err(e) => log::warn!("Skipping gap split suggestions: {:#}", e),
+    }
+}
+
+/// Static initializers (`.ctors` entries) are emitted at the end of their unit's `.text`, so
+/// the function following each one starts a new unit.
+fn suggest_ctors_dtors(obj: &ObjInfo, before: &HashSet<SectionAddress>, out: &mut Suggestions) {
+    for (address, section, split) in new_splits(obj, before) {
+        if section.kind != ObjSectionKind::Code {
+            continue;
+        }
+        let Ok(Some((_, function))) = obj.symbols.kind_at_section_address(
+            address.section,
+            address.address,
+            ObjSymbolKind::Function,
+        ) else {
+            continue;
+        };
+        let Some(next) = next_symbol(obj, address.section, split.end, ObjSymbolKind::Function)
+        else {
+            continue;
+        };
+        let reason = match unit_for_sinit(&function.name) {
+            Some(unit) => {
+                format!(".ctors: follows static initializer {} (end of {})", function.name, unit)
+            }
+            None => format!(".ctors/.dtors: follows {}", function.name),
+        };
+        out.add(SectionAddress::new(address.section, next.address as u32), 0.9, reason);
+    }
+}
+
+/// Exception tables are emitted per unit, depending on whether the unit was compiled with
+/// exceptions enabled. Functions switching between having and lacking an `extabindex` entry
+/// mark a likely boundary.
+fn suggest_extabindex(obj: &ObjInfo, before: &HashSet<SectionAddress>, out: &mut Suggestions) {
+    let functions = new_splits(obj, before)
+        .filter(|(_, section, _)| section.kind == ObjSectionKind::Code)
+        .map(|(address, _, _)| address)
+        .collect::<HashSet<_>>();
+    if functions.is_empty() {
+        return;
+    }
+    for (section_index, _) in obj.sections.by_kind(ObjSectionKind::Code) {
+        // Functions in user-defined splits didn't get an extabindex split
+        let symbols = obj
+            .symbols
+            .for_section(section_index)
+            .map(|(_, s)| (SectionAddress::new(section_index, s.address as u32), s))
+            .filter(|&(address, s)| {
+                s.kind == ObjSymbolKind::Function && s.size > 0 && !in_user_split(out.obj, address)
+            })
+            .collect_vec();
+        for (prev, next) in symbols.iter().tuple_windows() {
+            let (prev_entry, next_entry) =
+                (functions.contains(&prev.0), functions.contains(&next.0));
+            if prev_entry != next_entry {
+                let reason = if next_entry {
+                    "extabindex: first function with exception table"
+                } else {
+                    "extabindex: first function without exception table"
+                };
+                out.add(next.0, 0.3, reason.to_string());
+            }
+        }
+    }
+}
+
+/// String literals are pooled at the end of each unit's data (`@stringBase0`).
+fn suggest_string_pools(obj: &ObjInfo, out: &mut Suggestions) {
+    for (section_index, section) in obj.sections.iter() {
+        if !matches!(section.kind, ObjSectionKind::Data | ObjSectionKind::ReadOnlyData) {
+            continue;
+        }
+        let symbols = obj
+            .symbols
+            .for_section(section_index)
+            .map(|(_, s)| s)
+            .filter(|s| s.kind == ObjSymbolKind::Object && s.size > 0)
+            .collect_vec();
+        let is_string = |s: &ObjSymbol| {
+            matches!(
+                s.data_kind,
+                ObjDataKind::String | ObjDataKind::String16 | ObjDataKind::ShiftJIS
+            )
+        };
+        for (prev, next) in symbols.iter().tuple_windows() {
+            let address = SectionAddress::new(section_index, next.address as u32);
+            if prev.name.starts_with("@stringBase") {
+                out.add(address, 0.8, format!("string pool: follows {}", prev.name));
+            } else if is_string(prev) && !is_string(next) {
+                out.add(address, 0.3, format!("string pool: follows string {}", prev.name));
+            }
+        }
+    }
+}
+
+/// Each unit has its own pool of `.sdata2` constants, so a repeated constant value
+/// indicates that a new unit started since the previous occurrence.
+fn suggest_sdata2(obj: &ObjInfo, out: &mut Suggestions) -> Result<()> {
+    let Some((section_index, section)) = obj.sections.by_name(".sdata2")? else {
+        return Ok(());
+    };
+    let mut seen = HashSet::<&[u8]>::new();
+    for (_, symbol) in obj.symbols.for_section(section_index) {
+        if symbol.kind == ObjSymbolKind::Section || !matches!(symbol.size, 4 | 8) {
+            continue;
+        }
+        let Ok(data) = section.symbol_data(symbol) else { continue };
+        if !seen.insert(data) {
+            out.add(
+                SectionAddress::new(section_index, symbol.address as u32),
+                0.5,
+                format!(".sdata2: repeated constant {}", symbol.name),
+            );
+            seen.clear();
+            seen.insert(data);
+        }
+    }
+    Ok(())
+}
+
+/// Assertion strings name the source file of the functions that reference them.
+fn suggest_source_names(obj: &ObjInfo, out: &mut Suggestions) {
+    // Functions referencing each address in data sections
+    let mut references = HashMap::<SectionAddress, BTreeMap<SectionAddress, &ObjSymbol>>::new();
+    for (source_index, source_section) in obj.sections.by_kind(ObjSectionKind::Code) {
+        for (reloc_address, reloc) in source_section.relocations.iter() {
+            let target_symbol = &obj.symbols[reloc.target_symbol];
+            let Some(target_section) = target_symbol.section else { continue };
+            if !matches!(
+                obj.sections[target_section].kind,
+                ObjSectionKind::Data | ObjSectionKind::ReadOnlyData
+            ) {
+                continue;
+            }
+            let target = SectionAddress::new(
+                target_section,
+                (target_symbol.address as i64 + reloc.addend) as u32,
+            );
+            if let Some((_, function)) = obj
+                .symbols
+                .for_section_range(source_index, ..=reloc_address)
+                .rev()
+                .find(|(_, s)| s.kind == ObjSymbolKind::Function)
+            {
+                references
+                    .entry(target)
+                    .or_default()
+                    .insert(SectionAddress::new(source_index, function.address as u32), function);
+            }
+        }
+    }
+    if references.is_empty() {
+        return;
+    }
+
+    for (section_index, section) in obj.sections.iter() {
+        if !matches!(section.kind, ObjSectionKind::Data | ObjSectionKind::ReadOnlyData) {
+            continue;
+        }
+        let mut offset = 0;
+        for chunk in section.data.split(|&b| b == 0) {
+            let address = section.address as u32 + offset;
+            offset += chunk.len() as u32 + 1;
+            let Some(functions) = references.get(&SectionAddress::new(section_index, address))
+            else {
+                continue;
+            };
+            let Ok(string) = std::str::from_utf8(chunk) else { continue };
+            if !SOURCE_FILE.is_match(string) {
+                continue;
+            }
+            let (Some((&start, _)), Some((_, last))) =
+                (functions.first_key_value(), functions.last_key_value())
+            else {
+                continue;
+            };
+            if functions.keys().any(|a| a.section != start.section) {
+                continue;
+            }
+            let unit = string.rsplit(['/', '\\']).next().unwrap_or(string).to_string();
+            out.add_unit(
+                start,
+                Some((last.address + last.size) as u32),
+                Some(unit),
+                0.7,
+                format!(
+                    "source name: \"{}\" referenced by {} function(s)",
+                    string,
+                    functions.len()
+                ),
+            );
+        }
+    }
+}
+
+/// Suggests translation unit boundaries from evidence in the analyzed module.
+/// Relocations must already be applied.
+pub fn suggest_splits(obj: &ObjInfo) -> Result<Vec<SplitSuggestion>> {
+    let mut out = Suggestions { obj, out: Default::default() };
+    suggest_auto_splits(obj, &mut out);
+    suggest_string_pools(obj, &mut out);
+    suggest_sdata2(obj, &mut out)?;
+    suggest_source_names(obj, &mut out);
+    Ok(out.out.into_values().collect())
+}
+
+/// Writes suggestions in `splits.txt` syntax, to be reviewed and merged by hand. Each
+/// suggestion becomes a unit ending at the next suggestion or split, with its confidence and
+/// reasons in a comment.
+pub fn write_split_suggestions<W>(
+    w: &mut W,
+    obj: &ObjInfo,
+    suggestions: &[SplitSuggestion],
+) -> Result<()>
+where
+    W: Write + ?Sized,
+{
+    writeln!(w, "// Suggested unit boundaries for {}", obj.name)?;
+    writeln!(w, "// Confidence ranges from 0.00 to 1.00. Review before merging into splits.txt.")?;
+    write_split_sections(w, obj)?;
+    let mut units = HashSet::<(&str, SectionIndex)>::new();
+    for (i, suggestion) in suggestions.iter().enumerate() {
+        let address = suggestion.address;
+        let section = &obj.sections[address.section];
+        let next_suggestion = suggestions
+            .get(i + 1)
+            .filter(|next| next.address.section == address.section)
+            .map(|next| next.address.address);
+        let next_split = section.splits.for_range(address.address + 1..).next().map(|(a, _)| a);
+        let end = [suggestion.end, next_suggestion, next_split]
+            .into_iter()
+            .flatten()
+            .filter(|&end| end > address.address)
+            .fold((section.address + section.size) as u32, min);
+        // A unit can only have one split per section
+        let unit = match &suggestion.unit {
+            Some(unit) if units.insert((unit.as_str(), address.section)) => unit.clone(),
+            _ => format!(
+                "suggested_{:02}_{:08X}_{}",
+                address.section,
+                address.address,
+                section.name.trim_start_matches('.')
+            ),
+        };
+        writeln!(
+            w,
+            "\n// confidence:{:.2} {}",
+            suggestion.confidence,
+            suggestion.reasons.join("; ")
+        )?;
+        writeln!(w, "{}:", unit)?;
+        writeln!(w, "\t{:<11} start:{:#010X} end:{:#010X}", section.name, address.address, end)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{
+        obj::{ObjArchitecture, ObjKind},
+        util::config::apply_splits,
+    };
+
+    fn function(name: &str, address: u32, size: u32) -> ObjSymbol {
+        ObjSymbol {
+            name: name.to_string(),
+            address: address as u64,
+            section: Some(0),
+            size: size as u64,
+            size_known: true,
+            kind: ObjSymbolKind::Function,
+            ..Default::default()
+        }
+    }
+
+    fn test_obj() -> ObjInfo {
+        let text = ObjSection {
+            name: ".text".to_string(),
+            kind: ObjSectionKind::Code,
+            address: 0x80003000,
+            size: 0x30,
+            data: vec![0; 0x30],
+            align: 4,
+            elf_index: 0,
+            relocations: Default::default(),
+            virtual_address: Some(0x80003000),
+            file_offset: 0,
+            section_known: true,
+            splits: Default::default(),
+        };
+        // The second `bar` must be in another unit
+        let symbols = vec![
+            function("foo", 0x80003000, 0x10),
+            function("bar", 0x80003010, 0x10),
+            function("bar", 0x80003020, 0x10),
+        ];
+        ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "main".to_string(),
+            symbols,
+            vec![text],
+        )
+    }
+
+    #[test]
+    fn test_suggest_gap_split() {
+        let obj = test_obj();
+        let suggestions = suggest_splits(&obj).unwrap();
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].address, SectionAddress::new(0, 0x80003020));
+        assert!(suggestions[0].reasons[0].starts_with("gap split:"));
+    }
+
+    #[test]
+    fn test_write_split_suggestions() {
+        let mut obj = test_obj();
+        let suggestions = suggest_splits(&obj).unwrap();
+        let mut out = vec![];
+        write_split_suggestions(&mut out, &obj, &suggestions).unwrap();
+
+        // The output is valid splits.txt syntax
+        apply_splits(&mut Cursor::new(out), &mut obj).unwrap();
+        let (address, split) = obj.sections[0].splits.for_address(0x80003020).unwrap();
+        assert_eq!(address, 0x80003020);
+        assert_eq!(split.end, 0x80003030);
+        assert_eq!(split.unit, "suggested_00_80003020_text");
+    }
+}
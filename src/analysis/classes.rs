@@ -0,0 +1,827 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    io::Write,
+};
+
+use anyhow::Result;
+use cwdemangle::{demangle, DemangleOptions};
+use cwextab::decode_extab;
+use ppc750cl::Opcode;
+
+use crate::{
+    analysis::{
+        cfa::SectionAddress,
+        disassemble, read_u32,
+        references::containing_symbol,
+        vm::{is_load_op, is_loadf_op, is_store_op, is_storef_op},
+    },
+    obj::{ObjInfo, ObjRelocKind, ObjSectionKind, ObjSymbolKind, SymbolIndex},
+    util::config::is_auto_symbol,
+};
+
+/// A C++ class reconstructed from its vtable.
+#[derive(Debug, Clone)]
+pub struct ClassInfo {
+    /// Class name, from RTTI or the vtable symbol.
+    pub name: Option<String>,
+    pub vtable: SectionAddress,
+    pub rtti: Option<SectionAddress>,
+    /// Virtual functions in slot order. `None` for null slots.
+    pub slots: Vec<Option<SectionAddress>>,
+    pub bases: Vec<ClassBase>,
+    /// Classes destroyed by the constructors' exception cleanup actions: bases or members.
+    pub subobjects: Vec<ClassBase>,
+    /// Non-virtual functions that store the vtable.
+    pub constructors: Vec<SectionAddress>,
+    /// Virtual functions that store the vtable or are used as cleanup functions in extab.
+    pub destructors: Vec<SectionAddress>,
+    pub size: Option<ClassSize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClassBase {
+    pub name: Option<String>,
+    /// Vtable of the base class, if it was found.
+    pub vtable: Option<SectionAddress>,
+    /// Offset of the base within the class. Only known from RTTI.
+    pub offset: Option<i32>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ClassSize {
+    /// Size passed to `operator new` before a constructor call.
+    Allocated(u32),
+    /// Extent of member accesses through `this` in the constructors.
+    AtLeast(u32),
+}
+
+/// Parses a mangled class name, e.g. `3Foo` -> `Foo` or `Q23Foo3Bar` -> `Foo::Bar`.
+fn parse_class_name(mut s: &str) -> Option<String> {
+    let count = if let Some(rest) = s.strip_prefix('Q') {
+        let count = rest.get(..1)?.parse::<usize>().ok()?;
+        s = &rest[1..];
+        count
+    } else {
+        1
+    };
+    let mut parts = Vec::with_capacity(count);
+    for _ in 0..count {
+        let digits = s.find(|c: char| !c.is_ascii_digit())?;
+        let len = s[..digits].parse::<usize>().ok()?;
+        parts.push(s.get(digits..digits + len)?);
+        s = &s[digits + len..];
+    }
+    s.is_empty().then(|| parts.join("::"))
+}
+
+/// Mangles a class name, e.g. `Foo` -> `3Foo` or `Foo::Bar` -> `Q23Foo3Bar`.
+/// Template classes aren't supported.
+fn mangle_class_name(name: &str) -> Option<String> {
+    let parts = name.split("::").collect::<Vec<_>>();
+    if parts.len() > 9
+        || parts
+            .iter()
+            .any(|p| p.is_empty() || !p.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+    {
+        return None;
+    }
+    let mangled = parts.iter().map(|p| format!("{}{}", p.len(), p)).collect::<String>();
+    Some(if parts.len() > 1 { format!("Q{}{}", parts.len(), mangled) } else { mangled })
+}
+
+/// Reads an absolute pointer from the relocations at the address.
+fn read_pointer(obj: &ObjInfo, address: SectionAddress) -> Option<SectionAddress> {
+    let reloc = obj.sections[address.section].relocations.at(address.address)?;
+    if reloc.kind != ObjRelocKind::Absolute {
+        return None;
+    }
+    let target = &obj.symbols[reloc.target_symbol];
+    Some(SectionAddress::new(target.section?, (target.address as i64 + reloc.addend) as u32))
+}
+
+fn read_word(obj: &ObjInfo, address: SectionAddress) -> Option<u32> {
+    read_u32(&obj.sections[address.section], address.address)
+}
+
+fn read_string(obj: &ObjInfo, address: SectionAddress) -> Option<String> {
+    let section = &obj.sections[address.section];
+    let data = section.data_range(address.address, 0).ok()?;
+    let end = data.iter().position(|&b| b == 0)?;
+    let bytes = &data[..end];
+    if bytes.is_empty()
+        || bytes.len() > 256
+        || !bytes.iter().all(|c| c.is_ascii_graphic() || *c == b' ')
+    {
+        return None;
+    }
+    Some(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Reads an RTTI object: a pointer to the class name, followed by a pointer to a
+/// null-terminated list of (base RTTI, offset) pairs.
+fn read_rtti(
+    obj: &ObjInfo,
+    address: SectionAddress,
+) -> Option<(String, Vec<(SectionAddress, i32)>)> {
+    let name = read_string(obj, read_pointer(obj, address)?)?;
+    let mut bases = vec![];
+    if read_word(obj, address + 4)? != 0 {
+        let mut entry = read_pointer(obj, address + 4)?;
+        while let Some(base) = read_pointer(obj, entry) {
+            bases.push((base, read_word(obj, entry + 4)? as i32));
+            entry += 8;
+            if bases.len() > 32 {
+                return None;
+            }
+        }
+    }
+    Some((name, bases))
+}
+
+/// Returns the address of the function containing the address.
+fn containing_function(obj: &ObjInfo, address: SectionAddress) -> Option<SectionAddress> {
+    let symbol_idx = containing_symbol(obj, address.section, address.address)?;
+    let symbol = &obj.symbols[symbol_idx];
+    (symbol.kind == ObjSymbolKind::Function)
+        .then(|| SectionAddress::new(address.section, symbol.address as u32))
+}
+
+fn function_range(obj: &ObjInfo, function: SectionAddress) -> Option<(u32, u32)> {
+    let (_, symbol) = obj
+        .symbols
+        .kind_at_section_address(function.section, function.address, ObjSymbolKind::Function)
+        .ok()??;
+    Some((function.address, function.address + symbol.size as u32))
+}
+
+/// Iterates over the direct calls (`bl`) in the object, as (call site, target) pairs.
+fn calls(obj: &ObjInfo) -> impl Iterator<Item = (SectionAddress, SectionAddress)> + '_ {
+    obj.sections.by_kind(ObjSectionKind::Code).flat_map(move |(section_index, section)| {
+        section.relocations.iter().filter_map(move |(address, reloc)| {
+            if reloc.kind != ObjRelocKind::PpcRel24
+                || !disassemble(section, address).is_some_and(|ins| ins.field_lk())
+            {
+                return None;
+            }
+            let target = &obj.symbols[reloc.target_symbol];
+            let target =
+                SectionAddress::new(target.section?, (target.address as i64 + reloc.addend) as u32);
+            Some((SectionAddress::new(section_index, address), target))
+        })
+    })
+}
+
+/// Finds the allocation size of `li r3, N; bl operator new; ...; bl constructor` sequences.
+fn allocated_size(obj: &ObjInfo, call_site: SectionAddress) -> Option<u32> {
+    let section = &obj.sections[call_site.section];
+    let function = containing_function(obj, call_site)?;
+    let ins_at = |address: u32| {
+        (address >= function.address).then(|| disassemble(section, address)).flatten()
+    };
+    for new_offset in 1..=4 {
+        let new_call = call_site.address.checked_sub(new_offset * 4)?;
+        let ins = ins_at(new_call)?;
+        if ins.op != Opcode::B || !ins.field_lk() {
+            continue;
+        }
+        for li_offset in 1..=2 {
+            let ins = ins_at(new_call.checked_sub(li_offset * 4)?)?;
+            if ins.op == Opcode::Addi && ins.field_rd() == 3 && ins.field_ra() == 0 {
+                let size = ins.field_simm() as i32;
+                return (size > 0).then_some(size as u32);
+            }
+        }
+        break;
+    }
+    None
+}
+
+/// Estimates a lower bound for the class size from accesses through `this` in a constructor.
+fn member_extent(obj: &ObjInfo, function: SectionAddress) -> Option<u32> {
+    let (start, end) = function_range(obj, function)?;
+    let section = &obj.sections[function.section];
+    let mut this_regs = BTreeSet::from([3]);
+    let mut extent = 0u32;
+    for address in (start..end).step_by(4) {
+        let Some(ins) = disassemble(section, address) else { break };
+        let op = ins.op;
+        if op == Opcode::Or && ins.field_rs() == ins.field_rb() {
+            // mr rA, rS
+            if this_regs.contains(&ins.field_rs()) {
+                this_regs.insert(ins.field_ra());
+            } else {
+                this_regs.remove(&ins.field_ra());
+            }
+        } else if is_load_op(op) || is_store_op(op) || is_loadf_op(op) || is_storef_op(op) {
+            if this_regs.contains(&ins.field_ra()) {
+                let width = match op {
+                    Opcode::Lbz | Opcode::Stb => 1,
+                    Opcode::Lha | Opcode::Lhz | Opcode::Sth => 2,
+                    Opcode::Lwz | Opcode::Stw | Opcode::Lfs | Opcode::Stfs => 4,
+                    Opcode::Lfd | Opcode::Stfd => 8,
+                    _ => 0,
+                };
+                let offset = ins.field_simm() as i32;
+                if width > 0 && offset >= 0 {
+                    extent = extent.max(offset as u32 + width);
+                }
+            }
+            if is_load_op(op) {
+                this_regs.remove(&ins.field_rd());
+            }
+        } else if matches!(op, Opcode::Addi | Opcode::Addis) {
+            this_regs.remove(&ins.field_rd());
+        } else if op == Opcode::B && ins.field_lk() {
+            // Volatile registers are clobbered by calls
+            this_regs.retain(|&r| r >= 13);
+        }
+        if this_regs.is_empty() {
+            break;
+        }
+    }
+    (extent > 0).then_some(extent)
+}
+
+/// Collects the cleanup functions referenced by each function's exception table, using
+/// `extabindex` to associate functions with their `extab` entries.
+fn extab_cleanups(obj: &ObjInfo) -> Result<BTreeMap<SectionAddress, BTreeSet<SectionAddress>>> {
+    let mut out = BTreeMap::<SectionAddress, BTreeSet<SectionAddress>>::new();
+    let (Some((index_section_index, index_section)), Some((extab_section_index, extab_section))) =
+        (obj.sections.by_name("extabindex")?, obj.sections.by_name("extab")?)
+    else {
+        return Ok(out);
+    };
+    let mut entries = BTreeMap::<u32, SectionAddress>::new();
+    let start = index_section.address as u32;
+    for address in (start..start + index_section.size as u32).step_by(12) {
+        let entry = SectionAddress::new(index_section_index, address);
+        let (Some(function), Some(extab)) =
+            (read_pointer(obj, entry), read_pointer(obj, entry + 8))
+        else {
+            continue;
+        };
+        if extab.section == extab_section_index {
+            entries.insert(extab.address, function);
+        }
+    }
+    // Entries are contiguous, so each one ends where the next begins
+    let extab_end = (extab_section.address + extab_section.size) as u32;
+    let ends = entries.keys().skip(1).copied().chain([extab_end]).collect::<Vec<_>>();
+    for ((&start, &function), end) in entries.iter().zip(ends) {
+        let Ok(data) = extab_section.data_range(start, end) else { continue };
+        let decoded = match decode_extab(data) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                log::debug!("Failed to decode extab entry @ {:#010X}: {}", start, e);
+                continue;
+            }
+        };
+        for reloc in decoded.relocations {
+            let Some(target) =
+                read_pointer(obj, SectionAddress::new(extab_section_index, start + reloc.offset))
+            else {
+                continue;
+            };
+            if obj.sections[target.section].kind == ObjSectionKind::Code {
+                out.entry(function).or_default().insert(target);
+            }
+        }
+    }
+    Ok(out)
+}
+
+struct VtableCandidate {
+    symbol: SymbolIndex,
+    name: Option<String>,
+    rtti: Option<(SectionAddress, Vec<(SectionAddress, i32)>)>,
+    slots: Vec<Option<SectionAddress>>,
+}
+
+/// Reads a vtable: an RTTI pointer (or null), a zero `this` offset, then function pointers.
+/// Secondary vtables (for multiple inheritance) following the primary one are ignored.
+fn read_vtable(obj: &ObjInfo, symbol_idx: SymbolIndex) -> Option<VtableCandidate> {
+    let symbol = &obj.symbols[symbol_idx];
+    let address = SectionAddress::new(symbol.section?, symbol.address as u32);
+    if symbol.size < 12 || symbol.size % 4 != 0 || address.address % 4 != 0 {
+        return None;
+    }
+    let mut name = symbol.name.strip_prefix("__vt__").and_then(parse_class_name);
+    let rtti = if read_word(obj, address)? == 0 {
+        None
+    } else {
+        let rtti_addr = read_pointer(obj, address)?;
+        let (rtti_name, bases) = read_rtti(obj, rtti_addr)?;
+        name = Some(rtti_name);
+        Some((rtti_addr, bases))
+    };
+    if read_word(obj, address + 4)? != 0 {
+        return None;
+    }
+    let mut slots = vec![];
+    for offset in (8..symbol.size as u32).step_by(4) {
+        if let Some(target) = read_pointer(obj, address + offset) {
+            if obj.sections[target.section].kind != ObjSectionKind::Code {
+                break;
+            }
+            slots.push(Some(target));
+        } else if read_word(obj, address + offset)? == 0 {
+            slots.push(None);
+        } else {
+            break;
+        }
+    }
+    while slots.last().is_some_and(|s| s.is_none()) {
+        slots.pop();
+    }
+    if slots.is_empty() {
+        return None;
+    }
+    Some(VtableCandidate { symbol: symbol_idx, name, rtti, slots })
+}
+
+/// Reconstructs C++ classes from vtables, RTTI and the functions that store the vtables.
+/// Relocations must already be applied.
+///
+/// With `rename`, unnamed vtables, RTTI objects, destructors and virtual functions are named
+/// after their class.
+pub fn detect_classes(obj: &mut ObjInfo, rename: bool) -> Result<Vec<ClassInfo>> {
+    let mut candidates = BTreeMap::<SectionAddress, VtableCandidate>::new();
+    for (section_index, section) in obj.sections.iter() {
+        if !matches!(section.kind, ObjSectionKind::Data | ObjSectionKind::ReadOnlyData) {
+            continue;
+        }
+        for (symbol_idx, symbol) in obj.symbols.for_section(section_index) {
+            if symbol.kind == ObjSymbolKind::Section {
+                continue;
+            }
+            if let Some(candidate) = read_vtable(obj, symbol_idx) {
+                candidates
+                    .insert(SectionAddress::new(section_index, symbol.address as u32), candidate);
+            }
+        }
+    }
+
+    // Find the functions storing each vtable (`lis rX, vt@ha; addi rX, rX, vt@l`)
+    let mut users = BTreeMap::<SectionAddress, BTreeSet<SectionAddress>>::new();
+    for (section_index, section) in obj.sections.by_kind(ObjSectionKind::Code) {
+        for (address, reloc) in section.relocations.iter() {
+            if !matches!(reloc.kind, ObjRelocKind::PpcAddr16Lo | ObjRelocKind::PpcEmbSda21)
+                || !disassemble(section, address).is_some_and(|ins| ins.op == Opcode::Addi)
+            {
+                continue;
+            }
+            let target = &obj.symbols[reloc.target_symbol];
+            let Some(target_section) = target.section else { continue };
+            let target =
+                SectionAddress::new(target_section, (target.address as i64 + reloc.addend) as u32);
+            if !candidates.contains_key(&target) {
+                continue;
+            }
+            if let Some(function) =
+                containing_function(obj, SectionAddress::new(section_index, address))
+            {
+                users.entry(target).or_default().insert(function);
+            }
+        }
+    }
+
+    // Tables of function pointers without RTTI or a name need a constructor to qualify
+    candidates.retain(|address, candidate| {
+        candidate.rtti.is_some()
+            || obj.symbols[candidate.symbol].name.starts_with("__vt__")
+            || users.contains_key(address)
+    });
+
+    // Exception cleanup functions are destructors (or `operator delete`, which is never a slot)
+    let cleanups = extab_cleanups(obj)?;
+    let all_cleanups = cleanups.values().flatten().copied().collect::<HashSet<_>>();
+
+    let all_slots =
+        candidates.values().flat_map(|c| c.slots.iter().flatten().copied()).collect::<HashSet<_>>();
+    let mut classes = candidates
+        .iter()
+        .map(|(&vtable, candidate)| {
+            let (mut destructors, constructors) = users
+                .get(&vtable)
+                .map(|users| {
+                    users
+                        .iter()
+                        .copied()
+                        .partition::<Vec<_>, _>(|f| candidate.slots.contains(&Some(*f)))
+                })
+                .unwrap_or_default();
+            for &slot in candidate.slots.iter().flatten() {
+                if all_cleanups.contains(&slot) && !destructors.contains(&slot) {
+                    destructors.push(slot);
+                }
+            }
+            ClassInfo {
+                name: candidate.name.clone(),
+                vtable,
+                rtti: candidate.rtti.as_ref().map(|(rtti, _)| *rtti),
+                slots: candidate.slots.clone(),
+                bases: vec![],
+                subobjects: vec![],
+                constructors: constructors.into_iter().filter(|f| !all_slots.contains(f)).collect(),
+                destructors,
+                size: None,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // Base classes from RTTI, or else from destructors calling other destructors
+    let calls = calls(obj).collect::<Vec<_>>();
+    let rtti_classes =
+        classes.iter().filter_map(|c| Some((c.rtti?, c.vtable))).collect::<BTreeMap<_, _>>();
+    let dtor_classes = classes
+        .iter()
+        .flat_map(|c| c.destructors.iter().map(move |&d| (d, (c.vtable, c.name.clone()))))
+        .collect::<BTreeMap<_, _>>();
+    for class in &mut classes {
+        let mut seen_subobjects = BTreeSet::new();
+        for cleanup in class.constructors.iter().filter_map(|f| cleanups.get(f)).flatten() {
+            if let Some((vtable, name)) = dtor_classes.get(cleanup) {
+                if *vtable != class.vtable && seen_subobjects.insert(*vtable) {
+                    class.subobjects.push(ClassBase {
+                        name: name.clone(),
+                        vtable: Some(*vtable),
+                        offset: None,
+                    });
+                }
+            }
+        }
+
+        let candidate = &candidates[&class.vtable];
+        if let Some((_, bases)) = &candidate.rtti {
+            for &(base_rtti, offset) in bases {
+                class.bases.push(ClassBase {
+                    name: read_rtti(obj, base_rtti).map(|(name, _)| name),
+                    vtable: rtti_classes.get(&base_rtti).copied(),
+                    offset: Some(offset),
+                });
+            }
+            continue;
+        }
+        let mut seen = BTreeSet::new();
+        for &dtor in &class.destructors {
+            let Some((start, end)) = function_range(obj, dtor) else { continue };
+            for &(call_site, target) in &calls {
+                if call_site.section != dtor.section
+                    || call_site.address < start
+                    || call_site.address >= end
+                {
+                    continue;
+                }
+                if let Some((base_vtable, base_name)) = dtor_classes.get(&target) {
+                    if *base_vtable != class.vtable && seen.insert(*base_vtable) {
+                        class.bases.push(ClassBase {
+                            name: base_name.clone(),
+                            vtable: Some(*base_vtable),
+                            offset: None,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    // Estimate sizes
+    for class in &mut classes {
+        let allocated = calls
+            .iter()
+            .filter(|(_, target)| class.constructors.contains(target))
+            .filter_map(|&(call_site, _)| allocated_size(obj, call_site))
+            .max();
+        class.size = if let Some(size) = allocated {
+            Some(ClassSize::Allocated(size))
+        } else {
+            class
+                .constructors
+                .iter()
+                .filter_map(|&f| member_extent(obj, f))
+                .max()
+                .map(ClassSize::AtLeast)
+        };
+    }
+
+    if rename {
+        rename_symbols(obj, &classes)?;
+    }
+    Ok(classes)
+}
+
+fn rename_symbols(obj: &mut ObjInfo, classes: &[ClassInfo]) -> Result<()> {
+    let mut renames = BTreeMap::<SymbolIndex, String>::new();
+    let mut find = |obj: &ObjInfo, address: SectionAddress, kind: ObjSymbolKind, name: String| {
+        if let Ok(Some((symbol_idx, _))) =
+            obj.symbols.kind_at_section_address(address.section, address.address, kind)
+        {
+            renames.entry(symbol_idx).or_insert(name);
+        }
+    };
+    for class in classes {
+        let Some(mangled) = class.name.as_deref().and_then(mangle_class_name) else { continue };
+        find(obj, class.vtable, ObjSymbolKind::Object, format!("__vt__{}", mangled));
+        if let Some(rtti) = class.rtti {
+            find(obj, rtti, ObjSymbolKind::Object, format!("__RTTI__{}", mangled));
+        }
+        for &dtor in &class.destructors {
+            find(obj, dtor, ObjSymbolKind::Function, format!("__dt__{}Fv", mangled));
+        }
+    }
+    // Method names can't be recovered, so other slots get unmangled names like `Foo_vfunc_3`.
+    // They're named after the class with the smallest vtable containing them, since that's most
+    // likely the class declaring them.
+    let mut owners = BTreeMap::<SectionAddress, (usize, usize, &str)>::new();
+    for class in classes {
+        let Some(name) = class.name.as_deref().filter(|n| mangle_class_name(n).is_some()) else {
+            continue;
+        };
+        for (slot, function) in class.slots.iter().enumerate() {
+            let Some(function) = *function else { continue };
+            match owners.get(&function) {
+                Some(&(len, _, _)) if len <= class.slots.len() => {}
+                _ => {
+                    owners.insert(function, (class.slots.len(), slot, name));
+                }
+            }
+        }
+    }
+    for (function, (_, slot, name)) in owners {
+        let name = format!("{}_vfunc_{}", name.replace("::", "_"), slot);
+        find(obj, function, ObjSymbolKind::Function, name);
+    }
+
+    let mut used_names = HashSet::new();
+    for (symbol_idx, name) in renames {
+        let symbol = &obj.symbols[symbol_idx];
+        if !is_auto_symbol(symbol)
+            || obj.symbols.for_name(&name).next().is_some()
+            || !used_names.insert(name.clone())
+        {
+            continue;
+        }
+        log::debug!("Renaming {} to {}", symbol.name, name);
+        let mut symbol = symbol.clone();
+        symbol.demangled_name = demangle(&name, &DemangleOptions::default());
+        symbol.name = name;
+        obj.symbols.replace(symbol_idx, symbol)?;
+    }
+    Ok(())
+}
+
+fn symbol_name(obj: &ObjInfo, address: SectionAddress) -> String {
+    obj.symbols
+        .at_section_address(address.section, address.address)
+        .find(|(_, s)| s.kind != ObjSymbolKind::Section)
+        .map(|(_, s)| s.demangled_name.as_ref().unwrap_or(&s.name).clone())
+        .unwrap_or_else(|| format!("{:#010X}", address.address))
+}
+
+/// Writes a report of the reconstructed classes.
+pub fn write_class_report<W>(w: &mut W, obj: &ObjInfo, classes: &[ClassInfo]) -> Result<()>
+where W: Write + ?Sized {
+    writeln!(w, "// Classes reconstructed from vtables and RTTI for {}", obj.name)?;
+    for class in classes {
+        writeln!(w, "\nclass {}", class.name.as_deref().unwrap_or("<unknown>"))?;
+        writeln!(
+            w,
+            "\tvtable: {} @ {:#010X}",
+            symbol_name(obj, class.vtable),
+            class.vtable.address
+        )?;
+        if let Some(rtti) = class.rtti {
+            writeln!(w, "\trtti: {} @ {:#010X}", symbol_name(obj, rtti), rtti.address)?;
+        }
+        match class.size {
+            Some(ClassSize::Allocated(size)) => writeln!(w, "\tsize: {:#X}", size)?,
+            Some(ClassSize::AtLeast(size)) => writeln!(w, "\tsize: >= {:#X}", size)?,
+            None => {}
+        }
+        for base in &class.bases {
+            write!(w, "\tbase: {}", base.name.as_deref().unwrap_or("<unknown>"))?;
+            if let Some(offset) = base.offset {
+                write!(w, " offset:{:#X}", offset)?;
+            }
+            if let Some(vtable) = base.vtable {
+                write!(w, " vtable:{:#010X}", vtable.address)?;
+            }
+            writeln!(w)?;
+        }
+        for subobject in &class.subobjects {
+            write!(w, "\tsubobject: {}", subobject.name.as_deref().unwrap_or("<unknown>"))?;
+            if let Some(vtable) = subobject.vtable {
+                write!(w, " vtable:{:#010X}", vtable.address)?;
+            }
+            writeln!(w)?;
+        }
+        for &function in &class.constructors {
+            writeln!(
+                w,
+                "\tconstructor: {} @ {:#010X}",
+                symbol_name(obj, function),
+                function.address
+            )?;
+        }
+        for &function in &class.destructors {
+            writeln!(
+                w,
+                "\tdestructor: {} @ {:#010X}",
+                symbol_name(obj, function),
+                function.address
+            )?;
+        }
+        for (slot, function) in class.slots.iter().enumerate() {
+            match function {
+                Some(function) => writeln!(
+                    w,
+                    "\tslot {}: {} @ {:#010X}",
+                    slot,
+                    symbol_name(obj, *function),
+                    function.address
+                )?,
+                None => writeln!(w, "\tslot {}: null", slot)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{ObjArchitecture, ObjKind, ObjReloc, ObjSection, ObjSymbol};
+
+    fn section(name: &str, kind: ObjSectionKind, address: u64, words: &[u32]) -> ObjSection {
+        let data = words.iter().flat_map(|w| w.to_be_bytes()).collect::<Vec<_>>();
+        ObjSection {
+            name: name.to_string(),
+            kind,
+            address,
+            size: data.len() as u64,
+            data,
+            align: 4,
+            elf_index: 0,
+            relocations: Default::default(),
+            virtual_address: Some(address),
+            file_offset: 0,
+            section_known: true,
+            splits: Default::default(),
+        }
+    }
+
+    fn symbol(name: &str, kind: ObjSymbolKind, section: u32, address: u64, size: u64) -> ObjSymbol {
+        ObjSymbol {
+            name: name.to_string(),
+            address,
+            section: Some(section),
+            size,
+            size_known: true,
+            kind,
+            ..Default::default()
+        }
+    }
+
+    fn relocate(section: &mut ObjSection, address: u32, kind: ObjRelocKind, target: SymbolIndex) {
+        let reloc = ObjReloc { kind, target_symbol: target, addend: 0, module: None };
+        section.relocations.insert(address, reloc).unwrap();
+    }
+
+    /// `Base` has RTTI and an unnamed vtable, `Derived` has a named vtable without RTTI and a
+    /// destructor calling the `Base` destructor.
+    fn test_obj() -> ObjInfo {
+        const LIS_R4: u32 = 0x3C800000;
+        const ADDI_R4: u32 = 0x38840000;
+        const STW_R4_0_R3: u32 = 0x90830000;
+        const BL: u32 = 0x48000001;
+        const BLR: u32 = 0x4E800020;
+        #[rustfmt::skip]
+        let mut text = section(".text", ObjSectionKind::Code, 0x80003000, &[
+            // Base::Base
+            LIS_R4, ADDI_R4, STW_R4_0_R3, BLR,
+            // Base::~Base
+            LIS_R4, ADDI_R4, STW_R4_0_R3, BLR,
+            // Derived::Derived (stw r0, 8(r3))
+            LIS_R4, ADDI_R4, STW_R4_0_R3, 0x90030008, BLR,
+            // Derived::~Derived
+            LIS_R4, ADDI_R4, STW_R4_0_R3, BL, BLR,
+            // new Derived (li r3, 0x10)
+            0x38600010, BL, BL, BLR,
+        ]);
+        for (address, vtable) in
+            [(0x80003000, 5), (0x80003010, 5), (0x80003020, 8), (0x80003034, 8)]
+        {
+            relocate(&mut text, address, ObjRelocKind::PpcAddr16Ha, vtable);
+            relocate(&mut text, address + 4, ObjRelocKind::PpcAddr16Lo, vtable);
+        }
+        relocate(&mut text, 0x80003040, ObjRelocKind::PpcRel24, 1);
+        relocate(&mut text, 0x80003050, ObjRelocKind::PpcRel24, 2);
+        #[rustfmt::skip]
+        let mut data = section(".data", ObjSectionKind::Data, 0x80004000, &[
+            // Base vtable
+            0x8000400C, 0, 0x80003010,
+            // Base RTTI
+            0x80004014, 0,
+            // "Base"
+            0x42617365, 0,
+            // Derived vtable
+            0, 0, 0x80003034, 0x80003048,
+        ]);
+        relocate(&mut data, 0x80004000, ObjRelocKind::Absolute, 6);
+        relocate(&mut data, 0x80004008, ObjRelocKind::Absolute, 1);
+        relocate(&mut data, 0x8000400C, ObjRelocKind::Absolute, 7);
+        relocate(&mut data, 0x80004024, ObjRelocKind::Absolute, 3);
+        relocate(&mut data, 0x80004028, ObjRelocKind::Absolute, 4);
+        let symbols = vec![
+            symbol("fn_80003000", ObjSymbolKind::Function, 0, 0x80003000, 0x10),
+            symbol("fn_80003010", ObjSymbolKind::Function, 0, 0x80003010, 0x10),
+            symbol("fn_80003020", ObjSymbolKind::Function, 0, 0x80003020, 0x14),
+            symbol("fn_80003034", ObjSymbolKind::Function, 0, 0x80003034, 0x14),
+            symbol("fn_80003048", ObjSymbolKind::Function, 0, 0x80003048, 0x10),
+            symbol("lbl_80004000", ObjSymbolKind::Object, 1, 0x80004000, 0xC),
+            symbol("lbl_8000400C", ObjSymbolKind::Object, 1, 0x8000400C, 0x8),
+            symbol("lbl_80004014", ObjSymbolKind::Object, 1, 0x80004014, 0x8),
+            symbol("__vt__7Derived", ObjSymbolKind::Object, 1, 0x8000401C, 0x10),
+        ];
+        ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "main".to_string(),
+            symbols,
+            vec![text, data],
+        )
+    }
+
+    #[test]
+    fn test_detect_classes() {
+        let mut obj = test_obj();
+        let classes = detect_classes(&mut obj, false).unwrap();
+        assert_eq!(classes.len(), 2);
+
+        let base = &classes[0];
+        assert_eq!(base.name.as_deref(), Some("Base"));
+        assert_eq!(base.rtti, Some(SectionAddress::new(1, 0x8000400C)));
+        assert_eq!(base.slots, vec![Some(SectionAddress::new(0, 0x80003010))]);
+        assert_eq!(base.constructors, vec![SectionAddress::new(0, 0x80003000)]);
+        assert_eq!(base.destructors, vec![SectionAddress::new(0, 0x80003010)]);
+        assert_eq!(base.size, Some(ClassSize::AtLeast(4)));
+        assert!(base.bases.is_empty());
+
+        let derived = &classes[1];
+        assert_eq!(derived.name.as_deref(), Some("Derived"));
+        assert_eq!(derived.rtti, None);
+        assert_eq!(derived.slots, vec![
+            Some(SectionAddress::new(0, 0x80003034)),
+            Some(SectionAddress::new(0, 0x80003048))
+        ]);
+        assert_eq!(derived.constructors, vec![SectionAddress::new(0, 0x80003020)]);
+        assert_eq!(derived.destructors, vec![SectionAddress::new(0, 0x80003034)]);
+        assert_eq!(derived.size, Some(ClassSize::Allocated(0x10)));
+        assert_eq!(derived.bases.len(), 1);
+        assert_eq!(derived.bases[0].name.as_deref(), Some("Base"));
+        assert_eq!(derived.bases[0].vtable, Some(base.vtable));
+        // No exception tables
+        assert!(derived.subobjects.is_empty());
+    }
+
+    #[test]
+    fn test_rename_symbols() {
+        let mut obj = test_obj();
+        detect_classes(&mut obj, true).unwrap();
+        let names = obj.symbols.iter().map(|(_, s)| s.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec![
+            "fn_80003000",
+            "__dt__4BaseFv",
+            "fn_80003020",
+            "__dt__7DerivedFv",
+            "Derived_vfunc_1",
+            "__vt__4Base",
+            "__RTTI__4Base",
+            "lbl_80004014",
+            "__vt__7Derived",
+        ]);
+    }
+
+    #[test]
+    fn test_class_report() {
+        let mut obj = test_obj();
+        let classes = detect_classes(&mut obj, true).unwrap();
+        let mut out = Vec::<u8>::new();
+        write_class_report(&mut out, &obj, &classes).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("\nclass Derived\n\tvtable: __vt__7Derived @ 0x8000401C\n"));
+        assert!(out.contains("\tbase: Base vtable:0x80004000\n"));
+        assert!(out.contains("\tslot 0: Base::~Base() @ 0x80003010\n"));
+        assert!(out.contains("\tslot 1: Derived_vfunc_1 @ 0x80003048\n"));
+    }
+
+    #[test]
+    fn test_class_names() {
+        assert_eq!(parse_class_name("3Foo").as_deref(), Some("Foo"));
+        assert_eq!(parse_class_name("Q23Foo3Bar").as_deref(), Some("Foo::Bar"));
+        assert_eq!(parse_class_name("3Fo"), None);
+        assert_eq!(mangle_class_name("Foo").as_deref(), Some("3Foo"));
+        assert_eq!(mangle_class_name("Foo::Bar").as_deref(), Some("Q23Foo3Bar"));
+        assert_eq!(mangle_class_name("Foo<int>"), None);
+    }
+}
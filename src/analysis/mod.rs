@@ -11,13 +11,19 @@ use crate::{
     },
 };
 
+pub mod boundaries;
 pub mod cfa;
+pub mod classes;
 pub mod executor;
 pub mod objects;
 pub mod pass;
+pub mod port;
+pub mod references;
+pub mod shiftcheck;
 pub mod signatures;
 pub mod slices;
 pub mod tracker;
+pub mod versions;
 pub mod vm;
 
 pub fn disassemble(section: &ObjSection, address: u32) -> Option<Ins> {
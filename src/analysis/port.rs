@@ -0,0 +1,854 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use anyhow::{ensure, Result};
+use serde::Serialize;
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::{
+    analysis::{
+        cfa::SectionAddress,
+        tracker::{Relocation, Tracker},
+        RelocationTarget,
+    },
+    obj::{ObjInfo, ObjSplit, ObjSymbol, ObjSymbolKind, SectionIndex, SymbolIndex},
+    util::config::is_auto_symbol,
+};
+
+/// A function used for matching, with its references to other addresses in the module.
+struct MatchFunction {
+    symbol: SymbolIndex,
+    size: u32,
+    /// Hash of the function's instructions, with relocated fields masked out.
+    hash: u64,
+    /// Reference targets, keyed by offset from the start of the function.
+    references: BTreeMap<u32, SectionAddress>,
+    /// Call (and tail call) targets, in order.
+    calls: Vec<SectionAddress>,
+}
+
+type FunctionMap = BTreeMap<SectionAddress, MatchFunction>;
+
+/// Collects all sized functions in the module, using the tracker to locate relocated
+/// instructions.
+fn collect_functions(obj: &ObjInfo) -> Result<FunctionMap> {
+    let mut tracker = Tracker::new(obj);
+    tracker.process(obj)?;
+
+    let mut out = FunctionMap::new();
+    for (symbol_idx, symbol) in obj.symbols.by_kind(ObjSymbolKind::Function) {
+        let Some(section_index) = symbol.section else { continue };
+        if symbol.size == 0 {
+            continue;
+        }
+        let start = SectionAddress::new(section_index, symbol.address as u32);
+        let data = obj.sections[section_index]
+            .data_range(start.address, start.address + symbol.size as u32)?;
+        let mut masked = Vec::with_capacity(data.len());
+        let mut references = BTreeMap::new();
+        let mut calls = vec![];
+        for (offset, chunk) in (0u32..).step_by(4).zip(data.chunks_exact(4)) {
+            let mut ins = u32::from_be_bytes(chunk.try_into().unwrap());
+            if let Some(reloc) = tracker.relocations.get(&(start + offset)) {
+                let (mask, target) = match *reloc {
+                    Relocation::Ha(target) | Relocation::Hi(target) | Relocation::Lo(target) => {
+                        (!0xFFFF, target)
+                    }
+                    Relocation::Sda21(target) => (!0x1FFFFF, target),
+                    Relocation::Rel14(target) => (!0xFFFC, target),
+                    Relocation::Rel24(target) => (!0x3FFFFFC, target),
+                    Relocation::Absolute(target) => (0, target),
+                };
+                ins &= mask;
+                if let RelocationTarget::Address(target) = target {
+                    references.insert(offset, target);
+                    if matches!(reloc, Relocation::Rel24(_)) {
+                        calls.push(target);
+                    }
+                }
+            }
+            masked.extend_from_slice(&ins.to_be_bytes());
+        }
+        out.insert(start, MatchFunction {
+            symbol: symbol_idx,
+            size: symbol.size as u32,
+            hash: xxh3_64(&masked),
+            references,
+            calls,
+        });
+    }
+    Ok(out)
+}
+
+struct Matcher<'a> {
+    source: &'a FunctionMap,
+    target: &'a FunctionMap,
+    sections: &'a BTreeMap<SectionIndex, SectionIndex>,
+    /// Source function -> target function
+    matched: BTreeMap<SectionAddress, SectionAddress>,
+    /// Target functions that have been matched
+    matched_target: BTreeSet<SectionAddress>,
+}
+
+impl Matcher<'_> {
+    fn try_match(&mut self, source: SectionAddress, target: SectionAddress) -> bool {
+        if self.sections.get(&source.section) != Some(&target.section)
+            || !self.source.contains_key(&source)
+            || !self.target.contains_key(&target)
+            || self.matched.contains_key(&source)
+            || self.matched_target.contains(&target)
+        {
+            return false;
+        }
+        self.matched.insert(source, target);
+        self.matched_target.insert(target);
+        true
+    }
+
+    /// Unmatched source and target functions, grouped by hash.
+    fn unmatched_by_hash(&self) -> HashMap<u64, (Vec<SectionAddress>, Vec<SectionAddress>)> {
+        let mut out = HashMap::<u64, (Vec<SectionAddress>, Vec<SectionAddress>)>::new();
+        for (&addr, function) in self.source {
+            if !self.matched.contains_key(&addr) {
+                out.entry(function.hash).or_default().0.push(addr);
+            }
+        }
+        for (&addr, function) in self.target {
+            if !self.matched_target.contains(&addr) {
+                out.entry(function.hash).or_default().1.push(addr);
+            }
+        }
+        out
+    }
+
+    /// Matches functions whose hash is unique among the remaining unmatched functions.
+    fn match_unique_hashes(&mut self) {
+        for (source, target) in self.unmatched_by_hash().into_values() {
+            if let ([source], [target]) = (source.as_slice(), target.as_slice()) {
+                self.try_match(*source, *target);
+            }
+        }
+    }
+
+    /// Matches the callees of matched functions. If the caller's instructions are identical,
+    /// references are paired by offset, otherwise calls are paired in order when the
+    /// number of calls is the same.
+    fn match_callees(&mut self) {
+        let mut pairs = vec![];
+        for (source, target) in &self.matched {
+            let source = &self.source[source];
+            let target = &self.target[target];
+            if source.hash == target.hash {
+                for (offset, source_ref) in &source.references {
+                    if let Some(&target_ref) = target.references.get(offset) {
+                        pairs.push((*source_ref, target_ref));
+                    }
+                }
+            } else if source.calls.len() == target.calls.len() {
+                pairs.extend(source.calls.iter().copied().zip(target.calls.iter().copied()));
+            }
+        }
+        for (source, target) in pairs {
+            self.try_match(source, target);
+        }
+    }
+
+    /// Matches unmatched functions located between two matched functions, if both sides
+    /// have the same number of functions in between with the same sizes.
+    fn match_neighbours(&mut self) {
+        let mut pairs = vec![];
+        for ((&source_start, &target_start), (&source_end, &target_end)) in
+            self.matched.iter().zip(self.matched.iter().skip(1))
+        {
+            if source_start.section != source_end.section
+                || target_start.section != target_end.section
+                || target_start >= target_end
+            {
+                continue;
+            }
+            let source_between = self
+                .source
+                .range(source_start + 1..source_end)
+                .filter(|(addr, _)| !self.matched.contains_key(addr))
+                .collect::<Vec<_>>();
+            let target_between = self
+                .target
+                .range(target_start + 1..target_end)
+                .filter(|(addr, _)| !self.matched_target.contains(addr))
+                .collect::<Vec<_>>();
+            if source_between.is_empty()
+                || source_between.len() != target_between.len()
+                || source_between.iter().zip(&target_between).any(|(a, b)| a.1.size != b.1.size)
+            {
+                continue;
+            }
+            pairs.extend(source_between.iter().zip(&target_between).map(|(a, b)| (*a.0, *b.0)));
+        }
+        for (source, target) in pairs {
+            self.try_match(source, target);
+        }
+    }
+}
+
+/// A symbol address ported from the source module to the target module.
+#[derive(Debug, Copy, Clone)]
+struct PortedAddress {
+    target: SectionAddress,
+    /// Size of the symbol in the source module.
+    source_size: u32,
+    /// Size of the symbol in the target module.
+    target_size: u32,
+    /// Whether addresses inside the symbol can be ported by offset.
+    identical: bool,
+}
+
+type PortedMap = BTreeMap<SectionAddress, PortedAddress>;
+
+/// Ports an address at the start of a ported symbol, or inside a symbol that was ported from an
+/// identical function or data symbol.
+fn port_address(ported: &PortedMap, addr: SectionAddress) -> Option<SectionAddress> {
+    let (&start, info) = ported.range(SectionAddress::new(addr.section, 0)..=addr).next_back()?;
+    if start == addr {
+        Some(info.target)
+    } else if info.identical && addr.address < start.address + info.source_size {
+        Some(info.target + (addr.address - start.address))
+    } else {
+        None
+    }
+}
+
+/// Like [`port_address`], but also ports the end address of a ported symbol.
+fn port_end(ported: &PortedMap, addr: SectionAddress) -> Option<SectionAddress> {
+    port_address(ported, addr).or_else(|| {
+        let (&start, info) =
+            ported.range(SectionAddress::new(addr.section, 0)..addr).next_back()?;
+        (start.address + info.source_size == addr.address).then(|| info.target + info.target_size)
+    })
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PortReport {
+    pub name: String,
+    pub functions: usize,
+    pub matched_functions: usize,
+    pub ported_symbols: usize,
+    /// Functions with multiple equally likely candidates in the target module.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ambiguous: Vec<PortReportEntry>,
+    /// Functions without a match in the target module.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unmatched: Vec<PortReportEntry>,
+    /// Data symbols referenced from matched functions at inconsistent addresses.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<PortReportEntry>,
+    /// Splits that couldn't be ported, as `unit section start-end`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unported_splits: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PortReportEntry {
+    pub name: String,
+    pub section: String,
+    pub address: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub candidates: Vec<String>,
+}
+
+fn report_entry(obj: &ObjInfo, symbol: &ObjSymbol, candidates: Vec<String>) -> PortReportEntry {
+    PortReportEntry {
+        name: symbol.name.clone(),
+        section: symbol
+            .section
+            .map(|idx| obj.sections[idx].name.clone())
+            .unwrap_or_else(|| "ABS".to_string()),
+        address: format!("{:#010X}", symbol.address),
+        candidates,
+    }
+}
+
+/// Maps each source section to the target section at the same index.
+fn map_sections(
+    source: &ObjInfo,
+    target: &ObjInfo,
+) -> Result<BTreeMap<SectionIndex, SectionIndex>> {
+    ensure!(
+        source.sections.len() == target.sections.len(),
+        "Section count mismatch: {} has {}, {} has {}",
+        source.name,
+        source.sections.len(),
+        target.name,
+        target.sections.len()
+    );
+    let mut out = BTreeMap::new();
+    for ((source_index, source_section), (target_index, target_section)) in
+        source.sections.iter().zip(target.sections.iter())
+    {
+        ensure!(
+            source_section.kind == target_section.kind,
+            "Section {} kind mismatch: {:?} != {:?}",
+            source_section.name,
+            source_section.kind,
+            target_section.kind
+        );
+        out.insert(source_index, target_index);
+    }
+    Ok(out)
+}
+
+/// Ports symbols and splits from a symbolized source module to an analyzed target module
+/// (e.g. another regional version of the same game).
+///
+/// Functions are matched by their masked instruction hashes, then by their call graph and
+/// by their neighbours in address order. Data symbols are ported by comparing the references
+/// made from identical matched functions.
+pub fn port_symbols(source: &ObjInfo, target: &mut ObjInfo) -> Result<PortReport> {
+    let sections = map_sections(source, target)?;
+    let source_functions = collect_functions(source)?;
+    let target_functions = collect_functions(target)?;
+
+    let mut matcher = Matcher {
+        source: &source_functions,
+        target: &target_functions,
+        sections: &sections,
+        matched: Default::default(),
+        matched_target: Default::default(),
+    };
+    loop {
+        let count = matcher.matched.len();
+        matcher.match_unique_hashes();
+        matcher.match_callees();
+        matcher.match_neighbours();
+        if matcher.matched.len() == count {
+            break;
+        }
+    }
+
+    let mut report = PortReport {
+        name: source.name.clone(),
+        functions: source_functions.len(),
+        matched_functions: matcher.matched.len(),
+        ..Default::default()
+    };
+    let unmatched_by_hash = matcher.unmatched_by_hash();
+    for (addr, function) in &source_functions {
+        if matcher.matched.contains_key(addr) {
+            continue;
+        }
+        let symbol = &source.symbols[function.symbol];
+        let candidates = &unmatched_by_hash[&function.hash].1;
+        if candidates.len() > 1 {
+            let candidates =
+                candidates.iter().map(|addr| format!("{:#010X}", addr.address)).collect();
+            report.ambiguous.push(report_entry(source, symbol, candidates));
+        } else {
+            report.unmatched.push(report_entry(source, symbol, vec![]));
+        }
+    }
+
+    let mut ported = PortedMap::new();
+    for (source_addr, target_addr) in &matcher.matched {
+        let source_function = &source_functions[source_addr];
+        let target_function = &target_functions[target_addr];
+        ported.insert(*source_addr, PortedAddress {
+            target: *target_addr,
+            source_size: source_function.size,
+            target_size: target_function.size,
+            identical: source_function.hash == target_function.hash,
+        });
+    }
+
+    // Port data symbols referenced from identical functions
+    let mut source_symbols = BTreeMap::<SectionAddress, SymbolIndex>::new();
+    for (symbol_idx, symbol) in source.symbols.iter() {
+        let Some(section_index) = symbol.section else { continue };
+        if symbol.kind == ObjSymbolKind::Section {
+            continue;
+        }
+        source_symbols
+            .entry(SectionAddress::new(section_index, symbol.address as u32))
+            .and_modify(|idx| {
+                if source.symbols[*idx].size < symbol.size {
+                    *idx = symbol_idx;
+                }
+            })
+            .or_insert(symbol_idx);
+    }
+    let mut votes = BTreeMap::<SectionAddress, BTreeMap<SectionAddress, u32>>::new();
+    for (source_addr, target_addr) in &matcher.matched {
+        let source_function = &source_functions[source_addr];
+        let target_function = &target_functions[target_addr];
+        if source_function.hash != target_function.hash {
+            continue;
+        }
+        for (offset, &source_ref) in &source_function.references {
+            let Some(&target_ref) = target_function.references.get(offset) else { continue };
+            if sections.get(&source_ref.section) != Some(&target_ref.section) {
+                continue;
+            }
+            let Some((&symbol_addr, &symbol_idx)) = source_symbols
+                .range(SectionAddress::new(source_ref.section, 0)..=source_ref)
+                .next_back()
+            else {
+                continue;
+            };
+            let symbol = &source.symbols[symbol_idx];
+            if symbol.kind == ObjSymbolKind::Function
+                || (symbol_addr != source_ref
+                    && source_ref.address >= symbol_addr.address + symbol.size as u32)
+                || target_ref.address < source_ref.address - symbol_addr.address
+            {
+                continue;
+            }
+            let target_symbol_addr = target_ref - (source_ref.address - symbol_addr.address);
+            *votes.entry(symbol_addr).or_default().entry(target_symbol_addr).or_default() += 1;
+        }
+    }
+    for (source_addr, candidates) in votes {
+        let symbol = &source.symbols[source_symbols[&source_addr]];
+        if candidates.len() > 1 {
+            let candidates =
+                candidates.keys().map(|addr| format!("{:#010X}", addr.address)).collect();
+            report.conflicts.push(report_entry(source, symbol, candidates));
+            continue;
+        }
+        let target_addr = *candidates.keys().next().unwrap();
+        let target_section = &target.sections[target_addr.section];
+        let end = target_addr.address + symbol.size.max(1) as u32;
+        if !target_section.contains_range(target_addr.address..end) {
+            continue;
+        }
+        ported.insert(source_addr, PortedAddress {
+            target: target_addr,
+            source_size: symbol.size as u32,
+            target_size: symbol.size as u32,
+            identical: true,
+        });
+    }
+
+    // Apply symbols
+    for (_, symbol) in source.symbols.iter() {
+        let Some(section_index) = symbol.section else { continue };
+        if symbol.kind == ObjSymbolKind::Section || is_auto_symbol(symbol) {
+            continue;
+        }
+        let Some(target_addr) =
+            port_address(&ported, SectionAddress::new(section_index, symbol.address as u32))
+        else {
+            continue;
+        };
+        let size = match (symbol.kind, target_functions.get(&target_addr)) {
+            (ObjSymbolKind::Function, Some(function)) => function.size as u64,
+            _ => symbol.size,
+        };
+        target.add_symbol(
+            ObjSymbol {
+                address: target_addr.address as u64,
+                section: Some(target_addr.section),
+                size,
+                ..symbol.clone()
+            },
+            true,
+        )?;
+        report.ported_symbols += 1;
+    }
+
+    // Apply section names and splits
+    for (source_index, source_section) in source.sections.iter() {
+        let target_index = sections[&source_index];
+        if source_section.section_known {
+            let target_section = &mut target.sections[target_index];
+            target_section.rename(source_section.name.clone())?;
+            target_section.align = source_section.align;
+        }
+        let source_end = (source_section.address + source_section.size) as u32;
+        let target_section = &target.sections[target_index];
+        let (target_start, target_end) =
+            (target_section.address as u32, (target_section.address + target_section.size) as u32);
+        for (start, split) in source_section.splits.iter() {
+            let start_addr = if start == source_section.address as u32 {
+                Some(target_start)
+            } else {
+                port_address(&ported, SectionAddress::new(source_index, start)).map(|a| a.address)
+            };
+            let end_addr = if split.end == 0 || split.end == source_end {
+                Some(target_end)
+            } else {
+                port_end(&ported, SectionAddress::new(source_index, split.end)).map(|a| a.address)
+            };
+            let describe = || {
+                format!(
+                    "{} {} {:#010X}-{:#010X}",
+                    split.unit, source_section.name, start, split.end
+                )
+            };
+            let (Some(start_addr), Some(end_addr)) = (start_addr, end_addr) else {
+                report.unported_splits.push(describe());
+                continue;
+            };
+            if let Err(e) = target.add_split(target_index, start_addr, ObjSplit {
+                end: if split.end == 0 { 0 } else { end_addr },
+                ..split.clone()
+            }) {
+                report.unported_splits.push(format!("{}: {:#}", describe(), e));
+            }
+        }
+    }
+    target.link_order.clone_from(&source.link_order);
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{ObjArchitecture, ObjKind, ObjSection, ObjSectionKind};
+
+    const LIS_R3: u32 = 0x3C608000;
+    const ADDI_R3: u32 = 0x38630000;
+    const LIS_R4: u32 = 0x3C808000;
+    const ADDI_R4: u32 = 0x38840000;
+    const LI_R3: u32 = 0x38600000;
+    const BL_8: u32 = 0x48000009;
+    const NOP: u32 = 0x60000000;
+    const BLR: u32 = 0x4E800020;
+
+    fn section(name: &str, kind: ObjSectionKind, address: u64, words: &[u32]) -> ObjSection {
+        let data = words.iter().flat_map(|w| w.to_be_bytes()).collect::<Vec<_>>();
+        ObjSection {
+            name: name.to_string(),
+            kind,
+            address,
+            size: data.len() as u64,
+            data,
+            align: 4,
+            elf_index: 0,
+            relocations: Default::default(),
+            virtual_address: Some(address),
+            file_offset: 0,
+            section_known: true,
+            splits: Default::default(),
+        }
+    }
+
+    fn symbol(name: &str, kind: ObjSymbolKind, section: u32, address: u64, size: u64) -> ObjSymbol {
+        ObjSymbol {
+            name: name.to_string(),
+            address,
+            section: Some(section),
+            size,
+            size_known: true,
+            kind,
+            ..Default::default()
+        }
+    }
+
+    fn split(unit: &str, end: u32) -> ObjSplit {
+        ObjSplit {
+            unit: unit.to_string(),
+            end,
+            align: None,
+            common: false,
+            autogenerated: false,
+            skip: false,
+            rename: None,
+        }
+    }
+
+    /// Function names, offsets and sizes in .text. `func_b` and `func_d` have the same body, as
+    /// do `func_f` and `func_g`.
+    const FUNCTIONS: [(&str, u64, u64); 7] = [
+        ("func_a", 0x00, 0x10),
+        ("func_b", 0x10, 0x8),
+        ("func_c", 0x18, 0xC),
+        ("func_e", 0x24, 0x14),
+        ("func_d", 0x38, 0x8),
+        ("func_f", 0x40, 0x8),
+        ("func_g", 0x48, 0x8),
+    ];
+
+    /// Builds the functions at 0x80003000 + `shift`. `data` is the address of `data_x` and
+    /// `data_y`, referenced by `func_a` and `func_e`, and `c` is the constant in `func_c`.
+    #[rustfmt::skip]
+    fn text(shift: usize, data_x: [u32; 2], data_y: u32, c: u32) -> Vec<u32> {
+        let mut words = vec![NOP; shift / 4];
+        words.extend([
+            // func_a: calls func_b
+            LIS_R3, ADDI_R3 | data_x[0], BL_8, BLR,
+            // func_b
+            LI_R3 | 1, BLR,
+            // func_c
+            LI_R3 | c, NOP, BLR,
+            // func_e
+            LIS_R3, ADDI_R3 | data_x[1], LIS_R4, ADDI_R4 | data_y, BLR,
+            // func_d
+            LI_R3 | 1, BLR,
+            // func_f
+            LI_R3 | 6, BLR,
+            // func_g
+            LI_R3 | 6, BLR,
+        ]);
+        words
+    }
+
+    /// The symbolized source module, with splits.
+    fn source_obj() -> ObjInfo {
+        let mut symbols = FUNCTIONS
+            .iter()
+            .map(|&(name, offset, size)| {
+                symbol(name, ObjSymbolKind::Function, 0, 0x80003000 + offset, size)
+            })
+            .collect::<Vec<_>>();
+        symbols.push(symbol("data_x", ObjSymbolKind::Object, 1, 0x80004000, 8));
+        symbols.push(symbol("data_y", ObjSymbolKind::Object, 1, 0x80004008, 8));
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "main.dol".to_string(),
+            symbols,
+            vec![
+                section(
+                    ".text",
+                    ObjSectionKind::Code,
+                    0x80003000,
+                    &text(0, [0x4000; 2], 0x400C, 3),
+                ),
+                section(".data", ObjSectionKind::Data, 0x80004000, &[0; 4]),
+            ],
+        );
+        obj.add_split(0, 0x80003000, split("a.c", 0x80003018)).unwrap();
+        obj.add_split(0, 0x80003018, split("b.c", 0x80003040)).unwrap();
+        obj.add_split(0, 0x80003040, split("c.c", 0x80003050)).unwrap();
+        obj.add_split(1, 0x80004000, split("a.c", 0x80004010)).unwrap();
+        obj
+    }
+
+    /// The analyzed target module: .text is shifted by 0x10 and .data by 8. `func_c` loads a
+    /// different constant, and `func_e` references `data_x` at its old address.
+    fn target_obj() -> ObjInfo {
+        let symbols = FUNCTIONS
+            .iter()
+            .map(|&(_, offset, size)| {
+                let address = 0x80003010 + offset;
+                symbol(&format!("fn_{:08X}", address), ObjSymbolKind::Function, 0, address, size)
+            })
+            .collect();
+        ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "main.dol".to_string(),
+            symbols,
+            vec![
+                section(
+                    ".text",
+                    ObjSectionKind::Code,
+                    0x80003000,
+                    &text(0x10, [0x4008, 0x4000], 0x4014, 4),
+                ),
+                section(".data", ObjSectionKind::Data, 0x80004000, &[0; 6]),
+            ],
+        )
+    }
+
+    fn symbol_address(obj: &ObjInfo, name: &str) -> Option<u64> {
+        obj.symbols.by_name(name).unwrap().map(|(_, symbol)| symbol.address)
+    }
+
+    #[test]
+    fn test_port_symbols() {
+        let source = source_obj();
+        let mut target = target_obj();
+        let report = port_symbols(&source, &mut target).unwrap();
+
+        // func_a and func_e have unique hashes, func_b is called from func_a, func_c is
+        // between func_b and func_e, and func_d is unique once func_b is matched
+        assert_eq!(report.functions, 7);
+        assert_eq!(report.matched_functions, 5);
+        for name in ["func_a", "func_b", "func_c", "func_e", "func_d"] {
+            let offset = FUNCTIONS.iter().find(|(n, _, _)| *n == name).unwrap().1;
+            assert_eq!(symbol_address(&target, name), Some(0x80003010 + offset), "{}", name);
+        }
+
+        // func_f and func_g can't be told apart
+        assert!(report.unmatched.is_empty());
+        assert_eq!(report.ambiguous.len(), 2);
+        assert_eq!(report.ambiguous[0].name, "func_f");
+        assert_eq!(report.ambiguous[0].candidates, ["0x80003050", "0x80003058"]);
+        assert_eq!(symbol_address(&target, "func_f"), None);
+
+        // data_y is referenced consistently, but data_x isn't
+        assert_eq!(symbol_address(&target, "data_y"), Some(0x80004010));
+        assert_eq!(symbol_address(&target, "data_x"), None);
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.conflicts[0].name, "data_x");
+        assert_eq!(report.conflicts[0].candidates, ["0x80004000", "0x80004008"]);
+        assert_eq!(report.ported_symbols, 6);
+    }
+
+    #[test]
+    fn test_port_splits() {
+        let source = source_obj();
+        let mut target = target_obj();
+        let report = port_symbols(&source, &mut target).unwrap();
+
+        // b.c ends at the end of func_d, since func_f wasn't matched
+        let splits = |index: SectionIndex| {
+            target.sections[index]
+                .splits
+                .iter()
+                .map(|(start, split)| (split.unit.clone(), start, split.end))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(splits(0), [
+            ("a.c".to_string(), 0x80003000, 0x80003028),
+            ("b.c".to_string(), 0x80003028, 0x80003050),
+        ]);
+        assert_eq!(splits(1), [("a.c".to_string(), 0x80004000, 0x80004018)]);
+        assert_eq!(report.unported_splits, ["c.c .text 0x80003040-0x80003050"]);
+    }
+
+    fn function(size: u32, hash: u64, calls: &[SectionAddress]) -> MatchFunction {
+        MatchFunction {
+            symbol: 0,
+            size,
+            hash,
+            references: calls.iter().enumerate().map(|(i, &c)| (i as u32 * 4, c)).collect(),
+            calls: calls.to_vec(),
+        }
+    }
+
+    fn addr(address: u32) -> SectionAddress { SectionAddress::new(0, address) }
+
+    fn matcher<'a>(
+        source: &'a FunctionMap,
+        target: &'a FunctionMap,
+        sections: &'a BTreeMap<SectionIndex, SectionIndex>,
+    ) -> Matcher<'a> {
+        Matcher {
+            source,
+            target,
+            sections,
+            matched: Default::default(),
+            matched_target: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_match_unique_hashes() {
+        let sections = BTreeMap::from([(0, 0)]);
+        let source = FunctionMap::from([
+            (addr(0x0), function(4, 1, &[])),
+            (addr(0x10), function(4, 2, &[])),
+            (addr(0x20), function(4, 2, &[])),
+            (addr(0x30), function(4, 3, &[])),
+        ]);
+        let target = FunctionMap::from([
+            (addr(0x100), function(4, 1, &[])),
+            (addr(0x110), function(4, 2, &[])),
+            (addr(0x120), function(4, 2, &[])),
+        ]);
+        let mut matcher = matcher(&source, &target, &sections);
+        matcher.match_unique_hashes();
+        assert_eq!(matcher.matched, BTreeMap::from([(addr(0x0), addr(0x100))]));
+        assert_eq!(
+            matcher.unmatched_by_hash()[&2],
+            (vec![addr(0x10), addr(0x20)], vec![addr(0x110), addr(0x120)])
+        );
+    }
+
+    #[test]
+    fn test_match_callees() {
+        let sections = BTreeMap::from([(0, 0)]);
+        // 0x0 is identical and pairs its references by offset. 0x40 differs and pairs its calls
+        // in order. 0x80 differs and has a different number of calls, so its callee is skipped.
+        let source = FunctionMap::from([
+            (addr(0x0), function(8, 1, &[addr(0x10), addr(0x20)])),
+            (addr(0x10), function(4, 9, &[])),
+            (addr(0x20), function(4, 9, &[])),
+            (addr(0x40), function(4, 2, &[addr(0x50)])),
+            (addr(0x50), function(4, 9, &[])),
+            (addr(0x80), function(4, 3, &[addr(0x90)])),
+            (addr(0x90), function(4, 9, &[])),
+        ]);
+        let target = FunctionMap::from([
+            (addr(0x100), function(8, 1, &[addr(0x120), addr(0x110)])),
+            (addr(0x110), function(4, 9, &[])),
+            (addr(0x120), function(4, 9, &[])),
+            (addr(0x140), function(4, 4, &[addr(0x150)])),
+            (addr(0x150), function(4, 9, &[])),
+            (addr(0x180), function(4, 5, &[addr(0x190), addr(0x190)])),
+            (addr(0x190), function(4, 9, &[])),
+        ]);
+        let mut matcher = matcher(&source, &target, &sections);
+        for (source, target) in [(0x0, 0x100), (0x40, 0x140), (0x80, 0x180)] {
+            assert!(matcher.try_match(addr(source), addr(target)));
+        }
+        // Already matched
+        assert!(!matcher.try_match(addr(0x0), addr(0x110)));
+        matcher.match_callees();
+        assert_eq!(matcher.matched.get(&addr(0x10)), Some(&addr(0x120)));
+        assert_eq!(matcher.matched.get(&addr(0x20)), Some(&addr(0x110)));
+        assert_eq!(matcher.matched.get(&addr(0x50)), Some(&addr(0x150)));
+        assert_eq!(matcher.matched.get(&addr(0x90)), None);
+    }
+
+    #[test]
+    fn test_match_neighbours() {
+        let sections = BTreeMap::from([(0, 0)]);
+        // Two functions between 0x0 and 0x30 with the same sizes on both sides, and one
+        // between 0x30 and 0x60 with a different size.
+        let source = FunctionMap::from([
+            (addr(0x0), function(0x10, 1, &[])),
+            (addr(0x10), function(0x8, 2, &[])),
+            (addr(0x18), function(0x18, 3, &[])),
+            (addr(0x30), function(0x10, 4, &[])),
+            (addr(0x40), function(0x20, 5, &[])),
+            (addr(0x60), function(0x10, 6, &[])),
+        ]);
+        let target = FunctionMap::from([
+            (addr(0x100), function(0x10, 1, &[])),
+            (addr(0x110), function(0x8, 7, &[])),
+            (addr(0x118), function(0x18, 8, &[])),
+            (addr(0x130), function(0x10, 4, &[])),
+            (addr(0x140), function(0x24, 9, &[])),
+            (addr(0x164), function(0x10, 6, &[])),
+        ]);
+        let mut matcher = matcher(&source, &target, &sections);
+        matcher.match_unique_hashes();
+        assert_eq!(matcher.matched.len(), 3);
+        matcher.match_neighbours();
+        assert_eq!(matcher.matched.get(&addr(0x10)), Some(&addr(0x110)));
+        assert_eq!(matcher.matched.get(&addr(0x18)), Some(&addr(0x118)));
+        assert_eq!(matcher.matched.get(&addr(0x40)), None);
+    }
+
+    #[test]
+    fn test_port_address() {
+        let ported = PortedMap::from([
+            (addr(0x100), PortedAddress {
+                target: addr(0x200),
+                source_size: 0x10,
+                target_size: 0x10,
+                identical: true,
+            }),
+            (addr(0x110), PortedAddress {
+                target: addr(0x210),
+                source_size: 0x10,
+                target_size: 0x14,
+                identical: false,
+            }),
+        ]);
+        assert_eq!(port_address(&ported, addr(0x100)), Some(addr(0x200)));
+        // Inside an identical symbol
+        assert_eq!(port_address(&ported, addr(0x108)), Some(addr(0x208)));
+        // Only the start of a non-identical symbol
+        assert_eq!(port_address(&ported, addr(0x110)), Some(addr(0x210)));
+        assert_eq!(port_address(&ported, addr(0x118)), None);
+        // After the last symbol, or before the first
+        assert_eq!(port_address(&ported, addr(0x120)), None);
+        assert_eq!(port_address(&ported, addr(0x80)), None);
+        // The end of a symbol ports to the end of the target symbol
+        assert_eq!(port_end(&ported, addr(0x110)), Some(addr(0x210)));
+        assert_eq!(port_end(&ported, addr(0x120)), Some(addr(0x224)));
+        assert_eq!(port_end(&ported, addr(0x124)), None);
+    }
+}
@@ -0,0 +1,514 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Write,
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    analysis::{
+        disassemble,
+        vm::{is_load_op, is_loadf_op, is_store_op, is_storef_op},
+    },
+    obj::{ObjInfo, ObjRelocKind, ObjSymbolKind, SectionIndex, SymbolIndex},
+};
+
+/// A symbol in one of the modules passed to [`collect_references`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SymbolRef {
+    /// Index into the module list.
+    pub module: usize,
+    pub symbol: SymbolIndex,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReferenceKind {
+    /// Branch with link (`bl`).
+    Call,
+    /// Branch without link, e.g. a tail call.
+    Jump,
+    /// Load from the target.
+    Read,
+    /// Store to the target.
+    Write,
+    /// Address calculation (e.g. `addi`) without a direct load or store.
+    Address,
+    /// Pointer in data, e.g. a vtable or jump table entry.
+    Pointer,
+}
+
+impl ReferenceKind {
+    pub fn is_call(self) -> bool { matches!(self, ReferenceKind::Call | ReferenceKind::Jump) }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Reference {
+    pub source: SymbolRef,
+    /// Address of the relocation in the source module.
+    pub address: u32,
+    pub target: SymbolRef,
+    pub kind: ReferenceKind,
+}
+
+/// Finds the sized symbol containing the address. Labels (without a size) are skipped.
+pub fn containing_symbol(
+    obj: &ObjInfo,
+    section_index: SectionIndex,
+    address: u32,
+) -> Option<SymbolIndex> {
+    let (symbol_idx, symbol) = obj
+        .symbols
+        .for_section_range(section_index, ..=address)
+        .rev()
+        .find(|(_, s)| s.kind != ObjSymbolKind::Section && s.size > 0)?;
+    (address < (symbol.address + symbol.size) as u32).then_some(symbol_idx)
+}
+
+/// Collects all references between symbols from the relocations of each module.
+///
+/// References to symbols without a section (externs created for other modules) are resolved
+/// by name against the global symbols of all modules.
+pub fn collect_references(modules: &[&ObjInfo]) -> Vec<Reference> {
+    let mut defined = HashMap::<&str, SymbolRef>::new();
+    for (module, obj) in modules.iter().enumerate() {
+        for (symbol, s) in obj.symbols.iter() {
+            if s.section.is_some() && s.kind != ObjSymbolKind::Section && s.flags.is_global() {
+                defined.entry(s.name.as_str()).or_insert(SymbolRef { module, symbol });
+            }
+        }
+    }
+
+    let mut out = vec![];
+    for (module, obj) in modules.iter().enumerate() {
+        for (section_index, section) in obj.sections.iter() {
+            for (address, reloc) in section.relocations.iter() {
+                let kind = match reloc.kind {
+                    // Always paired with a low relocation
+                    ObjRelocKind::PpcAddr16Hi | ObjRelocKind::PpcAddr16Ha => continue,
+                    ObjRelocKind::Absolute => ReferenceKind::Pointer,
+                    ObjRelocKind::PpcRel24 => match disassemble(section, address) {
+                        Some(ins) if ins.field_lk() => ReferenceKind::Call,
+                        _ => ReferenceKind::Jump,
+                    },
+                    ObjRelocKind::PpcRel14 => ReferenceKind::Jump,
+                    ObjRelocKind::PpcAddr16Lo | ObjRelocKind::PpcEmbSda21 => {
+                        match disassemble(section, address).map(|ins| ins.op) {
+                            Some(op) if is_load_op(op) || is_loadf_op(op) => ReferenceKind::Read,
+                            Some(op) if is_store_op(op) || is_storef_op(op) => ReferenceKind::Write,
+                            _ => ReferenceKind::Address,
+                        }
+                    }
+                };
+                let Some(source) = containing_symbol(obj, section_index, address) else {
+                    continue;
+                };
+                let target_symbol = &obj.symbols[reloc.target_symbol];
+                let target = if let Some(target_section) = target_symbol.section {
+                    let target_address = (target_symbol.address as i64 + reloc.addend) as u32;
+                    let symbol = containing_symbol(obj, target_section, target_address)
+                        .unwrap_or(reloc.target_symbol);
+                    SymbolRef { module, symbol }
+                } else if let Some(&target) = defined.get(target_symbol.name.as_str()) {
+                    target
+                } else {
+                    continue;
+                };
+                out.push(Reference {
+                    source: SymbolRef { module, symbol: source },
+                    address,
+                    target,
+                    kind,
+                });
+            }
+        }
+    }
+    out
+}
+
+/// Returns the unit (split) containing the symbol, if any.
+pub fn symbol_unit(obj: &ObjInfo, symbol_idx: SymbolIndex) -> Option<&str> {
+    let symbol = &obj.symbols[symbol_idx];
+    let section = obj.sections.get(symbol.section?)?;
+    section.splits.for_address(symbol.address as u32).map(|(_, split)| split.unit.as_str())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphNode {
+    pub id: usize,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub demangled_name: Option<String>,
+    pub module: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub section: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphEdge {
+    pub source: usize,
+    pub target: usize,
+    pub kind: ReferenceKind,
+    /// Number of references merged into this edge.
+    pub count: usize,
+}
+
+/// A reference graph between symbols, or between units.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReferenceGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+impl ReferenceGraph {
+    /// Builds a graph from the collected references. Only calls are included, unless `data`
+    /// is set. With `units`, symbols are merged into the unit (split) that contains them,
+    /// and references within a unit are omitted.
+    pub fn new(modules: &[&ObjInfo], references: &[Reference], units: bool, data: bool) -> Self {
+        let mut graph = ReferenceGraph::default();
+        let mut node_ids = HashMap::<(usize, String), usize>::new();
+        let mut edges = BTreeMap::<(usize, usize, ReferenceKind), usize>::new();
+        let mut node_for = |graph: &mut ReferenceGraph, symbol_ref: SymbolRef| -> Option<usize> {
+            let obj = modules[symbol_ref.module];
+            let symbol = &obj.symbols[symbol_ref.symbol];
+            let unit = symbol_unit(obj, symbol_ref.symbol);
+            let key = if units {
+                (symbol_ref.module, unit?.to_string())
+            } else {
+                (symbol_ref.module, symbol_ref.symbol.to_string())
+            };
+            let id = *node_ids.entry(key).or_insert_with(|| {
+                let id = graph.nodes.len();
+                graph.nodes.push(if units {
+                    GraphNode {
+                        id,
+                        name: unit.unwrap_or_default().to_string(),
+                        demangled_name: None,
+                        module: obj.name.clone(),
+                        unit: None,
+                        section: None,
+                        address: None,
+                    }
+                } else {
+                    GraphNode {
+                        id,
+                        name: symbol.name.clone(),
+                        demangled_name: symbol.demangled_name.clone(),
+                        module: obj.name.clone(),
+                        unit: unit.map(|u| u.to_string()),
+                        section: symbol.section.map(|idx| obj.sections[idx].name.clone()),
+                        address: Some(symbol.address as u32),
+                    }
+                });
+                id
+            });
+            Some(id)
+        };
+        for reference in references {
+            if !data && !reference.kind.is_call() {
+                continue;
+            }
+            let (Some(source), Some(target)) =
+                (node_for(&mut graph, reference.source), node_for(&mut graph, reference.target))
+            else {
+                continue;
+            };
+            if units && source == target {
+                continue;
+            }
+            *edges.entry((source, target, reference.kind)).or_default() += 1;
+        }
+        graph.edges = edges
+            .into_iter()
+            .map(|((source, target, kind), count)| GraphEdge { source, target, kind, count })
+            .collect();
+        graph
+    }
+
+    /// Writes the graph in Graphviz DOT format, grouping nodes by module and unit.
+    pub fn write_dot<W>(&self, w: &mut W) -> Result<()>
+    where W: Write + ?Sized {
+        writeln!(w, "digraph references {{")?;
+        writeln!(w, "    node [shape=box];")?;
+        let mut clusters = BTreeMap::<(&str, Option<&str>), Vec<&GraphNode>>::new();
+        for node in &self.nodes {
+            clusters.entry((node.module.as_str(), node.unit.as_deref())).or_default().push(node);
+        }
+        for (cluster_idx, ((module, unit), nodes)) in clusters.into_iter().enumerate() {
+            let indent = if let Some(unit) = unit {
+                writeln!(w, "    subgraph cluster_{} {{", cluster_idx)?;
+                writeln!(w, "        label={};", dot_string(&format!("{}: {}", module, unit)))?;
+                "        "
+            } else {
+                "    "
+            };
+            for node in nodes {
+                let label = node.demangled_name.as_deref().unwrap_or(&node.name);
+                writeln!(w, "{}n{} [label={}];", indent, node.id, dot_string(label))?;
+            }
+            if unit.is_some() {
+                writeln!(w, "    }}")?;
+            }
+        }
+        for edge in &self.edges {
+            write!(w, "    n{} -> n{}", edge.source, edge.target)?;
+            let mut attrs = vec![];
+            if !edge.kind.is_call() {
+                attrs.push("style=dashed".to_string());
+            }
+            if edge.count > 1 {
+                attrs.push(format!("label=\"{}\"", edge.count));
+            }
+            if !attrs.is_empty() {
+                write!(w, " [{}]", attrs.join(", "))?;
+            }
+            writeln!(w, ";")?;
+        }
+        writeln!(w, "}}")?;
+        Ok(())
+    }
+}
+
+fn dot_string(s: &str) -> String { format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{
+        ObjArchitecture, ObjKind, ObjReloc, ObjSection, ObjSectionKind, ObjSplit, ObjSymbol,
+        ObjSymbolFlagSet, ObjSymbolFlags,
+    };
+
+    fn section(name: &str, kind: ObjSectionKind, address: u64, words: &[u32]) -> ObjSection {
+        let data = words.iter().flat_map(|w| w.to_be_bytes()).collect::<Vec<_>>();
+        ObjSection {
+            name: name.to_string(),
+            kind,
+            address,
+            size: data.len() as u64,
+            data,
+            align: 4,
+            elf_index: 0,
+            relocations: Default::default(),
+            virtual_address: Some(address),
+            file_offset: 0,
+            section_known: true,
+            splits: Default::default(),
+        }
+    }
+
+    fn symbol(
+        name: &str,
+        kind: ObjSymbolKind,
+        section: Option<u32>,
+        address: u64,
+        size: u64,
+    ) -> ObjSymbol {
+        ObjSymbol {
+            name: name.to_string(),
+            address,
+            section,
+            size,
+            size_known: true,
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+            kind,
+            ..Default::default()
+        }
+    }
+
+    fn relocate(
+        section: &mut ObjSection,
+        address: u32,
+        kind: ObjRelocKind,
+        target: SymbolIndex,
+        addend: i64,
+    ) {
+        let reloc = ObjReloc { kind, target_symbol: target, addend, module: None };
+        section.relocations.insert(address, reloc).unwrap();
+    }
+
+    fn split(unit: &str, end: u32) -> ObjSplit {
+        ObjSplit {
+            unit: unit.to_string(),
+            end,
+            align: None,
+            common: false,
+            autogenerated: false,
+            skip: false,
+            rename: None,
+        }
+    }
+
+    /// A DOL with `func_a` and `func_b` in `a.c` and `func_c` in `b.c`, and a REL module
+    /// defining `rel_func`.
+    fn test_modules() -> (ObjInfo, ObjInfo) {
+        const BL: u32 = 0x48000001;
+        const B: u32 = 0x48000000;
+        const LIS_R3: u32 = 0x3C600000;
+        const LWZ_R4_0_R3: u32 = 0x80830000;
+        const STW_R4_0_R3: u32 = 0x90830000;
+        const ADDI_R3: u32 = 0x38630000;
+        const BLR: u32 = 0x4E800020;
+        #[rustfmt::skip]
+        let mut text = section(".text", ObjSectionKind::Code, 0x80003000, &[
+            // func_a
+            BL, B, LIS_R3, LWZ_R4_0_R3, STW_R4_0_R3, ADDI_R3, BL, BLR,
+            // func_b
+            BL, BL, BLR,
+            // func_c
+            BLR,
+        ]);
+        relocate(&mut text, 0x80003000, ObjRelocKind::PpcRel24, 1, 0);
+        relocate(&mut text, 0x80003004, ObjRelocKind::PpcRel24, 1, 0);
+        // Through the section symbol, resolved to data_x
+        relocate(&mut text, 0x80003008, ObjRelocKind::PpcAddr16Ha, 5, 4);
+        for address in [0x8000300C, 0x80003010, 0x80003014] {
+            relocate(&mut text, address, ObjRelocKind::PpcAddr16Lo, 5, 4);
+        }
+        // Defined in the REL
+        relocate(&mut text, 0x80003018, ObjRelocKind::PpcRel24, 6, 0);
+        relocate(&mut text, 0x80003020, ObjRelocKind::PpcRel24, 2, 0);
+        relocate(&mut text, 0x80003024, ObjRelocKind::PpcRel24, 2, 0);
+        let mut data = section(".data", ObjSectionKind::Data, 0x80004000, &[0, 0, 0x8000302C]);
+        relocate(&mut data, 0x80004008, ObjRelocKind::Absolute, 2, 0);
+        let mut dol = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "main.dol".to_string(),
+            vec![
+                symbol("func_a", ObjSymbolKind::Function, Some(0), 0x80003000, 0x20),
+                symbol("func_b", ObjSymbolKind::Function, Some(0), 0x80003020, 0xC),
+                symbol("func_c", ObjSymbolKind::Function, Some(0), 0x8000302C, 0x4),
+                symbol("data_x", ObjSymbolKind::Object, Some(1), 0x80004000, 0x8),
+                symbol("data_y", ObjSymbolKind::Object, Some(1), 0x80004008, 0x4),
+                symbol(".data", ObjSymbolKind::Section, Some(1), 0x80004000, 0),
+                symbol("rel_func", ObjSymbolKind::Unknown, None, 0, 0),
+            ],
+            vec![text, data],
+        );
+        dol.add_split(0, 0x80003000, split("a.c", 0x8000302C)).unwrap();
+        dol.add_split(0, 0x8000302C, split("b.c", 0x80003030)).unwrap();
+        dol.add_split(1, 0x80004000, split("a.c", 0x8000400C)).unwrap();
+
+        // rel_func calls an undefined function
+        let mut text = section(".text", ObjSectionKind::Code, 0, &[BL, BLR]);
+        relocate(&mut text, 0, ObjRelocKind::PpcRel24, 1, 0);
+        let rel = ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            "module.rel".to_string(),
+            vec![
+                symbol("rel_func", ObjSymbolKind::Function, Some(0), 0, 8),
+                symbol("missing", ObjSymbolKind::Unknown, None, 0, 0),
+            ],
+            vec![text],
+        );
+        (dol, rel)
+    }
+
+    #[test]
+    fn test_collect_references() {
+        let (dol, rel) = test_modules();
+        let references = collect_references(&[&dol, &rel]);
+        let summary = references
+            .iter()
+            .map(|r| (r.address, r.source.symbol, r.target, r.kind))
+            .collect::<Vec<_>>();
+        let dol_symbol = |symbol: SymbolIndex| SymbolRef { module: 0, symbol };
+        assert_eq!(summary, [
+            (0x80003000, 0, dol_symbol(1), ReferenceKind::Call),
+            (0x80003004, 0, dol_symbol(1), ReferenceKind::Jump),
+            (0x8000300C, 0, dol_symbol(3), ReferenceKind::Read),
+            (0x80003010, 0, dol_symbol(3), ReferenceKind::Write),
+            (0x80003014, 0, dol_symbol(3), ReferenceKind::Address),
+            (0x80003018, 0, SymbolRef { module: 1, symbol: 0 }, ReferenceKind::Call),
+            (0x80003020, 1, dol_symbol(2), ReferenceKind::Call),
+            (0x80003024, 1, dol_symbol(2), ReferenceKind::Call),
+            (0x80004008, 4, dol_symbol(2), ReferenceKind::Pointer),
+        ]);
+        assert!(references.iter().all(|r| r.source.module == 0));
+    }
+
+    #[test]
+    fn test_symbol_graph() {
+        let (dol, rel) = test_modules();
+        let modules = [&dol, &rel];
+        let references = collect_references(&modules);
+
+        let graph = ReferenceGraph::new(&modules, &references, false, false);
+        let names = graph.nodes.iter().map(|n| n.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["func_a", "func_b", "rel_func", "func_c"]);
+        assert_eq!(graph.nodes[0].unit.as_deref(), Some("a.c"));
+        assert_eq!(graph.nodes[2].module, "module.rel");
+        assert_eq!(graph.nodes[2].unit, None);
+        let edges =
+            graph.edges.iter().map(|e| (e.source, e.target, e.kind, e.count)).collect::<Vec<_>>();
+        assert_eq!(edges, [
+            (0, 1, ReferenceKind::Call, 1),
+            (0, 1, ReferenceKind::Jump, 1),
+            (0, 2, ReferenceKind::Call, 1),
+            (1, 3, ReferenceKind::Call, 2),
+        ]);
+
+        let graph = ReferenceGraph::new(&modules, &references, false, true);
+        assert_eq!(graph.nodes.len(), 6);
+        assert_eq!(graph.edges.len(), 8);
+    }
+
+    #[test]
+    fn test_unit_graph() {
+        let (dol, rel) = test_modules();
+        let modules = [&dol, &rel];
+        let references = collect_references(&modules);
+
+        // References within a.c are dropped, and rel_func has no unit
+        let graph = ReferenceGraph::new(&modules, &references, true, true);
+        let names = graph.nodes.iter().map(|n| n.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["a.c", "b.c"]);
+        let edges =
+            graph.edges.iter().map(|e| (e.source, e.target, e.kind, e.count)).collect::<Vec<_>>();
+        assert_eq!(edges, [(0, 1, ReferenceKind::Call, 2), (0, 1, ReferenceKind::Pointer, 1)]);
+    }
+
+    #[test]
+    fn test_write_dot() {
+        let node = |id: usize, name: &str, unit: Option<&str>| GraphNode {
+            id,
+            name: name.to_string(),
+            demangled_name: None,
+            module: "main.dol".to_string(),
+            unit: unit.map(|u| u.to_string()),
+            section: None,
+            address: None,
+        };
+        let mut graph = ReferenceGraph {
+            nodes: vec![node(0, "func_a", Some("src/a.c")), node(1, "func_b", None)],
+            edges: vec![
+                GraphEdge { source: 0, target: 1, kind: ReferenceKind::Call, count: 2 },
+                GraphEdge { source: 1, target: 0, kind: ReferenceKind::Read, count: 1 },
+            ],
+        };
+        graph.nodes[0].demangled_name = Some(r#"operator"" \x(const char*)"#.to_string());
+        let mut out = vec![];
+        graph.write_dot(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"digraph references {
+    node [shape=box];
+    n1 [label="func_b"];
+    subgraph cluster_1 {
+        label="main.dol: src/a.c";
+        n0 [label="operator\"\" \\x(const char*)"];
+    }
+    n0 -> n1 [label="2"];
+    n1 -> n0 [style=dashed];
+}
+"#
+        );
+    }
+}
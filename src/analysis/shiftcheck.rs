@@ -0,0 +1,397 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::Write,
+};
+
+use anyhow::Result;
+use ppc750cl::{Argument, Ins, Opcode, GPR};
+
+use crate::{
+    analysis::{
+        cfa::SectionAddress,
+        references::containing_symbol,
+        tracker::Tracker,
+        vm::{is_load_op, is_load_store_op},
+    },
+    obj::{ObjInfo, ObjKind, ObjRelocKind, ObjSectionKind, ObjSymbolKind},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShiftIssueKind {
+    /// A data word holding an address without a relocation.
+    Word,
+    /// A `lis` + low half instruction pair building an address without relocations.
+    Pair { lo_address: u32 },
+    /// An instruction pair where only one half has a relocation.
+    PartialPair { lo_address: u32, hi_relocated: bool },
+}
+
+/// A location that still refers to the original layout after shifting its unit.
+#[derive(Debug, Clone)]
+pub struct ShiftIssue {
+    pub unit: String,
+    /// Original address of the word or `lis` instruction.
+    pub address: SectionAddress,
+    /// The original address it refers to.
+    pub value: u32,
+    pub kind: ShiftIssueKind,
+    /// The source or target is covered by `block_relocations` and the analyzer would have
+    /// found a relocation here otherwise.
+    pub blocked: bool,
+}
+
+/// Checks that every pointer in the split units is covered by a relocation.
+///
+/// Each unit is moved to a fake base past the end of the original layout and its relocations
+/// are re-applied. Any data word or `lis` pair that still holds an address in the original
+/// layout would break a shifted build.
+///
+/// REL modules are skipped: their sections all start at address 0, so addresses can't be told
+/// apart from other values, and the loader relocates every pointer through the REL's own
+/// relocation table.
+pub fn check_shiftable(obj: &ObjInfo, units: &[ObjInfo]) -> Result<Vec<ShiftIssue>> {
+    if obj.kind == ObjKind::Relocatable {
+        return Ok(vec![]);
+    }
+    let ranges = obj
+        .sections
+        .iter()
+        .map(|(_, s)| (s.address as u32, (s.address + s.size) as u32))
+        .collect::<Vec<_>>();
+    let start = ranges.iter().map(|&(start, _)| start).min().unwrap_or(0);
+    let end = ranges.iter().map(|&(_, end)| end).max().unwrap_or(0);
+    let base_delta = (end - start).next_multiple_of(0x100000);
+    let in_range = |value: u32| ranges.iter().any(|&(start, end)| value >= start && value < end);
+    // Each unit gets its own delta so that references between units change too
+    let delta = |unit: usize| base_delta.wrapping_add(unit as u32 * 0x20);
+
+    // Shifted addresses of symbols defined in each unit
+    let mut shifted = HashMap::<&str, u32>::new();
+    for (unit_index, unit) in units.iter().enumerate() {
+        for (_, symbol) in unit.symbols.iter() {
+            let Some(section_index) = symbol.section else { continue };
+            if symbol.flags.is_local() || symbol.kind == ObjSymbolKind::Section {
+                continue;
+            }
+            let section = &unit.sections[section_index];
+            let address = section.virtual_address.unwrap_or(0) as u32 + symbol.address as u32;
+            shifted.insert(symbol.name.as_str(), address.wrapping_add(delta(unit_index)));
+        }
+    }
+
+    // Pointers the analyzer finds with `block_relocations` ignored. The tracker skips blocked
+    // sources and targets, so run it on a copy with both ranges cleared.
+    let unblocked =
+        if obj.blocked_relocation_sources.is_empty() && obj.blocked_relocation_targets.is_empty() {
+            BTreeSet::new()
+        } else {
+            let mut unblocked_obj = obj.clone();
+            unblocked_obj.blocked_relocation_sources = Default::default();
+            unblocked_obj.blocked_relocation_targets = Default::default();
+            let mut tracker = Tracker::new(&unblocked_obj);
+            tracker.process(&unblocked_obj)?;
+            tracker.relocations.into_keys().collect()
+        };
+    let is_blocked = |address: SectionAddress, value: u32, kind: ShiftIssueKind| -> bool {
+        if !unblocked.contains(&address) {
+            return false;
+        }
+        let lo_address = match kind {
+            ShiftIssueKind::Word => None,
+            ShiftIssueKind::Pair { lo_address }
+            | ShiftIssueKind::PartialPair { lo_address, .. } => {
+                Some(SectionAddress::new(address.section, lo_address))
+            }
+        };
+        obj.blocked_relocation_sources.contains(address)
+            || lo_address.is_some_and(|lo| obj.blocked_relocation_sources.contains(lo))
+            || obj.sections.at_address(value).is_ok_and(|(target_section, _)| {
+                obj.blocked_relocation_targets.contains(SectionAddress::new(target_section, value))
+            })
+    };
+
+    let mut issues = vec![];
+    for (unit_index, unit) in units.iter().enumerate() {
+        let unit_delta = delta(unit_index);
+        for (_, section) in unit.sections.iter() {
+            if section.kind == ObjSectionKind::Bss {
+                continue;
+            }
+            let Some(virtual_address) = section.virtual_address.map(|v| v as u32) else {
+                continue;
+            };
+            let Ok((orig_section_index, _)) = obj.sections.at_address(virtual_address) else {
+                continue;
+            };
+
+            // Re-apply relocations at the shifted address
+            let mut data = section.data.clone();
+            let mut relocated = BTreeSet::new();
+            for (offset, reloc) in section.relocations.iter() {
+                relocated.insert(offset);
+                let symbol = &unit.symbols[reloc.target_symbol];
+                let target = if let Some(target_section) = symbol.section {
+                    let target_section = &unit.sections[target_section];
+                    (target_section.virtual_address.unwrap_or(0) as u32 + symbol.address as u32)
+                        .wrapping_add(unit_delta)
+                } else if let Some(&address) = shifted.get(symbol.name.as_str()) {
+                    address
+                } else if let Some((_, orig)) = obj.symbols.for_name(&symbol.name).next() {
+                    // Common and linker generated symbols stay in place
+                    orig.address as u32
+                } else {
+                    continue;
+                };
+                let target = (target as i64 + reloc.addend) as u32;
+                let Some(bytes) = data.get_mut(offset as usize..offset as usize + 4) else {
+                    continue;
+                };
+                let ins = u32::from_be_bytes(bytes.try_into().unwrap());
+                let ins = match reloc.kind {
+                    ObjRelocKind::Absolute => target,
+                    ObjRelocKind::PpcAddr16Hi => (ins & 0xffff0000) | (target >> 16),
+                    ObjRelocKind::PpcAddr16Ha => {
+                        (ins & 0xffff0000) | (target.wrapping_add(0x8000) >> 16)
+                    }
+                    ObjRelocKind::PpcAddr16Lo => (ins & 0xffff0000) | (target & 0xffff),
+                    // Relative and small data relocations are unaffected by the shift
+                    ObjRelocKind::PpcRel24 | ObjRelocKind::PpcRel14 | ObjRelocKind::PpcEmbSda21 => {
+                        continue
+                    }
+                };
+                bytes.copy_from_slice(&ins.to_be_bytes());
+            }
+
+            let mut push_issue = |offset: u32, value: u32, kind: ShiftIssueKind| {
+                let address = SectionAddress::new(orig_section_index, virtual_address + offset);
+                issues.push(ShiftIssue {
+                    unit: unit.name.clone(),
+                    address,
+                    value,
+                    kind,
+                    blocked: is_blocked(address, value, kind),
+                });
+            };
+
+            if section.kind == ObjSectionKind::Code {
+                // Track `lis` instructions per register until the low half is used.
+                // Values are (offset, shifted value, original value, relocated).
+                let mut hi: [Option<(u32, u32, u32, bool)>; 32] = [None; 32];
+                for offset in (0..data.len() as u32 & !3).step_by(4) {
+                    let range = offset as usize..offset as usize + 4;
+                    let ins = Ins::new(u32::from_be_bytes(data[range.clone()].try_into().unwrap()));
+                    let orig =
+                        Ins::new(u32::from_be_bytes(section.data[range].try_into().unwrap()));
+                    let op = ins.op;
+                    let is_relocated = relocated.contains(&offset);
+                    if op == Opcode::Addis && ins.field_ra() == 0 {
+                        hi[ins.field_rd() as usize] = Some((
+                            offset,
+                            (ins.field_simm() as u32) << 16,
+                            (orig.field_simm() as u32) << 16,
+                            is_relocated,
+                        ));
+                        continue;
+                    }
+                    let lo = match op {
+                        Opcode::Addi if ins.field_ra() != 0 => Some((
+                            ins.field_ra(),
+                            ins.field_simm() as i32 as u32,
+                            orig.field_simm() as i32 as u32,
+                        )),
+                        Opcode::Ori => Some((
+                            ins.field_rs(),
+                            ins.field_uimm() as u32,
+                            orig.field_uimm() as u32,
+                        )),
+                        op if is_load_store_op(op) && ins.field_ra() != 0 => Some((
+                            ins.field_ra(),
+                            ins.field_simm() as i32 as u32,
+                            orig.field_simm() as i32 as u32,
+                        )),
+                        _ => None,
+                    };
+                    if let Some((reg, lo, orig_lo)) = lo {
+                        if let Some((hi_offset, hi_value, orig_hi, hi_relocated)) = hi[reg as usize]
+                        {
+                            let lo_address = virtual_address + offset;
+                            let orig_value = orig_hi.wrapping_add(orig_lo);
+                            if hi_relocated != is_relocated {
+                                let kind = ShiftIssueKind::PartialPair { lo_address, hi_relocated };
+                                push_issue(hi_offset, orig_value, kind);
+                            } else if !hi_relocated && in_range(hi_value.wrapping_add(lo)) {
+                                let kind = ShiftIssueKind::Pair { lo_address };
+                                push_issue(hi_offset, orig_value, kind);
+                            }
+                        }
+                    }
+                    if matches!(op, Opcode::B | Opcode::Bc | Opcode::Bclr | Opcode::Bcctr) {
+                        hi = [None; 32];
+                    } else if is_load_op(op) {
+                        hi[ins.field_rd() as usize] = None;
+                    } else {
+                        for argument in ins.defs() {
+                            if let Argument::GPR(GPR(reg)) = argument {
+                                hi[reg as usize] = None;
+                            }
+                        }
+                    }
+                }
+            } else {
+                for offset in (0..data.len() as u32 & !3).step_by(4) {
+                    if (virtual_address + offset) & 3 != 0 || relocated.contains(&offset) {
+                        continue;
+                    }
+                    let value = u32::from_be_bytes(
+                        data[offset as usize..offset as usize + 4].try_into().unwrap(),
+                    );
+                    if in_range(value) {
+                        push_issue(offset, value, ShiftIssueKind::Word);
+                    }
+                }
+            }
+        }
+    }
+    Ok(issues)
+}
+
+/// Writes a shiftability report, grouped by unit.
+pub fn write_shift_report<W>(w: &mut W, obj: &ObjInfo, issues: &[ShiftIssue]) -> Result<()>
+where W: Write + ?Sized {
+    let describe = |address: u32| -> String {
+        let Ok((section_index, section)) = obj.sections.at_address(address) else {
+            return format!("{:#010X}", address);
+        };
+        let symbol = containing_symbol(obj, section_index, address)
+            .map(|idx| {
+                let symbol = &obj.symbols[idx];
+                match address - symbol.address as u32 {
+                    0 => format!(" ({})", symbol.name),
+                    offset => format!(" ({}+{:#X})", symbol.name, offset),
+                }
+            })
+            .unwrap_or_default();
+        let unit = section
+            .splits
+            .for_address(address)
+            .map(|(_, split)| format!(" [{}]", split.unit))
+            .unwrap_or_default();
+        format!("{}:{:#010X}{}{}", section.name, address, symbol, unit)
+    };
+
+    let mut last_unit = None;
+    for issue in issues {
+        if last_unit != Some(&issue.unit) {
+            if last_unit.is_some() {
+                writeln!(w)?;
+            }
+            writeln!(w, "{}:", issue.unit)?;
+            last_unit = Some(&issue.unit);
+        }
+        let section = &obj.sections[issue.address.section];
+        let source = format!("{}:{:#010X}", section.name, issue.address.address);
+        let target = describe(issue.value);
+        match issue.kind {
+            ShiftIssueKind::Word => {
+                write!(w, "\t{} word points to {}", source, target)?;
+            }
+            ShiftIssueKind::Pair { lo_address } => {
+                write!(w, "\t{}/{:#010X} pair builds {}", source, lo_address, target)?;
+            }
+            ShiftIssueKind::PartialPair { lo_address, hi_relocated } => {
+                write!(
+                    w,
+                    "\t{}/{:#010X} pair only has a {} relocation ({})",
+                    source,
+                    lo_address,
+                    if hi_relocated { "high" } else { "low" },
+                    target
+                )?;
+            }
+        }
+        if issue.blocked {
+            write!(w, " // found by analysis, check block_relocations")?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{ObjArchitecture, ObjSection};
+
+    fn data_section(address: u32, data: Vec<u8>) -> ObjSection {
+        ObjSection {
+            name: ".data".to_string(),
+            kind: ObjSectionKind::Data,
+            address: address as u64,
+            size: data.len() as u64,
+            data,
+            align: 4,
+            elf_index: 0,
+            relocations: Default::default(),
+            virtual_address: Some(address as u64),
+            file_offset: 0,
+            section_known: true,
+            splits: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_blocked_relocation() {
+        let mut data = vec![0u8; 0x10];
+        data[0..4].copy_from_slice(&0x80004008u32.to_be_bytes());
+        data[4..8].copy_from_slice(&0x8000400Cu32.to_be_bytes());
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "main".to_string(),
+            vec![],
+            vec![data_section(0x80004000, data.clone())],
+        );
+        obj.blocked_relocation_sources
+            .insert(SectionAddress::new(0, 0x80004000), SectionAddress::new(0, 0x80004004));
+        let unit = ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            "unit.c".to_string(),
+            vec![],
+            vec![data_section(0x80004000, data)],
+        );
+
+        let issues = check_shiftable(&obj, &[unit]).unwrap();
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].address, SectionAddress::new(0, 0x80004000));
+        assert_eq!(issues[0].kind, ShiftIssueKind::Word);
+        assert!(issues[0].blocked);
+        assert_eq!(issues[1].address, SectionAddress::new(0, 0x80004004));
+        assert!(!issues[1].blocked);
+    }
+
+    #[test]
+    fn test_skip_rel() {
+        // Small integers look like addresses in REL sections, which start at 0
+        let mut data = vec![0u8; 0x10];
+        data[0..4].copy_from_slice(&4u32.to_be_bytes());
+        data[4..8].copy_from_slice(&8u32.to_be_bytes());
+        let section = data_section(0, data);
+        let mut obj = ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            "module".to_string(),
+            vec![],
+            vec![section.clone()],
+        );
+        obj.module_id = 1;
+        let unit = ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            "unit.c".to_string(),
+            vec![],
+            vec![section],
+        );
+        assert!(check_shiftable(&obj, &[unit]).unwrap().is_empty());
+    }
+}
@@ -1,3 +1,9 @@
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{btree_map, BTreeMap, BTreeSet, HashSet},
+};
+
 use anyhow::{anyhow, Result};
 use itertools::Itertools;
 
@@ -8,9 +14,15 @@ use crate::{
     },
     obj::{
         ObjInfo, ObjSectionKind, ObjSplit, ObjSymbol, ObjSymbolFlagSet, ObjSymbolFlags,
-        ObjSymbolKind,
+        ObjSymbolKind, SymbolIndex,
+    },
+    util::{
+        config::is_auto_symbol,
+        signatures::{
+            apply_signature, check_signatures, check_signatures_str, parse_signatures,
+            FunctionSignature,
+        },
     },
-    util::signatures::{apply_signature, check_signatures, check_signatures_str, parse_signatures},
 };
 
 const SIGNATURES: &[(&str, &str)] = &[
@@ -203,7 +215,175 @@ const POST_SIGNATURES: &[(&str, &str)] = &[
     ),
 ];
 
-fn apply_signature_for_symbol(obj: &mut ObjInfo, name: &str, sig_str: &str) -> Result<()> {
+/// User-supplied signatures, checked alongside the built-in set.
+#[derive(Debug, Clone, Default)]
+pub struct SignatureDb {
+    /// Signatures grouped by symbol name, sorted by descending priority.
+    groups: Vec<UserSignatures>,
+    /// Information about each set of signatures added.
+    sets: Vec<SignatureSetInfo>,
+    /// Built-in signatures that should be skipped.
+    disabled: HashSet<String>,
+}
+
+/// Describes the library a set of signatures was built from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SignatureSetInfo {
+    /// Library name, e.g. `MSL_C` or `Runtime.PPCEABI.H`.
+    pub library: Option<String>,
+    /// Library version, e.g. `GC/2.7`.
+    pub version: Option<String>,
+    /// Version of the MW `.comment` section format used by the library's compiler.
+    pub mw_comment_version: Option<u8>,
+}
+
+#[derive(Debug, Clone)]
+struct UserSignatures {
+    name: String,
+    priority: i32,
+    set: usize,
+    signatures: Vec<FunctionSignature>,
+}
+
+impl SignatureDb {
+    /// Adds a set of signatures with the given priority. Built-in signatures have a priority
+    /// of 0, and groups with a higher priority are checked first.
+    pub fn add(
+        &mut self,
+        signatures: Vec<FunctionSignature>,
+        priority: i32,
+        info: SignatureSetInfo,
+    ) {
+        let set = self.sets.len();
+        self.sets.push(info);
+        for (name, signatures) in signatures
+            .into_iter()
+            .into_group_map_by(|sig| sig.symbols[sig.symbol as usize].name.clone())
+            .into_iter()
+            .sorted_by(|(a, _), (b, _)| a.cmp(b))
+        {
+            self.groups.push(UserSignatures { name, priority, set, signatures });
+        }
+        self.groups.sort_by_key(|group| Reverse(group.priority));
+    }
+
+    /// Determines the best-matching signature set for each library, by counting the known
+    /// symbols that match each set's signatures. Only sets with a library and version are
+    /// considered.
+    pub fn detect_library_sets(&self, obj: &ObjInfo) -> Result<Vec<&SignatureSetInfo>> {
+        let mut counts = vec![0usize; self.sets.len()];
+        for group in &self.groups {
+            let info = &self.sets[group.set];
+            if info.library.is_none() || info.version.is_none() {
+                continue;
+            }
+            for (_, symbol) in obj.symbols.for_name(&group.name) {
+                let Some(section_index) = symbol.section else {
+                    continue;
+                };
+                let section = &obj.sections[section_index];
+                if check_signatures(section, symbol.address as u32, &group.signatures)?.is_some() {
+                    counts[group.set] += 1;
+                    break;
+                }
+            }
+        }
+        let mut best = BTreeMap::<&str, usize>::new();
+        for (set, info) in self.sets.iter().enumerate() {
+            let (Some(library), Some(_)) = (&info.library, &info.version) else {
+                continue;
+            };
+            if counts[set] == 0 {
+                continue;
+            }
+            log::debug!("Signature set {:?} matched {} symbols", info, counts[set]);
+            match best.entry(library) {
+                btree_map::Entry::Vacant(e) => {
+                    e.insert(set);
+                }
+                btree_map::Entry::Occupied(mut e) => {
+                    if counts[set] > counts[*e.get()] {
+                        e.insert(set);
+                    }
+                }
+            }
+        }
+        Ok(best.into_values().map(|set| &self.sets[set]).collect())
+    }
+
+    /// Whether any signature set is labeled with a library and version.
+    pub fn has_library_sets(&self) -> bool {
+        self.sets.iter().any(|info| info.library.is_some() && info.version.is_some())
+    }
+
+    /// Removes the signature sets of each given library that don't match its version.
+    /// Libraries without a set for the given version keep all of their sets.
+    pub fn select_library_versions(&mut self, libraries: &BTreeMap<String, String>) {
+        let sets = &self.sets;
+        let matched = sets
+            .iter()
+            .filter_map(|info| {
+                let (library, version) = (info.library.as_ref()?, info.version.as_ref()?);
+                (libraries.get(library) == Some(version)).then_some(library)
+            })
+            .collect::<BTreeSet<_>>();
+        self.groups.retain(|group| {
+            let info = &sets[group.set];
+            match info.library.as_ref().filter(|library| matched.contains(library)) {
+                Some(library) => info.version.as_ref() == libraries.get(library),
+                None => true,
+            }
+        });
+    }
+
+    /// Disables the built-in signature for the given symbol.
+    pub fn disable(&mut self, name: &str) { self.disabled.insert(name.to_string()); }
+
+    fn is_disabled(&self, name: &str) -> bool { self.disabled.contains(name) }
+
+    /// Merges the built-in signatures with the user signatures, in the order they should be
+    /// checked.
+    fn groups<'a>(&'a self, builtin: &[(&'a str, &str)]) -> Result<Vec<SignatureGroup<'a>>> {
+        let mut groups = Vec::with_capacity(builtin.len() + self.groups.len());
+        for &(name, sig_str) in builtin {
+            if self.is_disabled(name) {
+                log::debug!("Skipping disabled signature {}", name);
+                continue;
+            }
+            groups.push(SignatureGroup {
+                name,
+                priority: 0,
+                signatures: Cow::Owned(parse_signatures(sig_str)?),
+                user: false,
+            });
+        }
+        for group in &self.groups {
+            groups.push(SignatureGroup {
+                name: &group.name,
+                priority: group.priority,
+                signatures: Cow::Borrowed(&group.signatures),
+                user: true,
+            });
+        }
+        // Stable sort: built-in signatures come first within the same priority
+        groups.sort_by_key(|group| Reverse(group.priority));
+        Ok(groups)
+    }
+}
+
+struct SignatureGroup<'a> {
+    name: &'a str,
+    priority: i32,
+    signatures: Cow<'a, [FunctionSignature]>,
+    user: bool,
+}
+
+fn apply_signature_for_symbol(
+    obj: &mut ObjInfo,
+    name: &str,
+    signatures: &[FunctionSignature],
+) -> Result<bool> {
+    let mut found = false;
     for symbol_idx in obj.symbols.for_name(name).map(|(i, _)| i).collect_vec() {
         let symbol = &obj.symbols[symbol_idx];
         let Some(section_index) = symbol.section else {
@@ -211,11 +391,42 @@ fn apply_signature_for_symbol(obj: &mut ObjInfo, name: &str, sig_str: &str) -> R
         };
         let addr = symbol.address as u32;
         let section = &obj.sections[section_index];
-        if let Some(signature) = check_signatures_str(section, addr, sig_str)? {
+        if let Some(signature) = check_signatures(section, addr, signatures)? {
             apply_signature(obj, SectionAddress::new(section_index, addr), &signature)?;
+            found = true;
         }
     }
-    Ok(())
+    Ok(found)
+}
+
+/// Searches all functions for the first match of the given signatures. When `user` is set,
+/// only functions without a name and with a matching size are considered.
+fn find_signature(
+    obj: &ObjInfo,
+    signatures: &[FunctionSignature],
+    user: bool,
+) -> Result<Option<(SymbolIndex, FunctionSignature)>> {
+    for (section_index, section) in obj.sections.by_kind(ObjSectionKind::Code) {
+        for (symbol_index, symbol) in obj
+            .symbols
+            .for_section(section_index)
+            .filter(|(_, sym)| sym.kind == ObjSymbolKind::Function)
+        {
+            if user
+                && (!is_auto_symbol(symbol)
+                    || (symbol.size_known
+                        && !signatures.iter().any(|sig| {
+                            sig.symbols[sig.symbol as usize].size as u64 == symbol.size
+                        })))
+            {
+                continue;
+            }
+            if let Some(signature) = check_signatures(section, symbol.address as u32, signatures)? {
+                return Ok(Some((symbol_index, signature)));
+            }
+        }
+    }
+    Ok(None)
 }
 
 fn apply_ctors_signatures(obj: &mut ObjInfo) -> Result<()> {
@@ -270,7 +481,7 @@ fn apply_ctors_signatures(obj: &mut ObjInfo) -> Result<()> {
     Ok(())
 }
 
-fn apply_dtors_signatures(obj: &mut ObjInfo) -> Result<()> {
+fn apply_dtors_signatures(obj: &mut ObjInfo, db: &SignatureDb) -> Result<()> {
     let (dtors_section_index, dtors_section) =
         if let Some((_, symbol)) = obj.symbols.by_name("_dtors")? {
             let section_index =
@@ -292,7 +503,7 @@ fn apply_dtors_signatures(obj: &mut ObjInfo) -> Result<()> {
     let mut found_fce = false;
 
     // First entry of dtors is __destroy_global_chain
-    if let Some(dgc_target) = dgc_target {
+    if let Some(dgc_target) = dgc_target.filter(|_| !db.is_disabled("__destroy_global_chain")) {
         if let Some(signature) = check_signatures_str(
             &obj.sections[dgc_target.section],
             dgc_target.address,
@@ -321,7 +532,7 @@ fn apply_dtors_signatures(obj: &mut ObjInfo) -> Result<()> {
     }
 
     // Second entry of dtors is __fini_cpp_exceptions
-    if let Some(fce_target) = fce_target {
+    if let Some(fce_target) = fce_target.filter(|_| !db.is_disabled("__fini_cpp_exceptions")) {
         if let Some(signature) = check_signatures_str(
             &obj.sections[fce_target.section],
             fce_target.address,
@@ -391,8 +602,8 @@ fn apply_init_user_signatures(obj: &mut ObjInfo) -> Result<()> {
     Ok(())
 }
 
-pub fn apply_signatures(obj: &mut ObjInfo) -> Result<()> {
-    if let Some(entry) = obj.entry.map(|n| n as u32) {
+pub fn apply_signatures(obj: &mut ObjInfo, db: &SignatureDb) -> Result<()> {
+    if let Some(entry) = obj.entry.map(|n| n as u32).filter(|_| !db.is_disabled("__start")) {
         let (entry_section_index, entry_section) = obj.sections.at_address(entry)?;
         if let Some(signature) = check_signatures_str(
             entry_section,
@@ -403,39 +614,47 @@ pub fn apply_signatures(obj: &mut ObjInfo) -> Result<()> {
         }
     }
 
-    for &(name, sig_str) in SIGNATURES {
-        apply_signature_for_symbol(obj, name, sig_str)?
+    // Once a symbol has been matched, lower priority signatures for it are skipped
+    let mut matched = HashSet::new();
+    for group in db.groups(SIGNATURES)? {
+        if matched.contains(group.name) {
+            continue;
+        }
+        if apply_signature_for_symbol(obj, group.name, &group.signatures)? {
+            matched.insert(group.name);
+        }
     }
 
-    apply_init_user_signatures(obj)?;
-    apply_ctors_signatures(obj)?;
-    apply_dtors_signatures(obj)?;
+    if !db.is_disabled("__init_cpp") {
+        apply_init_user_signatures(obj)?;
+    }
+    if !db.is_disabled("__init_cpp_exceptions") {
+        apply_ctors_signatures(obj)?;
+    }
+    apply_dtors_signatures(obj, db)?;
     Ok(())
 }
 
-pub fn apply_signatures_post(obj: &mut ObjInfo) -> Result<()> {
+pub fn apply_signatures_post(obj: &mut ObjInfo, db: &SignatureDb) -> Result<()> {
     log::debug!("Checking post CFA signatures");
-    for &(_name, sig_str) in POST_SIGNATURES {
-        let signatures = parse_signatures(sig_str)?;
-        let mut found_signature = None;
-        'outer: for (section_index, section) in obj.sections.by_kind(ObjSectionKind::Code) {
-            for (symbol_index, symbol) in obj
-                .symbols
-                .for_section(section_index)
-                .filter(|(_, sym)| sym.kind == ObjSymbolKind::Function)
-            {
-                if let Some(signature) =
-                    check_signatures(section, symbol.address as u32, &signatures)?
-                {
-                    found_signature = Some((symbol_index, signature));
-                    break 'outer;
-                }
+    let mut matched = HashSet::new();
+    for group in db.groups(POST_SIGNATURES)? {
+        if matched.contains(group.name) {
+            continue;
+        }
+        // User signatures for known symbols are only checked at the symbol's address
+        if group.user && obj.symbols.for_name(group.name).next().is_some() {
+            if apply_signature_for_symbol(obj, group.name, &group.signatures)? {
+                matched.insert(group.name);
             }
+            continue;
         }
-        if let Some((symbol_index, signature)) = found_signature {
+        if let Some((symbol_index, signature)) = find_signature(obj, &group.signatures, group.user)?
+        {
             let symbol = &obj.symbols[symbol_index];
             let symbol_addr = SectionAddress::new(symbol.section.unwrap(), symbol.address as u32);
             apply_signature(obj, symbol_addr, &signature)?;
+            matched.insert(group.name);
         }
     }
     Ok(())
@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use regex::bytes::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::signatures::SignatureDb,
+    obj::{ObjInfo, ObjSectionKind},
+};
+
+/// Library versions detected in a module.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LibraryVersions {
+    /// SDK build, from the library banners.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sdk: Option<SdkVersion>,
+    /// Version of each library (e.g. `MSL_C`, `Runtime.PPCEABI.H` or `MetroTRK`), from known
+    /// strings or the best-matching signature set.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub libraries: BTreeMap<String, String>,
+    /// Version of the MW `.comment` section format, from the best-matching signature sets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mw_comment_version: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SdkVersion {
+    /// `Dolphin SDK` or `RVL_SDK`.
+    pub platform: String,
+    /// Build date of the OS library (or the first library found, if OS is missing).
+    pub build: String,
+    /// Version code of the OS library, if present. (e.g. `0x2301`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Whether debug builds of the libraries were linked.
+    #[serde(default)]
+    pub debug: bool,
+    /// Build date of each library with a banner.
+    pub libraries: BTreeMap<String, String>,
+}
+
+/// Matches SDK library banners, such as:
+/// `<< Dolphin SDK - OS\trelease build: Apr  5 2004 04:14:16 (0x2301) >>`
+/// `<< RVL_SDK - OS \trelease build: Nov 30 2006 03:26:11 (0x4199_60831) >>`
+static SDK_BANNER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"<< (?P<platform>Dolphin SDK|RVL_SDK) - (?P<library>[A-Za-z0-9_]+)\s*(?P<kind>release|debug) build: (?P<build>[A-Za-z]{3} [ 0-9]\d \d{4} \d{2}:\d{2}:\d{2})(?: \((?P<version>0x[0-9A-Fa-f_]+)\))? >>",
+    )
+    .unwrap()
+});
+
+/// Detects the SDK build from library banners in the module's data sections.
+pub fn detect_sdk_version(obj: &ObjInfo) -> Option<SdkVersion> {
+    let mut result: Option<SdkVersion> = None;
+    for (_, section) in obj.sections.iter() {
+        if !matches!(section.kind, ObjSectionKind::Data | ObjSectionKind::ReadOnlyData) {
+            continue;
+        }
+        for captures in SDK_BANNER.captures_iter(&section.data) {
+            let capture =
+                |name: &str| captures.name(name).map(|m| String::from_utf8_lossy(m.as_bytes()));
+            let (Some(platform), Some(library), Some(kind), Some(build)) =
+                (capture("platform"), capture("library"), capture("kind"), capture("build"))
+            else {
+                continue;
+            };
+            log::debug!("Found {} {} {} build {}", platform, library, kind, build);
+            let sdk = result.get_or_insert_with(|| SdkVersion {
+                platform: platform.to_string(),
+                build: build.to_string(),
+                ..Default::default()
+            });
+            if library == "OS" {
+                sdk.build = build.to_string();
+                sdk.version = capture("version").map(|v| v.to_string());
+            }
+            sdk.debug |= kind == "debug";
+            sdk.libraries.insert(library.to_string(), build.to_string());
+        }
+    }
+    result
+}
+
+/// Version strings embedded in libraries, by library name. Each pattern captures `version`.
+/// MSL_C and Runtime.PPCEABI.H don't embed one, so their versions come from tagged signature
+/// sets only.
+static LIBRARY_STRINGS: Lazy<Vec<(&str, Regex)>> = Lazy::new(|| {
+    vec![
+        // Printed by TRK_NubWelcome, such as `MetroTRK for GAMECUBE v2.6`
+        ("MetroTRK", Regex::new(r"MetroTRK for [A-Za-z ]+ v(?P<version>\d+\.\d+)").unwrap()),
+    ]
+});
+
+/// Detects library versions from known strings in the module's data sections.
+pub fn detect_string_versions(obj: &ObjInfo) -> BTreeMap<String, String> {
+    let mut result = BTreeMap::new();
+    for (_, section) in obj.sections.iter() {
+        if !matches!(section.kind, ObjSectionKind::Data | ObjSectionKind::ReadOnlyData) {
+            continue;
+        }
+        for (library, regex) in LIBRARY_STRINGS.iter() {
+            let Some(version) = regex.captures(&section.data).and_then(|c| c.name("version"))
+            else {
+                continue;
+            };
+            let version = String::from_utf8_lossy(version.as_bytes()).to_string();
+            log::debug!("Found {} {} string", library, version);
+            result.entry(library.to_string()).or_insert(version);
+        }
+    }
+    result
+}
+
+/// Detects the SDK build and library versions, from known strings and the best-matching
+/// signature set for each library. Versions found in strings take precedence.
+pub fn detect_versions(obj: &ObjInfo, db: &SignatureDb) -> Result<LibraryVersions> {
+    let mut versions = LibraryVersions {
+        sdk: detect_sdk_version(obj),
+        libraries: detect_string_versions(obj),
+        ..Default::default()
+    };
+    for info in db.detect_library_sets(obj)? {
+        let (Some(library), Some(version)) = (&info.library, &info.version) else {
+            continue;
+        };
+        versions.libraries.entry(library.clone()).or_insert_with(|| version.clone());
+        if versions.mw_comment_version.is_none() {
+            versions.mw_comment_version = info.mw_comment_version;
+        }
+    }
+    Ok(versions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sdk_banner() {
+        let data = b"\0<< Dolphin SDK - OS\trelease build: Apr  5 2004 04:14:16 (0x2301) >>\0";
+        let captures = SDK_BANNER.captures(data).unwrap();
+        assert_eq!(&captures["platform"], b"Dolphin SDK");
+        assert_eq!(&captures["library"], b"OS");
+        assert_eq!(&captures["kind"], b"release");
+        assert_eq!(&captures["build"], b"Apr  5 2004 04:14:16");
+        assert_eq!(&captures["version"], b"0x2301");
+    }
+
+    #[test]
+    fn test_library_strings() {
+        let (library, regex) = &LIBRARY_STRINGS[0];
+        assert_eq!(*library, "MetroTRK");
+        let captures = regex.captures(b"\0MetroTRK for GAMECUBE v2.6\0").unwrap();
+        assert_eq!(&captures["version"], b"2.6");
+    }
+}
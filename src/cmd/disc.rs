@@ -1,6 +1,15 @@
-use anyhow::{Error, Result};
+use std::{fs, io::Write};
+
+use anyhow::{anyhow, Context, Error, Result};
 use argp::FromArgs;
-use nodtool::SubCommand;
+use nodtool::cmd::{convert, extract, info, verify};
+use typed_path::{Utf8NativePath, Utf8NativePathBuf};
+
+use crate::util::{
+    disc::{is_wii_boot, write_disc, DiscBuildInput, DiscEntry, DiscSource, WiiBuildInput},
+    file::buf_writer,
+    path::native_path,
+};
 
 #[derive(FromArgs, Debug)]
 /// Commands for processing disc images.
@@ -10,4 +19,97 @@ pub struct Args {
     command: SubCommand,
 }
 
-pub fn run(args: Args) -> Result<()> { nodtool::run(args.command).map_err(Error::new) }
+#[derive(FromArgs, Debug)]
+#[argp(subcommand)]
+enum SubCommand {
+    Info(info::Args),
+    Extract(extract::Args),
+    Convert(convert::Args),
+    Verify(verify::Args),
+    Build(BuildArgs),
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Builds a GameCube or Wii disc image from an extracted directory.
+#[argp(subcommand, name = "build")]
+pub struct BuildArgs {
+    #[argp(positional, from_str_fn(native_path))]
+    /// directory containing sys/ and files/ (for Wii, the extracted data partition)
+    input: Utf8NativePathBuf,
+    #[argp(option, short = 'o', from_str_fn(native_path))]
+    /// output ISO file
+    output: Utf8NativePathBuf,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let command = match args.command {
+        SubCommand::Info(c_args) => nodtool::SubCommand::Info(c_args),
+        SubCommand::Extract(c_args) => nodtool::SubCommand::Extract(c_args),
+        SubCommand::Convert(c_args) => nodtool::SubCommand::Convert(c_args),
+        SubCommand::Verify(c_args) => nodtool::SubCommand::Verify(c_args),
+        SubCommand::Build(c_args) => return build(c_args),
+    };
+    nodtool::run(command).map_err(Error::new)
+}
+
+fn build(args: BuildArgs) -> Result<()> {
+    // Extracting all partitions of a Wii disc places the data partition in DATA/
+    let data_dir = args.input.join("DATA");
+    let input_dir =
+        if fs::metadata(&data_dir).is_ok_and(|m| m.is_dir()) { data_dir } else { args.input };
+    let read = |name: &str| -> Result<Box<[u8]>> {
+        let path = input_dir.join(name);
+        Ok(fs::read(&path).with_context(|| format!("Failed to read '{}'", path))?.into())
+    };
+    let exists = |name: &str| fs::metadata(input_dir.join(name)).is_ok_and(|m| m.is_file());
+    let raw_boot = read("sys/boot.bin")?;
+    let raw_fst = if exists("sys/fst.bin") { Some(read("sys/fst.bin")?) } else { None };
+    let wii = if is_wii_boot(&raw_boot) {
+        Some(WiiBuildInput {
+            raw_disc_header: read("disc/header.bin")?,
+            raw_region: if exists("disc/region.bin") {
+                Some(read("disc/region.bin")?)
+            } else {
+                None
+            },
+            raw_ticket: read("ticket.bin")?,
+            raw_tmd: read("tmd.bin")?,
+            raw_cert_chain: read("cert.bin")?,
+        })
+    } else {
+        None
+    };
+    let input = DiscBuildInput {
+        raw_boot,
+        raw_bi2: read("sys/bi2.bin")?,
+        raw_apploader: read("sys/apploader.img")?,
+        raw_dol: read("sys/main.dol")?,
+        raw_fst,
+        files: read_dir_entries(&input_dir.join("files"))?,
+        wii,
+    };
+    let mut out = buf_writer(&args.output)?;
+    write_disc(&mut out, input)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn read_dir_entries(dir: &Utf8NativePath) -> Result<Vec<DiscEntry>> {
+    let mut entries = vec![];
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read directory '{}'", dir))? {
+        let entry = entry?;
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|name| anyhow!("Invalid file name {:?} in '{}'", name, dir))?;
+        let path = dir.join(&name);
+        let metadata = fs::metadata(&path)?;
+        if metadata.is_dir() {
+            entries.push(DiscEntry::Directory { entries: read_dir_entries(&path)?, name });
+        } else {
+            let source = DiscSource::Path(path);
+            entries.push(DiscEntry::File { name, source, size: metadata.len() });
+        }
+    }
+    Ok(entries)
+}
@@ -1,4 +1,5 @@
 use std::{
+    borrow::Cow,
     cmp::min,
     collections::{btree_map::Entry, hash_map, BTreeMap, HashMap},
     fs,
@@ -9,7 +10,7 @@ use std::{
     time::Instant,
 };
 
-use anyhow::{anyhow, bail, Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use argp::FromArgs;
 use cwdemangle::demangle;
 use itertools::Itertools;
@@ -21,14 +22,23 @@ use xxhash_rust::xxh3::xxh3_64;
 
 use crate::{
     analysis::{
+        boundaries::{suggest_splits, write_split_suggestions},
         cfa::{AnalyzerState, SectionAddress},
+        classes::{detect_classes, write_class_report, ClassInfo},
         objects::{detect_objects, detect_strings},
         pass::{
             AnalysisPass, FindRelCtorsDtors, FindRelRodataData, FindSaveRestSleds,
             FindTRKInterruptVectorTable,
         },
-        signatures::{apply_signatures, apply_signatures_post, update_ctors_dtors},
+        port::{port_symbols, PortReport},
+        references::{collect_references, symbol_unit, ReferenceGraph, ReferenceKind, SymbolRef},
+        shiftcheck::{check_shiftable, write_shift_report},
+        signatures::{
+            apply_signatures, apply_signatures_post, update_ctors_dtors, SignatureDb,
+            SignatureSetInfo,
+        },
         tracker::Tracker,
+        versions::{detect_versions, LibraryVersions},
     },
     cmd::shasum::file_sha1_string,
     obj::{
@@ -38,6 +48,7 @@ use crate::{
     util::{
         asm::write_asm,
         bin2c::{bin2c, HeaderKind},
+        c_asm::write_c_asm,
         comment::MWComment,
         config::{
             apply_splits_file, apply_symbols_file, is_auto_symbol, signed_hex_serde,
@@ -46,20 +57,31 @@ use crate::{
         dep::DepFile,
         diff::{calc_diff_ranges, print_diff, process_code},
         dol::process_dol,
-        elf::{process_elf, write_elf},
+        elf::{process_elf, process_elf_lines, write_elf},
         file::{
             buf_copy_with_hash, buf_writer, check_hash_str, touch, verify_hash, FileIterator,
             FileReadInfo,
         },
-        lcf::{asm_path_for_unit, generate_ldscript, obj_path_for_unit},
+        lcf::{asm_path_for_unit, c_asm_path_for_unit, generate_ldscript, obj_path_for_unit},
         map::apply_map_file,
+        ncompress::{
+            compress_yay0_with, compress_yaz0_with, decompress_yay0, decompress_yaz0,
+            CompressionAlgo,
+        },
         path::{check_path_buf, native_path},
-        rel::{process_rel, process_rel_header, update_rel_section_alignment},
-        rso::{process_rso, DOL_SECTION_ABS, DOL_SECTION_ETI, DOL_SECTION_NAMES},
+        rel::{process_rel, process_rel_header, update_rel_section_alignment, RelHeader, RelReloc},
+        rso::{
+            process_rso_module, symbol_hash, update_rso_section_alignment, RsoHeader, RsoLinkInfo,
+            DOL_SECTION_ABS, DOL_SECTION_ETI, DOL_SECTION_NAMES,
+        },
+        signatures::read_signatures,
         split::{is_linker_generated_object, split_obj, update_splits},
         IntoCow, ToCow,
     },
-    vfs::{detect, open_file, open_file_with_fs, open_fs, ArchiveKind, FileFormat, Vfs, VfsFile},
+    vfs::{
+        decompress_file, detect, open_file, open_file_with_fs, open_fs, open_path, ArchiveKind,
+        FileFormat, OpenResult, Vfs, VfsFile,
+    },
 };
 
 #[derive(FromArgs, PartialEq, Debug)]
@@ -78,6 +100,10 @@ enum SubCommand {
     Diff(DiffArgs),
     Apply(ApplyArgs),
     Config(ConfigArgs),
+    Port(PortArgs),
+    Xrefs(XrefsArgs),
+    Callgraph(CallgraphArgs),
+    Shiftcheck(ShiftcheckArgs),
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -90,6 +116,9 @@ pub struct InfoArgs {
     #[argp(option, short = 's', from_str_fn(native_path))]
     /// optional path to selfile.sel
     pub selfile: Option<Utf8NativePathBuf>,
+    #[argp(option, short = 'c', from_str_fn(native_path))]
+    /// optional project configuration file, to use its signatures
+    pub config: Option<Utf8NativePathBuf>,
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -108,6 +137,9 @@ pub struct SplitArgs {
     #[argp(option, short = 'j')]
     /// number of threads to use (default: number of logical CPUs)
     jobs: Option<usize>,
+    #[argp(switch)]
+    /// write suggested unit boundaries to suggested_splits.txt
+    suggest_splits: bool,
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -138,7 +170,7 @@ pub struct ApplyArgs {
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
-/// Generates a project configuration file from a DOL (& RELs).
+/// Generates a project configuration file from a DOL (& RELs) or a disc image.
 #[argp(subcommand, name = "config")]
 pub struct ConfigArgs {
     #[argp(positional, from_str_fn(native_path))]
@@ -147,6 +179,72 @@ pub struct ConfigArgs {
     #[argp(option, short = 'o', from_str_fn(native_path))]
     /// output config YAML file
     out_file: Utf8NativePathBuf,
+    #[argp(option, from_str_fn(native_path))]
+    /// disc image to find main.dol and RELs/RSOs in (instead of object files)
+    disc: Option<Utf8NativePathBuf>,
+    #[argp(switch)]
+    /// (disc) use .map files shipped on the disc
+    maps: bool,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Ports symbols and splits from one version of a game to another.
+#[argp(subcommand, name = "port")]
+pub struct PortArgs {
+    #[argp(positional, from_str_fn(native_path))]
+    /// source configuration file (fully symbolized)
+    source: Utf8NativePathBuf,
+    #[argp(positional, from_str_fn(native_path))]
+    /// target configuration file
+    target: Utf8NativePathBuf,
+    #[argp(option, short = 'r', from_str_fn(native_path))]
+    /// output report YAML file
+    report: Option<Utf8NativePathBuf>,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Lists references to and from a symbol.
+#[argp(subcommand, name = "xrefs")]
+pub struct XrefsArgs {
+    #[argp(positional, from_str_fn(native_path))]
+    /// input configuration file
+    config: Utf8NativePathBuf,
+    #[argp(positional)]
+    /// symbol name (or demangled name)
+    symbol: String,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Writes the call graph of a project.
+#[argp(subcommand, name = "callgraph")]
+pub struct CallgraphArgs {
+    #[argp(positional, from_str_fn(native_path))]
+    /// input configuration file
+    config: Utf8NativePathBuf,
+    #[argp(option, short = 'o', from_str_fn(native_path))]
+    /// output file
+    out_file: Utf8NativePathBuf,
+    #[argp(option, short = 'f')]
+    /// output format: dot or json (default: based on output file extension)
+    format: Option<String>,
+    #[argp(switch, short = 'u')]
+    /// group symbols by unit, showing dependencies between units
+    units: bool,
+    #[argp(switch, short = 'd')]
+    /// include data references
+    data: bool,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Checks that every pointer is covered by a relocation, so the DOL and modules can be shifted.
+#[argp(subcommand, name = "shiftcheck")]
+pub struct ShiftcheckArgs {
+    #[argp(positional, from_str_fn(native_path))]
+    /// input configuration file
+    config: Utf8NativePathBuf,
+    #[argp(option, short = 'o', from_str_fn(native_path))]
+    /// output file (default: stdout)
+    out_file: Option<Utf8NativePathBuf>,
 }
 
 #[inline]
@@ -218,8 +316,16 @@ pub struct ProjectConfig {
     pub detect_objects: bool,
     #[serde(default = "bool_true", skip_serializing_if = "is_true")]
     pub detect_strings: bool,
+    /// Reconstructs C++ classes from vtables, RTTI and exception tables, naming unnamed vtables,
+    /// RTTI objects, destructors and virtual functions. A report is written to `classes.txt`.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub detect_classes: bool,
     #[serde(default = "bool_true", skip_serializing_if = "is_true")]
     pub write_asm: bool,
+    /// Writes a C file for each unit with MWCC inline assembly functions and initialized
+    /// data, which can be built with the original compiler.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub write_c_asm: bool,
     /// Specifies the start of the common BSS section.
     #[serde(skip_serializing_if = "is_default")]
     pub common_start: Option<u32>,
@@ -240,6 +346,12 @@ pub struct ProjectConfig {
     /// will be used from the disc image directly without extraction.
     #[serde(default = "bool_true", skip_serializing_if = "is_true")]
     pub extract_objects: bool,
+    /// Additional signature databases to check during analysis.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signatures: Vec<SignatureConfig>,
+    /// Built-in signatures to disable, by symbol name.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disable_signatures: Vec<String>,
 }
 
 impl Default for ProjectConfig {
@@ -253,13 +365,17 @@ impl Default for ProjectConfig {
             modules: vec![],
             detect_objects: true,
             detect_strings: true,
+            detect_classes: false,
             write_asm: true,
+            write_c_asm: false,
             common_start: None,
             symbols_known: false,
             fill_gaps: true,
             export_all: true,
             object_base: None,
             extract_objects: true,
+            signatures: vec![],
+            disable_signatures: vec![],
         }
     }
 }
@@ -279,6 +395,10 @@ pub struct ModuleConfig {
     pub symbols: Option<Utf8UnixPathBuf>,
     #[serde(with = "unix_path_serde_option", default, skip_serializing_if = "is_default")]
     pub map: Option<Utf8UnixPathBuf>,
+    /// A debug ELF with DWARF 1.1 line tables, used to annotate the disassembly with original
+    /// source line numbers.
+    #[serde(with = "unix_path_serde_option", default, skip_serializing_if = "is_default")]
+    pub debug_elf: Option<Utf8UnixPathBuf>,
     /// Forces the given symbols to be active (exported) in the linker script.
     #[serde(default, skip_serializing_if = "is_default")]
     pub force_active: Vec<String>,
@@ -315,6 +435,16 @@ pub struct ExtractConfig {
     /// field can be used by external asset processing.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub header_type: Option<String>,
+    /// If the symbol's data is compressed (`yaz0` or `yay0`), the `binary` file is written
+    /// decompressed. The header still contains the original data.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<String>,
+    /// The encoder used to recompress the data: `nintendo`, `fast` or `best`.
+    /// If set, the data is recompressed during split and a warning is emitted if it does not
+    /// reproduce the original data. If unset, nothing is verified and the output config has no
+    /// encoder for this symbol.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression_algo: Option<String>,
     /// A user-defined type for use with external asset processing. This value is simply passed
     /// through to the `custom_type` field in the output config.
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -325,6 +455,29 @@ pub struct ExtractConfig {
     pub custom_data: Option<serde_json::Value>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SignatureConfig {
+    /// Path to a signature file (as written by `elf sigs`), or a directory of signature files.
+    #[serde(with = "unix_path_serde")]
+    pub path: Utf8UnixPathBuf,
+    /// Signatures with a higher priority are checked first.
+    /// Built-in signatures have a priority of 0.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub priority: i32,
+    /// The library these signatures were built from, e.g. `MSL_C`.
+    /// If multiple signature sets are specified for the same library, only the one that
+    /// matches best is used after initial analysis, and its version is reported in the
+    /// output config.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub library: Option<String>,
+    /// The library version, e.g. `GC/2.7`. Required for version detection.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub version: Option<String>,
+    /// Used as `mw_comment_version` if it's not specified and this set matches best.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub mw_comment_version: Option<u8>,
+}
+
 /// A relocation that should be blocked.
 /// Only one of `source` or `target` should be specified.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -364,6 +517,11 @@ impl ModuleConfig {
     }
 
     pub fn name(&self) -> &str { self.name.as_deref().unwrap_or_else(|| self.file_prefix()) }
+
+    /// Whether the module is an RSO (as opposed to a REL), based on its file extension.
+    pub fn is_rso(&self) -> bool {
+        self.object.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("rso"))
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -396,6 +554,8 @@ pub struct OutputExtract {
     #[serde(with = "unix_path_serde_option")]
     pub header: Option<Utf8UnixPathBuf>,
     pub header_type: String,
+    pub compression: Option<String>,
+    pub compression_algo: Option<String>,
     pub custom_type: Option<String>,
     pub custom_data: Option<serde_json::Value>,
 }
@@ -412,6 +572,9 @@ pub struct OutputConfig {
     pub base: OutputModule,
     pub modules: Vec<OutputModule>,
     pub links: Vec<OutputLink>,
+    /// Library versions detected in the DOL.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub versions: LibraryVersions,
 }
 
 pub fn run(args: Args) -> Result<()> {
@@ -421,11 +584,39 @@ pub fn run(args: Args) -> Result<()> {
         SubCommand::Diff(c_args) => diff(c_args),
         SubCommand::Apply(c_args) => apply(c_args),
         SubCommand::Config(c_args) => config(c_args),
+        SubCommand::Port(c_args) => port(c_args),
+        SubCommand::Xrefs(c_args) => xrefs(c_args),
+        SubCommand::Callgraph(c_args) => callgraph(c_args),
+        SubCommand::Shiftcheck(c_args) => shiftcheck(c_args),
     }
 }
 
-fn apply_selfile(obj: &mut ObjInfo, buf: &[u8]) -> Result<()> {
-    let rso = process_rso(&mut Cursor::new(buf))?;
+/// Resolves a selfile section index and offset to a DOL section and absolute address.
+pub fn resolve_selfile_address(
+    obj: &ObjInfo,
+    dol_section_index: u32,
+    offset: u32,
+) -> Result<(Option<SectionIndex>, u32)> {
+    if dol_section_index == DOL_SECTION_ABS {
+        return Ok((None, offset));
+    }
+    let dol_section_name = if dol_section_index == DOL_SECTION_ETI {
+        "extabindex"
+    } else {
+        DOL_SECTION_NAMES.get(dol_section_index as usize).and_then(|&opt| opt).ok_or_else(|| {
+            anyhow!("Can't add symbol for unknown DOL section {}", dol_section_index)
+        })?
+    };
+    let (dol_section_index, dol_section) = obj
+        .sections
+        .iter()
+        .find(|&(_, section)| section.name == dol_section_name)
+        .ok_or_else(|| anyhow!("Failed to locate DOL section {}", dol_section_name))?;
+    Ok((Some(dol_section_index), dol_section.address as u32 + offset))
+}
+
+pub fn apply_selfile(obj: &mut ObjInfo, buf: &[u8]) -> Result<RsoLinkInfo> {
+    let (_, rso, link_info) = process_rso_module(&mut Cursor::new(buf), "", 0)?;
     for (_, symbol) in rso.symbols.iter() {
         let dol_section_index = match symbol.section {
             Some(section) => section,
@@ -435,29 +626,9 @@ fn apply_selfile(obj: &mut ObjInfo, buf: &[u8]) -> Result<()> {
                 symbol.address
             ),
         };
-        let (section, address, section_kind) = if dol_section_index
-            == DOL_SECTION_ABS as SectionIndex
-        {
-            (None, symbol.address as u32, None)
-        } else {
-            let dol_section_name = if dol_section_index == DOL_SECTION_ETI as SectionIndex {
-                "extabindex"
-            } else {
-                DOL_SECTION_NAMES.get(dol_section_index as usize).and_then(|&opt| opt).ok_or_else(
-                    || anyhow!("Can't add symbol for unknown DOL section {}", dol_section_index),
-                )?
-            };
-            let (dol_section_index, dol_section) = obj
-                .sections
-                .iter()
-                .find(|&(_, section)| section.name == dol_section_name)
-                .ok_or_else(|| anyhow!("Failed to locate DOL section {}", dol_section_name))?;
-            (
-                Some(dol_section_index),
-                dol_section.address as u32 + symbol.address as u32,
-                Some(dol_section.kind),
-            )
-        };
+        let (section, address) =
+            resolve_selfile_address(obj, dol_section_index, symbol.address as u32)?;
+        let section_kind = section.map(|section| obj.sections[section].kind);
 
         let symbol_kind = match section_kind {
             Some(ObjSectionKind::Code) => ObjSymbolKind::Function,
@@ -506,7 +677,7 @@ fn apply_selfile(obj: &mut ObjInfo, buf: &[u8]) -> Result<()> {
             )?;
         }
     }
-    Ok(())
+    Ok(link_info)
 }
 
 pub fn info(args: InfoArgs) -> Result<()> {
@@ -514,7 +685,17 @@ pub fn info(args: InfoArgs) -> Result<()> {
         let mut file = open_file(&args.dol_file, true)?;
         process_dol(file.map()?, "")?
     };
-    apply_signatures(&mut obj)?;
+    let signatures = match &args.config {
+        Some(config_path) => {
+            let config: ProjectConfig = {
+                let mut config_file = open_file(config_path, true)?;
+                serde_yaml::from_reader(config_file.as_mut())?
+            };
+            load_signature_db(&config)?.0
+        }
+        None => SignatureDb::default(),
+    };
+    apply_signatures(&mut obj, &signatures)?;
 
     let mut state = AnalyzerState::default();
     FindSaveRestSleds::execute(&mut state, &obj)?;
@@ -527,7 +708,8 @@ pub fn info(args: InfoArgs) -> Result<()> {
     FindTRKInterruptVectorTable::execute(&mut state, &obj)?;
     state.apply(&mut obj)?;
 
-    apply_signatures_post(&mut obj)?;
+    let versions = detect_versions(&obj, &signatures)?;
+    apply_signatures_post(&mut obj, &select_signatures(&signatures, &versions))?;
 
     if let Some(selfile) = &args.selfile {
         let mut file = open_file(selfile, true)?;
@@ -538,6 +720,19 @@ pub fn info(args: InfoArgs) -> Result<()> {
     if let Some(entry) = obj.entry {
         println!("Entry point: {:#010X}", entry);
     }
+    if let Some(sdk) = &versions.sdk {
+        print!("{} build: {}", sdk.platform, sdk.build);
+        if let Some(version) = &sdk.version {
+            print!(" ({})", version);
+        }
+        println!("{}", if sdk.debug { " [debug]" } else { "" });
+        for (library, build) in &sdk.libraries {
+            println!("\t{: >10} | {}", library, build);
+        }
+    }
+    for (library, version) in &versions.libraries {
+        println!("{}: {}", library, version);
+    }
     println!("\nSections:");
     println!("\t{: >10} | {: <10} | {: <10} | {: <10}", "Name", "Address", "Size", "File Off");
     for (_, section) in obj.sections.iter() {
@@ -578,6 +773,8 @@ struct ModuleInfo<'a> {
     config: &'a ModuleConfig,
     symbols_cache: Option<FileReadInfo>,
     splits_cache: Option<FileReadInfo>,
+    /// RSO import and export tables (or the selfile exports, for the DOL)
+    rso: Option<RsoLinkInfo>,
 }
 
 type ModuleMapByName<'a> = BTreeMap<String, ModuleInfo<'a>>;
@@ -815,9 +1012,28 @@ struct AnalyzeResult {
     dep: Vec<Utf8NativePathBuf>,
     symbols_cache: Option<FileReadInfo>,
     splits_cache: Option<FileReadInfo>,
+    rso: Option<RsoLinkInfo>,
+    versions: LibraryVersions,
 }
 
-fn load_analyze_dol(config: &ProjectConfig, object_base: &ObjectBase) -> Result<AnalyzeResult> {
+/// Only keeps the best-matching signature set for each detected library.
+fn select_signatures<'a>(
+    signatures: &'a SignatureDb,
+    versions: &LibraryVersions,
+) -> Cow<'a, SignatureDb> {
+    if versions.libraries.is_empty() || !signatures.has_library_sets() {
+        return Cow::Borrowed(signatures);
+    }
+    let mut signatures = signatures.clone();
+    signatures.select_library_versions(&versions.libraries);
+    Cow::Owned(signatures)
+}
+
+fn load_analyze_dol(
+    config: &ProjectConfig,
+    object_base: &ObjectBase,
+    signatures: &SignatureDb,
+) -> Result<AnalyzeResult> {
     let object_path = object_base.join(&config.base.object);
     log::debug!("Loading {}", object_path);
     let mut obj = {
@@ -839,6 +1055,9 @@ fn load_analyze_dol(config: &ProjectConfig, object_base: &ObjectBase) -> Result<
         apply_map_file(&map_path, &mut obj, config.common_start, config.mw_comment_version)?;
         dep.push(map_path);
     }
+    if let Some(debug_elf) = &config.base.debug_elf {
+        dep.push(debug_elf.with_encoding());
+    }
 
     let splits_cache = if let Some(splits_path) = &config.base.splits {
         let splits_path = splits_path.with_encoding();
@@ -864,7 +1083,7 @@ fn load_analyze_dol(config: &ProjectConfig, object_base: &ObjectBase) -> Result<
     if !config.symbols_known {
         // TODO move before symbols?
         debug!("Performing signature analysis");
-        apply_signatures(&mut obj)?;
+        apply_signatures(&mut obj, signatures)?;
 
         if !config.quick_analysis {
             let mut state = AnalyzerState::default();
@@ -874,10 +1093,27 @@ fn load_analyze_dol(config: &ProjectConfig, object_base: &ObjectBase) -> Result<
             FindTRKInterruptVectorTable::execute(&mut state, &obj)?;
             state.apply(&mut obj)?;
         }
+    }
+
+    let versions = detect_versions(&obj, signatures)?;
+    if let Some(sdk) = &versions.sdk {
+        info!("Detected {} build {}", sdk.platform, sdk.build);
+    }
+    for (library, version) in &versions.libraries {
+        info!("Detected {} {}", library, version);
+    }
+    if obj.mw_comment.is_none() {
+        if let Some(comment_version) = versions.mw_comment_version {
+            info!("Using detected mw_comment_version {}", comment_version);
+            obj.mw_comment = Some(MWComment::new(comment_version)?);
+        }
+    }
 
-        apply_signatures_post(&mut obj)?;
+    if !config.symbols_known {
+        apply_signatures_post(&mut obj, &select_signatures(signatures, &versions))?;
     }
 
+    let mut rso = None;
     if let Some(selfile) = &config.selfile {
         let selfile_path = object_base.join(selfile);
         log::info!("Loading {}", selfile_path);
@@ -886,7 +1122,7 @@ fn load_analyze_dol(config: &ProjectConfig, object_base: &ObjectBase) -> Result<
         if let Some(hash) = &config.selfile_hash {
             verify_hash(data, hash)?;
         }
-        apply_selfile(&mut obj, data)?;
+        rso = Some(apply_selfile(&mut obj, data)?);
         dep.push(selfile_path);
     }
 
@@ -896,40 +1132,91 @@ fn load_analyze_dol(config: &ProjectConfig, object_base: &ObjectBase) -> Result<
     // Apply additional relocations from config
     apply_add_relocations(&mut obj, &config.base.add_relocations)?;
 
-    Ok(AnalyzeResult { obj, dep, symbols_cache, splits_cache })
+    Ok(AnalyzeResult { obj, dep, symbols_cache, splits_cache, rso, versions })
 }
 
-fn split_write_obj(
-    module: &mut ModuleInfo,
-    config: &ProjectConfig,
-    base_dir: &Utf8NativePath,
-    out_dir: &Utf8NativePath,
-    no_update: bool,
-) -> Result<OutputModule> {
+/// Rebuilds relocations, detects objects, strings and classes, and adjusts splits.
+fn analyze_relocations(obj: &mut ObjInfo, config: &ProjectConfig) -> Result<Vec<ClassInfo>> {
     debug!("Performing relocation analysis");
-    let mut tracker = Tracker::new(&module.obj);
-    tracker.process(&module.obj)?;
+    let mut tracker = Tracker::new(obj);
+    tracker.process(obj)?;
 
     debug!("Applying relocations");
-    tracker.apply(&mut module.obj, false)?;
+    tracker.apply(obj, false)?;
 
     if !config.symbols_known && config.detect_objects {
         debug!("Detecting object boundaries");
-        detect_objects(&mut module.obj)?;
+        detect_objects(obj)?;
     }
 
     if config.detect_strings {
         debug!("Detecting strings");
-        detect_strings(&mut module.obj)?;
+        detect_strings(obj)?;
     }
 
+    let classes = if config.detect_classes {
+        debug!("Detecting classes");
+        detect_classes(obj, !config.symbols_known)?
+    } else {
+        vec![]
+    };
+
     debug!("Adjusting splits");
+    let common_start = if obj.module_id == 0 { config.common_start } else { None };
+    update_splits(obj, common_start, config.fill_gaps)?;
+    Ok(classes)
+}
+
+/// Decompresses extracted data. If an encoder is given, checks that recompressing it
+/// reproduces the original.
+fn decompress_extract(
+    data: &[u8],
+    compression: &str,
+    verify_algo: Option<CompressionAlgo>,
+    symbol_name: &str,
+) -> Result<Box<[u8]>> {
+    let (decompressed, compress): (_, fn(&[u8], CompressionAlgo) -> Box<[u8]>) = match compression {
+        "yaz0" => (
+            decompress_yaz0(data)
+                .with_context(|| format!("Failed to decompress '{}' using Yaz0", symbol_name))?,
+            compress_yaz0_with,
+        ),
+        "yay0" => (
+            decompress_yay0(data)
+                .with_context(|| format!("Failed to decompress '{}' using Yay0", symbol_name))?,
+            compress_yay0_with,
+        ),
+        _ => bail!("Invalid compression '{}'", compression),
+    };
+    let Some(algo) = verify_algo else {
+        return Ok(decompressed);
+    };
+    let recompressed = compress(&decompressed, algo);
+    // Symbols may be padded after the compressed data
+    let matches = data.len() >= recompressed.len()
+        && data[..recompressed.len()] == *recompressed
+        && data[recompressed.len()..].iter().all(|&b| b == 0);
+    if !matches {
+        log::warn!(
+            "Recompressing '{}' with {} ({}) does not reproduce the original data",
+            symbol_name,
+            compression,
+            algo
+        );
+    }
+    Ok(decompressed)
+}
+
+fn split_write_obj(
+    module: &mut ModuleInfo,
+    config: &ProjectConfig,
+    base_dir: &Utf8NativePath,
+    out_dir: &Utf8NativePath,
+    no_update: bool,
+    suggest: bool,
+) -> Result<OutputModule> {
+    let classes = analyze_relocations(&mut module.obj, config)?;
     let module_id = module.obj.module_id;
-    update_splits(
-        &mut module.obj,
-        if module_id == 0 { config.common_start } else { None },
-        config.fill_gaps,
-    )?;
 
     if !no_update {
         debug!("Writing configuration");
@@ -955,6 +1242,20 @@ fn split_write_obj(
         .recursive(true)
         .create(out_dir)
         .with_context(|| format!("Failed to create out dir '{}'", out_dir))?;
+    if suggest {
+        let suggestions = suggest_splits(&module.obj)?;
+        let suggestions_path = out_dir.join("suggested_splits.txt");
+        info!("Writing {} split suggestions to {}", suggestions.len(), suggestions_path);
+        let mut w = buf_writer(&suggestions_path)?;
+        write_split_suggestions(&mut w, &module.obj, &suggestions)?;
+        w.flush()?;
+    }
+    if config.detect_classes {
+        let classes_path = out_dir.join("classes.txt");
+        let mut w = buf_writer(&classes_path)?;
+        write_class_report(&mut w, &module.obj, &classes)?;
+        w.flush()?;
+    }
     let obj_dir = out_dir.join("obj");
     let entry = if module.obj.kind == ObjKind::Executable {
         module.obj.entry.and_then(|e| {
@@ -1012,12 +1313,27 @@ fn split_write_obj(
         let section = &module.obj.sections[section_index];
         let data = section.symbol_data(symbol)?;
 
+        // Recompressing is slow, so it's only verified when an encoder is set explicitly
+        let verify_algo = match extract.compression_algo.as_deref() {
+            Some(value) => match CompressionAlgo::from_str(value) {
+                Ok(algo) => Some(algo),
+                Err(()) => bail!("Invalid compression algorithm '{}'", value),
+            },
+            None => None,
+        };
         if let Some(binary) = &extract.binary {
             let out_path = base_dir.join("bin").join(binary.with_encoding());
             if let Some(parent) = out_path.parent() {
                 DirBuilder::new().recursive(true).create(parent)?;
             }
-            write_if_changed(&out_path, data)?;
+            match extract.compression.as_deref() {
+                Some(compression) => {
+                    let decompressed =
+                        decompress_extract(data, compression, verify_algo, &symbol.name)?;
+                    write_if_changed(&out_path, &decompressed)?;
+                }
+                None => write_if_changed(&out_path, data)?,
+            }
         }
 
         let header_kind = match extract.header_type.as_deref() {
@@ -1047,6 +1363,12 @@ fn split_write_obj(
             binary: extract.binary.clone(),
             header: extract.header.clone(),
             header_type: header_kind.to_string(),
+            compression: extract.compression.clone(),
+            compression_algo: extract
+                .compression
+                .as_ref()
+                .and(verify_algo)
+                .map(|algo| algo.to_string()),
             custom_type: extract.custom_type.clone(),
             custom_data: extract.custom_data.clone(),
         });
@@ -1068,12 +1390,31 @@ fn split_write_obj(
 
     if config.write_asm {
         debug!("Writing disassembly");
+        let lines = match &module.config.debug_elf {
+            Some(path) => process_elf_lines(&path.with_encoding()).unwrap_or_else(|e| {
+                log::warn!("{e:#}, continuing without line numbers");
+                None
+            }),
+            None => None,
+        };
         let asm_dir = out_dir.join("asm");
         for (unit, split_obj) in module.obj.link_order.iter().zip(&split_objs) {
             let out_path = asm_dir.join(asm_path_for_unit(&unit.name));
 
             let mut w = buf_writer(&out_path)?;
-            write_asm(&mut w, split_obj)
+            write_asm(&mut w, split_obj, lines.as_ref())
+                .with_context(|| format!("Failed to write {}", out_path))?;
+            w.flush()?;
+        }
+    }
+    if config.write_c_asm {
+        debug!("Writing inline assembly");
+        let c_asm_dir = out_dir.join("c_asm");
+        for (unit, split_obj) in module.obj.link_order.iter().zip(&split_objs) {
+            let out_path = c_asm_dir.join(c_asm_path_for_unit(&unit.name));
+
+            let mut w = buf_writer(&out_path)?;
+            write_c_asm(&mut w, split_obj)
                 .with_context(|| format!("Failed to write {}", out_path))?;
             w.flush()?;
         }
@@ -1095,10 +1436,19 @@ fn write_if_changed(path: &Utf8NativePath, contents: &[u8]) -> Result<()> {
     Ok(())
 }
 
+enum ModuleHeader {
+    Rel(RelHeader),
+    Rso(RsoHeader),
+}
+
+/// Loads and analyzes a REL or RSO module.
+/// RSO modules don't have a module ID, so one must be assigned by the caller.
 fn load_analyze_rel(
     config: &ProjectConfig,
     object_base: &ObjectBase,
     module_config: &ModuleConfig,
+    rso_module_id: Option<u32>,
+    signatures: &SignatureDb,
 ) -> Result<AnalyzeResult> {
     let object_path = object_base.join(&module_config.object);
     debug!("Loading {}", object_path);
@@ -1107,7 +1457,14 @@ fn load_analyze_rel(
     if let Some(hash_str) = &module_config.hash {
         verify_hash(data, hash_str)?;
     }
-    let (header, mut module_obj) = process_rel(&mut Cursor::new(data), module_config.name())?;
+    let (header, mut module_obj, rso) = if let Some(module_id) = rso_module_id {
+        let (header, obj, link_info) =
+            process_rso_module(&mut Cursor::new(data), module_config.name(), module_id)?;
+        (ModuleHeader::Rso(header), obj, Some(link_info))
+    } else {
+        let (header, obj) = process_rel(&mut Cursor::new(data), module_config.name())?;
+        (ModuleHeader::Rel(header), obj, None)
+    };
 
     if let Some(comment_version) = config.mw_comment_version {
         module_obj.mw_comment = Some(MWComment::new(comment_version)?);
@@ -1119,6 +1476,9 @@ fn load_analyze_rel(
         apply_map_file(&map_path, &mut module_obj, None, None)?;
         dep.push(map_path);
     }
+    if let Some(debug_elf) = &module_config.debug_elf {
+        dep.push(debug_elf.with_encoding());
+    }
 
     let splits_cache = if let Some(splits_path) = &module_config.splits {
         let splits_path = splits_path.with_encoding();
@@ -1151,44 +1511,113 @@ fn load_analyze_rel(
             FindRelRodataData::execute(&mut state, &module_obj)?;
             state.apply(&mut module_obj)?;
         }
-        apply_signatures(&mut module_obj)?;
-        apply_signatures_post(&mut module_obj)?;
+        apply_signatures(&mut module_obj, signatures)?;
+        apply_signatures_post(&mut module_obj, signatures)?;
     }
 
     // Create _ctors and _dtors symbols if missing
     update_ctors_dtors(&mut module_obj)?;
 
-    // Determine REL section alignment
-    update_rel_section_alignment(&mut module_obj, &header)?;
+    // Determine module section alignment
+    match &header {
+        ModuleHeader::Rel(header) => update_rel_section_alignment(&mut module_obj, header)?,
+        ModuleHeader::Rso(header) => update_rso_section_alignment(&mut module_obj, header)?,
+    }
 
     // Apply additional relocations from config
     apply_add_relocations(&mut module_obj, &module_config.add_relocations)?;
 
-    Ok(AnalyzeResult { obj: module_obj, dep, symbols_cache, splits_cache })
+    Ok(AnalyzeResult {
+        obj: module_obj,
+        dep,
+        symbols_cache,
+        splits_cache,
+        rso,
+        versions: Default::default(),
+    })
 }
 
-fn split(args: SplitArgs) -> Result<()> {
-    if let Some(jobs) = args.jobs {
-        rayon::ThreadPoolBuilder::new().num_threads(jobs).build_global()?;
+/// Resolves RSO imports by name against the selfile and RSO export tables,
+/// adding them to the unresolved relocations of each module.
+fn resolve_rso_imports(dol: &ModuleInfo<'_>, modules: &mut ModuleMapByName<'_>) -> Result<()> {
+    struct ExportTarget {
+        name: String,
+        module_id: u32,
+        section: u8,
+        address: u32,
     }
 
-    let command_start = Instant::now();
-    info!("Loading {}", args.config);
-    let mut config: ProjectConfig = {
-        let mut config_file = open_file(&args.config, true)?;
-        serde_yaml::from_reader(config_file.as_mut())?
-    };
+    let mut exports = HashMap::<u32, Vec<ExportTarget>>::new();
+    if let Some(rso) = &dol.rso {
+        for export in &rso.exports {
+            let (Some(section_index), address) =
+                resolve_selfile_address(&dol.obj, export.section, export.offset)?
+            else {
+                log::debug!("Skipping absolute selfile export {}", export.name);
+                continue;
+            };
+            exports.entry(export.hash).or_default().push(ExportTarget {
+                name: export.name.clone(),
+                module_id: 0,
+                section: dol.obj.sections[section_index].elf_index as u8,
+                address,
+            });
+        }
+    }
+    for module in modules.values() {
+        let Some(rso) = &module.rso else { continue };
+        for export in &rso.exports {
+            if export.section == DOL_SECTION_ABS {
+                log::debug!("Skipping absolute export {} in {}", export.name, module.obj.name);
+                continue;
+            }
+            exports.entry(export.hash).or_default().push(ExportTarget {
+                name: export.name.clone(),
+                module_id: module.obj.module_id,
+                section: export.section as u8,
+                address: export.offset,
+            });
+        }
+    }
 
-    let mut object_base = find_object_base(&config)?;
-    if config.extract_objects && matches!(object_base, ObjectBase::Vfs(..)) {
-        // Extract files from the VFS into the object base directory
-        let target_dir = extract_objects(&config, &object_base)?;
-        object_base = ObjectBase::Directory(target_dir);
+    for module in modules.values_mut() {
+        let Some(rso) = &module.rso else { continue };
+        for import in &rso.imports {
+            let Some(target) = exports
+                .get(&symbol_hash(&import.symbol))
+                .and_then(|targets| targets.iter().find(|t| t.name == import.symbol))
+            else {
+                log::warn!("Unresolved import {} in module {}", import.symbol, module.obj.name);
+                continue;
+            };
+            module.obj.unresolved_relocations.push(RelReloc {
+                kind: import.kind,
+                section: import.section,
+                address: import.address,
+                module_id: target.module_id,
+                target_section: target.section,
+                addend: target.address + import.addend,
+                original_section: import.section,
+                original_target_section: target.section,
+            });
+        }
     }
+    Ok(())
+}
 
+/// Verifies (or computes) module hashes, and assigns module IDs to RSO modules.
+fn assign_module_ids(
+    config: &mut ProjectConfig,
+    object_base: &ObjectBase,
+) -> Result<Vec<Option<u32>>> {
+    let mut max_module_id = 0;
     for module_config in config.modules.iter_mut() {
         let mut file = object_base.open(&module_config.object)?;
         let mut data = file.map()?;
+        if !module_config.is_rso() {
+            let header = process_rel_header(&mut Cursor::new(data))?;
+            max_module_id = max_module_id.max(header.module_id);
+        }
         if let Some(hash_str) = &module_config.hash {
             verify_hash(data, hash_str)?;
         } else {
@@ -1196,9 +1625,36 @@ fn split(args: SplitArgs) -> Result<()> {
         }
     }
 
-    let out_config_path = args.out_dir.join("config.json");
-    let mut dep = DepFile::new(out_config_path.clone());
+    // RSO modules don't have module IDs, so assign them IDs after the last REL
+    let mut next_module_id = max_module_id + 1;
+    let rso_module_ids = config
+        .modules
+        .iter()
+        .map(|module_config| {
+            module_config.is_rso().then(|| {
+                next_module_id += 1;
+                next_module_id - 1
+            })
+        })
+        .collect_vec();
+    Ok(rso_module_ids)
+}
+
+struct LoadedProject<'a> {
+    dol: ModuleInfo<'a>,
+    modules: ModuleMapByName<'a>,
+    versions: LibraryVersions,
+    dep: Vec<Utf8NativePathBuf>,
+}
 
+/// Loads and analyzes the DOL and all modules, then resolves the relocations between them.
+fn load_analyze_project<'a>(
+    config: &'a ProjectConfig,
+    object_base: &ObjectBase,
+    signatures: &SignatureDb,
+    rso_module_ids: &[Option<u32>],
+) -> Result<LoadedProject<'a>> {
+    let mut dep = vec![];
     let module_count = config.modules.len() + 1;
     let num_threads = min(rayon::current_num_threads(), module_count);
     info!(
@@ -1208,59 +1664,66 @@ fn split(args: SplitArgs) -> Result<()> {
         num_threads,
         if num_threads == 1 { "" } else { "s" }
     );
-    let mut dol_result: Option<Result<AnalyzeResult>> = None;
-    let mut modules_result: Option<Result<Vec<AnalyzeResult>>> = None;
-    let start = Instant::now();
-    rayon::scope(|s| {
-        // DOL
-        s.spawn(|_| {
-            let _span = info_span!("module", name = %config.base.name()).entered();
-            dol_result =
-                Some(load_analyze_dol(&config, &object_base).with_context(|| {
-                    format!("While loading object '{}'", config.base.file_name())
-                }));
-        });
-        // Modules
-        s.spawn(|_| {
-            modules_result = Some(
-                config
-                    .modules
-                    .par_iter()
-                    .map(|module_config| {
-                        let _span = info_span!("module", name = %module_config.name()).entered();
-                        load_analyze_rel(&config, &object_base, module_config).with_context(|| {
-                            format!("While loading object '{}'", module_config.file_name())
-                        })
+    let analyze_modules = |signatures: &SignatureDb| -> Result<Vec<AnalyzeResult>> {
+        config
+            .modules
+            .par_iter()
+            .zip(rso_module_ids.par_iter())
+            .map(|(module_config, &rso_module_id)| {
+                let _span = info_span!("module", name = %module_config.name()).entered();
+                load_analyze_rel(config, object_base, module_config, rso_module_id, signatures)
+                    .with_context(|| {
+                        format!("While loading object '{}'", module_config.file_name())
                     })
-                    .collect(),
-            );
-        });
-    });
+            })
+            .collect()
+    };
+    let analyze_dol = || {
+        let _span = info_span!("module", name = %config.base.name()).entered();
+        load_analyze_dol(config, object_base, signatures)
+            .with_context(|| format!("While loading object '{}'", config.base.file_name()))
+    };
+    let start = Instant::now();
+    let (dol_result, modules_result) = if signatures.has_library_sets() {
+        // Modules use the signature sets selected by the library versions detected in the DOL
+        let dol_result = analyze_dol()?;
+        let modules_result = analyze_modules(&select_signatures(signatures, &dol_result.versions));
+        (dol_result, modules_result)
+    } else {
+        let (dol_result, modules_result) = rayon::join(analyze_dol, || analyze_modules(signatures));
+        (dol_result?, modules_result)
+    };
     let duration = start.elapsed();
-    let mut dol = {
-        let result = dol_result.unwrap()?;
-        dep.extend(result.dep);
-        ModuleInfo {
-            obj: result.obj,
-            config: &config.base,
-            symbols_cache: result.symbols_cache,
-            splits_cache: result.splits_cache,
-        }
+    dep.extend(dol_result.dep);
+    let versions = dol_result.versions;
+    let mut dol = ModuleInfo {
+        obj: dol_result.obj,
+        config: &config.base,
+        symbols_cache: dol_result.symbols_cache,
+        splits_cache: dol_result.splits_cache,
+        rso: dol_result.rso,
     };
     let mut function_count = dol.obj.symbols.by_kind(ObjSymbolKind::Function).count();
 
     let mut modules = ModuleMapByName::new();
-    for (idx, result) in modules_result.unwrap()?.into_iter().enumerate() {
+    for (idx, result) in modules_result?.into_iter().enumerate() {
         function_count += result.obj.symbols.by_kind(ObjSymbolKind::Function).count();
         dep.extend(result.dep);
-        match modules.entry(result.obj.name.clone()) {
+        let mut module_obj = result.obj;
+        if module_obj.mw_comment.is_none() {
+            if let Some(comment_version) = versions.mw_comment_version {
+                module_obj.mw_comment = Some(MWComment::new(comment_version)?);
+            }
+        }
+        match modules.entry(module_obj.name.clone()) {
             Entry::Vacant(e) => e.insert(ModuleInfo {
-                obj: result.obj,
+                obj: module_obj,
                 config: &config.modules[idx],
                 symbols_cache: result.symbols_cache,
                 splits_cache: result.splits_cache,
+                rso: result.rso,
             }),
-            Entry::Occupied(_) => bail!("Duplicate module name {}", result.obj.name),
+            Entry::Occupied(_) => bail!("Duplicate module name {}", module_obj.name),
         };
     }
     info!(
@@ -1270,49 +1733,12 @@ fn split(args: SplitArgs) -> Result<()> {
         function_count
     );
 
-    fn get_links<'a>(
-        module: &ModuleInfo<'_>,
-        modules: &'a ModuleMapByName<'a>,
-    ) -> Result<Vec<&'a ModuleInfo<'a>>> {
-        if let Some(links) = &module.config.links {
-            // Link to specified modules
-            links
-                .iter()
-                .map(|n| modules.get(n))
-                .collect::<Option<Vec<_>>>()
-                .with_context(|| format!("Failed to resolve links for module {}", module.obj.name))
-        } else {
-            // Link to all other modules
-            Ok(modules.values().collect())
-        }
-    }
-
-    fn get_links_map<'a>(
-        module: &ModuleInfo<'_>,
-        modules: &'a ModuleMapByName<'a>,
-    ) -> Result<ModuleMapById<'a>> {
-        let links = get_links(module, modules)?;
-        let mut map = ModuleMapById::new();
-        for link in links {
-            match map.entry(link.obj.module_id) {
-                Entry::Vacant(e) => {
-                    e.insert(link);
-                }
-                Entry::Occupied(_) => bail!(
-                    "Duplicate module ID {} in links for module {} (ID {}).\n\
-                    This likely means you need to specify the links manually.",
-                    link.obj.module_id,
-                    module.obj.name,
-                    module.obj.module_id
-                ),
-            }
-        }
-        Ok(map)
-    }
-
     if !modules.is_empty() {
         let module_names = modules.keys().cloned().collect_vec();
 
+        // Turn RSO imports into relocations against the exporting module
+        resolve_rso_imports(&dol, &mut modules)?;
+
         // Create any missing symbols (referenced from other modules) and set FORCEACTIVE
         update_symbols(&mut dol.obj, &modules.values().collect::<Vec<_>>(), !config.symbols_known)
             .with_context(|| format!("Updating symbols for module {}", dol.config.name()))?;
@@ -1345,6 +1771,80 @@ fn split(args: SplitArgs) -> Result<()> {
         }
     }
 
+    Ok(LoadedProject { dol, modules, versions, dep })
+}
+
+fn get_links<'a>(
+    module: &ModuleInfo<'_>,
+    modules: &'a ModuleMapByName<'a>,
+) -> Result<Vec<&'a ModuleInfo<'a>>> {
+    if let Some(links) = &module.config.links {
+        // Link to specified modules
+        links
+            .iter()
+            .map(|n| modules.get(n))
+            .collect::<Option<Vec<_>>>()
+            .with_context(|| format!("Failed to resolve links for module {}", module.obj.name))
+    } else {
+        // Link to all other modules
+        Ok(modules.values().collect())
+    }
+}
+
+fn get_links_map<'a>(
+    module: &ModuleInfo<'_>,
+    modules: &'a ModuleMapByName<'a>,
+) -> Result<ModuleMapById<'a>> {
+    let links = get_links(module, modules)?;
+    let mut map = ModuleMapById::new();
+    for link in links {
+        match map.entry(link.obj.module_id) {
+            Entry::Vacant(e) => {
+                e.insert(link);
+            }
+            Entry::Occupied(_) => bail!(
+                "Duplicate module ID {} in links for module {} (ID {}).\n\
+                This likely means you need to specify the links manually.",
+                link.obj.module_id,
+                module.obj.name,
+                module.obj.module_id
+            ),
+        }
+    }
+    Ok(map)
+}
+
+fn split(args: SplitArgs) -> Result<()> {
+    if let Some(jobs) = args.jobs {
+        rayon::ThreadPoolBuilder::new().num_threads(jobs).build_global()?;
+    }
+
+    let command_start = Instant::now();
+    info!("Loading {}", args.config);
+    let mut config: ProjectConfig = {
+        let mut config_file = open_file(&args.config, true)?;
+        serde_yaml::from_reader(config_file.as_mut())?
+    };
+
+    let mut object_base = find_object_base(&config)?;
+    if config.extract_objects && matches!(object_base, ObjectBase::Vfs(..)) {
+        // Extract files from the VFS into the object base directory
+        let target_dir = extract_objects(&config, &object_base)?;
+        object_base = ObjectBase::Directory(target_dir);
+    }
+
+    let rso_module_ids = assign_module_ids(&mut config, &object_base)?;
+
+    let out_config_path = args.out_dir.join("config.json");
+    let mut dep = DepFile::new(out_config_path.clone());
+
+    let (signatures, signatures_dep) = load_signature_db(&config)?;
+    dep.extend(signatures_dep);
+
+    let LoadedProject { mut dol, mut modules, versions, dep: load_dep } =
+        load_analyze_project(&config, &object_base, &signatures, &rso_module_ids)?;
+    dep.extend(load_dep);
+
     // Create out dirs
     DirBuilder::new().recursive(true).create(&args.out_dir)?;
     touch(&args.out_dir)?;
@@ -1362,14 +1862,21 @@ fn split(args: SplitArgs) -> Result<()> {
             let _span =
                 info_span!("module", name = %config.base.name(), id = dol.obj.module_id).entered();
             dol_result = Some(
-                split_write_obj(&mut dol, &config, &args.out_dir, &args.out_dir, args.no_update)
-                    .with_context(|| {
-                        format!(
-                            "While processing object '{}' (module ID {})",
-                            config.base.file_name(),
-                            dol.obj.module_id
-                        )
-                    }),
+                split_write_obj(
+                    &mut dol,
+                    &config,
+                    &args.out_dir,
+                    &args.out_dir,
+                    args.no_update,
+                    args.suggest_splits,
+                )
+                .with_context(|| {
+                    format!(
+                        "While processing object '{}' (module ID {})",
+                        config.base.file_name(),
+                        dol.obj.module_id
+                    )
+                }),
             );
         });
         // Modules
@@ -1382,7 +1889,7 @@ fn split(args: SplitArgs) -> Result<()> {
                             info_span!("module", name = %module.config.name(), id = module.obj.module_id)
                                 .entered();
                         let out_dir = args.out_dir.join(module.config.name());
-                        split_write_obj(module, &config, &args.out_dir, &out_dir, args.no_update).with_context(
+                        split_write_obj(module, &config, &args.out_dir, &out_dir, args.no_update, args.suggest_splits).with_context(
                             || {
                                 format!(
                                     "While processing object '{}' (module {} ID {})",
@@ -1408,6 +1915,7 @@ fn split(args: SplitArgs) -> Result<()> {
         base: dol_result.unwrap()?,
         modules: modules_config,
         links: vec![],
+        versions,
     };
     let mut object_count = out_config.base.units.len();
     for module in &out_config.modules {
@@ -2040,39 +2548,21 @@ fn apply(args: ApplyArgs) -> Result<()> {
 fn config(args: ConfigArgs) -> Result<()> {
     let mut config = ProjectConfig::default();
     let mut modules = Vec::<(u32, ModuleConfig)>::new();
+    if let Some(disc_path) = &args.disc {
+        if !args.objects.is_empty() {
+            bail!("Object files can't be used with --disc");
+        }
+        config_from_disc(&mut config, &mut modules, disc_path, args.maps)?;
+    } else if args.maps {
+        bail!("--maps requires --disc");
+    }
     for result in FileIterator::new(&args.objects)? {
-        let (path, mut entry) = result?;
+        let (path, entry) = result?;
         log::info!("Loading {}", path);
         let Some(ext) = path.extension() else {
             bail!("No file extension for {}", path);
         };
-        match ext.to_ascii_lowercase().as_str() {
-            "dol" => {
-                config.base.object = path.with_unix_encoding();
-                config.base.hash = Some(file_sha1_string(&mut entry)?);
-            }
-            "rel" => {
-                let header = process_rel_header(&mut entry)?;
-                entry.rewind()?;
-                modules.push((header.module_id, ModuleConfig {
-                    object: path.with_unix_encoding(),
-                    hash: Some(file_sha1_string(&mut entry)?),
-                    ..Default::default()
-                }));
-            }
-            "sel" => {
-                config.selfile = Some(path.with_unix_encoding());
-                config.selfile_hash = Some(file_sha1_string(&mut entry)?);
-            }
-            "rso" => {
-                config.modules.push(ModuleConfig {
-                    object: path.with_unix_encoding(),
-                    hash: Some(file_sha1_string(&mut entry)?),
-                    ..Default::default()
-                });
-            }
-            _ => bail!("Unknown file extension: '{}'", ext),
-        }
+        add_config_object(&mut config, &mut modules, path.with_unix_encoding(), ext, entry)?;
     }
     modules.sort_by(|(a_id, a_config), (b_id, b_config)| {
         // Sort by module ID, then by name
@@ -2086,6 +2576,459 @@ fn config(args: ConfigArgs) -> Result<()> {
     Ok(())
 }
 
+/// Adds a DOL, REL, SEL or RSO to the project configuration, based on its extension.
+fn add_config_object(
+    config: &mut ProjectConfig,
+    modules: &mut Vec<(u32, ModuleConfig)>,
+    path: Utf8UnixPathBuf,
+    ext: &str,
+    mut entry: Box<dyn VfsFile>,
+) -> Result<()> {
+    match ext.to_ascii_lowercase().as_str() {
+        "dol" => {
+            config.base.object = path;
+            config.base.hash = Some(file_sha1_string(&mut entry)?);
+        }
+        "rel" => {
+            let header = process_rel_header(&mut entry)?;
+            entry.rewind()?;
+            modules.push((header.module_id, ModuleConfig {
+                object: path,
+                hash: Some(file_sha1_string(&mut entry)?),
+                ..Default::default()
+            }));
+        }
+        "sel" => {
+            config.selfile = Some(path);
+            config.selfile_hash = Some(file_sha1_string(&mut entry)?);
+        }
+        "rso" => {
+            config.modules.push(ModuleConfig {
+                object: path,
+                hash: Some(file_sha1_string(&mut entry)?),
+                ..Default::default()
+            });
+        }
+        _ => bail!("Unknown file extension: '{}'", ext),
+    }
+    Ok(())
+}
+
+/// Fills in the project configuration from a disc image: `sys/main.dol` and every REL, SEL and
+/// RSO below `files/`, including inside (compressed) RARC and U8 archives.
+fn config_from_disc(
+    config: &mut ProjectConfig,
+    modules: &mut Vec<(u32, ModuleConfig)>,
+    disc_path: &Utf8NativePath,
+    use_maps: bool,
+) -> Result<()> {
+    let (mut file, path) = match open_path(disc_path, false)? {
+        OpenResult::File(file, path) => (file, path),
+        OpenResult::Directory(_, path) => bail!("{} is not a disc image", path),
+    };
+    let format =
+        detect(file.as_mut()).with_context(|| format!("Detecting file type for {}", path))?;
+    let FileFormat::Archive(kind @ ArchiveKind::Disc(_)) = format else {
+        bail!("{} is not a disc image", path);
+    };
+    let mut disc_fs = open_fs(file, kind).with_context(|| format!("Failed to open {}", path))?;
+
+    // Objects are loaded from the disc image found in the object base directory
+    let object_base = match disc_path.parent().map(|p| p.with_unix_encoding()) {
+        Some(parent) if !parent.as_str().is_empty() => parent,
+        _ => Utf8UnixPathBuf::from("."),
+    };
+    config.object_base = Some(object_base);
+
+    let dol_path = Utf8UnixPath::new("sys/main.dol");
+    log::info!("Loading {}", dol_path);
+    let dol = disc_fs.open(dol_path).with_context(|| format!("Failed to open {}", dol_path))?;
+    add_config_object(config, modules, dol_path.to_owned(), "dol", dol)?;
+
+    let mut objects = vec![];
+    let mut maps = vec![];
+    find_disc_objects(disc_fs.as_mut(), Utf8UnixPath::new("files"), "", &mut objects, &mut maps)?;
+    for (object_path, ext) in objects {
+        log::info!("Loading {}", object_path);
+        let entry = open_file_with_fs(disc_fs.clone(), &object_path.with_encoding(), true)?;
+        add_config_object(config, modules, object_path, &ext, entry)?;
+    }
+
+    if use_maps {
+        assign_disc_maps(config, modules, disc_path, maps);
+    }
+    Ok(())
+}
+
+/// Strips compression extensions and returns the lowercase extension of an object file name.
+fn object_extension(name: &str) -> Option<String> {
+    let mut name = name.to_ascii_lowercase();
+    for suffix in [".szs", ".yaz0", ".yay0", ".lz"] {
+        if let Some(stripped) = name.strip_suffix(suffix) {
+            name = stripped.to_string();
+        }
+    }
+    name.rsplit_once('.').map(|(_, ext)| ext.to_string())
+}
+
+/// Collects the paths of RELs, SELs, RSOs and map files, looking inside archives.
+/// Paths are relative to the disc root, with `:` separating archive contents.
+fn find_disc_objects(
+    vfs: &mut dyn Vfs,
+    dir: &Utf8UnixPath,
+    prefix: &str,
+    objects: &mut Vec<(Utf8UnixPathBuf, String)>,
+    maps: &mut Vec<Utf8UnixPathBuf>,
+) -> Result<()> {
+    for name in vfs.read_dir(dir).with_context(|| format!("Failed to read {}{}", prefix, dir))? {
+        let entry_path = dir.join(&name);
+        let path = Utf8UnixPathBuf::from(format!("{}{}", prefix, entry_path));
+        let metadata = vfs
+            .metadata(&entry_path)
+            .with_context(|| format!("Failed to fetch metadata for {}", path))?;
+        if metadata.is_dir() {
+            find_disc_objects(vfs, &entry_path, prefix, objects, maps)?;
+            continue;
+        }
+        match object_extension(&name).as_deref() {
+            Some(ext @ ("rel" | "sel" | "rso")) => {
+                objects.push((path, ext.to_string()));
+                continue;
+            }
+            Some("map") => {
+                maps.push(path);
+                continue;
+            }
+            _ => {}
+        }
+
+        // Search archives, which may be compressed
+        let mut file = vfs.open(&entry_path).with_context(|| format!("Failed to open {}", path))?;
+        let mut format = detect(file.as_mut())?;
+        if let FileFormat::Compressed(kind) = format {
+            file = match decompress_file(file.as_mut(), kind) {
+                Ok(file) => file,
+                Err(e) => {
+                    log::warn!("Failed to decompress {}: {}", path, e);
+                    continue;
+                }
+            };
+            format = detect(file.as_mut())?;
+        }
+        if let FileFormat::Archive(kind @ (ArchiveKind::Rarc | ArchiveKind::U8)) = format {
+            let mut archive = match open_fs(file, kind) {
+                Ok(archive) => archive,
+                Err(e) => {
+                    log::warn!("Failed to open {}: {}", path, e);
+                    continue;
+                }
+            };
+            let prefix = format!("{}:", path);
+            find_disc_objects(archive.as_mut(), Utf8UnixPath::new(""), &prefix, objects, maps)?;
+        }
+    }
+    Ok(())
+}
+
+/// Assigns map files to modules with the same name. A single remaining map is used for the DOL.
+fn assign_disc_maps(
+    config: &mut ProjectConfig,
+    modules: &mut [(u32, ModuleConfig)],
+    disc_path: &Utf8NativePath,
+    maps: Vec<Utf8UnixPathBuf>,
+) {
+    // Maps are loaded relative to the project, not the object base
+    let disc_path = disc_path.with_unix_encoding();
+    let mut remaining = vec![];
+    for map in maps {
+        let stem = map.file_stem().unwrap_or_default().to_ascii_lowercase();
+        let module = modules
+            .iter_mut()
+            .map(|(_, module)| module)
+            .chain(config.modules.iter_mut())
+            .find(|module| module.map.is_none() && module.name().eq_ignore_ascii_case(&stem));
+        let map_path = Utf8UnixPathBuf::from(format!("{}:{}", disc_path, map));
+        match module {
+            Some(module) => module.map = Some(map_path),
+            None => remaining.push(map_path),
+        }
+    }
+    match remaining.len() {
+        0 => {}
+        1 => config.base.map = remaining.pop(),
+        _ => log::warn!(
+            "Found multiple map files that don't match a module, not using one for main.dol: {}",
+            remaining.iter().join(", ")
+        ),
+    }
+}
+
+fn port(args: PortArgs) -> Result<()> {
+    log::info!("Loading {}", args.source);
+    let source_config: ProjectConfig = {
+        let mut config_file = open_file(&args.source, true)?;
+        serde_yaml::from_reader(config_file.as_mut())?
+    };
+    log::info!("Loading {}", args.target);
+    let target_config: ProjectConfig = {
+        let mut config_file = open_file(&args.target, true)?;
+        serde_yaml::from_reader(config_file.as_mut())?
+    };
+    let source_base = find_object_base(&source_config)?;
+    let target_base = find_object_base(&target_config)?;
+    let (source_signatures, _) = load_signature_db(&source_config)?;
+    let (target_signatures, _) = load_signature_db(&target_config)?;
+
+    let mut reports = vec![];
+    let (source_versions, target_versions) = {
+        let source = load_analyze_dol(&source_config, &source_base, &source_signatures)
+            .with_context(|| {
+                format!("While loading object '{}'", source_config.base.file_name())
+            })?;
+        let target = load_analyze_dol(&target_config, &target_base, &target_signatures)
+            .with_context(|| {
+                format!("While loading object '{}'", target_config.base.file_name())
+            })?;
+        let versions = (source.versions.clone(), target.versions.clone());
+        reports.push(port_module(&source.obj, target, &target_config.base)?);
+        versions
+    };
+    // Modules use the signature sets selected by the library versions detected in each DOL
+    let source_signatures = select_signatures(&source_signatures, &source_versions);
+    let target_signatures = select_signatures(&target_signatures, &target_versions);
+    for module_config in &target_config.modules {
+        if module_config.is_rso() {
+            log::warn!("Skipping RSO module {}", module_config.name());
+            continue;
+        }
+        let Some(source_module_config) =
+            source_config.modules.iter().find(|m| m.name() == module_config.name())
+        else {
+            log::warn!("Module {} not found in {}", module_config.name(), args.source);
+            continue;
+        };
+        let source = load_analyze_rel(
+            &source_config,
+            &source_base,
+            source_module_config,
+            None,
+            &source_signatures,
+        )
+        .with_context(|| format!("While loading object '{}'", source_module_config.file_name()))?;
+        let target =
+            load_analyze_rel(&target_config, &target_base, module_config, None, &target_signatures)
+                .with_context(|| format!("While loading object '{}'", module_config.file_name()))?;
+        reports.push(port_module(&source.obj, target, module_config)?);
+    }
+
+    if let Some(report_path) = &args.report {
+        let mut out = buf_writer(report_path)?;
+        serde_yaml::to_writer(&mut out, &reports)?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+fn port_module(
+    source: &ObjInfo,
+    mut target: AnalyzeResult,
+    module_config: &ModuleConfig,
+) -> Result<PortReport> {
+    let report = port_symbols(source, &mut target.obj)
+        .with_context(|| format!("While porting module {}", module_config.name()))?;
+    info!(
+        "{}: matched {}/{} functions ({} ambiguous), ported {} symbols, {} splits failed",
+        module_config.name(),
+        report.matched_functions,
+        report.functions,
+        report.ambiguous.len(),
+        report.ported_symbols,
+        report.unported_splits.len()
+    );
+
+    let (Some(symbols_path), Some(splits_path)) = (&module_config.symbols, &module_config.splits)
+    else {
+        bail!("No symbols or splits file specified for module {}", module_config.name());
+    };
+    write_symbols_file(&symbols_path.with_encoding(), &target.obj, target.symbols_cache)?;
+    write_splits_file(&splits_path.with_encoding(), &target.obj, false, target.splits_cache)?;
+    Ok(report)
+}
+
+/// Loads a project like `dol split` and rebuilds relocations, without writing anything.
+/// Returns the DOL, followed by each module.
+fn load_analyze_objects(config_path: &Utf8NativePath) -> Result<Vec<ObjInfo>> {
+    info!("Loading {}", config_path);
+    let mut config: ProjectConfig = {
+        let mut config_file = open_file(config_path, true)?;
+        serde_yaml::from_reader(config_file.as_mut())?
+    };
+    let object_base = find_object_base(&config)?;
+    let rso_module_ids = assign_module_ids(&mut config, &object_base)?;
+    let (signatures, _) = load_signature_db(&config)?;
+    let LoadedProject { dol, modules, .. } =
+        load_analyze_project(&config, &object_base, &signatures, &rso_module_ids)?;
+
+    info!("Rebuilding relocations");
+    let mut objs = vec![dol.obj];
+    objs.extend(modules.into_values().map(|module| module.obj));
+    objs.par_iter_mut().try_for_each(|obj| {
+        analyze_relocations(obj, &config)
+            .map(|_| ())
+            .with_context(|| format!("While processing module {}", obj.name))
+    })?;
+    Ok(objs)
+}
+
+fn xrefs(args: XrefsArgs) -> Result<()> {
+    let objs = load_analyze_objects(&args.config)?;
+    let modules = objs.iter().collect_vec();
+    let references = collect_references(&modules);
+
+    let describe = |symbol_ref: SymbolRef| -> String {
+        let obj = modules[symbol_ref.module];
+        let symbol = &obj.symbols[symbol_ref.symbol];
+        let section = symbol.section.map_or("ABS", |idx| obj.sections[idx].name.as_str());
+        format!(
+            "{} ({:?}) @ {}:{}:{:#010X} [{}]",
+            symbol.demangled_name.as_ref().unwrap_or(&symbol.name),
+            symbol.kind,
+            obj.name,
+            section,
+            symbol.address,
+            symbol_unit(obj, symbol_ref.symbol).unwrap_or("(generated)")
+        )
+    };
+
+    let mut found = false;
+    for (module, obj) in modules.iter().enumerate() {
+        for (symbol, _) in obj.symbols.iter().filter(|(_, s)| {
+            s.kind != ObjSymbolKind::Section
+                && s.section.is_some()
+                && (s.name == args.symbol || s.demangled_name.as_ref() == Some(&args.symbol))
+        }) {
+            let symbol_ref = SymbolRef { module, symbol };
+            if found {
+                println!();
+            }
+            found = true;
+            println!("Located symbol {}", describe(symbol_ref));
+
+            for (title, outgoing, kinds) in [
+                ("Called by", false, &[ReferenceKind::Call, ReferenceKind::Jump][..]),
+                ("Read by", false, &[ReferenceKind::Read][..]),
+                ("Written by", false, &[ReferenceKind::Write][..]),
+                ("Referenced by", false, &[ReferenceKind::Address, ReferenceKind::Pointer][..]),
+                ("Calls", true, &[ReferenceKind::Call, ReferenceKind::Jump][..]),
+                ("Reads", true, &[ReferenceKind::Read][..]),
+                ("Writes", true, &[ReferenceKind::Write][..]),
+                ("References", true, &[ReferenceKind::Address, ReferenceKind::Pointer][..]),
+            ] {
+                let entries = references
+                    .iter()
+                    .filter(|r| {
+                        kinds.contains(&r.kind)
+                            && if outgoing {
+                                r.source == symbol_ref
+                            } else {
+                                r.target == symbol_ref
+                            }
+                    })
+                    .map(|r| if outgoing { r.target } else { r.source })
+                    .unique()
+                    .collect_vec();
+                if entries.is_empty() {
+                    continue;
+                }
+                println!("\n{}:", title);
+                for entry in entries {
+                    println!(">>> {}", describe(entry));
+                }
+            }
+        }
+    }
+    ensure!(found, "Failed to find symbol '{}'", args.symbol);
+    Ok(())
+}
+
+fn callgraph(args: CallgraphArgs) -> Result<()> {
+    let json = match args.format.as_deref() {
+        Some("json") => true,
+        Some("dot") => false,
+        Some(format) => bail!("Unknown format: '{}'", format),
+        None => args.out_file.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")),
+    };
+    let objs = load_analyze_objects(&args.config)?;
+    let modules = objs.iter().collect_vec();
+    let references = collect_references(&modules);
+    let graph = ReferenceGraph::new(&modules, &references, args.units, args.data);
+    info!(
+        "Writing {} nodes and {} edges to {}",
+        graph.nodes.len(),
+        graph.edges.len(),
+        args.out_file
+    );
+
+    let mut out = buf_writer(&args.out_file)?;
+    if json {
+        serde_json::to_writer_pretty(&mut out, &graph)?;
+    } else {
+        graph.write_dot(&mut out)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn shiftcheck(args: ShiftcheckArgs) -> Result<()> {
+    let objs = load_analyze_objects(&args.config)?;
+    let mut results = Vec::with_capacity(objs.len());
+    for obj in &objs {
+        if obj.kind == ObjKind::Relocatable {
+            info!("Skipping REL module {}: RELs are always fully relocated", obj.name);
+            continue;
+        }
+        let units =
+            split_obj(obj, None).with_context(|| format!("While splitting module {}", obj.name))?;
+        info!("Checking {} units in {}", units.len(), obj.name);
+        let issues = check_shiftable(obj, &units)
+            .with_context(|| format!("While checking module {}", obj.name))?;
+        results.push((obj, issues));
+    }
+
+    let write_report = |w: &mut dyn Write| -> Result<()> {
+        for (obj, issues) in results.iter().filter(|(_, issues)| !issues.is_empty()) {
+            if objs.len() > 1 {
+                writeln!(w, "# {}", obj.name)?;
+            }
+            write_shift_report(w, obj, issues)?;
+            writeln!(w)?;
+        }
+        Ok(())
+    };
+    if let Some(out_file) = &args.out_file {
+        let mut out = buf_writer(out_file)?;
+        write_report(&mut out)?;
+        out.flush()?;
+    } else {
+        write_report(&mut std::io::stdout().lock())?;
+    }
+    let issue_count = results.iter().map(|(_, issues)| issues.len()).sum::<usize>();
+    let units_affected = results
+        .iter()
+        .map(|(obj, issues)| issues.iter().map(|i| (&obj.name, &i.unit)).unique().count())
+        .sum::<usize>();
+    if issue_count == 0 {
+        info!("No missing relocations found");
+    } else {
+        log::warn!(
+            "Found {} potential missing relocations in {} units",
+            issue_count,
+            units_affected
+        );
+    }
+    Ok(())
+}
+
 /// Applies the blocked relocation ranges from module config `blocked_relocations`
 fn apply_block_relocations(
     obj: &mut ObjInfo,
@@ -2188,6 +3131,28 @@ impl ObjectBase {
     }
 }
 
+/// Loads the signature databases specified in the project configuration.
+/// Returns the database along with the files that were read.
+pub fn load_signature_db(config: &ProjectConfig) -> Result<(SignatureDb, Vec<Utf8NativePathBuf>)> {
+    let mut db = SignatureDb::default();
+    let mut dep = vec![];
+    for signature_config in &config.signatures {
+        let path = signature_config.path.with_encoding();
+        let (signatures, files) = read_signatures(&path)?;
+        log::info!("Loaded {} signatures from {}", signatures.len(), path);
+        db.add(signatures, signature_config.priority, SignatureSetInfo {
+            library: signature_config.library.clone(),
+            version: signature_config.version.clone(),
+            mw_comment_version: signature_config.mw_comment_version,
+        });
+        dep.extend(files);
+    }
+    for name in &config.disable_signatures {
+        db.disable(name);
+    }
+    Ok((db, dep))
+}
+
 pub fn find_object_base(config: &ProjectConfig) -> Result<ObjectBase> {
     if let Some(base) = &config.object_base {
         let base = base.with_encoding();
@@ -1,27 +1,33 @@
 use std::{
     collections::{btree_map, BTreeMap},
+    fmt,
     io::{stdout, Cursor, Read, Write},
     ops::Bound::{Excluded, Unbounded},
-    str::from_utf8,
+    str::{from_utf8, FromStr},
 };
 
 use anyhow::{anyhow, bail, Context, Result};
 use argp::FromArgs;
-use object::{
-    elf, Object, ObjectSection, ObjectSymbol, RelocationFlags, RelocationTarget, Section,
-};
+use object::Object;
+use serde::Serialize;
 use syntect::{
     highlighting::{Color, HighlightIterator, HighlightState, Highlighter, Theme, ThemeSet},
     parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet},
 };
 use typed_path::Utf8NativePathBuf;
+use xxhash_rust::xxh3::xxh3_64;
 
 use crate::{
     util::{
         dwarf::{
             process_compile_unit, process_cu_tag, process_overlay_branch, read_debug_section,
-            should_skip_tag, tag_type_string, AttributeKind, TagKind,
+            read_line_table, relocated_section_data, should_skip_tag, subroutine_type_string,
+            tag_type_string, type_string, ud_type, ud_type_def, AttributeKind, CompileUnit,
+            DwarfInfo, LineTable, Modifier, StructureKind, StructureMember, SubroutineBlock,
+            SubroutineType, SubroutineVariable, Tag, TagKind, TagType, Type, TypeKind, TypedefMap,
+            UserDefinedType, VariableTag, Visibility,
         },
+        dwarf2::read_debug_info_section,
         file::buf_writer,
         path::native_path,
     },
@@ -29,7 +35,7 @@ use crate::{
 };
 
 #[derive(FromArgs, PartialEq, Debug)]
-/// Commands for processing DWARF 1.1 information.
+/// Commands for processing DWARF 1.1 and 2/3 information.
 #[argp(subcommand, name = "dwarf")]
 pub struct Args {
     #[argp(subcommand)]
@@ -40,10 +46,11 @@ pub struct Args {
 #[argp(subcommand)]
 enum SubCommand {
     Dump(DumpArgs),
+    Lines(LinesArgs),
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
-/// Dumps DWARF 1.1 info from an object or archive.
+/// Dumps DWARF 1.1 or 2/3 info from an object or archive.
 #[argp(subcommand, name = "dump")]
 pub struct DumpArgs {
     #[argp(positional, from_str_fn(native_path))]
@@ -55,15 +62,62 @@ pub struct DumpArgs {
     #[argp(switch)]
     /// Disable color output.
     no_color: bool,
+    #[argp(option, short = 'f', default = "DumpFormat::Text", from_str_fn(dump_format))]
+    /// Output format: text (default) or json.
+    format: DumpFormat,
     #[argp(switch)]
     /// Attempt to reconstruct tags that have been removed by the linker, e.g.
     /// tags from unused functions or functions that have been inlined away.
     include_erased: bool,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum DumpFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for DumpFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for DumpFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text => write!(f, "text"),
+            Self::Json => write!(f, "json"),
+        }
+    }
+}
+
+fn dump_format(s: &str) -> Result<DumpFormat, String> {
+    DumpFormat::from_str(s).map_err(|_| format!("Invalid format: {}", s))
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Prints the DWARF 1.1 line number tables of an object.
+#[argp(subcommand, name = "lines")]
+pub struct LinesArgs {
+    #[argp(positional, from_str_fn(native_path))]
+    /// Input object. (ELF)
+    in_file: Utf8NativePathBuf,
+    #[argp(option, short = 'o', from_str_fn(native_path))]
+    /// Output file. (Default: stdout)
+    out: Option<Utf8NativePathBuf>,
+}
+
 pub fn run(args: Args) -> Result<()> {
     match args.command {
         SubCommand::Dump(c_args) => dump(c_args),
+        SubCommand::Lines(c_args) => lines(c_args),
     }
 }
 
@@ -77,9 +131,12 @@ fn dump(args: DumpArgs) -> Result<()> {
     let theme = theme_set.themes.get("Solarized (dark)").context("Failed to load theme")?;
     let syntax = syntax_set.find_syntax_by_name("C++").context("Failed to find syntax")?.clone();
 
+    let json = args.format == DumpFormat::Json;
+
     let mut file = open_file(&args.in_file, true)?;
     let buf = file.map()?;
     if buf.starts_with(b"!<arch>\n") {
+        let mut dumps = Vec::new();
         let mut archive = ar::Archive::new(buf);
         while let Some(result) = archive.next_entry() {
             let mut e = match result {
@@ -90,83 +147,100 @@ fn dump(args: DumpArgs) -> Result<()> {
             let mut data = vec![0u8; e.header().size() as usize];
             e.read_exact(&mut data)?;
             let obj_file = object::read::File::parse(&*data)?;
-            let debug_section = match obj_file.section_by_name(".debug") {
-                Some(section) => {
+            let info = match read_dwarf_info(&obj_file, args.include_erased)
+                .with_context(|| format!("Failed to read debug information from '{}'", name))?
+            {
+                Some(info) => {
                     log::info!("Processing '{}'", name);
-                    section
+                    info
                 }
                 None => {
-                    log::warn!("Object '{}' missing .debug section", name);
+                    log::warn!("Object '{}' missing debug information", name);
                     continue;
                 }
             };
-            if let Some(out_path) = &args.out {
-                // TODO make a basename method
-                let name = name.trim_start_matches("D:").replace('\\', "/");
-                let name = name.rsplit_once('/').map(|(_, b)| b).unwrap_or(&name);
-                let file_path = out_path.join(format!("{}.txt", name));
+            // TODO make a basename method
+            let base_name = name.trim_start_matches("D:").replace('\\', "/");
+            let base_name = base_name.rsplit_once('/').map(|(_, b)| b).unwrap_or(&base_name);
+            if json {
+                let mut dump = dump_debug_json(&info, line_section_data(&obj_file)?.as_deref())?;
+                dump.object = Some(name);
+                if let Some(out_path) = &args.out {
+                    let file_path = out_path.join(format!("{}.json", base_name));
+                    write_json(&mut buf_writer(&file_path)?, &dump)?;
+                } else {
+                    dumps.push(dump);
+                }
+            } else if let Some(out_path) = &args.out {
+                let file_path = out_path.join(format!("{}.txt", base_name));
                 let mut file = buf_writer(&file_path)?;
-                dump_debug_section(&args, &mut file, &obj_file, debug_section)?;
+                dump_debug_section(&mut file, &obj_file, info)?;
                 file.flush()?;
             } else if args.no_color {
                 println!("\n// File {}:", name);
-                dump_debug_section(&args, &mut stdout(), &obj_file, debug_section)?;
+                dump_debug_section(&mut stdout(), &obj_file, info)?;
             } else {
                 let mut writer = HighlightWriter::new(syntax_set.clone(), syntax.clone(), theme);
                 writeln!(writer, "\n// File {}:", name)?;
-                dump_debug_section(&args, &mut writer, &obj_file, debug_section)?;
+                dump_debug_section(&mut writer, &obj_file, info)?;
             }
         }
+        if json && args.out.is_none() {
+            write_json(&mut stdout().lock(), &dumps)?;
+        }
     } else {
         let obj_file = object::read::File::parse(buf)?;
-        let debug_section = obj_file
-            .section_by_name(".debug")
-            .ok_or_else(|| anyhow!("Failed to locate .debug section"))?;
-        if let Some(out_path) = &args.out {
+        let info = read_dwarf_info(&obj_file, args.include_erased)?
+            .ok_or_else(|| anyhow!("Failed to locate .debug or .debug_info section"))?;
+        if json {
+            let dump = dump_debug_json(&info, line_section_data(&obj_file)?.as_deref())?;
+            match &args.out {
+                Some(out_path) => write_json(&mut buf_writer(out_path)?, &dump)?,
+                None => write_json(&mut stdout().lock(), &dump)?,
+            }
+        } else if let Some(out_path) = &args.out {
             let mut file = buf_writer(out_path)?;
-            dump_debug_section(&args, &mut file, &obj_file, debug_section)?;
+            dump_debug_section(&mut file, &obj_file, info)?;
             file.flush()?;
         } else if args.no_color {
-            dump_debug_section(&args, &mut stdout(), &obj_file, debug_section)?;
+            dump_debug_section(&mut stdout(), &obj_file, info)?;
         } else {
             let mut writer = HighlightWriter::new(syntax_set, syntax, theme);
-            dump_debug_section(&args, &mut writer, &obj_file, debug_section)?;
+            dump_debug_section(&mut writer, &obj_file, info)?;
         }
     }
     Ok(())
 }
 
-fn dump_debug_section<W>(
-    args: &DumpArgs,
-    w: &mut W,
-    obj_file: &object::File<'_>,
-    debug_section: Section,
-) -> Result<()>
-where
-    W: Write + ?Sized,
-{
-    let mut data = debug_section.uncompressed_data()?.into_owned();
-
-    // Apply relocations to data
-    for (addr, reloc) in debug_section.relocations() {
-        match reloc.flags() {
-            RelocationFlags::Elf { r_type: elf::R_PPC_ADDR32 | elf::R_PPC_UADDR32 } => {
-                let target = match reloc.target() {
-                    RelocationTarget::Symbol(symbol_idx) => {
-                        let symbol = obj_file.symbol_by_index(symbol_idx)?;
-                        (symbol.address() as i64 + reloc.addend()) as u32
-                    }
-                    _ => bail!("Invalid .debug relocation target"),
-                };
-                data[addr as usize..addr as usize + 4].copy_from_slice(&target.to_be_bytes());
-            }
-            RelocationFlags::Elf { r_type: elf::R_PPC_NONE } => {}
-            _ => bail!("Unhandled .debug relocation type {:?}", reloc.kind()),
-        }
+/// Reads DWARF 1.1 information from `.debug`, or DWARF 2/3 information from `.debug_info`.
+fn read_dwarf_info(obj_file: &object::File<'_>, include_erased: bool) -> Result<Option<DwarfInfo>> {
+    let e = obj_file.endianness().into();
+    if let Some(section) = obj_file.section_by_name(".debug") {
+        let data = relocated_section_data(obj_file, &section)?;
+        let mut reader = Cursor::new(&*data);
+        return Ok(Some(read_debug_section(&mut reader, e, include_erased)?));
     }
+    let Some(info_section) = obj_file.section_by_name(".debug_info") else {
+        return Ok(None);
+    };
+    let abbrev_section = obj_file
+        .section_by_name(".debug_abbrev")
+        .ok_or_else(|| anyhow!("Failed to locate .debug_abbrev section"))?;
+    let info_data = relocated_section_data(obj_file, &info_section)?;
+    let abbrev_data = relocated_section_data(obj_file, &abbrev_section)?;
+    let str_data = match obj_file.section_by_name(".debug_str") {
+        Some(section) => relocated_section_data(obj_file, &section)?,
+        None => Vec::new(),
+    };
+    Ok(Some(read_debug_info_section(&info_data, &abbrev_data, &str_data, e)?))
+}
 
-    let mut reader = Cursor::new(&*data);
-    let info = read_debug_section(&mut reader, obj_file.endianness().into(), args.include_erased)?;
+fn dump_debug_section<W>(w: &mut W, obj_file: &object::File<'_>, info: DwarfInfo) -> Result<()>
+where W: Write + ?Sized {
+    let line_data = match obj_file.section_by_name(".line") {
+        Some(section) => Some(relocated_section_data(obj_file, &section)?),
+        None => None,
+    };
 
     for (&addr, tag) in &info.tags {
         log::debug!("{}: {:?}", addr, tag);
@@ -232,23 +306,10 @@ where
                     }
                     writeln!(w, "*/")?;
 
-                    let mut children = tag.children(&info.tags);
-
-                    // merge in erased tags
-                    let range = match tag.next_sibling(&info.tags) {
-                        Some(next) => (Excluded(tag.key), Excluded(next.key)),
-                        None => (Excluded(tag.key), Unbounded),
-                    };
-                    for (_, child) in info.tags.range(range) {
-                        if child.is_erased_root {
-                            children.push(child);
-                        }
-                    }
-                    children.sort_by_key(|x| x.key);
-
+                    let line_table = unit_line_table(&info, &unit, line_data.as_deref());
                     let mut typedefs = BTreeMap::<u32, Vec<u32>>::new();
-                    for child in children {
-                        let tag_type = match process_cu_tag(&info, child) {
+                    for child in unit_children(&info, tag) {
+                        let mut tag_type = match process_cu_tag(&info, child) {
                             Ok(tag_type) => tag_type,
                             Err(e) => {
                                 log::error!(
@@ -268,6 +329,13 @@ where
                         if should_skip_tag(&tag_type, child.is_erased) {
                             continue;
                         }
+                        if let (
+                            Some(table),
+                            TagType::UserDefined(UserDefinedType::Subroutine(subroutine)),
+                        ) = (&line_table, &mut tag_type)
+                        {
+                            apply_line_range(table, subroutine);
+                        }
                         match tag_type_string(&info, &typedefs, &tag_type, child.is_erased) {
                             Ok(s) => writeln!(w, "{}", s)?,
                             Err(e) => {
@@ -320,6 +388,744 @@ where
     Ok(())
 }
 
+/// Returns the root tags of a compile unit, including erased tags if they were reconstructed.
+fn unit_children<'a>(info: &'a DwarfInfo, tag: &Tag) -> Vec<&'a Tag> {
+    let mut children = tag.children(&info.tags);
+
+    // merge in erased tags
+    let range = match tag.next_sibling(&info.tags) {
+        Some(next) => (Excluded(tag.key), Excluded(next.key)),
+        None => (Excluded(tag.key), Unbounded),
+    };
+    for (_, child) in info.tags.range(range) {
+        if child.is_erased_root {
+            children.push(child);
+        }
+    }
+    children.sort_by_key(|x| x.key);
+    children
+}
+
+fn unit_line_table(
+    info: &DwarfInfo,
+    unit: &CompileUnit,
+    line_data: Option<&[u8]>,
+) -> Option<LineTable> {
+    let (Some(offset), Some(data)) = (unit.line_offset, line_data) else {
+        return None;
+    };
+    match read_line_table(&mut Cursor::new(data), info.e, offset) {
+        Ok(table) => Some(table),
+        Err(e) => {
+            log::warn!("Failed to read line table (unit {}): {}", unit.name, e);
+            None
+        }
+    }
+}
+
+fn apply_line_range(table: &LineTable, subroutine: &mut SubroutineType) {
+    if let (Some(start), Some(end)) = (subroutine.start_address, subroutine.end_address) {
+        subroutine.line_range = table.line_range(start, end);
+    }
+}
+
+fn lines(args: LinesArgs) -> Result<()> {
+    let mut file = open_file(&args.in_file, true)?;
+    let obj_file = object::read::File::parse(file.map()?)?;
+    let debug_section = obj_file
+        .section_by_name(".debug")
+        .ok_or_else(|| anyhow!("Failed to locate .debug section"))?;
+    let line_section = obj_file
+        .section_by_name(".line")
+        .ok_or_else(|| anyhow!("Failed to locate .line section"))?;
+    let debug_data = relocated_section_data(&obj_file, &debug_section)?;
+    let info =
+        read_debug_section(&mut Cursor::new(&*debug_data), obj_file.endianness().into(), false)?;
+    let line_data = relocated_section_data(&obj_file, &line_section)?;
+
+    let mut w: Box<dyn Write> = match &args.out {
+        Some(out_path) => Box::new(buf_writer(out_path)?),
+        None => Box::new(stdout()),
+    };
+    let mut tag = info.tags.first_key_value().map(|(_, tag)| tag);
+    while let Some(current) = tag {
+        tag = current.next_sibling(&info.tags);
+        if current.kind != TagKind::CompileUnit {
+            continue;
+        }
+        let unit = process_compile_unit(current)?;
+        let Some(offset) = unit.line_offset else {
+            continue;
+        };
+        let table = read_line_table(&mut Cursor::new(&*line_data), info.e, offset)
+            .with_context(|| format!("Failed to read line table for {}", unit.name))?;
+        writeln!(w, "// Compile unit: {}", unit.name)?;
+        writeln!(w, "// Base address: {:#010X}", table.base_address)?;
+        for entry in &table.entries {
+            match entry.column {
+                Some(column) => writeln!(w, "{:#010X}\t{}:{}", entry.address, entry.line, column)?,
+                None => writeln!(w, "{:#010X}\t{}", entry.address, entry.line)?,
+            }
+        }
+        writeln!(w)?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// Structured form of `dwarf dump`, for `--format json`.
+///
+/// User defined types are identified by a key derived from their content, so that it stays the
+/// same across rebuilds: the kind and name for named types, e.g. `struct Foo`, or the kind and
+/// a hash of the definition for anonymous types, e.g. `array#0123456789abcdef`. Named types
+/// with conflicting definitions also get the hash appended.
+#[derive(Serialize)]
+struct JsonDump {
+    /// Archive member name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    object: Option<String>,
+    units: Vec<JsonUnit>,
+    /// Every user defined type defined in or referenced by the units, sorted by ID
+    types: Vec<JsonTypeDef>,
+}
+
+#[derive(Serialize)]
+struct JsonUnit {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    producer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comp_dir: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start_address: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_address: Option<u32>,
+    /// IDs of the types defined at the unit's top level
+    types: Vec<String>,
+    typedefs: Vec<JsonTypedef>,
+    variables: Vec<JsonVariable>,
+    functions: Vec<JsonFunction>,
+}
+
+/// A reference to a type.
+#[derive(Serialize)]
+struct JsonType {
+    /// C spelling of the type, e.g. `const char *`. Omitted for anonymous types, which are
+    /// spelled as their full definition.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fundamental: Option<&'static str>,
+    /// ID of the user defined type
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    /// Outermost first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    modifiers: Vec<&'static str>,
+}
+
+#[derive(Serialize)]
+struct JsonTypedef {
+    name: String,
+    #[serde(rename = "type")]
+    kind: JsonType,
+}
+
+#[derive(Serialize)]
+struct JsonVariable {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mangled_name: Option<String>,
+    #[serde(rename = "type")]
+    kind: JsonType,
+    /// Address of globals and statics, or register and stack offset of locals, e.g. `r1+0x8`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    location: Option<String>,
+    /// Static to its unit or function
+    local: bool,
+    erased: bool,
+}
+
+#[derive(Serialize)]
+struct JsonParameter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(rename = "type")]
+    kind: JsonType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    location: Option<String>,
+}
+
+#[derive(Serialize)]
+struct JsonLabel {
+    name: String,
+    address: u32,
+}
+
+#[derive(Serialize)]
+struct JsonBlock {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start_address: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_address: Option<u32>,
+    variables: Vec<JsonVariable>,
+    blocks: Vec<JsonBlock>,
+    inlines: Vec<JsonFunction>,
+}
+
+/// A function, or a function type.
+#[derive(Serialize)]
+struct JsonFunction {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mangled_name: Option<String>,
+    return_type: JsonType,
+    parameters: Vec<JsonParameter>,
+    var_args: bool,
+    prototyped: bool,
+    /// ID of the class the function is a member of
+    #[serde(skip_serializing_if = "Option::is_none")]
+    member_of: Option<String>,
+    local: bool,
+    inline: bool,
+    #[serde(rename = "virtual")]
+    virtual_: bool,
+    erased: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start_address: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_address: Option<u32>,
+    /// First and last source line
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lines: Option<(u32, u32)>,
+    /// Names of referenced globals, mangled if available
+    references: Vec<String>,
+    variables: Vec<JsonVariable>,
+    labels: Vec<JsonLabel>,
+    blocks: Vec<JsonBlock>,
+    inlines: Vec<JsonFunction>,
+}
+
+#[derive(Serialize)]
+struct JsonMember {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(rename = "type")]
+    kind: JsonType,
+    offset: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bit_size: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bit_offset: Option<u16>,
+    visibility: &'static str,
+}
+
+#[derive(Serialize)]
+struct JsonBase {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(rename = "type")]
+    kind: JsonType,
+    offset: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    visibility: Option<&'static str>,
+    #[serde(rename = "virtual")]
+    virtual_base: bool,
+}
+
+#[derive(Serialize)]
+struct JsonEnumMember {
+    name: String,
+    value: i32,
+}
+
+#[derive(Serialize)]
+struct JsonTypeDef {
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u32>,
+    #[serde(flatten)]
+    def: JsonTypeKind,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum JsonTypeKind {
+    Struct {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        bases: Vec<JsonBase>,
+        members: Vec<JsonMember>,
+    },
+    Class {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        bases: Vec<JsonBase>,
+        members: Vec<JsonMember>,
+    },
+    Union {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        members: Vec<JsonMember>,
+    },
+    Enum {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        members: Vec<JsonEnumMember>,
+    },
+    Array {
+        element_type: JsonType,
+        /// Element count of each dimension, null if unbounded
+        dimensions: Vec<Option<u32>>,
+    },
+    Function(Box<JsonFunction>),
+    PtrToMember {
+        #[serde(rename = "type")]
+        kind: JsonType,
+        containing_type: String,
+    },
+    /// Placeholder for a referenced type that failed to process
+    Unresolved {
+        error: String,
+    },
+}
+
+/// Converts processed tags to their JSON form, collecting referenced types as it goes.
+struct JsonBuilder<'a> {
+    info: &'a DwarfInfo,
+    /// Type IDs by tag offset
+    ids: BTreeMap<u32, String>,
+    /// Forward declarations, mapped to the tag offset of their definition
+    declarations: BTreeMap<u32, u32>,
+    types: BTreeMap<String, JsonTypeDef>,
+    /// Tag offsets of referenced types not converted yet
+    pending: Vec<u32>,
+}
+
+impl<'a> JsonBuilder<'a> {
+    fn new(info: &'a DwarfInfo) -> Self {
+        let (ids, declarations) = type_ids(info);
+        Self { info, ids, declarations, types: BTreeMap::new(), pending: vec![] }
+    }
+
+    /// ID of the type tag at `key`, without queueing it for conversion.
+    fn id(&self, key: u32) -> String {
+        self.ids.get(&key).cloned().unwrap_or_else(|| format!("unresolved@{:X}", key))
+    }
+
+    /// ID of the type tag at `key`, queueing it for conversion if needed.
+    fn type_id(&mut self, key: u32) -> String {
+        let id = self.id(key);
+        if !self.types.contains_key(&id) {
+            self.pending.push(key);
+        }
+        id
+    }
+
+    fn type_ref(&mut self, t: &Type) -> Result<JsonType> {
+        let ts = type_string(self.info, &TypedefMap::new(), t, false)?;
+        let (fundamental, id) = match t.kind {
+            TypeKind::Fundamental(ft) => (Some(ft.name()?), None),
+            TypeKind::UserDefined(key) => (None, Some(self.type_id(key))),
+        };
+        let modifiers = t
+            .modifiers
+            .iter()
+            .map(|m| match m {
+                Modifier::MwPointerTo | Modifier::PointerTo => "pointer",
+                Modifier::ReferenceTo => "reference",
+                Modifier::Const => "const",
+                Modifier::Volatile => "volatile",
+            })
+            .collect();
+        let name = format!("{}{}", ts.prefix, ts.suffix);
+        let name = (!name.contains('\n')).then_some(name);
+        Ok(JsonType { name, fundamental, id, modifiers })
+    }
+
+    fn reference(&self, key: u32) -> Option<String> {
+        let tag = self.info.tags.get(&key)?;
+        tag.string_attribute(AttributeKind::MwMangled)
+            .or_else(|| tag.string_attribute(AttributeKind::Name))
+            .cloned()
+    }
+
+    fn variable(&mut self, v: &SubroutineVariable, local: bool) -> Result<JsonVariable> {
+        Ok(JsonVariable {
+            name: v.name.clone(),
+            mangled_name: v.mangled_name.clone(),
+            kind: self.type_ref(&v.kind)?,
+            location: v.location.clone(),
+            local,
+            erased: false,
+        })
+    }
+
+    fn global_variable(&mut self, v: &VariableTag, erased: bool) -> Result<JsonVariable> {
+        Ok(JsonVariable {
+            name: v.name.clone(),
+            mangled_name: v.mangled_name.clone(),
+            kind: self.type_ref(&v.kind)?,
+            location: v.address.map(|addr| format!("@ {:#010X}", addr)),
+            local: v.local,
+            erased,
+        })
+    }
+
+    fn block(&mut self, b: &SubroutineBlock) -> Result<JsonBlock> {
+        Ok(JsonBlock {
+            name: b.name.clone(),
+            start_address: b.start_address,
+            end_address: b.end_address,
+            variables: b
+                .variables
+                .iter()
+                .map(|v| self.variable(v, false))
+                .collect::<Result<_>>()?,
+            blocks: b.blocks.iter().map(|b| self.block(b)).collect::<Result<_>>()?,
+            inlines: b.inlines.iter().map(|f| self.function(f, false)).collect::<Result<_>>()?,
+        })
+    }
+
+    fn function(&mut self, f: &SubroutineType, erased: bool) -> Result<JsonFunction> {
+        Ok(JsonFunction {
+            name: f.name.clone(),
+            mangled_name: f.mangled_name.clone(),
+            return_type: self.type_ref(&f.return_type)?,
+            parameters: f
+                .parameters
+                .iter()
+                .map(|p| {
+                    Ok(JsonParameter {
+                        name: p.name.clone(),
+                        kind: self.type_ref(&p.kind)?,
+                        location: p.location.clone(),
+                    })
+                })
+                .collect::<Result<_>>()?,
+            var_args: f.var_args,
+            prototyped: f.prototyped,
+            member_of: f.member_of.map(|key| self.type_id(key)),
+            local: f.local,
+            inline: f.inline,
+            virtual_: f.virtual_,
+            erased,
+            start_address: f.start_address,
+            end_address: f.end_address,
+            lines: f.line_range,
+            references: f.references.iter().filter_map(|&key| self.reference(key)).collect(),
+            variables: f
+                .variables
+                .iter()
+                .map(|v| self.variable(v, false))
+                .collect::<Result<_>>()?,
+            labels: f
+                .labels
+                .iter()
+                .map(|l| JsonLabel { name: l.name.clone(), address: l.address })
+                .collect(),
+            blocks: f.blocks.iter().map(|b| self.block(b)).collect::<Result<_>>()?,
+            inlines: f.inlines.iter().map(|f| self.function(f, false)).collect::<Result<_>>()?,
+        })
+    }
+
+    fn member(&mut self, m: &StructureMember) -> Result<JsonMember> {
+        Ok(JsonMember {
+            name: m.name.clone(),
+            kind: self.type_ref(&m.kind)?,
+            offset: m.offset,
+            bit_size: m.bit.as_ref().map(|b| b.bit_size),
+            bit_offset: m.bit.as_ref().map(|b| b.bit_offset),
+            visibility: visibility_name(m.visibility),
+        })
+    }
+
+    fn type_def(&mut self, key: u32, t: &UserDefinedType) -> Result<()> {
+        if let Some(&definition) = self.declarations.get(&key) {
+            // Shares the ID of the definition, which is emitted instead
+            self.type_id(definition);
+            return Ok(());
+        }
+        let size = t.size(self.info)?;
+        let def = match t {
+            UserDefinedType::Structure(t) => {
+                let name = t.name.clone();
+                let bases = t
+                    .bases
+                    .iter()
+                    .map(|b| {
+                        Ok(JsonBase {
+                            name: b.name.clone(),
+                            kind: self.type_ref(&b.base_type)?,
+                            offset: b.offset,
+                            visibility: b.visibility.map(visibility_name),
+                            virtual_base: b.virtual_base,
+                        })
+                    })
+                    .collect::<Result<_>>()?;
+                let members = t.members.iter().map(|m| self.member(m)).collect::<Result<_>>()?;
+                match t.kind {
+                    StructureKind::Struct => JsonTypeKind::Struct { name, bases, members },
+                    StructureKind::Class => JsonTypeKind::Class { name, bases, members },
+                }
+            }
+            UserDefinedType::Union(t) => JsonTypeKind::Union {
+                name: t.name.clone(),
+                members: t.members.iter().map(|m| self.member(m)).collect::<Result<_>>()?,
+            },
+            UserDefinedType::Enumeration(t) => JsonTypeKind::Enum {
+                name: t.name.clone(),
+                members: t
+                    .members
+                    .iter()
+                    .map(|m| JsonEnumMember { name: m.name.clone(), value: m.value })
+                    .collect(),
+            },
+            UserDefinedType::Array(t) => JsonTypeKind::Array {
+                element_type: self.type_ref(&t.element_type)?,
+                dimensions: t.dimensions.iter().map(|d| d.size.map(|s| s.get())).collect(),
+            },
+            UserDefinedType::Subroutine(t) => {
+                JsonTypeKind::Function(Box::new(self.function(t, false)?))
+            }
+            UserDefinedType::PtrToMember(t) => JsonTypeKind::PtrToMember {
+                kind: self.type_ref(&t.kind)?,
+                containing_type: self.type_id(t.containing_type),
+            },
+        };
+        let id = self.id(key);
+        self.types.insert(id.clone(), JsonTypeDef { id, size: Some(size), def });
+        Ok(())
+    }
+
+    /// Converts the referenced types that haven't been converted yet. Types that fail to
+    /// process are emitted as placeholders, so that every referenced ID is defined.
+    fn resolve_pending(&mut self) {
+        while let Some(key) = self.pending.pop() {
+            let id = self.id(key);
+            if self.types.contains_key(&id) {
+                continue;
+            }
+            let result = self
+                .info
+                .tags
+                .get(&key)
+                .ok_or_else(|| anyhow!("Failed to locate user defined type"))
+                .and_then(|tag| ud_type(self.info, tag))
+                .and_then(|t| self.type_def(key, &t));
+            if let Err(e) = result {
+                log::warn!("Failed to process type {:X}: {:#}", key, e);
+                let def = JsonTypeKind::Unresolved { error: format!("{:#}", e) };
+                self.types.insert(id.clone(), JsonTypeDef { id, size: None, def });
+            }
+        }
+    }
+}
+
+/// Assigns every user defined type tag its ID. See [`JsonDump`].
+///
+/// Empty forward declarations get the ID of the definition with the same name, and are
+/// returned mapped to the definition's tag offset. If the definitions conflict, they keep an
+/// ID of their own.
+fn type_ids(info: &DwarfInfo) -> (BTreeMap<u32, String>, BTreeMap<u32, u32>) {
+    let mut ids = BTreeMap::new();
+    let mut declarations = BTreeMap::new();
+    let mut named = BTreeMap::<String, Vec<(u32, u64, bool)>>::new();
+    for (&key, tag) in &info.tags {
+        if !matches!(
+            tag.kind,
+            TagKind::ArrayType
+                | TagKind::ClassType
+                | TagKind::EnumerationType
+                | TagKind::PtrToMemberType
+                | TagKind::StructureType
+                | TagKind::SubroutineType
+                | TagKind::UnionType
+        ) {
+            continue;
+        }
+        let (kind, name, hash, declaration) = match type_id_parts(info, tag) {
+            Ok(parts) => parts,
+            Err(e) => {
+                log::warn!("Failed to process type {:X}: {:#}", key, e);
+                continue;
+            }
+        };
+        match name {
+            Some(name) => named.entry(format!("{} {}", kind, name)).or_default().push((
+                key,
+                hash,
+                declaration,
+            )),
+            None => {
+                ids.insert(key, format!("{}#{:016x}", kind, hash));
+            }
+        }
+    }
+    for (base, defs) in named {
+        let (decls, defs): (Vec<_>, Vec<_>) = defs.into_iter().partition(|&(_, _, decl)| decl);
+        let unique = defs.iter().all(|&(_, hash, _)| hash == defs[0].1);
+        for &(key, hash, _) in &defs {
+            ids.insert(key, if unique { base.clone() } else { format!("{}#{:016x}", base, hash) });
+        }
+        for (key, hash, _) in decls {
+            match defs.first() {
+                Some(&(definition, _, _)) if unique => {
+                    declarations.insert(key, definition);
+                    ids.insert(key, base.clone());
+                }
+                Some(_) => {
+                    ids.insert(key, format!("{}#{:016x}", base, hash));
+                }
+                None => {
+                    ids.insert(key, base.clone());
+                }
+            }
+        }
+    }
+    (ids, declarations)
+}
+
+/// Returns the kind and name of a user defined type, a hash of its definition, and whether it's
+/// an empty forward declaration.
+fn type_id_parts(info: &DwarfInfo, tag: &Tag) -> Result<(&'static str, Option<String>, u64, bool)> {
+    let typedefs = TypedefMap::new();
+    let t = ud_type(info, tag)?;
+    let (kind, name) = match &t {
+        UserDefinedType::Structure(t) => match t.kind {
+            StructureKind::Struct => ("struct", t.name.clone()),
+            StructureKind::Class => ("class", t.name.clone()),
+        },
+        UserDefinedType::Union(t) => ("union", t.name.clone()),
+        UserDefinedType::Enumeration(t) => ("enum", t.name.clone()),
+        UserDefinedType::Array(_) => ("array", None),
+        UserDefinedType::Subroutine(_) => ("function", None),
+        UserDefinedType::PtrToMember(_) => ("ptr_to_member", None),
+    };
+    // Names like `@class$123` are generated by the compiler and change between builds
+    let name = name.filter(|name| !name.starts_with('@'));
+    let def = match &t {
+        // Function definitions include addresses, so hash the signature only
+        UserDefinedType::Subroutine(t) => subroutine_type_string(info, &typedefs, t)
+            .map(|ts| format!("{}{}", ts.prefix, ts.suffix)),
+        t => ud_type_def(info, &typedefs, t, false),
+    }
+    .and_then(|def| Ok(format!("{}\n{}", t.size(info)?, def)))
+    // Still give types that fail to process an ID, they're emitted as placeholders
+    .unwrap_or_else(|e| format!("{:#}", e));
+    // Forward declarations have no members and no size (empty C++ classes have a size of 1)
+    let declaration = match &t {
+        UserDefinedType::Structure(t) => {
+            t.members.is_empty() && t.bases.is_empty() && t.byte_size.unwrap_or(0) == 0
+        }
+        UserDefinedType::Union(t) => t.members.is_empty() && t.byte_size == 0,
+        UserDefinedType::Enumeration(t) => t.members.is_empty() && t.byte_size == 0,
+        _ => false,
+    };
+    Ok((kind, name, xxh3_64(def.as_bytes()), declaration))
+}
+
+fn visibility_name(visibility: Visibility) -> &'static str {
+    match visibility {
+        Visibility::Private => "private",
+        Visibility::Protected => "protected",
+        Visibility::Public => "public",
+    }
+}
+
+fn line_section_data(obj_file: &object::File<'_>) -> Result<Option<Vec<u8>>> {
+    match obj_file.section_by_name(".line") {
+        Some(section) => Ok(Some(relocated_section_data(obj_file, &section)?)),
+        None => Ok(None),
+    }
+}
+
+fn dump_debug_json(info: &DwarfInfo, line_data: Option<&[u8]>) -> Result<JsonDump> {
+    let mut builder = JsonBuilder::new(info);
+    let mut units = Vec::new();
+    let mut tag = info.tags.first_key_value().map(|(_, tag)| tag);
+    while let Some(current) = tag {
+        tag = current.next_sibling(&info.tags);
+        match current.kind {
+            TagKind::CompileUnit => {}
+            TagKind::Padding | TagKind::MwOverlayBranch => continue,
+            kind => bail!("Unhandled root tag type {:?}", kind),
+        }
+        let unit = process_compile_unit(current)?;
+        let line_table = unit_line_table(info, &unit, line_data);
+        let mut out = JsonUnit {
+            name: unit.name.clone(),
+            producer: unit.producer.clone(),
+            comp_dir: unit.comp_dir.clone(),
+            language: unit.language.map(|l| l.to_string()),
+            start_address: unit.start_address,
+            end_address: unit.end_address,
+            types: vec![],
+            typedefs: vec![],
+            variables: vec![],
+            functions: vec![],
+        };
+        for child in unit_children(info, current) {
+            let mut tag_type = match process_cu_tag(info, child) {
+                Ok(tag_type) => tag_type,
+                Err(e) => {
+                    log::error!(
+                        "Failed to process tag {:X} (unit {}): {}",
+                        child.key,
+                        unit.name,
+                        e
+                    );
+                    continue;
+                }
+            };
+            if should_skip_tag(&tag_type, child.is_erased) {
+                continue;
+            }
+            if let (Some(table), TagType::UserDefined(UserDefinedType::Subroutine(subroutine))) =
+                (&line_table, &mut tag_type)
+            {
+                apply_line_range(table, subroutine);
+            }
+            let result = match &tag_type {
+                TagType::Typedef(t) => builder.type_ref(&t.kind).map(|kind| {
+                    out.typedefs.push(JsonTypedef { name: t.name.clone(), kind });
+                }),
+                TagType::Variable(v) => {
+                    builder.global_variable(v, child.is_erased).map(|v| out.variables.push(v))
+                }
+                TagType::UserDefined(UserDefinedType::Subroutine(f))
+                    if child.kind != TagKind::SubroutineType =>
+                {
+                    builder.function(f, child.is_erased).map(|f| out.functions.push(f))
+                }
+                TagType::UserDefined(t) => {
+                    builder.type_def(child.key, t).map(|()| out.types.push(builder.id(child.key)))
+                }
+            };
+            if let Err(e) = result {
+                log::error!("Failed to emit tag {:X} (unit {}): {}", child.key, unit.name, e);
+            }
+        }
+        units.push(out);
+    }
+    builder.resolve_pending();
+    Ok(JsonDump { object: None, units, types: builder.types.into_values().collect() })
+}
+
+fn write_json<T>(w: &mut dyn Write, value: &T) -> Result<()>
+where T: Serialize + ?Sized {
+    serde_json::to_writer_pretty(&mut *w, value)?;
+    writeln!(w)?;
+    w.flush()?;
+    Ok(())
+}
+
 struct HighlightWriter<'a> {
     line: String,
     highlighter: Highlighter<'a>,
@@ -397,3 +1203,151 @@ impl Write for HighlightWriter<'_> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::{
+        dwarf::{Attribute, AttributeValue},
+        reader::Endian,
+    };
+
+    fn tag(key: u32, kind: TagKind, attributes: Vec<(AttributeKind, AttributeValue)>) -> Tag {
+        Tag {
+            key,
+            kind,
+            is_erased: false,
+            is_erased_root: false,
+            attributes: attributes
+                .into_iter()
+                .map(|(kind, value)| Attribute { kind, value })
+                .collect(),
+        }
+    }
+
+    /// A unit at `key` defining `struct Foo { <fund_type> x; }` and a variable of that type.
+    fn unit_tags(key: u32, name: &str, fund_type: u16) -> Vec<Tag> {
+        vec![
+            tag(key, TagKind::CompileUnit, vec![
+                (AttributeKind::Name, AttributeValue::String(name.to_string())),
+                (AttributeKind::Sibling, AttributeValue::Reference(key + 0x100)),
+            ]),
+            tag(key + 0x10, TagKind::StructureType, vec![
+                (AttributeKind::Name, AttributeValue::String("Foo".to_string())),
+                (AttributeKind::ByteSize, AttributeValue::Data4(4)),
+                (AttributeKind::Sibling, AttributeValue::Reference(key + 0x30)),
+            ]),
+            tag(key + 0x20, TagKind::Member, vec![
+                (AttributeKind::Name, AttributeValue::String("x".to_string())),
+                (AttributeKind::FundType, AttributeValue::Data2(fund_type)),
+                (AttributeKind::Location, AttributeValue::Block(vec![4, 0, 0, 0, 0, 7])),
+                (AttributeKind::Sibling, AttributeValue::Reference(key + 0x28)),
+            ]),
+            tag(key + 0x28, TagKind::Padding, vec![]),
+            tag(key + 0x30, TagKind::GlobalVariable, vec![
+                (AttributeKind::Name, AttributeValue::String(format!("foo_{}", name))),
+                (AttributeKind::UserDefType, AttributeValue::Reference(key + 0x10)),
+                (AttributeKind::Sibling, AttributeValue::Reference(key + 0x40)),
+            ]),
+            tag(key + 0x40, TagKind::Padding, vec![]),
+        ]
+    }
+
+    fn dwarf_info(units: &[Vec<Tag>]) -> DwarfInfo {
+        let tags = units.iter().flatten().map(|tag| (tag.key, tag.clone())).collect();
+        DwarfInfo { e: Endian::Big, tags }
+    }
+
+    #[test]
+    fn test_json_type_ids() {
+        // Identical definitions share an ID derived from the name
+        let info = dwarf_info(&[unit_tags(0, "a.c", 7), unit_tags(0x100, "b.c", 7)]);
+        let dump = dump_debug_json(&info, None).unwrap();
+        assert_eq!(dump.units[0].types, ["struct Foo"]);
+        assert_eq!(dump.units[1].types, ["struct Foo"]);
+        assert_eq!(dump.units[1].variables[0].kind.id.as_deref(), Some("struct Foo"));
+        assert_eq!(dump.types.len(), 1);
+        assert_eq!(dump.types[0].id, "struct Foo");
+        assert_eq!(dump.types[0].size, Some(4));
+
+        // IDs don't depend on tag offsets
+        let shifted = dwarf_info(&[unit_tags(0x1000, "a.c", 7)]);
+        assert_eq!(dump_debug_json(&shifted, None).unwrap().units[0].types, ["struct Foo"]);
+
+        // Conflicting definitions are told apart by a hash
+        let info = dwarf_info(&[unit_tags(0, "a.c", 7), unit_tags(0x100, "b.c", 0xa)]);
+        let dump = dump_debug_json(&info, None).unwrap();
+        let (a, b) = (&dump.units[0].types[0], &dump.units[1].types[0]);
+        assert!(a.starts_with("struct Foo#") && b.starts_with("struct Foo#"));
+        assert_ne!(a, b);
+        assert_eq!(dump.types.len(), 2);
+    }
+
+    /// A unit at `key` declaring `struct Foo;` and a variable of that type.
+    fn declaration_unit_tags(key: u32, name: &str) -> Vec<Tag> {
+        vec![
+            tag(key, TagKind::CompileUnit, vec![
+                (AttributeKind::Name, AttributeValue::String(name.to_string())),
+                (AttributeKind::Sibling, AttributeValue::Reference(key + 0x100)),
+            ]),
+            tag(key + 0x10, TagKind::StructureType, vec![
+                (AttributeKind::Name, AttributeValue::String("Foo".to_string())),
+                (AttributeKind::Sibling, AttributeValue::Reference(key + 0x20)),
+            ]),
+            tag(key + 0x20, TagKind::GlobalVariable, vec![
+                (AttributeKind::Name, AttributeValue::String(format!("foo_{}", name))),
+                (AttributeKind::UserDefType, AttributeValue::Reference(key + 0x10)),
+                (AttributeKind::Sibling, AttributeValue::Reference(key + 0x30)),
+            ]),
+            tag(key + 0x30, TagKind::Padding, vec![]),
+        ]
+    }
+
+    #[test]
+    fn test_json_forward_declaration() {
+        // Declarations use the ID of the definition, whichever unit comes first
+        for units in [[declaration_unit_tags(0, "a.c"), unit_tags(0x100, "b.c", 7)], [
+            unit_tags(0, "a.c", 7),
+            declaration_unit_tags(0x100, "b.c"),
+        ]] {
+            let dump = dump_debug_json(&dwarf_info(&units), None).unwrap();
+            for unit in &dump.units {
+                assert_eq!(unit.types, ["struct Foo"]);
+                assert_eq!(unit.variables[0].kind.id.as_deref(), Some("struct Foo"));
+            }
+            assert_eq!(dump.types.len(), 1);
+            assert_eq!(dump.types[0].size, Some(4));
+            assert!(
+                matches!(&dump.types[0].def, JsonTypeKind::Struct { members, .. } if members.len() == 1)
+            );
+        }
+
+        // With conflicting definitions, the declaration gets its own ID
+        let info = dwarf_info(&[
+            unit_tags(0, "a.c", 7),
+            unit_tags(0x100, "b.c", 0xa),
+            declaration_unit_tags(0x200, "c.c"),
+        ]);
+        let dump = dump_debug_json(&info, None).unwrap();
+        let ids = dump.units.iter().map(|u| u.types[0].as_str()).collect::<Vec<_>>();
+        assert!(ids.iter().all(|id| id.starts_with("struct Foo#")));
+        assert!(ids[2] != ids[0] && ids[2] != ids[1]);
+        assert_eq!(dump.types.len(), 3);
+    }
+
+    #[test]
+    fn test_json_unresolved_type() {
+        let mut unit = unit_tags(0, "a.c", 7);
+        // `struct Foo { Missing *x; }`, where the tag of `Missing` doesn't exist
+        unit[2].attributes[1] = Attribute {
+            kind: AttributeKind::ModUDType,
+            value: AttributeValue::Block(vec![1, 0, 0, 0x0f, 0xff]),
+        };
+        let dump = dump_debug_json(&dwarf_info(&[unit]), None).unwrap();
+        // The referenced type is emitted as a placeholder instead of dangling
+        assert_eq!(dump.units[0].variables[0].kind.id.as_deref(), Some("struct Foo"));
+        assert_eq!(dump.types.len(), 1);
+        assert_eq!(dump.types[0].id, "struct Foo");
+        assert!(matches!(dump.types[0].def, JsonTypeKind::Unresolved { .. }));
+    }
+}
@@ -2,7 +2,7 @@ use std::{
     collections::{btree_map, BTreeMap, HashMap},
     fs,
     fs::DirBuilder,
-    io::{Cursor, Write},
+    io::{Cursor, Read, Write},
 };
 
 use anyhow::{anyhow, ensure, Context, Result};
@@ -22,14 +22,17 @@ use crate::{
         asm::write_asm,
         comment::{CommentSym, MWComment},
         config::{write_splits_file, write_symbols_file},
-        elf::process_elf,
+        elf::{process_elf, process_elf_data, process_elf_lines},
         file::{buf_writer, process_rsp},
         path::native_path,
         reader::{Endian, FromReader},
-        signatures::{compare_signature, generate_signature, FunctionSignature},
+        signatures::{
+            compare_signature, generate_signature, generate_signatures, FunctionSignature,
+        },
         split::split_obj,
         IntoCow, ToCow,
     },
+    vfs::open_file,
 };
 
 #[derive(FromArgs, PartialEq, Debug)]
@@ -88,14 +91,19 @@ pub struct ConfigArgs {
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
 /// Builds function signatures from an ELF file.
+/// Without a symbol name, builds signatures for every function in the
+/// given object files and static libraries.
 #[argp(subcommand, name = "sigs")]
 pub struct SignaturesArgs {
     #[argp(positional, from_str_fn(native_path))]
     /// input file(s)
     files: Vec<Utf8NativePathBuf>,
     #[argp(option, short = 's')]
-    /// symbol name
-    symbol: String,
+    /// symbol name (if not specified, all functions in objects / archives)
+    symbol: Option<String>,
+    #[argp(option)]
+    /// minimum function size in bytes when building all signatures (default: 16)
+    min_size: Option<u32>,
     #[argp(option, short = 'o', from_str_fn(native_path))]
     /// output yml
     out_file: Utf8NativePathBuf,
@@ -133,6 +141,10 @@ fn config(args: ConfigArgs) -> Result<()> {
 fn disasm(args: DisasmArgs) -> Result<()> {
     log::info!("Loading {}", args.elf_file);
     let obj = process_elf(&args.elf_file)?;
+    let lines = process_elf_lines(&args.elf_file).unwrap_or_else(|e| {
+        log::warn!("{e:#}, continuing without line numbers");
+        None
+    });
     match obj.kind {
         ObjKind::Executable => {
             log::info!("Splitting {} objects", obj.link_order.len());
@@ -150,7 +162,7 @@ fn disasm(args: DisasmArgs) -> Result<()> {
                 log::info!("Writing {}", out_path);
 
                 let mut w = buf_writer(&out_path)?;
-                write_asm(&mut w, split_obj)?;
+                write_asm(&mut w, split_obj, lines.as_ref())?;
                 w.flush()?;
 
                 writeln!(files_out, "{}.o", out_name)?;
@@ -159,7 +171,7 @@ fn disasm(args: DisasmArgs) -> Result<()> {
         }
         ObjKind::Relocatable => {
             let mut w = buf_writer(&args.out)?;
-            write_asm(&mut w, &obj)?;
+            write_asm(&mut w, &obj, lines.as_ref())?;
             w.flush()?;
         }
     }
@@ -396,26 +408,79 @@ fn signatures(args: SignaturesArgs) -> Result<()> {
     let files = process_rsp(&args.files)?;
 
     let mut signatures: HashMap<String, FunctionSignature> = HashMap::new();
-    for path in files {
-        log::info!("Processing {}", path);
-        let signature = match generate_signature(&path, &args.symbol) {
-            Ok(Some(signature)) => signature,
-            Ok(None) => continue,
-            Err(e) => {
-                eprintln!("Failed: {:?}", e);
-                continue;
+    if let Some(symbol) = &args.symbol {
+        for path in files {
+            log::info!("Processing {}", path);
+            let signature = match generate_signature(&path, symbol) {
+                Ok(Some(signature)) => signature,
+                Ok(None) => continue,
+                Err(e) => {
+                    eprintln!("Failed: {:?}", e);
+                    continue;
+                }
+            };
+            log::info!("Comparing hash {}", signature.hash);
+            if let Some(existing) = signatures.get_mut(&signature.hash) {
+                compare_signature(existing, &signature)?;
+            } else {
+                signatures.insert(signature.hash.clone(), signature);
+            }
+        }
+    } else {
+        let min_size = args.min_size.unwrap_or(16);
+        for path in files {
+            log::info!("Processing {}", path);
+            let mut file = open_file(&path, true)?;
+            let buf = file.map()?;
+            let mut objects = vec![];
+            if buf.starts_with(b"!<arch>\n") {
+                let mut archive = ar::Archive::new(buf);
+                while let Some(entry) = archive.next_entry() {
+                    let mut entry =
+                        entry.with_context(|| format!("Processing entry in {}", path))?;
+                    let name = String::from_utf8_lossy(entry.header().identifier()).to_string();
+                    let mut data = vec![0u8; entry.header().size() as usize];
+                    entry.read_exact(&mut data)?;
+                    objects.push((name, data));
+                }
+            } else {
+                objects.push((path.to_string(), buf.to_vec()));
+            }
+            for (name, data) in objects {
+                let obj = match process_elf_data(&data) {
+                    Ok(obj) => obj,
+                    Err(e) => {
+                        log::warn!("Skipping {}: {:?}", name, e);
+                        continue;
+                    }
+                };
+                let object_signatures = generate_signatures(&obj, min_size)
+                    .with_context(|| format!("While processing {}", name))?;
+                log::debug!("{}: {} signatures", name, object_signatures.len());
+                // Functions are only merged if both the name and the hash match
+                for signature in object_signatures {
+                    let key = format!(
+                        "{}:{}",
+                        signature.symbols[signature.symbol as usize].name, signature.hash
+                    );
+                    if let Some(existing) = signatures.get_mut(&key) {
+                        if let Err(e) = compare_signature(existing, &signature) {
+                            log::warn!("Failed to merge {} from {}: {:?}", key, name, e);
+                        }
+                    } else {
+                        signatures.insert(key, signature);
+                    }
+                }
             }
-        };
-        log::info!("Comparing hash {}", signature.hash);
-        if let Some(existing) = signatures.get_mut(&signature.hash) {
-            compare_signature(existing, &signature)?;
-        } else {
-            signatures.insert(signature.hash.clone(), signature);
         }
     }
     let mut signatures = signatures.into_values().collect::<Vec<FunctionSignature>>();
     log::info!("{} unique signatures", signatures.len());
-    signatures.sort_by_key(|s| s.signature.len());
+    signatures.sort_by(|a, b| {
+        let a_name = &a.symbols[a.symbol as usize].name;
+        let b_name = &b.symbols[b.symbol as usize].name;
+        a_name.cmp(b_name).then(a.signature.len().cmp(&b.signature.len()))
+    });
     let mut out = buf_writer(&args.out_file)?;
     serde_yaml::to_writer(&mut out, &signatures)?;
     out.flush()?;
@@ -6,7 +6,7 @@ use object::{Architecture, Endianness, Object, ObjectKind, ObjectSection, Sectio
 use typed_path::Utf8NativePathBuf;
 
 use crate::{
-    obj::ObjSectionKind,
+    obj::{ObjInfo, ObjSectionKind},
     util::{alf::ALF_MAGIC, dol::process_dol, file::buf_writer, path::native_path},
     vfs::open_file,
 };
@@ -124,35 +124,8 @@ pub fn run(args: Args) -> Result<()> {
         header.bss_size = (address + size) - header.bss_address;
     }
 
-    // Offsets
     out.rewind()?;
-    for section in &header.text_sections {
-        out.write_all(&section.offset.to_be_bytes())?;
-    }
-    for section in &header.data_sections {
-        out.write_all(&section.offset.to_be_bytes())?;
-    }
-
-    // Addresses
-    for section in &header.text_sections {
-        out.write_all(&section.address.to_be_bytes())?;
-    }
-    for section in &header.data_sections {
-        out.write_all(&section.address.to_be_bytes())?;
-    }
-
-    // Sizes
-    for section in &header.text_sections {
-        out.write_all(&section.size.to_be_bytes())?;
-    }
-    for section in &header.data_sections {
-        out.write_all(&section.size.to_be_bytes())?;
-    }
-
-    // BSS + entry
-    out.write_all(&header.bss_address.to_be_bytes())?;
-    out.write_all(&header.bss_size.to_be_bytes())?;
-    out.write_all(&header.entry_point.to_be_bytes())?;
+    write_header(&mut out, &header)?;
 
     // Done!
     out.flush()?;
@@ -161,10 +134,18 @@ pub fn run(args: Args) -> Result<()> {
 
 fn convert_alf(args: Args, data: &[u8]) -> Result<()> {
     let obj = process_dol(data, "")?;
+    let mut out = buf_writer(&args.dol_file)?;
+    write_dol(&obj, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
 
-    let mut header = DolHeader { entry_point: obj.entry.unwrap() as u32, ..Default::default() };
+/// Writes a DOL file from an executable object.
+pub fn write_dol<W>(obj: &ObjInfo, out: &mut W) -> Result<()>
+where W: Write + Seek + ?Sized {
+    let entry_point = obj.entry.ok_or_else(|| anyhow!("Missing entry point"))? as u32;
+    let mut header = DolHeader { entry_point, ..Default::default() };
     let mut offset = 0x100u32;
-    let mut out = buf_writer(&args.dol_file)?;
     out.seek(SeekFrom::Start(offset as u64))?;
 
     // Text sections
@@ -176,7 +157,7 @@ fn convert_alf(args: Args, data: &[u8]) -> Result<()> {
             anyhow!("Too many text sections (while processing '{}')", section.name)
         })? = DolSection { offset, address, size };
         header.text_section_count += 1;
-        write_aligned(&mut out, &section.data, size)?;
+        write_aligned(out, &section.data, size)?;
         offset += size;
     }
 
@@ -193,7 +174,7 @@ fn convert_alf(args: Args, data: &[u8]) -> Result<()> {
             anyhow!("Too many data sections (while processing '{}')", section.name)
         })? = DolSection { offset, address, size };
         header.data_section_count += 1;
-        write_aligned(&mut out, &section.data, size)?;
+        write_aligned(out, &section.data, size)?;
         offset += size;
     }
 
@@ -207,8 +188,14 @@ fn convert_alf(args: Args, data: &[u8]) -> Result<()> {
         header.bss_size = (address + size) - header.bss_address;
     }
 
-    // Offsets
     out.rewind()?;
+    write_header(out, &header)?;
+    Ok(())
+}
+
+fn write_header<W>(out: &mut W, header: &DolHeader) -> std::io::Result<()>
+where W: Write + ?Sized {
+    // Offsets
     for section in &header.text_sections {
         out.write_all(&section.offset.to_be_bytes())?;
     }
@@ -236,9 +223,6 @@ fn convert_alf(args: Args, data: &[u8]) -> Result<()> {
     out.write_all(&header.bss_address.to_be_bytes())?;
     out.write_all(&header.bss_size.to_be_bytes())?;
     out.write_all(&header.entry_point.to_be_bytes())?;
-
-    // Done!
-    out.flush()?;
     Ok(())
 }
 
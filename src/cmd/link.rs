@@ -0,0 +1,65 @@
+use std::{fs, io::Write};
+
+use anyhow::{Context, Result};
+use argp::FromArgs;
+use typed_path::Utf8NativePathBuf;
+
+use crate::{
+    cmd::elf2dol::write_dol,
+    util::{
+        elf::{process_elf, write_elf},
+        file::{buf_writer, process_rsp},
+        link::{link, parse_linker_script},
+        path::native_path,
+    },
+};
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Links split objects into an ELF and DOL without mwld.
+#[argp(subcommand, name = "link")]
+pub struct Args {
+    #[argp(option, short = 'l', from_str_fn(native_path))]
+    /// linker script (ldscript.lcf)
+    lcf: Utf8NativePathBuf,
+    #[argp(option, short = 'o', from_str_fn(native_path))]
+    /// output ELF file
+    out: Utf8NativePathBuf,
+    #[argp(option, from_str_fn(native_path))]
+    /// output DOL file
+    dol: Option<Utf8NativePathBuf>,
+    #[argp(switch)]
+    /// keep unreferenced functions and objects (like mwld -nodeadstrip)
+    no_dead_strip: bool,
+    #[argp(positional, from_str_fn(native_path))]
+    /// input object files, in link order (supports @rspfile)
+    files: Vec<Utf8NativePathBuf>,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let script_text = fs::read_to_string(&args.lcf)
+        .with_context(|| format!("Failed to read linker script '{}'", args.lcf))?;
+    let script = parse_linker_script(&script_text)
+        .with_context(|| format!("Failed to parse linker script '{}'", args.lcf))?;
+
+    let files = process_rsp(&args.files)?;
+    let mut objects = Vec::with_capacity(files.len());
+    for path in &files {
+        let mut obj =
+            process_elf(path).with_context(|| format!("Failed to load object '{}'", path))?;
+        if obj.name.is_empty() {
+            obj.name = path.to_string();
+        }
+        objects.push(obj);
+    }
+
+    let obj = link(&script, &objects, !args.no_dead_strip)?;
+    log::info!("Linked {} objects", objects.len());
+    fs::write(&args.out, write_elf(&obj, false)?)
+        .with_context(|| format!("Failed to write ELF '{}'", args.out))?;
+    if let Some(dol_path) = &args.dol {
+        let mut out = buf_writer(dol_path)?;
+        write_dol(&obj, &mut out)?;
+        out.flush()?;
+    }
+    Ok(())
+}
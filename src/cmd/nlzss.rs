@@ -1,11 +1,17 @@
-use std::fs;
+use std::{fmt, fs, str::FromStr};
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use argp::FromArgs;
 use typed_path::Utf8NativePathBuf;
 
 use crate::{
-    util::{file::process_rsp, nlzss, path::native_path, IntoCow, ToCow},
+    util::{
+        file::process_rsp,
+        nlzss,
+        nlzss::{CompressionLevel, LZ11_MAX_LENGTH, LZ77_MAGIC},
+        path::native_path,
+        IntoCow, ToCow,
+    },
     vfs::open_file,
 };
 
@@ -20,11 +26,65 @@ pub struct Args {
 #[derive(FromArgs, PartialEq, Debug)]
 #[argp(subcommand)]
 enum SubCommand {
+    Compress(CompressArgs),
     Decompress(DecompressArgs),
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
-/// Decompresses NLZSS-compressed files.
+/// Compresses files using NLZSS.
+#[argp(subcommand, name = "compress")]
+pub struct CompressArgs {
+    #[argp(positional, from_str_fn(native_path))]
+    /// Files to compress
+    files: Vec<Utf8NativePathBuf>,
+    #[argp(option, short = 'o', from_str_fn(native_path))]
+    /// Output file (or directory, if multiple files are specified).
+    /// If not specified, compresses in-place.
+    output: Option<Utf8NativePathBuf>,
+    #[argp(option, short = 'f', default = "NlzssFormat::Lz10", from_str_fn(nlzss_format))]
+    /// Compression format: lz10 (default), lz11 or rle.
+    format: NlzssFormat,
+    #[argp(switch)]
+    /// Prepend the "LZ77" magic. (LZ10 only)
+    lz77: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NlzssFormat {
+    Lz10,
+    Lz11,
+    Rle,
+}
+
+impl FromStr for NlzssFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lz10" => Ok(Self::Lz10),
+            "lz11" => Ok(Self::Lz11),
+            "rle" => Ok(Self::Rle),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for NlzssFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Lz10 => write!(f, "lz10"),
+            Self::Lz11 => write!(f, "lz11"),
+            Self::Rle => write!(f, "rle"),
+        }
+    }
+}
+
+fn nlzss_format(s: &str) -> Result<NlzssFormat, String> {
+    NlzssFormat::from_str(s).map_err(|_| format!("Invalid compression format: {}", s))
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Decompresses NLZSS-compressed files. (LZ10, LZ11, Huffman and RLE)
 #[argp(subcommand, name = "decompress")]
 pub struct DecompressArgs {
     #[argp(positional, from_str_fn(native_path))]
@@ -38,10 +98,49 @@ pub struct DecompressArgs {
 
 pub fn run(args: Args) -> Result<()> {
     match args.command {
+        SubCommand::Compress(args) => compress(args),
         SubCommand::Decompress(args) => decompress(args),
     }
 }
 
+fn compress(args: CompressArgs) -> Result<()> {
+    if args.lz77 && args.format != NlzssFormat::Lz10 {
+        bail!("--lz77 is only supported with LZ10");
+    }
+    let files = process_rsp(&args.files)?;
+    let single_file = files.len() == 1;
+    for path in files {
+        let data = {
+            let mut file = open_file(&path, false)?;
+            let input = file.map()?;
+            let mut out = vec![];
+            if args.lz77 {
+                out.extend_from_slice(&LZ77_MAGIC);
+            }
+            match args.format {
+                NlzssFormat::Lz10 => nlzss::compress(input, &mut out, CompressionLevel::LZ10)?,
+                NlzssFormat::Lz11 => {
+                    nlzss::compress(input, &mut out, CompressionLevel::LZ11(LZ11_MAX_LENGTH))?
+                }
+                NlzssFormat::Rle => out = nlzss::compress_rle(input)?,
+            }
+            out
+        };
+        let out_path = if let Some(output) = &args.output {
+            if single_file {
+                output.as_path().to_cow()
+            } else {
+                output.join(path.file_name().unwrap()).into_cow()
+            }
+        } else {
+            path.as_path().to_cow()
+        };
+        fs::write(out_path.as_ref(), data)
+            .with_context(|| format!("Failed to write '{}'", out_path))?;
+    }
+    Ok(())
+}
+
 fn decompress(args: DecompressArgs) -> Result<()> {
     let files = process_rsp(&args.files)?;
     let single_file = files.len() == 1;
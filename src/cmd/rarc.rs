@@ -1,9 +1,22 @@
-use anyhow::Result;
+use std::{collections::HashMap, fs};
+
+use anyhow::{anyhow, bail, Context, Result};
 use argp::FromArgs;
-use typed_path::Utf8NativePathBuf;
+use serde::{Deserialize, Serialize};
+use typed_path::{Utf8NativePath, Utf8NativePathBuf};
 
 use super::vfs;
-use crate::util::path::native_path;
+use crate::{
+    util::{
+        ncompress::{compress_yaz0_with, compression_algo, CompressionAlgo},
+        path::native_path,
+        rarc::{
+            write_rarc, RarcDirEntry, RarcDirectory, RarcEntry, RarcFileEntry, RarcPreload,
+            RarcView,
+        },
+    },
+    vfs::open_file,
+};
 
 #[derive(FromArgs, PartialEq, Debug)]
 /// Commands for processing RSO files.
@@ -18,6 +31,7 @@ pub struct Args {
 enum SubCommand {
     List(ListArgs),
     Extract(ExtractArgs),
+    Create(CreateArgs),
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -50,24 +64,277 @@ pub struct ExtractArgs {
     quiet: bool,
 }
 
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Creates a RARC file from a directory.
+#[argp(subcommand, name = "create")]
+pub struct CreateArgs {
+    #[argp(positional, from_str_fn(native_path))]
+    /// input directory
+    input: Utf8NativePathBuf,
+    #[argp(option, short = 'o', from_str_fn(native_path))]
+    /// output RARC file
+    output: Utf8NativePathBuf,
+    #[argp(option, short = 'm', from_str_fn(native_path))]
+    /// manifest file (YAML) with the entry order and preload types
+    /// (default: .rarc.yml in the input directory, if present)
+    manifest: Option<Utf8NativePathBuf>,
+    #[argp(switch)]
+    /// Compress the output with Yaz0.
+    yaz0: bool,
+    #[argp(option, default = "CompressionAlgo::Fast", from_str_fn(compression_algo))]
+    /// Yaz0 encoder: fast (default), nintendo or best.
+    algo: CompressionAlgo,
+}
+
+/// Name of the manifest written by `rarc extract` and read by `rarc create`.
+const MANIFEST_FILE: &str = ".rarc.yml";
+
+/// Manifest for `rarc create`. Written by `rarc extract`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RarcManifest {
+    /// Name of the root directory. Defaults to the input directory name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    root_name: Option<String>,
+    /// Whether file IDs match node indices. (Default: true)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sync_ids: Option<bool>,
+    /// Files and directories in archive order, by path relative to the input directory.
+    /// Entries not listed are added after the listed ones, sorted by name.
+    entries: Vec<RarcManifestEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RarcManifestEntry {
+    path: String,
+    /// Preload type of a file. (Default: mram)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    preload: Option<RarcPreload>,
+}
+
 pub fn run(args: Args) -> Result<()> {
     match args.command {
         SubCommand::List(c_args) => list(c_args),
         SubCommand::Extract(c_args) => extract(c_args),
+        SubCommand::Create(c_args) => create(c_args),
     }
 }
 
 fn list(args: ListArgs) -> Result<()> {
     let path = Utf8NativePathBuf::from(format!("{}:", args.file));
-    vfs::ls(vfs::LsArgs { path, short: args.short, recursive: true })
+    vfs::ls(vfs::LsArgs { path, short: args.short, recursive: true, json: false })
 }
 
 fn extract(args: ExtractArgs) -> Result<()> {
+    let manifest = {
+        let mut file = open_file(&args.file, true)?;
+        let view = RarcView::new(file.map()?).map_err(|e| anyhow!(e))?;
+        rarc_manifest(&view)?
+    };
     let path = Utf8NativePathBuf::from(format!("{}:", args.file));
     let output = args.output.unwrap_or_else(|| Utf8NativePathBuf::from("."));
     vfs::cp(vfs::CpArgs {
-        paths: vec![path, output],
+        paths: vec![path, output.clone()],
         no_decompress: args.no_decompress,
         quiet: args.quiet,
+    })?;
+    let manifest_path = output.join(MANIFEST_FILE);
+    fs::write(&manifest_path, serde_yaml::to_string(&manifest)?)
+        .with_context(|| format!("Failed to write manifest '{}'", manifest_path))?;
+    Ok(())
+}
+
+/// Records the root name, file ID mode, entry order and preload types of an archive.
+fn rarc_manifest(view: &RarcView) -> Result<RarcManifest> {
+    let root = view.directories[0];
+    let root_name = view.get_string(root.name_offset()).map_err(|e| anyhow!(e))?;
+    let mut entries = vec![];
+    manifest_entries(view, root, "", &mut entries)?;
+    Ok(RarcManifest {
+        root_name: Some(root_name.to_string()),
+        sync_ids: Some(view.has_synced_ids()),
+        entries,
     })
 }
+
+fn manifest_entries(
+    view: &RarcView,
+    dir: RarcDirectory,
+    prefix: &str,
+    out: &mut Vec<RarcManifestEntry>,
+) -> Result<()> {
+    for node in view.children(dir) {
+        let name = view.get_string(node.name_offset()).map_err(|e| anyhow!(e))?;
+        if name == "." || name == ".." {
+            continue;
+        }
+        let path = format!("{}{}", prefix, name);
+        if node.is_dir() {
+            let sub = view
+                .directories
+                .get(node.data_offset() as usize)
+                .copied()
+                .ok_or_else(|| anyhow!("Invalid directory index for '{}'", path))?;
+            let prefix = format!("{}/", path);
+            out.push(RarcManifestEntry { path, preload: None });
+            manifest_entries(view, sub, &prefix, out)?;
+        } else {
+            out.push(RarcManifestEntry { path, preload: Some(node.preload()) });
+        }
+    }
+    Ok(())
+}
+
+fn create(args: CreateArgs) -> Result<()> {
+    let manifest_path = match args.manifest {
+        Some(path) => Some(path),
+        None => Some(args.input.join(MANIFEST_FILE))
+            .filter(|path| fs::metadata(path).is_ok_and(|m| m.is_file())),
+    };
+    let mut manifest = match &manifest_path {
+        Some(path) => {
+            let text = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read manifest '{}'", path))?;
+            serde_yaml::from_str(&text)
+                .with_context(|| format!("Failed to parse manifest '{}'", path))?
+        }
+        None => RarcManifest::default(),
+    };
+    let root_name = match manifest.root_name.take() {
+        Some(name) => name,
+        None => args
+            .input
+            .file_name()
+            .ok_or_else(|| anyhow!("Input path '{}' has no directory name", args.input))?
+            .to_string(),
+    };
+    let mut order = HashMap::with_capacity(manifest.entries.len());
+    for (index, entry) in manifest.entries.into_iter().enumerate() {
+        if order.insert(entry.path.clone(), (index, entry.preload)).is_some() {
+            bail!("Duplicate manifest entry '{}'", entry.path);
+        }
+    }
+    let root = read_dir_entry(&args.input, root_name, "", &mut order)?;
+    if let Some((path, _)) = order.iter().min_by_key(|(_, (index, _))| *index) {
+        bail!("Manifest entry '{}' not found in '{}'", path, args.input);
+    }
+
+    let mut data = write_rarc(&root, manifest.sync_ids.unwrap_or(true))?;
+    if args.yaz0 {
+        data = compress_yaz0_with(&data, args.algo).into_vec();
+    }
+    fs::write(&args.output, data)
+        .with_context(|| format!("Failed to write RARC file '{}'", args.output))?;
+    Ok(())
+}
+
+/// Reads a directory tree. Entries are ordered as listed in the manifest, followed by the
+/// unlisted entries sorted case-insensitively by name.
+fn read_dir_entry(
+    path: &Utf8NativePath,
+    name: String,
+    prefix: &str,
+    order: &mut HashMap<String, (usize, Option<RarcPreload>)>,
+) -> Result<RarcDirEntry> {
+    let mut entries = fs::read_dir(path)
+        .with_context(|| format!("Failed to read directory '{}'", path))?
+        .map(|entry| {
+            let entry = entry?;
+            let name = entry
+                .file_name()
+                .into_string()
+                .map_err(|name| anyhow!("Invalid file name {:?} in '{}'", name, path))?;
+            let rel_path = format!("{}{}", prefix, name);
+            let (index, preload) = order.remove(&rel_path).unwrap_or((usize::MAX, None));
+            Ok((index, name, rel_path, preload, entry.file_type()?.is_dir()))
+        })
+        .filter(|result| {
+            // Skip the manifest written by `rarc extract`
+            !matches!(result, Ok((_, _, rel_path, _, false)) if rel_path == MANIFEST_FILE)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    entries.sort_by(|(a_index, a, ..), (b_index, b, ..)| {
+        a_index
+            .cmp(b_index)
+            .then_with(|| a.to_ascii_lowercase().cmp(&b.to_ascii_lowercase()))
+            .then_with(|| a.cmp(b))
+    });
+
+    let mut dir = RarcDirEntry { name, ..Default::default() };
+    for (_, name, rel_path, preload, is_dir) in entries {
+        let child_path = path.join(&name);
+        if is_dir {
+            if preload.is_some() {
+                bail!("Manifest entry '{}' is a directory and can't have a preload type", rel_path);
+            }
+            let prefix = format!("{}/", rel_path);
+            dir.entries.push(RarcEntry::Dir(read_dir_entry(&child_path, name, &prefix, order)?));
+        } else {
+            let data = fs::read(&child_path)
+                .with_context(|| format!("Failed to read file '{}'", child_path))?;
+            let preload = preload.unwrap_or_default();
+            dir.entries.push(RarcEntry::File(RarcFileEntry { name, data, preload }));
+        }
+    }
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, data: &[u8], preload: RarcPreload) -> RarcEntry {
+        RarcEntry::File(RarcFileEntry { name: name.to_string(), data: data.to_vec(), preload })
+    }
+
+    fn dir(name: &str, entries: Vec<RarcEntry>) -> RarcEntry {
+        RarcEntry::Dir(RarcDirEntry { name: name.to_string(), entries })
+    }
+
+    #[test]
+    fn test_extract_create_roundtrip() {
+        // Unsorted names, directories before files and non-default preload types
+        let root = RarcDirEntry {
+            name: "stage".to_string(),
+            entries: vec![
+                dir("zdir", vec![
+                    file("b.bin", b"b", RarcPreload::Dvd),
+                    file("A.bin", b"a", RarcPreload::Mram),
+                ]),
+                file("room.bin", &[1; 40], RarcPreload::Aram),
+                dir("Adir", vec![dir("inner", vec![file("c.bin", b"c", RarcPreload::Mram)])]),
+                file("event.dat", b"event", RarcPreload::Mram),
+            ],
+        };
+        let data = write_rarc(&root, false).unwrap();
+
+        let temp_dir = std::env::temp_dir().join(format!("dtk-rarc-test-{}", std::process::id()));
+        let temp_dir = Utf8NativePathBuf::from(temp_dir.to_str().unwrap());
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir).unwrap();
+        let arc_path = temp_dir.join("input.arc");
+        let out_path = temp_dir.join("output.arc");
+        let extract_dir = temp_dir.join("extracted");
+        fs::write(&arc_path, &data).unwrap();
+
+        extract(ExtractArgs {
+            file: arc_path,
+            output: Some(extract_dir.clone()),
+            no_decompress: true,
+            quiet: true,
+        })
+        .unwrap();
+        create(CreateArgs {
+            input: extract_dir,
+            output: out_path.clone(),
+            manifest: None,
+            yaz0: false,
+            algo: CompressionAlgo::Fast,
+        })
+        .unwrap();
+        let result = fs::read(&out_path).unwrap();
+        fs::remove_dir_all(&temp_dir).unwrap();
+        assert!(result == data, "Recreated archive differs from the original");
+    }
+}
@@ -23,7 +23,7 @@ use crate::{
             AnalysisPass, FindRelCtorsDtors, FindRelRodataData, FindSaveRestSleds,
             FindTRKInterruptVectorTable,
         },
-        signatures::{apply_signatures, apply_signatures_post},
+        signatures::{apply_signatures, apply_signatures_post, SignatureDb},
         tracker::Tracker,
     },
     array_ref_mut,
@@ -419,8 +419,9 @@ fn info(args: InfoArgs) -> Result<()> {
     FindRelRodataData::execute(&mut state, &module_obj)?;
     state.apply(&mut module_obj)?;
 
-    apply_signatures(&mut module_obj)?;
-    apply_signatures_post(&mut module_obj)?;
+    let signatures = SignatureDb::default();
+    apply_signatures(&mut module_obj, &signatures)?;
+    apply_signatures_post(&mut module_obj, &signatures)?;
 
     println!("REL module ID: {}", header.module_id);
     println!("REL version: {}", header.version);
@@ -486,7 +487,8 @@ fn merge(args: MergeArgs) -> Result<()> {
     };
 
     log::info!("Performing signature analysis");
-    apply_signatures(&mut obj)?;
+    let signatures = SignatureDb::default();
+    apply_signatures(&mut obj, &signatures)?;
     let Some(arena_lo) = obj.arena_lo else { bail!("Failed to locate __ArenaLo in DOL") };
 
     let mut processed = 0;
@@ -602,7 +604,7 @@ fn merge(args: MergeArgs) -> Result<()> {
     FindTRKInterruptVectorTable::execute(&mut state, &obj)?;
     state.apply(&mut obj)?;
 
-    apply_signatures_post(&mut obj)?;
+    apply_signatures_post(&mut obj, &signatures)?;
 
     log::info!("Performing relocation analysis");
     let mut tracker = Tracker::new(&obj);
@@ -617,7 +619,7 @@ fn merge(args: MergeArgs) -> Result<()> {
     Ok(())
 }
 
-fn link_relocations(obj: &mut ObjInfo) -> Result<()> {
+pub fn link_relocations(obj: &mut ObjInfo) -> Result<()> {
     for (_, section) in obj.sections.iter_mut() {
         for (source_address, reloc) in section.relocations.iter() {
             let target_address =
@@ -1,6 +1,10 @@
-use std::io::{BufRead, Seek, Write};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{BufRead, Seek, Write},
+};
 
-use anyhow::{bail, ensure, Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use argp::FromArgs;
 use object::{
     elf::{R_PPC_NONE, R_PPC_REL24},
@@ -10,12 +14,31 @@ use object::{
 use typed_path::{Utf8NativePath, Utf8NativePathBuf};
 
 use crate::{
+    analysis::{
+        cfa::{AnalyzerState, SectionAddress},
+        pass::{AnalysisPass, FindSaveRestSleds, FindTRKInterruptVectorTable},
+        signatures::{apply_signatures, apply_signatures_post, SignatureDb},
+        tracker::Tracker,
+    },
+    cmd::{
+        dol::{apply_selfile, resolve_selfile_address},
+        rel::link_relocations,
+    },
+    obj::{
+        ObjInfo, ObjReloc, ObjRelocKind, ObjSection, ObjSymbol, SectionIndex as ObjSectionIndex,
+    },
     util::{
-        file::buf_writer,
+        align_up,
+        config::parse_u32,
+        dol::process_dol,
+        elf::write_elf,
+        file::{buf_writer, FileIterator},
+        nested::NestedMap,
         path::native_path,
         reader::{Endian, ToWriter},
         rso::{
-            process_rso, symbol_hash, RsoHeader, RsoRelocation, RsoSectionHeader, RsoSymbol,
+            process_rso, process_rso_module, symbol_hash, update_rso_section_alignment, RsoHeader,
+            RsoLinkInfo, RsoRelocation, RsoSectionHeader, RsoSymbol, DOL_SECTION_ABS,
             RSO_SECTION_NAMES,
         },
     },
@@ -35,6 +58,7 @@ pub struct Args {
 enum SubCommand {
     Info(InfoArgs),
     Make(MakeArgs),
+    Merge(MergeArgs),
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -67,10 +91,34 @@ pub struct MakeArgs {
     export: Option<Utf8NativePathBuf>,
 }
 
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Merges a DOL + SEL + RSO(s) into an ELF.
+#[argp(subcommand, name = "merge")]
+pub struct MergeArgs {
+    #[argp(positional, from_str_fn(native_path))]
+    /// DOL file
+    dol_file: Utf8NativePathBuf,
+    #[argp(positional, from_str_fn(native_path))]
+    /// RSO file(s)
+    rso_files: Vec<Utf8NativePathBuf>,
+    #[argp(option, short = 's', from_str_fn(native_path))]
+    /// SEL file
+    selfile: Utf8NativePathBuf,
+    #[argp(option, short = 'o', from_str_fn(native_path))]
+    /// output ELF
+    out_file: Utf8NativePathBuf,
+    #[argp(option, short = 'a', from_str_fn(parse_address))]
+    /// (optional) load address for each RSO, in order (default: after __ArenaLo)
+    addresses: Vec<u32>,
+}
+
+fn parse_address(value: &str) -> Result<u32, String> { parse_u32(value).map_err(|e| e.to_string()) }
+
 pub fn run(args: Args) -> Result<()> {
     match args.command {
         SubCommand::Info(c_args) => info(c_args),
         SubCommand::Make(c_args) => make(c_args),
+        SubCommand::Merge(c_args) => merge(c_args),
     }
 }
 
@@ -118,6 +166,198 @@ fn make(args: MakeArgs) -> Result<()> {
     Ok(())
 }
 
+fn merge(args: MergeArgs) -> Result<()> {
+    log::info!("Loading {}", args.dol_file);
+    let mut obj = {
+        let mut file = open_file(&args.dol_file, true)?;
+        let name = args.dol_file.file_stem().unwrap_or_default();
+        process_dol(file.map()?, name)?
+    };
+
+    log::info!("Performing signature analysis");
+    let signatures = SignatureDb::default();
+    apply_signatures(&mut obj, &signatures)?;
+
+    log::info!("Loading {}", args.selfile);
+    let sel_info = {
+        let mut file = open_file(&args.selfile, true)?;
+        apply_selfile(&mut obj, file.map()?)?
+    };
+
+    // RSOs don't have module IDs, so assign them sequentially
+    let mut modules = Vec::<(ObjInfo, RsoLinkInfo)>::new();
+    for result in FileIterator::new(&args.rso_files)? {
+        let (path, mut entry) = result?;
+        log::info!("Loading {}", path);
+        let name = path.file_stem().unwrap_or_default();
+        let (header, mut module, link_info) =
+            process_rso_module(&mut entry, name, modules.len() as u32 + 1)?;
+        update_rso_section_alignment(&mut module, &header)?;
+        modules.push((module, link_info));
+    }
+
+    log::info!("Merging {} RSO(s)", modules.len());
+    let mut section_map: BTreeMap<u32, BTreeMap<ObjSectionIndex, u32>> = BTreeMap::new();
+    let mut offset = match args.addresses.first() {
+        Some(&address) => address,
+        None => {
+            let Some(arena_lo) = obj.arena_lo else { bail!("Failed to locate __ArenaLo in DOL") };
+            align_up(arena_lo + 0x2000, 32)
+        }
+    };
+    for (module_index, (module, _)) in modules.iter().enumerate() {
+        if let Some(&address) = args.addresses.get(module_index) {
+            offset = address;
+        }
+        log::info!("Placing {} at {:#010X}", module.name, offset);
+        for (mod_section_index, mod_section) in module.sections.iter() {
+            ensure!(mod_section.relocations.is_empty(), "Unsupported relocations during merge");
+            offset = align_up(offset, mod_section.align.max(32) as u32);
+            let section_idx = obj.sections.push(ObjSection {
+                name: format!("{}:{}", mod_section.name, module.name),
+                kind: mod_section.kind,
+                address: offset as u64,
+                size: mod_section.size,
+                data: mod_section.data.clone(),
+                align: mod_section.align,
+                elf_index: mod_section.elf_index,
+                relocations: Default::default(),
+                virtual_address: mod_section.virtual_address,
+                file_offset: mod_section.file_offset,
+                section_known: mod_section.section_known,
+                splits: mod_section.splits.clone(),
+            });
+            section_map.nested_insert(module.module_id, mod_section.elf_index, offset)?;
+            for (_, mod_symbol) in module.symbols.for_section(mod_section_index) {
+                obj.symbols.add_direct(ObjSymbol {
+                    address: mod_symbol.address + offset as u64,
+                    section: Some(section_idx),
+                    ..mod_symbol.clone()
+                })?;
+            }
+            offset += mod_section.size as u32;
+        }
+        offset = align_up(offset, 32);
+    }
+
+    // Collect the SEL and RSO export tables for resolving imports by name
+    let mut exports = HashMap::<String, u32>::new();
+    for export in &sel_info.exports {
+        let (_, address) = resolve_selfile_address(&obj, export.section, export.offset)?;
+        exports.entry(export.name.clone()).or_insert(address);
+    }
+    for (module, link_info) in &modules {
+        for export in &link_info.exports {
+            let address = if export.section == DOL_SECTION_ABS {
+                export.offset
+            } else {
+                section_map
+                    .get(&module.module_id)
+                    .and_then(|m| m.get(&export.section))
+                    .with_context(|| {
+                        format!("Export {} in unknown section {}", export.name, export.section)
+                    })?
+                    + export.offset
+            };
+            if exports.insert(export.name.clone(), address).is_some() {
+                log::warn!("Duplicate export {} in {}", export.name, module.name);
+            }
+        }
+    }
+
+    log::info!("Applying RSO relocations");
+    for (module, link_info) in &modules {
+        let Some(section_map) = section_map.get(&module.module_id) else { continue };
+        for reloc in &module.unresolved_relocations {
+            let source_addr = section_map[&(reloc.section as ObjSectionIndex)] + reloc.address;
+            let target_addr =
+                section_map.get(&(reloc.target_section as ObjSectionIndex)).with_context(|| {
+                    format!(
+                        "Relocation against unknown section {} in {}",
+                        reloc.target_section, module.name
+                    )
+                })? + reloc.addend;
+            add_merged_relocation(&mut obj, reloc.kind, source_addr, target_addr)?;
+        }
+        for import in &link_info.imports {
+            let Some(&symbol_addr) = exports.get(&import.symbol) else {
+                log::warn!("Unresolved import {} in {}", import.symbol, module.name);
+                continue;
+            };
+            let source_addr = section_map[&(import.section as ObjSectionIndex)] + import.address;
+            let target_addr = symbol_addr + import.addend;
+            add_merged_relocation(&mut obj, import.kind, source_addr, target_addr)?;
+        }
+    }
+
+    // Apply relocations to code/data for analyzer
+    link_relocations(&mut obj)?;
+
+    log::info!("Detecting function boundaries");
+    let mut state = AnalyzerState::default();
+    FindSaveRestSleds::execute(&mut state, &obj)?;
+    state.detect_functions(&obj)?;
+    log::info!(
+        "Discovered {} functions",
+        state.functions.iter().filter(|(_, i)| i.is_function()).count()
+    );
+
+    FindTRKInterruptVectorTable::execute(&mut state, &obj)?;
+    state.apply(&mut obj)?;
+
+    apply_signatures_post(&mut obj, &signatures)?;
+
+    log::info!("Performing relocation analysis");
+    let mut tracker = Tracker::new(&obj);
+    tracker.process(&obj)?;
+
+    log::info!("Applying relocations");
+    tracker.apply(&mut obj, false)?;
+
+    // Write ELF
+    log::info!("Writing {}", args.out_file);
+    fs::write(&args.out_file, write_elf(&obj, false)?)?;
+    Ok(())
+}
+
+fn add_merged_relocation(
+    obj: &mut ObjInfo,
+    kind: ObjRelocKind,
+    source_addr: u32,
+    target_addr: u32,
+) -> Result<()> {
+    let (source_section_index, _) = obj.sections.at_address(source_addr)?;
+    let (target_section_index, _) = obj.sections.at_address(target_addr).map_err(|_| {
+        anyhow!(
+            "Relocation at {:#010X} targets unmapped address {:#010X}",
+            source_addr,
+            target_addr
+        )
+    })?;
+
+    let (symbol_idx, addend) = if let Some((symbol_idx, symbol)) =
+        obj.symbols.for_relocation(SectionAddress::new(target_section_index, target_addr), kind)?
+    {
+        (symbol_idx, target_addr as i64 - symbol.address as i64)
+    } else {
+        // Create a new label
+        let symbol_idx = obj.symbols.add_direct(ObjSymbol {
+            name: String::new(),
+            address: target_addr as u64,
+            section: Some(target_section_index),
+            ..Default::default()
+        })?;
+        (symbol_idx, 0)
+    };
+    obj.sections[source_section_index].relocations.insert(source_addr, ObjReloc {
+        kind,
+        target_symbol: symbol_idx,
+        addend,
+        module: None,
+    })?;
+    Ok(())
+}
+
 fn make_sel(
     _file: object::File,
     _output: &Utf8NativePath,
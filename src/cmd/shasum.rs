@@ -1,24 +1,33 @@
 use std::{
+    collections::BTreeSet,
+    fmt,
     fs::File,
-    io::{stdout, BufRead, Read, Write},
+    io::{stdout, BufRead, Read, Seek, SeekFrom, Write},
+    str::FromStr,
 };
 
 use anyhow::{anyhow, bail, Context, Result};
 use argp::FromArgs;
 use owo_colors::{OwoColorize, Stream};
 use sha1::{Digest, Sha1};
-use typed_path::{Utf8NativePath, Utf8NativePathBuf};
+use sha2::Sha256;
+use typed_path::{Utf8NativePath, Utf8NativePathBuf, Utf8UnixPath, Utf8UnixPathBuf};
+use xxhash_rust::xxh3::xxh3_64;
 
 use crate::{
     util::{
         file::{buf_writer, process_rsp, touch},
+        ncompress::{check_roundtrip, compression_algo, CompressionAlgo},
         path::native_path,
     },
-    vfs::open_file,
+    vfs::{
+        decompress_file, detect, open_file, open_fs, open_path, ArchiveKind, FileFormat,
+        OpenResult, Vfs, VfsError, VfsFile, VfsFileType,
+    },
 };
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
-/// Print or check SHA1 (160-bit) checksums.
+/// Print or check SHA1 (160-bit) checksums, optionally for each file in a container.
 #[argp(subcommand, name = "shasum")]
 pub struct Args {
     #[argp(switch, short = 'c')]
@@ -34,15 +43,99 @@ pub struct Args {
     #[argp(switch, short = 'q')]
     /// only print failures and a summary
     quiet: bool,
+    #[argp(option, from_str_fn(compression_algo))]
+    /// (check) also check that Yaz0/Yay0 files recompress identically
+    /// with this encoder (nintendo, fast or best)
+    roundtrip: Option<CompressionAlgo>,
+    #[argp(option, short = 'm', from_str_fn(native_path))]
+    /// (hash) hash each file inside this directory, disc image or archive
+    /// (check) check the listed files inside this directory, disc image or archive
+    manifest: Option<Utf8NativePathBuf>,
+    #[argp(option, short = 'a', default = "HashKind::Sha1", from_str_fn(hash_kind))]
+    /// (hash) hash algorithm (sha1, sha256, crc32 or xxh3)
+    hash: HashKind,
+    #[argp(switch, short = 'd')]
+    /// (manifest) hash the decompressed contents of Yaz0/Yay0/NLZSS files
+    decompress: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HashKind {
+    Sha1,
+    Sha256,
+    Crc32,
+    Xxh3,
+}
+
+impl FromStr for HashKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sha1" => Ok(Self::Sha1),
+            "sha256" => Ok(Self::Sha256),
+            "crc32" => Ok(Self::Crc32),
+            "xxh3" => Ok(Self::Xxh3),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for HashKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sha1 => write!(f, "sha1"),
+            Self::Sha256 => write!(f, "sha256"),
+            Self::Crc32 => write!(f, "crc32"),
+            Self::Xxh3 => write!(f, "xxh3"),
+        }
+    }
+}
+
+impl HashKind {
+    /// Determines the algorithm from the length of a hex-encoded hash.
+    fn from_hex_len(len: usize) -> Option<Self> {
+        match len {
+            8 => Some(Self::Crc32),
+            16 => Some(Self::Xxh3),
+            40 => Some(Self::Sha1),
+            64 => Some(Self::Sha256),
+            _ => None,
+        }
+    }
+
+    fn hash(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Sha1 => Sha1::digest(data).to_vec(),
+            Self::Sha256 => Sha256::digest(data).to_vec(),
+            Self::Crc32 => crc32fast::hash(data).to_be_bytes().to_vec(),
+            Self::Xxh3 => xxh3_64(data).to_be_bytes().to_vec(),
+        }
+    }
+
+    fn hash_reader<R>(self, reader: &mut R) -> Result<Vec<u8>>
+    where R: Read + ?Sized {
+        if self == Self::Sha1 {
+            return Ok(file_sha1(reader)?.to_vec());
+        }
+        let mut data = vec![];
+        reader.read_to_end(&mut data).context("File read failed")?;
+        Ok(self.hash(&data))
+    }
+}
+
+fn hash_kind(s: &str) -> Result<HashKind, String> {
+    HashKind::from_str(s).map_err(|_| format!("Invalid hash algorithm: {}", s))
 }
 
 const DEFAULT_BUF_SIZE: usize = 8192;
 
 pub fn run(args: Args) -> Result<()> {
     if args.check {
+        let mut root = args.manifest.as_deref().map(ManifestRoot::open).transpose()?;
         for path in process_rsp(&args.files)? {
             let mut file = open_file(&path, false)?;
-            check(&args, file.as_mut())?;
+            check(&args, file.as_mut(), root.as_mut())?;
         }
         if let Some(out_path) = &args.output {
             touch(out_path)
@@ -57,18 +150,180 @@ pub fn run(args: Args) -> Result<()> {
         } else {
             Box::new(stdout())
         };
+        if let Some(manifest) = &args.manifest {
+            if !args.files.is_empty() {
+                bail!("Input files can't be used with --manifest");
+            }
+            hash_manifest(&args, w.as_mut(), manifest)?;
+        }
         for path in process_rsp(&args.files)? {
             let mut file = open_file(&path, false)?;
-            hash(w.as_mut(), file.as_mut(), &path)?
+            hash(w.as_mut(), file.as_mut(), &path, args.hash)?
+        }
+    }
+    Ok(())
+}
+
+/// The directory, disc image or archive described by a manifest.
+struct ManifestRoot {
+    fs: Box<dyn Vfs>,
+    dir: Utf8UnixPathBuf,
+    /// Nested archives opened for the last path, outermost first, with their paths. Manifests
+    /// list the files of an archive together, so this avoids opening and decompressing the
+    /// archive again for each of them.
+    archives: Vec<(String, Box<dyn Vfs>)>,
+}
+
+impl ManifestRoot {
+    fn open(path: &Utf8NativePath) -> Result<Self> {
+        let (mut file, file_path) = match open_path(path, false)? {
+            OpenResult::Directory(fs, dir) => return Ok(Self { fs, dir, archives: vec![] }),
+            OpenResult::File(file, file_path) => (file, file_path),
+        };
+        let mut format = detect(file.as_mut())?;
+        if let FileFormat::Compressed(kind) = format {
+            file = decompress_file(file.as_mut(), kind)?;
+            format = detect(file.as_mut())?;
+        }
+        let FileFormat::Archive(kind) = format else {
+            bail!("{} is not a directory, disc image or archive", file_path);
+        };
+        let fs = open_fs(file, kind).with_context(|| format!("Failed to open {}", file_path))?;
+        Ok(Self { fs, dir: Utf8UnixPathBuf::new(), archives: vec![] })
+    }
+
+    /// Lists all files below the root, sorted by path. Files inside nested archives follow
+    /// the archive, e.g. `files/foo.szs:bar.bin`.
+    fn files(&mut self) -> Result<Vec<String>> {
+        let mut files = vec![];
+        list_files(self.fs.as_mut(), &self.dir, "", &mut files)?;
+        files.sort();
+        Ok(files)
+    }
+
+    /// Opens a file by its path relative to the root, returning `None` if it doesn't exist.
+    fn open_file(&mut self, name: &str, decompress: bool) -> Result<Option<Box<dyn VfsFile>>> {
+        let segments = name.split(':').collect::<Vec<_>>();
+        let depth = segments.len() - 1;
+        // Keep the archives shared with the last path
+        let shared = self
+            .archives
+            .iter()
+            .take(depth)
+            .enumerate()
+            .take_while(|(i, (path, _))| *path == segments[..=*i].join(":"))
+            .count();
+        self.archives.truncate(shared);
+        while self.archives.len() < depth {
+            let i = self.archives.len();
+            let Some(file) = self.open_segment(i, segments[i], name)? else {
+                return Ok(None);
+            };
+            let Some(fs) =
+                open_archive(file).with_context(|| format!("Failed to open {}", name))?
+            else {
+                return Ok(None);
+            };
+            self.archives.push((segments[..=i].join(":"), fs));
+        }
+        let Some(mut file) = self.open_segment(depth, segments[depth], name)? else {
+            return Ok(None);
+        };
+        if decompress {
+            if let FileFormat::Compressed(kind) = detect(file.as_mut())? {
+                file = decompress_file(file.as_mut(), kind)
+                    .with_context(|| format!("Failed to decompress {}", name))?;
+            }
+        }
+        Ok(Some(file))
+    }
+
+    /// Opens a path segment in the root (`depth` 0) or in the archive at `depth - 1`.
+    fn open_segment(
+        &mut self,
+        depth: usize,
+        segment: &str,
+        name: &str,
+    ) -> Result<Option<Box<dyn VfsFile>>> {
+        let result = match depth {
+            0 => self.fs.open(&self.dir.join(segment)),
+            _ => self.archives[depth - 1].1.open(Utf8UnixPath::new(segment)),
+        };
+        match result {
+            Ok(file) => Ok(Some(file)),
+            Err(VfsError::NotFound) => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to open {}", name)),
+        }
+    }
+}
+
+/// Opens a file as a RARC or U8 archive, decompressing it first if needed. Returns `None` if
+/// it isn't one.
+fn open_archive(mut file: Box<dyn VfsFile>) -> Result<Option<Box<dyn Vfs>>> {
+    let mut format = detect(file.as_mut())?;
+    if let FileFormat::Compressed(kind) = format {
+        file = decompress_file(file.as_mut(), kind)?;
+        format = detect(file.as_mut())?;
+    }
+    match format {
+        FileFormat::Archive(kind @ (ArchiveKind::Rarc | ArchiveKind::U8)) => {
+            Ok(Some(open_fs(file, kind)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Lists the files below `dir`, recursing into nested archives. `prefix` is prepended to each
+/// path.
+fn list_files(
+    fs: &mut dyn Vfs,
+    dir: &Utf8UnixPath,
+    prefix: &str,
+    out: &mut Vec<String>,
+) -> Result<()> {
+    for name in fs.read_dir(dir).with_context(|| format!("Failed to read {}", dir))? {
+        let path = dir.join(&name);
+        let name = format!("{}{}", prefix, name);
+        let metadata =
+            fs.metadata(&path).with_context(|| format!("Failed to fetch metadata for {}", path))?;
+        match metadata.file_type {
+            VfsFileType::File => {
+                let file = fs.open(&path).with_context(|| format!("Failed to open {}", name))?;
+                let nested = open_archive(file).unwrap_or_else(|e| {
+                    log::warn!("Failed to open {}: {:#}", name, e);
+                    None
+                });
+                out.push(name.clone());
+                if let Some(mut nested) = nested {
+                    list_files(nested.as_mut(), Utf8UnixPath::new(""), &format!("{}:", name), out)?;
+                }
+            }
+            VfsFileType::Directory => list_files(fs, &path, &format!("{}/", name), out)?,
         }
     }
     Ok(())
 }
 
-fn check<R>(args: &Args, reader: &mut R) -> Result<()>
+fn hash_manifest<W>(args: &Args, w: &mut W, path: &Utf8NativePath) -> Result<()>
+where W: Write + ?Sized {
+    let mut root = ManifestRoot::open(path)?;
+    for name in root.files()? {
+        let Some(mut file) = root.open_file(&name, args.decompress)? else {
+            bail!("Failed to open {}", name);
+        };
+        let data = file.map().with_context(|| format!("Failed to read {}", name))?;
+        writeln!(w, "{}  {}", hex::encode(args.hash.hash(data)), name)?;
+    }
+    Ok(())
+}
+
+fn check<R>(args: &Args, reader: &mut R, mut root: Option<&mut ManifestRoot>) -> Result<()>
 where R: BufRead + ?Sized {
     let mut matches = 0usize;
     let mut mismatches = 0usize;
+    let mut roundtrip_failures = 0usize;
+    let mut missing = 0usize;
+    let mut listed = BTreeSet::new();
     for line in reader.lines() {
         let line = match line {
             Ok(line) => line,
@@ -80,15 +335,57 @@ where R: BufRead + ?Sized {
             Some(' ') | Some('*') => &file_name[1..],
             _ => bail!("Invalid line: {line}"),
         };
-        let mut hash_bytes = [0u8; 20];
-        hex::decode_to_slice(hash, &mut hash_bytes)
-            .with_context(|| format!("Invalid line: {line}"))?;
-
-        let found_hash = file_sha1(
-            &mut File::open(file_name)
-                .with_context(|| format!("Failed to open file '{file_name}'"))?,
-        )?;
-        if hash_bytes == found_hash.as_ref() {
+        let kind =
+            HashKind::from_hex_len(hash.len()).ok_or_else(|| anyhow!("Invalid line: {line}"))?;
+        let hash_bytes = hex::decode(hash).with_context(|| format!("Invalid line: {line}"))?;
+
+        let (found_hash, roundtrip_ok) = match root.as_deref_mut() {
+            Some(root) => {
+                listed.insert(file_name.to_string());
+                let Some(mut file) = root.open_file(file_name, args.decompress)? else {
+                    println!(
+                        "{}: {}",
+                        file_name,
+                        "MISSING".if_supports_color(Stream::Stdout, |t| t.red())
+                    );
+                    missing += 1;
+                    continue;
+                };
+                let data = file.map().with_context(|| format!("Failed to read '{file_name}'"))?;
+                let roundtrip_ok = match args.roundtrip {
+                    Some(algo) => check_roundtrip(data, algo)
+                        .with_context(|| format!("Failed to decompress '{file_name}'"))?
+                        .unwrap_or(true),
+                    None => true,
+                };
+                (kind.hash(data), roundtrip_ok)
+            }
+            None => {
+                let mut file = File::open(file_name)
+                    .with_context(|| format!("Failed to open file '{file_name}'"))?;
+                let found_hash = kind.hash_reader(&mut file)?;
+                let roundtrip_ok = match args.roundtrip {
+                    Some(algo) => {
+                        let mut data = vec![];
+                        file.seek(SeekFrom::Start(0))?;
+                        file.read_to_end(&mut data)?;
+                        check_roundtrip(&data, algo)
+                            .with_context(|| format!("Failed to decompress '{file_name}'"))?
+                            .unwrap_or(true)
+                    }
+                    None => true,
+                };
+                (found_hash, roundtrip_ok)
+            }
+        };
+        if hash_bytes == found_hash && !roundtrip_ok {
+            println!(
+                "{}: {}",
+                file_name,
+                "ROUNDTRIP FAILED".if_supports_color(Stream::Stdout, |t| t.red())
+            );
+            roundtrip_failures += 1;
+        } else if hash_bytes == found_hash {
             if !args.quiet {
                 println!(
                     "{}: {}",
@@ -102,6 +399,19 @@ where R: BufRead + ?Sized {
             mismatches += 1;
         }
     }
+    let mut unlisted = 0usize;
+    if let Some(root) = root {
+        for name in root.files()? {
+            if !listed.contains(&name) {
+                println!(
+                    "{}: {}",
+                    name,
+                    "NOT IN MANIFEST".if_supports_color(Stream::Stdout, |t| t.red())
+                );
+                unlisted += 1;
+            }
+        }
+    }
     if args.quiet && matches > 0 {
         println!("{} files {}", matches, "OK".if_supports_color(Stream::Stdout, |t| t.green()));
     }
@@ -111,21 +421,41 @@ where R: BufRead + ?Sized {
             format!("WARNING: {mismatches} computed checksum(s) did NOT match")
                 .if_supports_color(Stream::Stdout, |t| t.yellow())
         );
+    }
+    if roundtrip_failures != 0 {
+        eprintln!(
+            "{}",
+            format!("WARNING: {roundtrip_failures} file(s) did NOT recompress identically")
+                .if_supports_color(Stream::Stdout, |t| t.yellow())
+        );
+    }
+    if missing != 0 {
+        eprintln!(
+            "{}",
+            format!("WARNING: {missing} listed file(s) are missing")
+                .if_supports_color(Stream::Stdout, |t| t.yellow())
+        );
+    }
+    if unlisted != 0 {
+        eprintln!(
+            "{}",
+            format!("WARNING: {unlisted} file(s) are not in the manifest")
+                .if_supports_color(Stream::Stdout, |t| t.yellow())
+        );
+    }
+    if mismatches != 0 || roundtrip_failures != 0 || missing != 0 || unlisted != 0 {
         std::process::exit(1);
     }
     Ok(())
 }
 
-fn hash<R, W>(w: &mut W, reader: &mut R, path: &Utf8NativePath) -> Result<()>
+fn hash<R, W>(w: &mut W, reader: &mut R, path: &Utf8NativePath, kind: HashKind) -> Result<()>
 where
     R: Read + ?Sized,
     W: Write + ?Sized,
 {
-    let hash = file_sha1(reader)?;
-    let mut hash_buf = [0u8; 40];
-    let hash_str = base16ct::lower::encode_str(&hash, &mut hash_buf)
-        .map_err(|e| anyhow!("Failed to encode hash: {e}"))?;
-    writeln!(w, "{}  {}", hash_str, path.with_unix_encoding())?;
+    let hash = kind.hash_reader(reader)?;
+    writeln!(w, "{}  {}", hex::encode(hash), path.with_unix_encoding())?;
     Ok(())
 }
 
@@ -1,9 +1,14 @@
-use anyhow::Result;
+use std::fs;
+
+use anyhow::{anyhow, bail, Context, Result};
 use argp::FromArgs;
-use typed_path::Utf8NativePathBuf;
+use typed_path::{Utf8NativePath, Utf8NativePathBuf};
 
 use super::vfs;
-use crate::util::path::native_path;
+use crate::util::{
+    path::native_path,
+    u8_arc::{add_imd5, add_imet, strip_header, write_u8, ImetHeader, StrippedHeader, U8Entry},
+};
 
 #[derive(FromArgs, PartialEq, Debug)]
 /// Commands for processing U8 (arc) files.
@@ -18,6 +23,8 @@ pub struct Args {
 enum SubCommand {
     List(ListArgs),
     Extract(ExtractArgs),
+    Create(CreateArgs),
+    Strip(StripArgs),
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -50,16 +57,51 @@ pub struct ExtractArgs {
     quiet: bool,
 }
 
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Creates a U8 (arc) file from a directory.
+#[argp(subcommand, name = "create")]
+pub struct CreateArgs {
+    #[argp(positional, from_str_fn(native_path))]
+    /// input directory
+    input: Utf8NativePathBuf,
+    #[argp(option, short = 'o', from_str_fn(native_path))]
+    /// output U8 (arc) file
+    output: Utf8NativePathBuf,
+    #[argp(switch)]
+    /// Add an IMD5 header. (banner.bin, icon.bin, sound.bin)
+    imd5: bool,
+    #[argp(option)]
+    /// Add an IMET header with this title, repeated in language order. (opening.bnr)
+    imet_title: Vec<String>,
+    #[argp(option, from_str_fn(native_path))]
+    /// Add an IMET header copied from this file. (opening.bnr)
+    imet_header: Option<Utf8NativePathBuf>,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Removes the IMET or IMD5 header from a file.
+#[argp(subcommand, name = "strip")]
+pub struct StripArgs {
+    #[argp(positional, from_str_fn(native_path))]
+    /// input file
+    input: Utf8NativePathBuf,
+    #[argp(option, short = 'o', from_str_fn(native_path))]
+    /// output file
+    output: Utf8NativePathBuf,
+}
+
 pub fn run(args: Args) -> Result<()> {
     match args.command {
         SubCommand::List(c_args) => list(c_args),
         SubCommand::Extract(c_args) => extract(c_args),
+        SubCommand::Create(c_args) => create(c_args),
+        SubCommand::Strip(c_args) => strip(c_args),
     }
 }
 
 fn list(args: ListArgs) -> Result<()> {
     let path = Utf8NativePathBuf::from(format!("{}:", args.file));
-    vfs::ls(vfs::LsArgs { path, short: args.short, recursive: true })
+    vfs::ls(vfs::LsArgs { path, short: args.short, recursive: true, json: false })
 }
 
 fn extract(args: ExtractArgs) -> Result<()> {
@@ -71,3 +113,100 @@ fn extract(args: ExtractArgs) -> Result<()> {
         quiet: args.quiet,
     })
 }
+
+fn create(args: CreateArgs) -> Result<()> {
+    let imet = match (&args.imet_header, args.imet_title.is_empty()) {
+        (Some(_), false) => bail!("--imet-header and --imet-title are mutually exclusive"),
+        (Some(path), true) => {
+            let data = fs::read(path).with_context(|| format!("Failed to read '{}'", path))?;
+            let (header, _) =
+                strip_header(&data).with_context(|| format!("Failed to read '{}'", path))?;
+            let StrippedHeader::Imet { header, prefix } = header else {
+                bail!("'{}' does not have an IMET header", path);
+            };
+            Some((header.clone(), prefix.to_vec()))
+        }
+        (None, false) => Some((ImetHeader::new(&args.imet_title)?, vec![])),
+        (None, true) => None,
+    };
+    if imet.is_some() && args.imd5 {
+        bail!("Cannot add both IMET and IMD5 headers");
+    }
+
+    let entries = read_entries(&args.input)?;
+    let mut data = write_u8(&entries)?;
+    if let Some((mut header, prefix)) = imet {
+        let file_size = |name: &str| -> u32 {
+            find_file(&entries, &["meta", name]).map(|d| d.len() as u32).unwrap_or(0)
+        };
+        header.set_sizes([file_size("icon.bin"), file_size("banner.bin"), file_size("sound.bin")]);
+        data = add_imet(header, &prefix, &data);
+    } else if args.imd5 {
+        data = add_imd5(&data);
+    }
+    fs::write(&args.output, data)
+        .with_context(|| format!("Failed to write U8 file '{}'", args.output))?;
+    Ok(())
+}
+
+/// Reads a directory tree, sorting entries case-insensitively by name.
+fn read_entries(path: &Utf8NativePath) -> Result<Vec<U8Entry>> {
+    let mut names = fs::read_dir(path)
+        .with_context(|| format!("Failed to read directory '{}'", path))?
+        .map(|entry| {
+            let entry = entry?;
+            let name = entry
+                .file_name()
+                .into_string()
+                .map_err(|name| anyhow!("Invalid file name {:?} in '{}'", name, path))?;
+            Ok((name, entry.file_type()?.is_dir()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    names.sort_by(|(a, _), (b, _)| {
+        a.to_ascii_lowercase().cmp(&b.to_ascii_lowercase()).then(a.cmp(b))
+    });
+
+    let mut entries = Vec::with_capacity(names.len());
+    for (name, is_dir) in names {
+        let child_path = path.join(&name);
+        if is_dir {
+            entries.push(U8Entry::Directory { entries: read_entries(&child_path)?, name });
+        } else {
+            let data = fs::read(&child_path)
+                .with_context(|| format!("Failed to read file '{}'", child_path))?;
+            entries.push(U8Entry::File { name, data });
+        }
+    }
+    Ok(entries)
+}
+
+fn find_file<'a>(entries: &'a [U8Entry], path: &[&str]) -> Option<&'a [u8]> {
+    let (first, rest) = path.split_first()?;
+    entries.iter().find_map(|entry| match entry {
+        U8Entry::File { name, data } if rest.is_empty() && name.eq_ignore_ascii_case(first) => {
+            Some(data.as_slice())
+        }
+        U8Entry::Directory { name, entries } if name.eq_ignore_ascii_case(first) => {
+            find_file(entries, rest)
+        }
+        _ => None,
+    })
+}
+
+fn strip(args: StripArgs) -> Result<()> {
+    let data = fs::read(&args.input).with_context(|| format!("Failed to read '{}'", args.input))?;
+    let (header, payload) =
+        strip_header(&data).with_context(|| format!("Failed to read '{}'", args.input))?;
+    match header {
+        StrippedHeader::Imd5(_) => log::info!("Removed IMD5 header"),
+        StrippedHeader::Imet { header, .. } => {
+            log::info!("Removed IMET header");
+            for title in header.titles().iter().filter(|t| !t.is_empty()) {
+                log::info!("Title: {}", title);
+            }
+        }
+    }
+    fs::write(&args.output, payload)
+        .with_context(|| format!("Failed to write '{}'", args.output))?;
+    Ok(())
+}
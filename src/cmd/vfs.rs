@@ -2,15 +2,22 @@ use std::{fs, fs::File, io::Write};
 
 use anyhow::{anyhow, bail, Context};
 use argp::FromArgs;
+use regex::Regex;
+use serde::Serialize;
 use size::Size;
 use typed_path::{Utf8NativePath, Utf8NativePathBuf, Utf8UnixPath};
 use unicode_width::UnicodeWidthStr;
 
 use crate::{
-    util::{file::buf_copy, path::native_path},
+    util::{
+        file::{buf_copy, buf_writer},
+        ncompress::{compression_algo, CompressionAlgo},
+        path::native_path,
+    },
     vfs::{
-        decompress_file, detect, open_path, FileFormat, OpenResult, Vfs, VfsFile, VfsFileType,
-        VfsMetadata,
+        decompress_file, detect, open_file, open_fs, open_path, repack, ArchiveKind,
+        CompressionKind, FileFormat, OpenResult, RepackOptions, RepackTree, Vfs, VfsFile,
+        VfsFileType, VfsMetadata,
     },
 };
 
@@ -26,7 +33,10 @@ pub struct Args {
 #[argp(subcommand)]
 enum SubCommand {
     Ls(LsArgs),
+    Find(FindArgs),
+    Tree(TreeArgs),
     Cp(CpArgs),
+    Repack(RepackArgs),
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -42,6 +52,51 @@ pub struct LsArgs {
     #[argp(switch, short = 'r')]
     /// Recursively list files in directories.
     pub recursive: bool,
+    #[argp(switch)]
+    /// Output JSON.
+    pub json: bool,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Find files in a directory or container, including nested containers.
+#[argp(subcommand, name = "find")]
+pub struct FindArgs {
+    #[argp(positional, from_str_fn(native_path))]
+    /// Directory or container path.
+    pub path: Utf8NativePathBuf,
+    #[argp(option, short = 'n')]
+    /// Match file names against a glob pattern. (case-insensitive)
+    pub name: Option<String>,
+    #[argp(option, short = 'e')]
+    /// Match full paths against a regular expression.
+    pub regex: Option<String>,
+    #[argp(option, short = 't')]
+    /// Only match this format after decompression. (e.g. file, rarc, u8, wad, disc)
+    pub format: Vec<String>,
+    #[argp(option, short = 'c')]
+    /// Only match this compression. (e.g. none, yaz0, yay0, lz10, lz11)
+    pub compression: Vec<String>,
+    #[argp(switch)]
+    /// Do not search inside nested containers.
+    pub no_nested: bool,
+    #[argp(switch)]
+    /// Output JSON.
+    pub json: bool,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Print the file tree of a directory or container.
+#[argp(subcommand, name = "tree")]
+pub struct TreeArgs {
+    #[argp(positional, from_str_fn(native_path))]
+    /// Directory or container path.
+    pub path: Utf8NativePathBuf,
+    #[argp(switch)]
+    /// Show the contents of nested containers.
+    pub nested: bool,
+    #[argp(switch)]
+    /// Output JSON.
+    pub json: bool,
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -59,10 +114,37 @@ pub struct CpArgs {
     pub quiet: bool,
 }
 
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Rebuild a container with replaced files.
+#[argp(subcommand, name = "repack")]
+pub struct RepackArgs {
+    #[argp(positional, from_str_fn(native_path))]
+    /// Container path.
+    pub path: Utf8NativePathBuf,
+    #[argp(option, short = 'r')]
+    /// Replacement as `inner/path=file`. Use `:` to replace files in nested containers.
+    pub replace: Vec<String>,
+    #[argp(option, short = 'a')]
+    /// File to add or replace, as `inner/path=file`.
+    pub add: Vec<String>,
+    #[argp(option, short = 'o', from_str_fn(native_path))]
+    /// Output file.
+    pub output: Utf8NativePathBuf,
+    #[argp(option, default = "CompressionAlgo::Nintendo", from_str_fn(compression_algo))]
+    /// Yaz0/Yay0 encoder: nintendo (default), fast or best.
+    pub algo: CompressionAlgo,
+    #[argp(switch)]
+    /// Fakesign rebuilt WADs.
+    pub fakesign: bool,
+}
+
 pub fn run(args: Args) -> anyhow::Result<()> {
     match args.command {
         SubCommand::Ls(args) => ls(args),
+        SubCommand::Find(args) => find(args),
+        SubCommand::Tree(args) => tree(args),
         SubCommand::Cp(args) => cp(args),
+        SubCommand::Repack(args) => repack_cmd(args),
     }
 }
 
@@ -79,70 +161,225 @@ fn column_widths<const N: usize>(entries: &[Columns<N>]) -> [usize; N] {
     widths
 }
 
-fn file_info(
+/// Detected format of a file, and of its decompressed contents if compressed.
+struct FileInfo {
+    format: FileFormat,
+    /// Decompressed size and format
+    decompressed: Option<(u64, FileFormat)>,
+}
+
+impl FileInfo {
+    /// Format of the file contents, after decompression.
+    fn inner_format(&self) -> FileFormat {
+        self.decompressed.map_or(self.format, |(_, format)| format)
+    }
+
+    fn compression(&self) -> Option<CompressionKind> {
+        match self.format {
+            FileFormat::Compressed(kind) => Some(kind),
+            _ => None,
+        }
+    }
+}
+
+fn file_info(filename: &str, file: &mut dyn VfsFile) -> anyhow::Result<FileInfo> {
+    Ok(detect_file(filename, file)?.0)
+}
+
+/// Detects the format of a file. Compressed files are decompressed to detect the format of their
+/// contents, and the decompressed file is returned for reuse.
+fn detect_file(
     filename: &str,
     file: &mut dyn VfsFile,
-    metadata: &VfsMetadata,
-) -> anyhow::Result<Columns<5>> {
+) -> anyhow::Result<(FileInfo, Option<Box<dyn VfsFile>>)> {
     let format =
         detect(file).with_context(|| format!("Failed to detect file format for {}", filename))?;
-    let mut info: Columns<5> = [
+    let FileFormat::Compressed(kind) = format else {
+        return Ok((FileInfo { format, decompressed: None }, None));
+    };
+    let mut file = decompress_file(file, kind)?;
+    let metadata =
+        file.metadata().with_context(|| format!("Failed to fetch metadata for {}", filename))?;
+    let inner_format = detect(file.as_mut())
+        .with_context(|| format!("Failed to detect file format for {}", filename))?;
+    Ok((FileInfo { format, decompressed: Some((metadata.len, inner_format)) }, Some(file)))
+}
+
+/// Opens and detects the format of a file, returning its contents (decompressed if
+/// necessary) for use with [`open_container`].
+fn open_file_info(
+    fs: &mut dyn Vfs,
+    path: &Utf8UnixPath,
+    display_path: &str,
+) -> anyhow::Result<(FileInfo, Box<dyn VfsFile>)> {
+    let mut file = fs.open(path).with_context(|| format!("Failed to open file {}", path))?;
+    let (info, decompressed) = detect_file(display_path, file.as_mut())?;
+    Ok((info, decompressed.unwrap_or(file)))
+}
+
+fn file_columns(filename: &str, metadata: &VfsMetadata, info: &FileInfo) -> Columns<5> {
+    let mut columns: Columns<5> = [
         Size::from_bytes(metadata.len).to_string(),
         filename.to_string(),
-        format.to_string(),
+        info.format.to_string(),
         String::new(),
         String::new(),
     ];
-    if let FileFormat::Compressed(kind) = format {
-        let mut decompressed = decompress_file(file, kind)?;
-        let metadata = decompressed
-            .metadata()
-            .with_context(|| format!("Failed to fetch metadata for {}", filename))?;
-        let format = detect(decompressed.as_mut())
-            .with_context(|| format!("Failed to detect file format for {}", filename))?;
-        info[3] = format!("Decompressed: {}", Size::from_bytes(metadata.len));
-        info[4] = format.to_string();
+    if let Some((len, format)) = info.decompressed {
+        columns[3] = format!("Decompressed: {}", Size::from_bytes(len));
+        columns[4] = format.to_string();
+    }
+    columns
+}
+
+fn directory_columns(filename: &str) -> Columns<5> {
+    [
+        "        ".to_string(),
+        format!("{}/", filename),
+        "Directory".to_string(),
+        String::new(),
+        String::new(),
+    ]
+}
+
+/// Short lowercase name of a format, as used in JSON output and filters.
+fn format_name(format: FileFormat) -> String {
+    match format {
+        FileFormat::Regular => "file".to_string(),
+        FileFormat::Compressed(kind) => kind.to_string().to_ascii_lowercase(),
+        FileFormat::Archive(ArchiveKind::Disc(_)) => "disc".to_string(),
+        FileFormat::Archive(kind) => kind.to_string().to_ascii_lowercase(),
     }
-    Ok(info)
+}
+
+/// A file or directory in JSON output.
+#[derive(Serialize)]
+struct JsonEntry {
+    path: String,
+    #[serde(rename = "type")]
+    file_type: &'static str,
+    size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offset: Option<u64>,
+    /// Format of the file contents, after decompression
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compression: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    decompressed_size: Option<u64>,
+    /// Entries of directories and nested containers (`vfs tree` only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    children: Option<Vec<JsonEntry>>,
+}
+
+impl JsonEntry {
+    fn new(path: String, metadata: &VfsMetadata, info: Option<&FileInfo>) -> Self {
+        Self {
+            path,
+            file_type: if metadata.is_dir() { "directory" } else { "file" },
+            size: metadata.len,
+            offset: metadata.offset,
+            format: info.map(|info| format_name(info.inner_format())),
+            compression: info
+                .and_then(|info| info.compression())
+                .map(|kind| kind.to_string().to_ascii_lowercase()),
+            decompressed_size: info.and_then(|info| info.decompressed).map(|(len, _)| len),
+            children: None,
+        }
+    }
+}
+
+fn print_json<T>(value: &T) -> anyhow::Result<()>
+where T: Serialize + ?Sized {
+    let mut out = std::io::stdout().lock();
+    serde_json::to_writer_pretty(&mut out, value)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Joins a path for display, keeping it usable as a VFS path.
+fn join_display(base: &str, name: &str) -> String {
+    if base.is_empty() || base.ends_with(':') || base.ends_with('/') {
+        format!("{}{}", base, name)
+    } else {
+        format!("{}/{}", base, name)
+    }
+}
+
+/// Opens the (decompressed) contents of a file as a container.
+fn open_container(
+    contents: Box<dyn VfsFile>,
+    info: &FileInfo,
+) -> anyhow::Result<Option<Box<dyn Vfs>>> {
+    let FileFormat::Archive(kind) = info.inner_format() else {
+        return Ok(None);
+    };
+    Ok(Some(open_fs(contents, kind)?))
+}
+
+struct ListEntry {
+    path: String,
+    metadata: VfsMetadata,
+    info: Option<FileInfo>,
 }
 
 pub fn ls(args: LsArgs) -> anyhow::Result<()> {
-    let mut files = Vec::new();
+    let mut entries = Vec::new();
     match open_path(&args.path, false)? {
         OpenResult::File(mut file, path) => {
             let filename = path.file_name().ok_or_else(|| anyhow!("Path has no filename"))?;
-            if args.short {
-                println!("{}", filename);
-            } else {
-                let metadata = file
-                    .metadata()
-                    .with_context(|| format!("Failed to fetch metadata for {}", path))?;
-                files.push(file_info(filename, file.as_mut(), &metadata)?);
-            }
+            let metadata = file
+                .metadata()
+                .with_context(|| format!("Failed to fetch metadata for {}", path))?;
+            let info = if args.short { None } else { Some(file_info(filename, file.as_mut())?) };
+            entries.push(ListEntry { path: filename.to_string(), metadata, info });
         }
         OpenResult::Directory(mut fs, path) => {
-            ls_directory(fs.as_mut(), &path, Utf8UnixPath::new(""), &args, &mut files)?;
+            ls_directory(fs.as_mut(), &path, Utf8UnixPath::new(""), &args, &mut entries)?;
         }
     }
-    if !args.short {
-        let widths = column_widths(&files);
-        for entry in files {
-            let mut written = 0;
-            for (i, column) in entry.iter().enumerate() {
-                if widths[i] > 0 {
-                    if written > 0 {
-                        print!("{}", SEPARATOR);
-                    }
-                    written += 1;
-                    print!("{}", column);
-                    let remain = widths[i].saturating_sub(column.width_cjk());
-                    if remain > 0 {
-                        print!("{:width$}", "", width = remain);
-                    }
+    if args.json {
+        let entries = entries
+            .into_iter()
+            .map(|entry| JsonEntry::new(entry.path, &entry.metadata, entry.info.as_ref()))
+            .collect::<Vec<_>>();
+        return print_json(&entries);
+    }
+    if args.short {
+        for entry in entries {
+            if entry.metadata.is_dir() {
+                println!("{}/", entry.path);
+            } else {
+                println!("{}", entry.path);
+            }
+        }
+        return Ok(());
+    }
+    let files = entries
+        .iter()
+        .map(|entry| match &entry.info {
+            Some(info) => file_columns(&entry.path, &entry.metadata, info),
+            None => directory_columns(&entry.path),
+        })
+        .collect::<Vec<_>>();
+    let widths = column_widths(&files);
+    for entry in files {
+        let mut written = 0;
+        for (i, column) in entry.iter().enumerate() {
+            if widths[i] > 0 {
+                if written > 0 {
+                    print!("{}", SEPARATOR);
+                }
+                written += 1;
+                print!("{}", column);
+                let remain = widths[i].saturating_sub(column.width_cjk());
+                if remain > 0 {
+                    print!("{:width$}", "", width = remain);
                 }
             }
-            println!();
         }
+        println!();
     }
     Ok(())
 }
@@ -152,11 +389,11 @@ fn ls_directory(
     path: &Utf8UnixPath,
     base_filename: &Utf8UnixPath,
     args: &LsArgs,
-    files: &mut Vec<Columns<5>>,
+    entries: &mut Vec<ListEntry>,
 ) -> anyhow::Result<()> {
-    let entries = fs.read_dir(path)?;
-    files.reserve(entries.len());
-    for filename in entries {
+    let filenames = fs.read_dir(path)?;
+    entries.reserve(filenames.len());
+    for filename in filenames {
         let entry_path = path.join(&filename);
         let display_path = base_filename.join(&filename);
         let metadata = fs
@@ -164,36 +401,265 @@ fn ls_directory(
             .with_context(|| format!("Failed to fetch metadata for {}", entry_path))?;
         match metadata.file_type {
             VfsFileType::File => {
-                let mut file = fs
-                    .open(&entry_path)
-                    .with_context(|| format!("Failed to open file {}", entry_path))?;
-                if args.short {
-                    println!("{}", display_path);
+                let info = if args.short {
+                    None
                 } else {
-                    files.push(file_info(display_path.as_str(), file.as_mut(), &metadata)?);
-                }
+                    let mut file = fs
+                        .open(&entry_path)
+                        .with_context(|| format!("Failed to open file {}", entry_path))?;
+                    Some(file_info(display_path.as_str(), file.as_mut())?)
+                };
+                entries.push(ListEntry { path: display_path.to_string(), metadata, info });
             }
             VfsFileType::Directory => {
-                if args.short {
-                    println!("{}/", display_path);
-                } else {
-                    files.push([
-                        "        ".to_string(),
-                        format!("{}/", display_path),
-                        "Directory".to_string(),
-                        String::new(),
-                        String::new(),
-                    ]);
-                }
+                entries.push(ListEntry { path: display_path.to_string(), metadata, info: None });
                 if args.recursive {
-                    ls_directory(fs, &entry_path, &display_path, args, files)?;
+                    ls_directory(fs, &entry_path, &display_path, args, entries)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Name and format filters for `vfs find`.
+struct FindFilter {
+    name: Option<glob::Pattern>,
+    regex: Option<Regex>,
+    formats: Vec<String>,
+    compressions: Vec<String>,
+}
+
+impl FindFilter {
+    fn matches(&self, path: &str, info: &FileInfo) -> bool {
+        let filename = path.rsplit(['/', ':']).next().unwrap_or(path);
+        let options = glob::MatchOptions { case_sensitive: false, ..Default::default() };
+        if self.name.as_ref().is_some_and(|p| !p.matches_with(filename, options)) {
+            return false;
+        }
+        if self.regex.as_ref().is_some_and(|r| !r.is_match(path)) {
+            return false;
+        }
+        if !self.formats.is_empty()
+            && !self
+                .formats
+                .iter()
+                .any(|f| f.eq_ignore_ascii_case(&format_name(info.inner_format())))
+        {
+            return false;
+        }
+        if !self.compressions.is_empty() {
+            let compression = info
+                .compression()
+                .map_or_else(|| "none".to_string(), |kind| kind.to_string().to_ascii_lowercase());
+            if !self.compressions.iter().any(|c| c.eq_ignore_ascii_case(&compression)) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+pub fn find(args: FindArgs) -> anyhow::Result<()> {
+    let filter = FindFilter {
+        name: args.name.as_deref().map(glob::Pattern::new).transpose()?,
+        regex: args.regex.as_deref().map(Regex::new).transpose()?,
+        formats: args.format.clone(),
+        compressions: args.compression.clone(),
+    };
+    let base = args.path.with_unix_encoding().to_string();
+    let mut results = Vec::new();
+    match open_path(&args.path, false)? {
+        OpenResult::File(mut file, _) => {
+            let metadata = file.metadata()?;
+            let (info, decompressed) = detect_file(&base, file.as_mut())?;
+            match open_container(decompressed.unwrap_or(file), &info)? {
+                Some(mut fs) => {
+                    let base = format!("{}:", base.trim_end_matches(':'));
+                    find_recursive(
+                        fs.as_mut(),
+                        Utf8UnixPath::new(""),
+                        &base,
+                        &args,
+                        &filter,
+                        &mut results,
+                    )?;
+                }
+                None => {
+                    if filter.matches(&base, &info) {
+                        results.push(JsonEntry::new(base, &metadata, Some(&info)));
+                    }
+                }
+            }
+        }
+        OpenResult::Directory(mut fs, path) => {
+            find_recursive(fs.as_mut(), &path, &base, &args, &filter, &mut results)?;
+        }
+    }
+    if args.json {
+        return print_json(&results);
+    }
+    for entry in results {
+        println!("{}", entry.path);
+    }
+    Ok(())
+}
+
+fn find_recursive(
+    fs: &mut dyn Vfs,
+    path: &Utf8UnixPath,
+    base: &str,
+    args: &FindArgs,
+    filter: &FindFilter,
+    results: &mut Vec<JsonEntry>,
+) -> anyhow::Result<()> {
+    for filename in fs.read_dir(path)? {
+        let entry_path = path.join(&filename);
+        let display_path = join_display(base, &filename);
+        let metadata = fs
+            .metadata(&entry_path)
+            .with_context(|| format!("Failed to fetch metadata for {}", entry_path))?;
+        match metadata.file_type {
+            VfsFileType::File => {
+                let (info, file) = match open_file_info(fs, &entry_path, &display_path) {
+                    Ok(result) => result,
+                    Err(e) => {
+                        log::warn!("Skipping {}: {:#}", display_path, e);
+                        continue;
+                    }
+                };
+                if filter.matches(&display_path, &info) {
+                    results.push(JsonEntry::new(display_path.clone(), &metadata, Some(&info)));
+                }
+                if args.no_nested {
+                    continue;
+                }
+                match open_container(file, &info) {
+                    Ok(Some(mut fs)) => {
+                        let base = format!("{}:", display_path);
+                        find_recursive(
+                            fs.as_mut(),
+                            Utf8UnixPath::new(""),
+                            &base,
+                            args,
+                            filter,
+                            results,
+                        )?;
+                    }
+                    Ok(None) => {}
+                    Err(e) => log::warn!("Failed to open container {}: {:#}", display_path, e),
                 }
             }
+            VfsFileType::Directory => {
+                find_recursive(fs, &entry_path, &display_path, args, filter, results)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn tree(args: TreeArgs) -> anyhow::Result<()> {
+    let base = args.path.with_unix_encoding().to_string();
+    let root = match open_path(&args.path, false)? {
+        OpenResult::File(mut file, _) => {
+            let metadata = file.metadata()?;
+            let (info, decompressed) = detect_file(&base, file.as_mut())?;
+            let mut root = JsonEntry::new(base.clone(), &metadata, Some(&info));
+            if let Some(mut fs) = open_container(decompressed.unwrap_or(file), &info)? {
+                let base = format!("{}:", base.trim_end_matches(':'));
+                root.children =
+                    Some(tree_recursive(fs.as_mut(), Utf8UnixPath::new(""), &base, &args)?);
+            }
+            root
+        }
+        OpenResult::Directory(mut fs, path) => {
+            let metadata = fs.metadata(&path)?;
+            let mut root = JsonEntry::new(base.clone(), &metadata, None);
+            root.children = Some(tree_recursive(fs.as_mut(), &path, &base, &args)?);
+            root
         }
+    };
+    if args.json {
+        return print_json(&root);
     }
+    println!("{}", root.path);
+    print_tree(root.children.as_deref().unwrap_or_default(), "");
     Ok(())
 }
 
+fn tree_recursive(
+    fs: &mut dyn Vfs,
+    path: &Utf8UnixPath,
+    base: &str,
+    args: &TreeArgs,
+) -> anyhow::Result<Vec<JsonEntry>> {
+    let mut entries = Vec::new();
+    for filename in fs.read_dir(path)? {
+        let entry_path = path.join(&filename);
+        let display_path = join_display(base, &filename);
+        let metadata = fs
+            .metadata(&entry_path)
+            .with_context(|| format!("Failed to fetch metadata for {}", entry_path))?;
+        match metadata.file_type {
+            VfsFileType::File => {
+                let (info, file) = match open_file_info(fs, &entry_path, &display_path) {
+                    Ok(result) => result,
+                    Err(e) => {
+                        log::warn!("Failed to read {}: {:#}", display_path, e);
+                        entries.push(JsonEntry::new(display_path, &metadata, None));
+                        continue;
+                    }
+                };
+                let mut entry = JsonEntry::new(display_path.clone(), &metadata, Some(&info));
+                if args.nested {
+                    match open_container(file, &info) {
+                        Ok(Some(mut fs)) => {
+                            let base = format!("{}:", display_path);
+                            entry.children = Some(tree_recursive(
+                                fs.as_mut(),
+                                Utf8UnixPath::new(""),
+                                &base,
+                                args,
+                            )?);
+                        }
+                        Ok(None) => {}
+                        Err(e) => log::warn!("Failed to open container {}: {:#}", display_path, e),
+                    }
+                }
+                entries.push(entry);
+            }
+            VfsFileType::Directory => {
+                let mut entry = JsonEntry::new(display_path.clone(), &metadata, None);
+                entry.children = Some(tree_recursive(fs, &entry_path, &display_path, args)?);
+                entries.push(entry);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+fn print_tree(entries: &[JsonEntry], prefix: &str) {
+    for (i, entry) in entries.iter().enumerate() {
+        let last = i == entries.len() - 1;
+        let name = entry.path.rsplit(['/', ':']).next().unwrap_or(&entry.path);
+        let connector = if last { "└── " } else { "├── " };
+        if entry.file_type == "directory" {
+            println!("{}{}{}/", prefix, connector, name);
+        } else {
+            let mut details = vec![Size::from_bytes(entry.size).to_string()];
+            details.extend(entry.compression.clone());
+            if let Some(format) = entry.format.as_deref().filter(|&f| f != "file") {
+                details.push(format.to_string());
+            }
+            println!("{}{}{} ({})", prefix, connector, name, details.join(", "));
+        }
+        if let Some(children) = &entry.children {
+            let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+            print_tree(children, &prefix);
+        }
+    }
+}
+
 pub fn cp(mut args: CpArgs) -> anyhow::Result<()> {
     if args.paths.len() < 2 {
         bail!("Both source and destination paths must be provided");
@@ -289,3 +755,25 @@ fn cp_recursive(
     }
     Ok(())
 }
+
+fn repack_cmd(args: RepackArgs) -> anyhow::Result<()> {
+    let mut tree = RepackTree::default();
+    let replacements = args.replace.iter().map(|r| (r, false));
+    for (replace, add) in replacements.chain(args.add.iter().map(|r| (r, true))) {
+        let (inner, path) = replace
+            .rsplit_once('=')
+            .ok_or_else(|| anyhow!("Invalid replacement '{}', expected path=file", replace))?;
+        let data = fs::read(path).with_context(|| format!("Failed to read {}", path))?;
+        tree.insert(inner, data.into(), add)?;
+    }
+    if tree.is_empty() {
+        bail!("No replacements provided");
+    }
+    let file = open_file(&args.path, false)?;
+    let options = RepackOptions { algo: args.algo, fakesign: args.fakesign };
+    let mut w = buf_writer(&args.output)?;
+    repack(file, &tree, &options, &mut w)
+        .with_context(|| format!("Failed to repack {}", args.path))?;
+    w.flush().with_context(|| format!("Failed to write file {}", args.output))?;
+    Ok(())
+}
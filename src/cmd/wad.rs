@@ -1,13 +1,16 @@
-use anyhow::Result;
+use std::{fs, io::Write};
+
+use anyhow::{anyhow, bail, Context, Result};
 use argp::FromArgs;
 use size::Size;
-use typed_path::Utf8NativePathBuf;
+use typed_path::{Utf8NativePath, Utf8NativePathBuf};
 
 use crate::{
     cmd::vfs,
     util::{
+        file::buf_writer,
         path::native_path,
-        wad::{process_wad, verify_wad},
+        wad::{process_wad, tmd_contents, verify_wad, write_wad, WadKind, WadPackInput},
     },
     vfs::open_file,
 };
@@ -26,6 +29,7 @@ enum SubCommand {
     Extract(ExtractArgs),
     Info(InfoArgs),
     Verify(VerifyArgs),
+    Pack(PackArgs),
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -64,21 +68,49 @@ pub struct VerifyArgs {
     file: Utf8NativePathBuf,
 }
 
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Packs extracted WAD contents into a WAD file.
+#[argp(subcommand, name = "pack")]
+pub struct PackArgs {
+    #[argp(positional, from_str_fn(native_path))]
+    /// directory containing the ticket, TMD, certificate chain and contents
+    input: Utf8NativePathBuf,
+    #[argp(option, short = 'o', from_str_fn(native_path))]
+    /// output WAD file
+    output: Utf8NativePathBuf,
+    #[argp(switch)]
+    /// Fakesign the ticket and TMD.
+    fakesign: bool,
+    #[argp(switch)]
+    /// Write a boot2 (ib) WAD.
+    boot2: bool,
+}
+
 pub fn run(args: Args) -> Result<()> {
     match args.command {
         SubCommand::Info(c_args) => info(c_args),
         SubCommand::Verify(c_args) => verify(c_args),
         SubCommand::Extract(c_args) => extract(c_args),
+        SubCommand::Pack(c_args) => pack(c_args),
     }
 }
 
 fn info(args: InfoArgs) -> Result<()> {
     let mut file = open_file(&args.file, true)?;
     let wad = process_wad(file.as_mut())?;
-    println!("Title ID: {}", hex::encode(wad.ticket().title_id));
-    println!("Title key: {}", hex::encode(wad.title_key));
-    println!("Fake signed: {}", wad.fake_signed);
-    for content in wad.contents() {
+    println!("WAD type: {}", wad.kind);
+    println!("Title ID: {}", hex::encode(wad.title_id()));
+    if let Some(header) = &wad.backup_header {
+        println!("Console ID: {:08X}", header.console_id.get());
+    } else {
+        println!("Title key: {}", hex::encode(wad.title_key));
+        println!("Fake signed: {}", wad.fake_signed);
+    }
+    for (i, content) in wad.contents().iter().enumerate() {
+        if !wad.is_included(i as u16) {
+            println!("Content {:08x}: Not included", content.content_index.get());
+            continue;
+        }
         println!(
             "Content {:08x}: Offset {:#X}, size {}",
             content.content_index.get(),
@@ -106,3 +138,45 @@ fn extract(args: ExtractArgs) -> Result<()> {
         quiet: args.quiet,
     })
 }
+
+fn pack(args: PackArgs) -> Result<()> {
+    // Find the title ID from the TMD file name, as written by `wad extract`
+    let mut title_id = None;
+    for entry in fs::read_dir(&args.input)
+        .with_context(|| format!("Failed to read directory '{}'", args.input))?
+    {
+        let name = entry?.file_name();
+        let Some(stem) = name.to_str().and_then(|name| name.strip_suffix(".tmd")) else {
+            continue;
+        };
+        if title_id.is_some() {
+            bail!("Multiple TMD files found in '{}'", args.input);
+        }
+        title_id = Some(stem.to_string());
+    }
+    let title_id = title_id.ok_or_else(|| anyhow!("No TMD file found in '{}'", args.input))?;
+    let read = |path: &Utf8NativePath| -> Result<Box<[u8]>> {
+        Ok(fs::read(path).with_context(|| format!("Failed to read '{}'", path))?.into())
+    };
+
+    let raw_tmd = read(&args.input.join(format!("{}.tmd", title_id)))?;
+    let raw_ticket = read(&args.input.join(format!("{}.tik", title_id)))?;
+    let raw_cert_chain = read(&args.input.join(format!("{}.cert", title_id)))?;
+    let trailer_path = args.input.join(format!("{}.trailer", title_id));
+    let footer = if fs::metadata(&trailer_path).is_ok_and(|m| m.is_file()) {
+        read(&trailer_path)?
+    } else {
+        Box::default()
+    };
+    let contents = tmd_contents(&raw_tmd)?
+        .iter()
+        .map(|content| read(&args.input.join(format!("{:08x}.app", content.content_index.get()))))
+        .collect::<Result<Vec<_>>>()?;
+
+    let kind = if args.boot2 { WadKind::Boot2 } else { WadKind::Installable };
+    let input = WadPackInput { kind, raw_cert_chain, raw_ticket, raw_tmd, contents, footer };
+    let mut out = buf_writer(&args.output)?;
+    write_wad(&mut out, input, args.fakesign)?;
+    out.flush()?;
+    Ok(())
+}
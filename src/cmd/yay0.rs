@@ -7,7 +7,7 @@ use typed_path::Utf8NativePathBuf;
 use crate::{
     util::{
         file::process_rsp,
-        ncompress::{compress_yay0, decompress_yay0},
+        ncompress::{compress_yay0_with, compression_algo, decompress_yay0, CompressionAlgo},
         path::native_path,
         IntoCow, ToCow,
     },
@@ -40,6 +40,9 @@ pub struct CompressArgs {
     /// Output file (or directory, if multiple files are specified).
     /// If not specified, compresses in-place.
     output: Option<Utf8NativePathBuf>,
+    #[argp(option, default = "CompressionAlgo::Fast", from_str_fn(compression_algo))]
+    /// Encoder: fast (default), nintendo (byte-identical to SDK tools) or best (smallest).
+    algo: CompressionAlgo,
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -68,7 +71,7 @@ fn compress(args: CompressArgs) -> Result<()> {
     for path in files {
         let data = {
             let mut file = open_file(&path, false)?;
-            compress_yay0(file.map()?)
+            compress_yay0_with(file.map()?, args.algo)
         };
         let out_path = if let Some(output) = &args.output {
             if single_file {
@@ -31,6 +31,9 @@ impl AddressRanges {
         }
     }
 
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.inner.is_empty() }
+
     pub fn contains(&self, address: SectionAddress) -> bool {
         let pos = match self.inner.binary_search_by_key(&address, |&(start, _)| start) {
             Ok(_) => return true,
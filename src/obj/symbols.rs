@@ -51,6 +51,8 @@ flags! {
         NoExport,
         /// Symbol does not contain any relocations
         NoReloc,
+        /// Symbol is referenced but not defined (ELF `SHN_UNDEF`)
+        Undefined,
     }
 }
 
@@ -104,6 +106,9 @@ impl ObjSymbolFlagSet {
     #[inline]
     pub fn is_no_reloc(&self) -> bool { self.0.contains(ObjSymbolFlags::NoReloc) }
 
+    #[inline]
+    pub fn is_undefined(&self) -> bool { self.0.contains(ObjSymbolFlags::Undefined) }
+
     #[inline]
     pub fn set_scope(&mut self, scope: ObjSymbolScope) {
         match scope {
@@ -13,7 +13,7 @@ use crate::{
         ObjDataKind, ObjInfo, ObjReloc, ObjRelocKind, ObjSection, ObjSectionKind, ObjSymbol,
         ObjSymbolKind, SymbolIndex,
     },
-    util::nested::NestedVec,
+    util::{dwarf::LineInfo, nested::NestedVec},
 };
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -29,7 +29,9 @@ struct SymbolEntry {
     kind: SymbolEntryKind,
 }
 
-pub fn write_asm<W>(w: &mut W, obj: &ObjInfo) -> Result<()>
+/// Writes an object as assembly. If `lines` is provided, statements are annotated with their
+/// original source file and line.
+pub fn write_asm<W>(w: &mut W, obj: &ObjInfo, lines: Option<&LineInfo>) -> Result<()>
 where W: Write + ?Sized {
     writeln!(w, ".include \"macros.inc\"")?;
     if !obj.name.is_empty() {
@@ -210,6 +212,7 @@ where W: Write + ?Sized {
                         current_address,
                         section_end,
                         &section_entries,
+                        lines,
                     )?;
                 }
                 ObjSectionKind::Bss => {
@@ -235,6 +238,7 @@ where W: Write + ?Sized {
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn write_code_chunk<W>(
     w: &mut W,
     symbols: &[ObjSymbol],
@@ -243,11 +247,18 @@ fn write_code_chunk<W>(
     section: &ObjSection,
     address: u32,
     data: &[u8],
+    lines: Option<&LineInfo>,
 ) -> Result<()>
 where
     W: Write + ?Sized,
 {
     for (addr, ins) in InsIter::new(data, address) {
+        if let Some((file, line)) = lines.and_then(|lines| {
+            lines.line_at((addr as u64 + section.virtual_address.unwrap_or(0)) as u32)
+        }) {
+            let file = file.rsplit_once(['/', '\\']).map(|(_, b)| b).unwrap_or(file);
+            writeln!(w, "/* {}:{} */", file, line)?;
+        }
         let reloc = relocations.get(&addr);
         let file_offset = section.file_offset + (addr as u64 - section.address);
         write_ins(w, symbols, addr, ins, reloc, file_offset, section.virtual_address)?;
@@ -477,6 +488,7 @@ fn write_data<W>(
     start: u32,
     end: u32,
     section_entries: &[BTreeMap<u32, Vec<SymbolEntry>>],
+    lines: Option<&LineInfo>,
 ) -> Result<()>
 where
     W: Write + ?Sized,
@@ -570,7 +582,16 @@ where
                 entry,
                 reloc,
             );
-            write_code_chunk(w, symbols, entries, relocations, section, current_address, data)?;
+            write_code_chunk(
+                w,
+                symbols,
+                entries,
+                relocations,
+                section,
+                current_address,
+                data,
+                lines,
+            )?;
         } else {
             write_data_chunk(w, data, current_data_kind)?;
         }
@@ -1033,6 +1054,6 @@ where W: Write + ?Sized {
 }
 
 #[inline]
-fn is_illegal_instruction(code: u32) -> bool {
+pub fn is_illegal_instruction(code: u32) -> bool {
     matches!(code, 0x43000000 /* bc 24, lt, 0x0 */ | 0xB8030000 /* lmw r0, 0(r3) */)
 }
@@ -0,0 +1,702 @@
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+    ops::Range,
+};
+
+use anyhow::Result;
+use itertools::Itertools;
+use ppc750cl::{Argument, InsIter, Opcode};
+
+use crate::{
+    obj::{
+        ObjDataKind, ObjInfo, ObjReloc, ObjRelocKind, ObjSection, ObjSectionKind, ObjSymbol,
+        ObjSymbolKind, SectionIndex, SymbolIndex,
+    },
+    util::{asm::is_illegal_instruction, nested::NestedVec},
+};
+
+/// Converts a symbol name into a C identifier, replacing invalid characters.
+fn c_identifier(name: &str) -> Cow<str> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    if !name.is_empty()
+        && name.chars().all(valid)
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+    {
+        return Cow::Borrowed(name);
+    }
+    let mut out = String::with_capacity(name.len() + 1);
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        out.push('_');
+    }
+    out.extend(name.chars().map(|c| if valid(c) { c } else { '_' }));
+    Cow::Owned(out)
+}
+
+/// A data object to define, either from a symbol or from an unsymbolized gap.
+struct DataObject<'a> {
+    name: Cow<'a, str>,
+    start: u32,
+    end: u32,
+    local: bool,
+    align: Option<u32>,
+    data_kind: ObjDataKind,
+}
+
+impl DataObject<'_> {
+    /// Returns the C element type and element count.
+    fn c_type(&self, section: &ObjSection) -> (&'static str, u32) {
+        let size = self.end - self.start;
+        let read_only = section.kind == ObjSectionKind::ReadOnlyData;
+        if section.kind != ObjSectionKind::Bss && self.is_pointer_table(section) {
+            return (if read_only { "void* const" } else { "void*" }, size / 4);
+        }
+        if section.kind != ObjSectionKind::Bss && self.is_string(section) {
+            return (if read_only { "const char" } else { "char" }, size);
+        }
+        (if read_only { "const unsigned char" } else { "unsigned char" }, size)
+    }
+
+    /// Whether the object can be written as an array of pointers, which is required to
+    /// express relocations.
+    fn is_pointer_table(&self, section: &ObjSection) -> bool {
+        let mut relocations = section.relocations.range(self.start..self.end).peekable();
+        relocations.peek().is_some()
+            && self.start % 4 == 0
+            && (self.end - self.start) % 4 == 0
+            && relocations
+                .all(|(address, reloc)| address % 4 == 0 && reloc.kind == ObjRelocKind::Absolute)
+    }
+
+    fn is_string(&self, section: &ObjSection) -> bool {
+        if self.data_kind != ObjDataKind::String
+            || section.relocations.range(self.start..self.end).next().is_some()
+        {
+            return false;
+        }
+        let Ok(data) = section.data_range(self.start, self.end) else { return false };
+        matches!(data.split_last(), Some((0, rest)) if !rest.contains(&0))
+    }
+}
+
+struct CAsmWriter<'a> {
+    obj: &'a ObjInfo,
+    /// C identifier for each symbol.
+    names: Vec<Cow<'a, str>>,
+    cpp: bool,
+}
+
+pub fn write_c_asm<W>(w: &mut W, obj: &ObjInfo) -> Result<()>
+where W: Write + ?Sized {
+    let cpp = [".cpp", ".cp", ".cxx", ".cc"].iter().any(|ext| obj.name.ends_with(ext));
+    let writer = CAsmWriter {
+        obj,
+        names: obj.symbols.iter().map(|(_, s)| c_identifier(&s.name)).collect(),
+        cpp,
+    };
+    for (symbol, name) in obj.symbols.iter().map(|(_, s)| s).zip(&writer.names) {
+        if symbol.flags.is_global()
+            && symbol.kind != ObjSymbolKind::Section
+            && matches!(name, Cow::Owned(_))
+        {
+            log::warn!("Global symbol {} renamed to {} in {}", symbol.name, name, obj.name);
+        }
+    }
+
+    writeln!(w, "/*")?;
+    if !obj.name.is_empty() {
+        writeln!(w, " * {}", obj.name)?;
+    }
+    writeln!(w, " * Inline assembly for mwcceppc, generated by decomp-toolkit.")?;
+    writeln!(w, " */")?;
+    writeln!(w, "\n#ifdef __cplusplus\nextern \"C\" {{\n#endif")?;
+    writer.write_declarations(w)?;
+    for (section_index, section) in obj.sections.iter() {
+        writer.write_section(w, section_index, section)?;
+    }
+    writeln!(w, "\n#ifdef __cplusplus\n}}\n#endif")?;
+    w.flush()?;
+    Ok(())
+}
+
+impl CAsmWriter<'_> {
+    /// Returns the sized symbols defined in the section, skipping aliases.
+    fn section_symbols(
+        &self,
+        section_index: SectionIndex,
+    ) -> Vec<(SymbolIndex, &ObjSymbol, Vec<SymbolIndex>)> {
+        let mut out: Vec<(SymbolIndex, &ObjSymbol, Vec<SymbolIndex>)> = vec![];
+        for (symbol_index, symbol) in self.obj.symbols.for_section(section_index) {
+            if symbol.kind == ObjSymbolKind::Section {
+                continue;
+            }
+            if let Some((_, last, aliases)) = out.last_mut() {
+                if symbol.address < last.address + last.size {
+                    aliases.push(symbol_index);
+                    continue;
+                }
+            }
+            if symbol.size > 0 {
+                out.push((symbol_index, symbol, vec![]));
+            }
+        }
+        out
+    }
+
+    /// Splits the section into objects, filling unsymbolized gaps.
+    fn data_objects(&self, section_index: SectionIndex, section: &ObjSection) -> Vec<DataObject> {
+        let mut out = vec![];
+        let mut current = section.address as u32;
+        let section_end = (section.address + section.size) as u32;
+        let gap = |start: u32, end: u32| DataObject {
+            name: Cow::Owned(format!(
+                "gap_{}_{:08X}",
+                c_identifier(section.name.trim_start_matches('.')),
+                start as u64 + section.virtual_address.unwrap_or(0)
+            )),
+            start,
+            end,
+            local: true,
+            align: None,
+            data_kind: ObjDataKind::Unknown,
+        };
+        for (symbol_index, symbol, _) in self.section_symbols(section_index) {
+            let start = symbol.address as u32;
+            if start > current {
+                out.push(gap(current, start));
+            }
+            current = start + symbol.size as u32;
+            out.push(DataObject {
+                name: Cow::Borrowed(self.names[symbol_index as usize].as_ref()),
+                start,
+                end: current,
+                local: symbol.flags.is_local(),
+                align: symbol.align,
+                data_kind: symbol.data_kind,
+            });
+        }
+        if section_end > current {
+            out.push(gap(current, section_end));
+        }
+        out
+    }
+
+    fn write_declarations<W>(&self, w: &mut W) -> Result<()>
+    where W: Write + ?Sized {
+        // Referenced symbols defined in other units
+        let mut externs = BTreeMap::<&str, bool>::new();
+        for (_, section) in self.obj.sections.iter() {
+            for (_, reloc) in section.relocations.iter() {
+                let symbol = &self.obj.symbols[reloc.target_symbol];
+                if symbol.section.is_some() || symbol.flags.is_common() {
+                    continue;
+                }
+                let function = symbol.kind == ObjSymbolKind::Function
+                    || matches!(reloc.kind, ObjRelocKind::PpcRel24 | ObjRelocKind::PpcRel14);
+                *externs.entry(self.names[reloc.target_symbol as usize].as_ref()).or_default() |=
+                    function;
+            }
+        }
+        if !externs.is_empty() {
+            writeln!(w)?;
+        }
+        for (name, function) in externs {
+            if function {
+                writeln!(w, "extern void {}(void);", name)?;
+            } else {
+                writeln!(w, "extern unsigned char {}[];", name)?;
+            }
+        }
+
+        let common = self.obj.symbols.iter().filter(|(_, s)| s.flags.is_common()).collect_vec();
+        if !common.is_empty() {
+            writeln!(w)?;
+        }
+        for (symbol_index, symbol) in common {
+            writeln!(
+                w,
+                "unsigned char {}[{:#X}];",
+                self.names[symbol_index as usize], symbol.size
+            )?;
+        }
+
+        // Forward declarations, since objects and functions may reference each other
+        writeln!(w)?;
+        for (section_index, section) in self.obj.sections.iter() {
+            if section.kind == ObjSectionKind::Code {
+                for (symbol_index, symbol, _) in self.section_symbols(section_index) {
+                    let storage = if symbol.flags.is_local() { "static " } else { "" };
+                    writeln!(w, "{}void {}(void);", storage, self.names[symbol_index as usize])?;
+                }
+                continue;
+            }
+            for object in self.data_objects(section_index, section) {
+                // Tentative definitions of static objects aren't allowed in C++
+                if object.local && self.cpp {
+                    continue;
+                }
+                let storage = if object.local { "static " } else { "extern " };
+                let (ty, count) = object.c_type(section);
+                writeln!(w, "{}{} {}[{:#X}];", storage, ty, object.name, count)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_section<W>(
+        &self,
+        w: &mut W,
+        section_index: SectionIndex,
+        section: &ObjSection,
+    ) -> Result<()>
+    where
+        W: Write + ?Sized,
+    {
+        writeln!(w, "\n/* {} */", section.name)?;
+        if section.kind == ObjSectionKind::Code {
+            let mut current = section.address as u32;
+            for (symbol_index, symbol, aliases) in self.section_symbols(section_index) {
+                if symbol.address as u32 > current {
+                    log::warn!(
+                        "Unsymbolized code in {} at {:#010X}",
+                        section.name,
+                        current as u64 + section.virtual_address.unwrap_or(0)
+                    );
+                }
+                current = (symbol.address + symbol.size) as u32;
+                self.write_function(w, section_index, section, symbol_index, symbol, &aliases)?;
+            }
+        } else {
+            for object in self.data_objects(section_index, section) {
+                self.write_object(w, section, &object)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolves a relocation target to a C identifier and addend. Labels without a size are
+    /// resolved relative to the symbol containing them.
+    fn resolve_target(&self, reloc: &ObjReloc) -> (&str, i64) {
+        let symbol = &self.obj.symbols[reloc.target_symbol];
+        let name = self.names[reloc.target_symbol as usize].as_ref();
+        if symbol.size > 0 {
+            return (name, reloc.addend);
+        }
+        let Some(section_index) = symbol.section else { return (name, reloc.addend) };
+        let address = symbol.address as u32;
+        match self
+            .obj
+            .symbols
+            .for_section_range(section_index, ..=address)
+            .rev()
+            .find(|(_, s)| s.kind != ObjSymbolKind::Section && s.size > 0)
+        {
+            Some((container_index, container))
+                if address < (container.address + container.size) as u32 =>
+            {
+                (
+                    self.names[container_index as usize].as_ref(),
+                    reloc.addend + (symbol.address - container.address) as i64,
+                )
+            }
+            _ => (name, reloc.addend),
+        }
+    }
+
+    /// Formats a relocation target as an assembler expression.
+    fn target_expr(&self, reloc: &ObjReloc) -> String {
+        let (name, addend) = self.resolve_target(reloc);
+        match addend.cmp(&0) {
+            Ordering::Greater => format!("({}+{:#X})", name, addend),
+            Ordering::Less => format!("({}-{:#X})", name, -addend),
+            Ordering::Equal => name.to_string(),
+        }
+    }
+
+    fn write_function<W>(
+        &self,
+        w: &mut W,
+        section_index: SectionIndex,
+        section: &ObjSection,
+        symbol_index: SymbolIndex,
+        symbol: &ObjSymbol,
+        aliases: &[SymbolIndex],
+    ) -> Result<()>
+    where
+        W: Write + ?Sized,
+    {
+        let start = symbol.address as u32;
+        let end = (symbol.address + symbol.size) as u32;
+        let vaddr = section.virtual_address.unwrap_or(0);
+        let data = section.data_range(start, end)?;
+
+        // Global symbols inside of the function become additional entry points
+        let mut entries = BTreeMap::<u32, Vec<&str>>::new();
+        for &alias_index in aliases {
+            let alias = &self.obj.symbols[alias_index];
+            if alias.flags.is_global() && alias.address as u32 != start {
+                entries
+                    .nested_push(alias.address as u32, self.names[alias_index as usize].as_ref());
+            }
+        }
+
+        // Local branch targets
+        let mut labels = BTreeSet::new();
+        for (address, ins) in InsIter::new(data, start) {
+            let target = match section.relocations.at(address) {
+                Some(reloc)
+                    if matches!(reloc.kind, ObjRelocKind::PpcRel24 | ObjRelocKind::PpcRel14) =>
+                {
+                    let target = &self.obj.symbols[reloc.target_symbol];
+                    if target.section != Some(section_index) {
+                        continue;
+                    }
+                    (target.address as i64 + reloc.addend) as u32
+                }
+                Some(_) => continue,
+                None => match ins.branch_dest(address) {
+                    Some(dest) if !ins.field_aa() => dest,
+                    _ => continue,
+                },
+            };
+            if target > start && target < end {
+                labels.insert(target);
+            }
+        }
+
+        if symbol.kind == ObjSymbolKind::Object {
+            writeln!(w, "\n/* Object in code section */")?;
+        }
+        writeln!(w)?;
+        if let Some(demangled) = &symbol.demangled_name {
+            writeln!(w, "/* {} */", demangled)?;
+        }
+        let storage = if symbol.flags.is_local() { "static " } else { "" };
+        writeln!(w, "{}asm void {}(void) {{", storage, self.names[symbol_index as usize])?;
+        writeln!(w, "\tnofralloc")?;
+        for (address, mut ins) in InsIter::new(data, start) {
+            if let Some(names) = entries.get(&address) {
+                for name in names {
+                    writeln!(w, "\tentry {}", name)?;
+                }
+            }
+            if labels.contains(&address) {
+                writeln!(w, "L_{:08X}:", address as u64 + vaddr)?;
+            }
+
+            let reloc = section.relocations.at(address);
+            let branch_dest = ins.branch_dest(address).filter(|_| !ins.field_aa());
+            if let Some(reloc) = reloc {
+                // Zero out relocations. SDA base registers are kept, since they're not
+                // assigned by the inline assembler.
+                ins.code = match reloc.kind {
+                    ObjRelocKind::Absolute => 0,
+                    ObjRelocKind::PpcRel24 => ins.code & !0x3FFFFFC,
+                    ObjRelocKind::PpcRel14 => ins.code & !0xFFFC,
+                    ObjRelocKind::PpcEmbSda21
+                    | ObjRelocKind::PpcAddr16Hi
+                    | ObjRelocKind::PpcAddr16Ha
+                    | ObjRelocKind::PpcAddr16Lo => ins.code & !0xFFFF,
+                };
+            }
+            if symbol.kind != ObjSymbolKind::Function
+                || ins.op == Opcode::Illegal
+                || is_illegal_instruction(ins.code)
+            {
+                writeln!(w, "\topword {:#010X}", ins.code)?;
+                continue;
+            }
+
+            let sins = ins.simplified();
+            write!(w, "\t{}", sins.mnemonic)?;
+            let mut writing_offset = false;
+            for (i, arg) in sins.args_iter().enumerate() {
+                if !writing_offset {
+                    write!(w, "{}", if i == 0 { " " } else { ", " })?;
+                }
+                match arg {
+                    Argument::Uimm(_)
+                    | Argument::Simm(_)
+                    | Argument::BranchDest(_)
+                    | Argument::Offset(_) => {
+                        if let Some(reloc) = reloc {
+                            self.write_reloc(w, section_index, start..end, vaddr, reloc)?;
+                        } else if let (true, Some(dest)) =
+                            (matches!(arg, Argument::BranchDest(_)), branch_dest)
+                        {
+                            self.write_branch_dest(w, section_index, start..end, vaddr, dest)?;
+                        } else {
+                            write!(w, "{}", arg)?;
+                        }
+                        if matches!(arg, Argument::Offset(_)) {
+                            write!(w, "(")?;
+                            writing_offset = true;
+                            continue;
+                        }
+                    }
+                    _ => write!(w, "{}", arg)?,
+                }
+                if writing_offset {
+                    write!(w, ")")?;
+                    writing_offset = false;
+                }
+            }
+            writeln!(w)?;
+        }
+        writeln!(w, "}}")?;
+        Ok(())
+    }
+
+    fn write_branch_dest<W>(
+        &self,
+        w: &mut W,
+        section_index: SectionIndex,
+        function: Range<u32>,
+        vaddr: u64,
+        dest: u32,
+    ) -> Result<()>
+    where
+        W: Write + ?Sized,
+    {
+        if dest != function.start && function.contains(&dest) {
+            write!(w, "L_{:08X}", dest as u64 + vaddr)?;
+            return Ok(());
+        }
+        match self
+            .obj
+            .symbols
+            .at_section_address(section_index, dest)
+            .find(|(_, s)| s.kind != ObjSymbolKind::Section)
+        {
+            Some((symbol_index, _)) => write!(w, "{}", self.names[symbol_index as usize])?,
+            None => write!(w, "{:#X}", dest as u64 + vaddr)?,
+        }
+        Ok(())
+    }
+
+    fn write_reloc<W>(
+        &self,
+        w: &mut W,
+        section_index: SectionIndex,
+        function: Range<u32>,
+        vaddr: u64,
+        reloc: &ObjReloc,
+    ) -> Result<()>
+    where
+        W: Write + ?Sized,
+    {
+        let target = &self.obj.symbols[reloc.target_symbol];
+        if matches!(reloc.kind, ObjRelocKind::PpcRel24 | ObjRelocKind::PpcRel14)
+            && target.section == Some(section_index)
+        {
+            let dest = (target.address as i64 + reloc.addend) as u32;
+            if dest != function.start && function.contains(&dest) {
+                write!(w, "L_{:08X}", dest as u64 + vaddr)?;
+                return Ok(());
+            }
+        }
+        let expr = self.target_expr(reloc);
+        match reloc.kind {
+            ObjRelocKind::Absolute | ObjRelocKind::PpcRel24 | ObjRelocKind::PpcRel14 => {
+                write!(w, "{}", expr)?
+            }
+            ObjRelocKind::PpcAddr16Hi => write!(w, "{}@h", expr)?,
+            ObjRelocKind::PpcAddr16Ha => write!(w, "{}@ha", expr)?,
+            ObjRelocKind::PpcAddr16Lo => write!(w, "{}@l", expr)?,
+            ObjRelocKind::PpcEmbSda21 => write!(w, "{}@sda21", expr)?,
+        }
+        Ok(())
+    }
+
+    fn write_object<W>(&self, w: &mut W, section: &ObjSection, object: &DataObject) -> Result<()>
+    where W: Write + ?Sized {
+        let (ty, count) = object.c_type(section);
+        writeln!(w)?;
+        if section.kind != ObjSectionKind::Bss && !object.is_pointer_table(section) {
+            // Relocations that can't be expressed in C fail the build instead of silently
+            // producing zeroed data.
+            for (address, reloc) in section.relocations.range(object.start..object.end) {
+                let address = address as u64 + section.virtual_address.unwrap_or(0);
+                log::warn!(
+                    "Unsupported {:?} relocation in {} ({}) at {:#010X}",
+                    reloc.kind,
+                    object.name,
+                    section.name,
+                    address
+                );
+                writeln!(
+                    w,
+                    "#error \"Unsupported {:?} relocation in {} at {:#010X} to {}\"",
+                    reloc.kind,
+                    object.name,
+                    address,
+                    self.target_expr(reloc)
+                )?;
+            }
+        }
+        if !matches!(section.name.as_str(), ".data" | ".bss") {
+            write!(w, "__declspec(section \"{}\") ", section.name)?;
+        }
+        if object.local {
+            write!(w, "static ")?;
+        } else if self.cpp && section.kind == ObjSectionKind::ReadOnlyData {
+            // const objects have internal linkage in C++
+            write!(w, "extern ")?;
+        }
+        write!(w, "{} {}[{:#X}]", ty, object.name, count)?;
+        if let Some(align) = object.align {
+            write!(w, " __attribute__((aligned({})))", align)?;
+        }
+        if section.kind == ObjSectionKind::Bss {
+            writeln!(w, ";")?;
+            return Ok(());
+        }
+
+        let data = section.data_range(object.start, object.end)?;
+        if object.is_pointer_table(section) {
+            writeln!(w, " = {{")?;
+            for (i, word) in data.chunks_exact(4).enumerate() {
+                let address = object.start + i as u32 * 4;
+                match section.relocations.at(address) {
+                    Some(reloc) => {
+                        let (name, addend) = self.resolve_target(reloc);
+                        if addend == 0 {
+                            writeln!(w, "\t(void*)&{},", name)?;
+                        } else {
+                            writeln!(w, "\t(void*)((char*)&{} + {:#X}),", name, addend)?;
+                        }
+                    }
+                    None => writeln!(
+                        w,
+                        "\t(void*){:#010X},",
+                        u32::from_be_bytes(word.try_into().unwrap())
+                    )?,
+                }
+            }
+            writeln!(w, "}};")?;
+        } else if object.is_string(section) {
+            write!(w, " = \"")?;
+            for &b in &data[..data.len() - 1] {
+                match b {
+                    b'\n' => write!(w, "\\n")?,
+                    b'\t' => write!(w, "\\t")?,
+                    b'\r' => write!(w, "\\r")?,
+                    b'\\' => write!(w, "\\\\")?,
+                    b'"' => write!(w, "\\\"")?,
+                    b'?' => write!(w, "\\?")?,
+                    b if b.is_ascii_graphic() || b == b' ' => write!(w, "{}", b as char)?,
+                    b => write!(w, "\\{:03o}", b)?,
+                }
+            }
+            writeln!(w, "\";")?;
+        } else {
+            writeln!(w, " = {{")?;
+            for chunk in data.chunks(16) {
+                let bytes = chunk.iter().map(|b| format!("{:#04X}", b)).join(", ");
+                writeln!(w, "\t{},", bytes)?;
+            }
+            writeln!(w, "}};")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{ObjArchitecture, ObjKind};
+
+    fn section(name: &str, kind: ObjSectionKind, address: u64, data: Vec<u8>) -> ObjSection {
+        ObjSection {
+            name: name.to_string(),
+            kind,
+            address,
+            size: data.len() as u64,
+            data,
+            align: 4,
+            elf_index: 0,
+            relocations: Default::default(),
+            virtual_address: Some(address),
+            file_offset: 0,
+            section_known: true,
+            splits: Default::default(),
+        }
+    }
+
+    fn symbol(name: &str, kind: ObjSymbolKind, section: SectionIndex, address: u64) -> ObjSymbol {
+        ObjSymbol {
+            name: name.to_string(),
+            address,
+            section: Some(section),
+            size: 8,
+            size_known: true,
+            kind,
+            ..Default::default()
+        }
+    }
+
+    fn reloc(kind: ObjRelocKind, target_symbol: SymbolIndex) -> ObjReloc {
+        ObjReloc { kind, target_symbol, addend: 0, module: None }
+    }
+
+    fn test_obj() -> ObjInfo {
+        // lis r3, table@ha; addi r3, r3, table@l
+        let mut text = section(".text", ObjSectionKind::Code, 0x80003000, vec![
+            0x3C, 0x60, 0x00, 0x00, 0x38, 0x63, 0x00, 0x00,
+        ]);
+        text.relocations.insert(0x80003000, reloc(ObjRelocKind::PpcAddr16Ha, 1)).unwrap();
+        text.relocations.insert(0x80003004, reloc(ObjRelocKind::PpcAddr16Lo, 1)).unwrap();
+        let mut data = section(".data", ObjSectionKind::Data, 0x80004000, vec![0; 0x10]);
+        data.relocations.insert(0x80004000, reloc(ObjRelocKind::Absolute, 0)).unwrap();
+        data.relocations.insert(0x80004004, reloc(ObjRelocKind::Absolute, 0)).unwrap();
+        // Not expressible in C
+        data.relocations.insert(0x8000400A, reloc(ObjRelocKind::PpcAddr16Lo, 0)).unwrap();
+        let symbols = vec![
+            symbol("foo", ObjSymbolKind::Function, 0, 0x80003000),
+            symbol("table", ObjSymbolKind::Object, 1, 0x80004000),
+            symbol("bad", ObjSymbolKind::Object, 1, 0x80004008),
+        ];
+        ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            "test.c".to_string(),
+            symbols,
+            vec![text, data],
+        )
+    }
+
+    #[test]
+    fn test_c_identifier() {
+        assert_eq!(c_identifier("foo"), "foo");
+        assert_eq!(c_identifier("__ct__3FooFv"), "__ct__3FooFv");
+        assert_eq!(c_identifier("@123"), "_123");
+        assert_eq!(c_identifier("1abc"), "_1abc");
+        assert_eq!(c_identifier(""), "_");
+    }
+
+    #[test]
+    fn test_write_c_asm() {
+        let obj = test_obj();
+        let mut out = Vec::<u8>::new();
+        write_c_asm(&mut out, &obj).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("asm void foo(void) {\n\tnofralloc\n"));
+        assert!(out.contains("table@ha"));
+        assert!(out.contains("table@l"));
+        assert!(out.contains("void* table[0x2] = {\n\t(void*)&foo,\n\t(void*)&foo,\n};"));
+    }
+
+    #[test]
+    fn test_unsupported_relocation() {
+        let obj = test_obj();
+        let mut out = Vec::<u8>::new();
+        write_c_asm(&mut out, &obj).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(
+            "#error \"Unsupported PpcAddr16Lo relocation in bad at 0x8000400A to foo\"\nunsigned \
+             char bad[0x8] = {"
+        ));
+    }
+}
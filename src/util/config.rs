@@ -428,14 +428,7 @@ pub fn write_splits_file(
 
 pub fn write_splits<W>(w: &mut W, obj: &ObjInfo, all: bool) -> Result<()>
 where W: Write + ?Sized {
-    writeln!(w, "Sections:")?;
-    for (_, section) in obj.sections.iter() {
-        write!(w, "\t{:<11} type:{}", section.name, section_kind_to_str(section.kind))?;
-        if section.align > 0 {
-            write!(w, " align:{}", section.align)?;
-        }
-        writeln!(w)?;
-    }
+    write_split_sections(w, obj)?;
     for unit in obj.link_order.iter().filter(|unit| all || !unit.autogenerated) {
         write!(w, "\n{}:", unit.name)?;
         if let Some(comment_version) = unit.comment_version {
@@ -476,6 +469,20 @@ where W: Write + ?Sized {
     Ok(())
 }
 
+/// Writes the `Sections:` header of a splits file.
+pub fn write_split_sections<W>(w: &mut W, obj: &ObjInfo) -> Result<()>
+where W: Write + ?Sized {
+    writeln!(w, "Sections:")?;
+    for (_, section) in obj.sections.iter() {
+        write!(w, "\t{:<11} type:{}", section.name, section_kind_to_str(section.kind))?;
+        if section.align > 0 {
+            write!(w, " align:{}", section.align)?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
 struct SplitSection {
     name: String,
     start: u32,
@@ -0,0 +1,799 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io,
+    io::{Read, Seek, SeekFrom, Write},
+};
+
+use aes::cipher::{BlockEncryptMut, KeyIvInit};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use encoding_rs::SHIFT_JIS;
+use nodtool::nod::Ticket;
+use sha1::{Digest, Sha1};
+use typed_path::Utf8NativePathBuf;
+use zerocopy::FromBytes;
+
+use crate::{
+    array_ref,
+    util::wad::{fakesign_tmd, tmd_contents_mut, HashBytes, KeyBytes},
+    vfs::VfsFile,
+};
+
+/// Size of `boot.bin`: the disc header followed by the partition header.
+const BOOT_SIZE: usize = 0x440;
+/// Size of `bi2.bin`.
+const BI2_SIZE: usize = 0x2000;
+/// Offset of the apploader, directly after `bi2.bin`.
+const APPLOADER_OFFSET: u64 = (BOOT_SIZE + BI2_SIZE) as u64;
+/// Capacity of a GameCube disc.
+const MINI_DVD_SIZE: u64 = 1_459_978_240;
+
+const WII_MAGIC: [u8; 4] = [0x5D, 0x1C, 0x9E, 0xA3];
+const GCN_MAGIC: [u8; 4] = [0xC2, 0x33, 0x9F, 0x3D];
+
+// Offsets in boot.bin
+const DOL_OFFSET: usize = 0x420;
+const FST_OFFSET: usize = 0x424;
+const FST_SIZE: usize = 0x428;
+const FST_MAX_SIZE: usize = 0x42C;
+
+/// Alignment of files that aren't in the original FST.
+const DEFAULT_FILE_ALIGN: u64 = 0x8000;
+
+// Wii disc layout. Retail discs usually start with an update partition; only the data partition
+// is written.
+const WII_DISC_HEADER_SIZE: usize = 0x100;
+const WII_PARTITION_INFO_OFFSET: usize = 0x40000;
+const WII_PARTITION_TABLE_OFFSET: usize = 0x40020;
+const WII_REGION_OFFSET: usize = 0x4E000;
+const WII_REGION_SIZE: usize = 0x20;
+const WII_PARTITION_OFFSET: usize = 0x50000;
+/// Capacity of a dual-layer Wii disc.
+const WII_DUAL_LAYER_SIZE: u64 = 8_511_160_320;
+
+// Offsets in the partition header
+const WII_TMD_OFFSET: usize = 0x2C0;
+const WII_H3_OFFSET: usize = 0x8000;
+const WII_H3_SIZE: usize = 0x18000;
+const WII_DATA_OFFSET: usize = 0x20000;
+
+// Partition data is stored in clusters of 0x400 bytes of hashes and 0x7C00 bytes of data.
+// Hashes cover groups of 64 clusters.
+const WII_CLUSTER_SIZE: usize = 0x8000;
+const WII_HASHES_SIZE: usize = 0x400;
+const WII_CLUSTER_DATA_SIZE: usize = WII_CLUSTER_SIZE - WII_HASHES_SIZE;
+const WII_SUBGROUP_SIZE: usize = 8 * WII_CLUSTER_SIZE;
+const WII_GROUP_CLUSTERS: usize = 64;
+const WII_GROUP_DATA_SIZE: usize = WII_GROUP_CLUSTERS * WII_CLUSTER_DATA_SIZE;
+
+type Aes128CbcEnc = cbc::Encryptor<aes::Aes128>;
+
+/// A file or directory below `files/`.
+#[derive(Clone)]
+pub enum DiscEntry {
+    File { name: String, source: DiscSource, size: u64 },
+    Directory { name: String, entries: Vec<DiscEntry> },
+}
+
+/// Where the data of a file comes from.
+#[derive(Clone)]
+pub enum DiscSource {
+    /// A file on the host filesystem.
+    Path(Utf8NativePathBuf),
+    /// A file opened from a VFS, e.g. an unchanged file of the disc being rebuilt.
+    File(Box<dyn VfsFile>),
+}
+
+impl DiscSource {
+    fn open(&self) -> io::Result<Box<dyn Read + '_>> {
+        match self {
+            DiscSource::Path(path) => Ok(Box::new(File::open(path)?)),
+            DiscSource::File(file) => {
+                let mut file = file.clone();
+                file.seek(SeekFrom::Start(0))?;
+                Ok(Box::new(file))
+            }
+        }
+    }
+}
+
+impl DiscEntry {
+    fn name(&self) -> &str {
+        match self {
+            DiscEntry::File { name, .. } | DiscEntry::Directory { name, .. } => name,
+        }
+    }
+}
+
+/// The contents of an extracted GameCube disc, as written by `disc extract`.
+pub struct DiscBuildInput {
+    pub raw_boot: Box<[u8]>,
+    pub raw_bi2: Box<[u8]>,
+    pub raw_apploader: Box<[u8]>,
+    pub raw_dol: Box<[u8]>,
+    /// The original FST, used to keep the original file order and placement.
+    pub raw_fst: Option<Box<[u8]>>,
+    pub files: Vec<DiscEntry>,
+    /// Partition metadata, for Wii discs.
+    pub wii: Option<WiiBuildInput>,
+}
+
+/// The files of an extracted Wii data partition, besides `sys/` and `files/`.
+pub struct WiiBuildInput {
+    /// `disc/header.bin`: the start of the disc header, outside of the partition.
+    pub raw_disc_header: Box<[u8]>,
+    /// `disc/region.bin`, if present.
+    pub raw_region: Option<Box<[u8]>>,
+    pub raw_ticket: Box<[u8]>,
+    pub raw_tmd: Box<[u8]>,
+    pub raw_cert_chain: Box<[u8]>,
+}
+
+/// Position of a node in the original FST.
+struct OriginalNode {
+    index: usize,
+    /// Data offset, for files.
+    offset: Option<u64>,
+}
+
+/// Reads the node order and file offsets from an FST, keyed by lowercase path. Wii FSTs store
+/// offsets divided by 4, so `shift` is 2 for them.
+fn read_original_fst(data: &[u8], shift: u32) -> Result<HashMap<String, OriginalNode>> {
+    let read_u32 = |offset: usize| -> Result<u32> {
+        data.get(offset..offset + 4)
+            .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+            .ok_or_else(|| anyhow!("FST entry out of bounds"))
+    };
+    let count = read_u32(8)? as usize;
+    let string_table = data.get(count * 12..).ok_or_else(|| anyhow!("FST too small"))?;
+
+    let mut nodes = HashMap::with_capacity(count);
+    // Directory end index and path prefix
+    let mut dirs: Vec<(usize, String)> = vec![(count, String::new())];
+    for index in 1..count {
+        while dirs.last().is_some_and(|&(end, _)| index >= end) {
+            dirs.pop();
+        }
+        let word = read_u32(index * 12)?;
+        let name_offset = (word & 0xFFFFFF) as usize;
+        let name = string_table
+            .get(name_offset..)
+            .and_then(|s| s.split(|&b| b == 0).next())
+            .ok_or_else(|| anyhow!("FST name out of bounds"))?;
+        let (name, _, _) = SHIFT_JIS.decode(name);
+        let prefix = dirs.last().map(|(_, prefix)| prefix.as_str()).unwrap_or_default();
+        let path = format!("{}{}", prefix, name.to_ascii_lowercase());
+        if word >> 24 != 0 {
+            let end = read_u32(index * 12 + 8)? as usize;
+            dirs.push((end, format!("{}/", path)));
+            nodes.insert(path, OriginalNode { index, offset: None });
+        } else {
+            let offset = (read_u32(index * 12 + 4)? as u64) << shift;
+            nodes.insert(path, OriginalNode { index, offset: Some(offset) });
+        }
+    }
+    Ok(nodes)
+}
+
+/// Sorts directory entries by their original FST order. New entries follow, sorted by name.
+fn sort_entries(entries: &mut [DiscEntry], prefix: &str, original: &HashMap<String, OriginalNode>) {
+    entries.sort_by_cached_key(|entry| {
+        let name = entry.name().to_ascii_lowercase();
+        let path = format!("{}{}", prefix, name);
+        (original.get(&path).map_or(usize::MAX, |node| node.index), name)
+    });
+    for entry in entries {
+        if let DiscEntry::Directory { name, entries } = entry {
+            let prefix = format!("{}{}/", prefix, name.to_ascii_lowercase());
+            sort_entries(entries, &prefix, original);
+        }
+    }
+}
+
+enum PlacementKind<'a> {
+    Dol,
+    Fst,
+    /// A file, its FST node and its path.
+    File(&'a DiscSource, usize, String),
+}
+
+/// Data to be placed on the disc.
+struct Placement<'a> {
+    kind: PlacementKind<'a>,
+    size: u64,
+    original_offset: Option<u64>,
+    offset: u64,
+}
+
+struct FstBuilder<'a> {
+    /// Raw FST entries: type and name offset, offset or parent, size or end index.
+    nodes: Vec<[u32; 3]>,
+    string_table: Vec<u8>,
+    files: Vec<Placement<'a>>,
+}
+
+impl<'a> FstBuilder<'a> {
+    fn add_entries(
+        &mut self,
+        entries: &'a [DiscEntry],
+        parent: usize,
+        prefix: &str,
+        original: &HashMap<String, OriginalNode>,
+    ) -> Result<()> {
+        for entry in entries {
+            let index = self.nodes.len();
+            let (encoded, _, had_errors) = SHIFT_JIS.encode(entry.name());
+            ensure!(!had_errors, "File name {} can't be encoded as Shift-JIS", entry.name());
+            let name_offset = self.string_table.len() as u32;
+            ensure!(name_offset <= 0xFFFFFF, "FST string table too large");
+            self.string_table.extend_from_slice(&encoded);
+            self.string_table.push(0);
+
+            let path = format!("{}{}", prefix, entry.name().to_ascii_lowercase());
+            match entry {
+                DiscEntry::File { source, size, .. } => {
+                    ensure!(*size <= u32::MAX as u64, "File {} is too large", path);
+                    self.nodes.push([name_offset, 0, *size as u32]);
+                    self.files.push(Placement {
+                        kind: PlacementKind::File(source, index, path.clone()),
+                        size: *size,
+                        original_offset: original.get(&path).and_then(|node| node.offset),
+                        offset: 0,
+                    });
+                }
+                DiscEntry::Directory { entries, .. } => {
+                    self.nodes.push([(1 << 24) | name_offset, parent as u32, 0]);
+                    self.add_entries(entries, index, &format!("{}/", path), original)?;
+                    self.nodes[index][2] = self.nodes.len() as u32;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> usize { self.nodes.len() * 12 + self.string_table.len() }
+
+    fn write(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.size());
+        for node in &self.nodes {
+            for value in node {
+                out.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        out.extend_from_slice(&self.string_table);
+        out
+    }
+}
+
+/// Alignment implied by an original offset, capped at 0x8000 and at least 4.
+fn original_align(offset: u64) -> u64 {
+    if offset == 0 {
+        return DEFAULT_FILE_ALIGN;
+    }
+    (offset & offset.wrapping_neg()).clamp(4, DEFAULT_FILE_ALIGN)
+}
+
+/// Whether a `boot.bin` disc header belongs to a Wii disc.
+pub fn is_wii_boot(boot: &[u8]) -> bool { boot.get(0x18..0x1C) == Some(&WII_MAGIC) }
+
+/// Writes a GameCube or Wii disc image.
+///
+/// `main.dol`, the FST and files keep their original offsets when they still fit, so an
+/// unmodified tree rebuilds the original layout. Anything that no longer fits is moved after the
+/// previous file, keeping the alignment of its original offset. New files are placed at the end.
+///
+/// Wii images get a single data partition, see [`write_wii_partition`].
+pub fn write_disc<W>(w: &mut W, mut input: DiscBuildInput) -> Result<()>
+where W: Write + ?Sized {
+    ensure!(input.raw_boot.len() == BOOT_SIZE, "Invalid boot.bin size");
+    ensure!(input.raw_bi2.len() == BI2_SIZE, "Invalid bi2.bin size");
+    let wii = is_wii_boot(&input.raw_boot);
+    if wii {
+        ensure!(
+            input.wii.is_some(),
+            "boot.bin is a Wii disc header, but no partition was provided"
+        );
+    } else if input.raw_boot[0x1C..0x20] != GCN_MAGIC {
+        bail!("boot.bin is not a GameCube or Wii disc header");
+    } else {
+        ensure!(input.wii.is_none(), "boot.bin is a GameCube disc header");
+    }
+    // Wii partitions store offsets and sizes divided by 4
+    let shift = if wii { 2 } else { 0 };
+    let read_boot_u32 = |boot: &[u8], offset: usize| {
+        (u32::from_be_bytes(boot[offset..offset + 4].try_into().unwrap()) as u64) << shift
+    };
+    let write_boot_u32 = |boot: &mut [u8], offset: usize, value: u64| -> Result<()> {
+        let value = u32::try_from(value >> shift).map_err(|_| anyhow!("Disc image too large"))?;
+        boot[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+        Ok(())
+    };
+
+    let original = match &input.raw_fst {
+        Some(raw_fst) => {
+            read_original_fst(raw_fst, shift).context("Failed to parse original FST")?
+        }
+        None => HashMap::new(),
+    };
+    sort_entries(&mut input.files, "", &original);
+
+    let mut fst = FstBuilder { nodes: vec![[1 << 24, 0, 0]], string_table: vec![], files: vec![] };
+    fst.add_entries(&input.files, 0, "", &original)?;
+    fst.nodes[0][2] = fst.nodes.len() as u32;
+    let fst_size = (fst.size() as u64).next_multiple_of(1 << shift);
+
+    // Place main.dol, the FST and files in their original order
+    let mut placements = vec![
+        Placement {
+            kind: PlacementKind::Dol,
+            size: input.raw_dol.len() as u64,
+            original_offset: Some(read_boot_u32(&input.raw_boot, DOL_OFFSET)),
+            offset: 0,
+        },
+        Placement {
+            kind: PlacementKind::Fst,
+            size: fst_size,
+            original_offset: Some(read_boot_u32(&input.raw_boot, FST_OFFSET)),
+            offset: 0,
+        },
+    ];
+    placements.append(&mut fst.files);
+    let mut order = (0..placements.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| placements[i].original_offset.unwrap_or(u64::MAX));
+    let mut cursor = APPLOADER_OFFSET + input.raw_apploader.len() as u64;
+    for &i in &order {
+        let placement = &mut placements[i];
+        let align = placement.original_offset.map_or(DEFAULT_FILE_ALIGN, original_align);
+        let aligned = cursor.next_multiple_of(align);
+        placement.offset = match placement.original_offset {
+            Some(offset) if offset >= aligned => offset,
+            _ => aligned,
+        };
+        cursor = placement.offset + placement.size;
+    }
+    if !wii && cursor > MINI_DVD_SIZE {
+        bail!("Disc image is too large ({:#X} > {:#X} bytes)", cursor, MINI_DVD_SIZE);
+    }
+
+    let dol_offset = placements[0].offset;
+    let fst_offset = placements[1].offset;
+    for placement in &placements {
+        if let PlacementKind::File(_, node, ref path) = placement.kind {
+            fst.nodes[node][1] = u32::try_from(placement.offset >> shift)
+                .map_err(|_| anyhow!("File {} is placed too far into the disc", path))?;
+        }
+    }
+    let mut raw_fst = fst.write();
+    raw_fst.resize(fst_size as usize, 0);
+
+    // Update the header with the new layout
+    let fst_max_size = read_boot_u32(&input.raw_boot, FST_MAX_SIZE).max(fst_size);
+    let boot = &mut input.raw_boot;
+    write_boot_u32(boot, DOL_OFFSET, dol_offset)?;
+    write_boot_u32(boot, FST_OFFSET, fst_offset)?;
+    write_boot_u32(boot, FST_SIZE, fst_size)?;
+    write_boot_u32(boot, FST_MAX_SIZE, fst_max_size)?;
+
+    let data = DiscData {
+        boot: &input.raw_boot,
+        bi2: &input.raw_bi2,
+        apploader: &input.raw_apploader,
+        dol: &input.raw_dol,
+        fst: &raw_fst,
+        placements: &placements,
+        order: &order,
+    };
+    match &input.wii {
+        Some(wii) => write_wii_partition(w, wii, &data),
+        None => data.write(w),
+    }
+}
+
+/// The contents of a GameCube disc or Wii partition, in placement order.
+struct DiscData<'a> {
+    boot: &'a [u8],
+    bi2: &'a [u8],
+    apploader: &'a [u8],
+    dol: &'a [u8],
+    fst: &'a [u8],
+    placements: &'a [Placement<'a>],
+    order: &'a [usize],
+}
+
+impl DiscData<'_> {
+    fn write<W>(&self, w: &mut W) -> Result<()>
+    where W: Write + ?Sized {
+        w.write_all(self.boot)?;
+        w.write_all(self.bi2)?;
+        w.write_all(self.apploader)?;
+        // Offsets only increase in placement order
+        let mut position = APPLOADER_OFFSET + self.apploader.len() as u64;
+        for &i in self.order {
+            let placement = &self.placements[i];
+            io::copy(&mut io::repeat(0).take(placement.offset - position), w)?;
+            match placement.kind {
+                PlacementKind::Dol => w.write_all(self.dol)?,
+                PlacementKind::Fst => w.write_all(self.fst)?,
+                PlacementKind::File(source, _, ref path) => {
+                    let file =
+                        source.open().with_context(|| format!("Failed to open '{}'", path))?;
+                    let copied = io::copy(&mut file.take(placement.size), w)
+                        .with_context(|| format!("Failed to read '{}'", path))?;
+                    ensure!(copied == placement.size, "File '{}' changed while building", path);
+                }
+            }
+            position = placement.offset + placement.size;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a Wii disc image with a single data partition.
+///
+/// The partition data is hashed and encrypted with the title key from the ticket. The TMD is
+/// updated with the size and the H3 table hash of the new data, and fakesigned, since its
+/// original signature no longer matches. Since the H3 table precedes the data, the data is read
+/// twice: once to hash it, and once to write it.
+fn write_wii_partition<W>(w: &mut W, input: &WiiBuildInput, data: &DiscData) -> Result<()>
+where W: Write + ?Sized {
+    ensure!(input.raw_disc_header.len() == WII_DISC_HEADER_SIZE, "Invalid disc/header.bin size");
+    let ticket = Ticket::ref_from_bytes(&input.raw_ticket)
+        .map_err(|_| anyhow!("Invalid ticket size {:#X}", input.raw_ticket.len()))?;
+    let title_key = ticket.decrypt_title_key()?;
+
+    let mut hasher = PartitionWriter::<io::Sink>::new(None, title_key);
+    data.write(&mut hasher)?;
+    let h3 = hasher.finish()?;
+    let data_size = (h3.len() * WII_GROUP_CLUSTERS * WII_CLUSTER_SIZE) as u64;
+    let disc_size = (WII_PARTITION_OFFSET + WII_DATA_OFFSET) as u64 + data_size;
+    if disc_size > WII_DUAL_LAYER_SIZE {
+        bail!("Disc image is too large ({:#X} > {:#X} bytes)", disc_size, WII_DUAL_LAYER_SIZE);
+    }
+    let mut raw_h3 = vec![0u8; WII_H3_SIZE];
+    for (i, hash) in h3.iter().enumerate() {
+        raw_h3[i * 20..i * 20 + 20].copy_from_slice(hash);
+    }
+
+    let mut raw_tmd = input.raw_tmd.to_vec();
+    let content = tmd_contents_mut(&mut raw_tmd)?
+        .first_mut()
+        .ok_or_else(|| anyhow!("TMD has no content records"))?;
+    content.size.set(data_size);
+    content.hash = HashBytes::from(Sha1::digest(&raw_h3));
+    fakesign_tmd(&mut raw_tmd)?;
+
+    let cert_offset = (WII_TMD_OFFSET + raw_tmd.len()).next_multiple_of(0x20);
+    ensure!(
+        cert_offset + input.raw_cert_chain.len() <= WII_H3_OFFSET,
+        "TMD and certificate chain are too large"
+    );
+
+    // Disc header, partition table, region and partition header
+    let mut header = vec![0u8; WII_PARTITION_OFFSET + WII_DATA_OFFSET];
+    let mut put = |offset: usize, value: u32| {
+        header[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+    };
+    put(WII_PARTITION_INFO_OFFSET, 1);
+    put(WII_PARTITION_INFO_OFFSET + 4, (WII_PARTITION_TABLE_OFFSET >> 2) as u32);
+    // Data partition (type 0)
+    put(WII_PARTITION_TABLE_OFFSET, (WII_PARTITION_OFFSET >> 2) as u32);
+    put(WII_PARTITION_TABLE_OFFSET + 4, 0);
+    let partition = WII_PARTITION_OFFSET;
+    put(partition + 0x2A4, raw_tmd.len() as u32);
+    put(partition + 0x2A8, (WII_TMD_OFFSET >> 2) as u32);
+    put(partition + 0x2AC, input.raw_cert_chain.len() as u32);
+    put(partition + 0x2B0, (cert_offset >> 2) as u32);
+    put(partition + 0x2B4, (WII_H3_OFFSET >> 2) as u32);
+    put(partition + 0x2B8, (WII_DATA_OFFSET >> 2) as u32);
+    put(partition + 0x2BC, (data_size >> 2) as u32);
+    let mut copy = |offset: usize, data: &[u8]| {
+        header[offset..offset + data.len()].copy_from_slice(data);
+    };
+    copy(0, &input.raw_disc_header);
+    if let Some(region) = &input.raw_region {
+        ensure!(region.len() == WII_REGION_SIZE, "Invalid disc/region.bin size");
+        copy(WII_REGION_OFFSET, region);
+    }
+    copy(partition, &input.raw_ticket);
+    copy(partition + WII_TMD_OFFSET, &raw_tmd);
+    copy(partition + cert_offset, &input.raw_cert_chain);
+    copy(partition + WII_H3_OFFSET, &raw_h3);
+    w.write_all(&header)?;
+
+    let mut writer = PartitionWriter::new(Some(w), title_key);
+    data.write(&mut writer)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Hashes and encrypts Wii partition data, one group of 64 clusters at a time.
+struct PartitionWriter<'a, W: Write + ?Sized> {
+    /// Output for encrypted clusters. Without it, only the H3 table is computed.
+    out: Option<&'a mut W>,
+    title_key: KeyBytes,
+    /// Data of the current group
+    buf: Vec<u8>,
+    /// Hash of the H2 table of each group
+    h3: Vec<HashBytes>,
+}
+
+impl<'a, W: Write + ?Sized> PartitionWriter<'a, W> {
+    fn new(out: Option<&'a mut W>, title_key: KeyBytes) -> Self {
+        Self { out, title_key, buf: Vec::with_capacity(WII_GROUP_DATA_SIZE), h3: vec![] }
+    }
+
+    fn write_group(&mut self) -> io::Result<()> {
+        self.buf.resize(WII_GROUP_DATA_SIZE, 0);
+        let mut group = vec![0u8; WII_GROUP_CLUSTERS * WII_CLUSTER_SIZE];
+        // H0: hashes of each 0x400 byte block of the cluster data
+        for (cluster, data) in group
+            .chunks_exact_mut(WII_CLUSTER_SIZE)
+            .zip(self.buf.chunks_exact(WII_CLUSTER_DATA_SIZE))
+        {
+            let (hashes, cluster_data) = cluster.split_at_mut(WII_HASHES_SIZE);
+            for (i, block) in data.chunks_exact(0x400).enumerate() {
+                hashes[i * 20..i * 20 + 20].copy_from_slice(&Sha1::digest(block));
+            }
+            cluster_data.copy_from_slice(data);
+        }
+        // H1: hashes of the H0 tables of each cluster in a subgroup of 8
+        for subgroup in group.chunks_exact_mut(WII_SUBGROUP_SIZE) {
+            let mut h1 = [0u8; 0xA0];
+            for (i, cluster) in subgroup.chunks_exact(WII_CLUSTER_SIZE).enumerate() {
+                h1[i * 20..i * 20 + 20].copy_from_slice(&Sha1::digest(&cluster[..0x26C]));
+            }
+            for cluster in subgroup.chunks_exact_mut(WII_CLUSTER_SIZE) {
+                cluster[0x280..0x320].copy_from_slice(&h1);
+            }
+        }
+        // H2: hashes of the H1 tables of each subgroup
+        let mut h2 = [0u8; 0xA0];
+        for (i, subgroup) in group.chunks_exact(WII_SUBGROUP_SIZE).enumerate() {
+            h2[i * 20..i * 20 + 20].copy_from_slice(&Sha1::digest(&subgroup[0x280..0x320]));
+        }
+        for cluster in group.chunks_exact_mut(WII_CLUSTER_SIZE) {
+            cluster[0x340..0x3E0].copy_from_slice(&h2);
+        }
+        self.h3.push(HashBytes::from(Sha1::digest(h2)));
+        self.buf.clear();
+
+        let Some(out) = &mut self.out else { return Ok(()) };
+        for cluster in group.chunks_exact_mut(WII_CLUSTER_SIZE) {
+            // The hashes are encrypted with a zero IV, and the data with the last 16 bytes of
+            // the encrypted H2 table
+            let (hashes, cluster_data) = cluster.split_at_mut(WII_HASHES_SIZE);
+            encrypt(hashes, &self.title_key, &[0; 16]);
+            encrypt(cluster_data, &self.title_key, array_ref!(hashes, 0x3D0, 16));
+        }
+        out.write_all(&group)
+    }
+
+    /// Pads and writes the last group, and returns the H3 table entries.
+    fn finish(mut self) -> io::Result<Vec<HashBytes>> {
+        if !self.buf.is_empty() {
+            self.write_group()?;
+        }
+        Ok(self.h3)
+    }
+}
+
+impl<W: Write + ?Sized> Write for PartitionWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(WII_GROUP_DATA_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&buf[..len]);
+        if self.buf.len() == WII_GROUP_DATA_SIZE {
+            self.write_group()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.out {
+            Some(out) => out.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+fn encrypt(data: &mut [u8], key: &KeyBytes, iv: &[u8; 16]) {
+    let mut encryptor = Aes128CbcEnc::new(key.into(), iv.into());
+    for block in data.chunks_exact_mut(16) {
+        encryptor.encrypt_block_mut(block.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOL_SIZE: usize = 0x100;
+    const APPLOADER_SIZE: usize = 0x20;
+
+    fn be_u32(data: &[u8], offset: usize) -> u32 {
+        u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// Writes test files to a temporary directory and returns the FST entries for them.
+    fn test_entries(name: &str, a_size: usize) -> (Utf8NativePathBuf, Vec<DiscEntry>) {
+        let dir = std::env::temp_dir().join(format!("dtk-disc-{}-{}", name, std::process::id()));
+        let dir = Utf8NativePathBuf::from(dir.to_str().unwrap());
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str, data: Vec<u8>| {
+            let source = dir.join(name);
+            std::fs::write(&source, &data).unwrap();
+            DiscEntry::File {
+                name: name.to_string(),
+                source: DiscSource::Path(source),
+                size: data.len() as u64,
+            }
+        };
+        let entries = vec![
+            DiscEntry::Directory {
+                name: "dir".to_string(),
+                entries: vec![file("b.bin", vec![2; 0x20])],
+            },
+            file("a.bin", vec![1; a_size]),
+        ];
+        (dir, entries)
+    }
+
+    fn test_input(files: Vec<DiscEntry>) -> DiscBuildInput {
+        let mut boot = vec![0u8; BOOT_SIZE];
+        boot[0x1C..0x20].copy_from_slice(&GCN_MAGIC);
+        boot[DOL_OFFSET..DOL_OFFSET + 4].copy_from_slice(&0x3000u32.to_be_bytes());
+        boot[FST_OFFSET..FST_OFFSET + 4].copy_from_slice(&0x4000u32.to_be_bytes());
+        DiscBuildInput {
+            raw_boot: boot.into(),
+            raw_bi2: vec![0; BI2_SIZE].into(),
+            raw_apploader: vec![0xAA; APPLOADER_SIZE].into(),
+            raw_dol: vec![0xDD; DOL_SIZE].into(),
+            raw_fst: None,
+            files,
+            wii: None,
+        }
+    }
+
+    fn build(input: DiscBuildInput) -> Vec<u8> {
+        let mut out = vec![];
+        write_disc(&mut out, input).unwrap();
+        out
+    }
+
+    fn fst(disc: &[u8]) -> &[u8] {
+        let offset = be_u32(disc, FST_OFFSET) as usize;
+        &disc[offset..offset + be_u32(disc, FST_SIZE) as usize]
+    }
+
+    #[test]
+    fn test_write_fst() {
+        let (dir, entries) = test_entries("fst", 0x10);
+        let disc = build(test_input(entries));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(be_u32(&disc, DOL_OFFSET), 0x3000);
+        assert_eq!(be_u32(&disc, FST_OFFSET), 0x4000);
+        assert_eq!(&disc[0x3000..0x3000 + DOL_SIZE], &[0xDD; DOL_SIZE]);
+        assert_eq!(&disc[APPLOADER_OFFSET as usize..][..APPLOADER_SIZE], &[0xAA; APPLOADER_SIZE]);
+
+        // New entries are sorted by name and files are aligned to 0x8000
+        let fst = fst(&disc);
+        let node = |index: usize| [0, 4, 8].map(|offset| be_u32(fst, index * 12 + offset));
+        assert_eq!(node(0), [1 << 24, 0, 4]);
+        assert_eq!(node(1), [0, 0x8000, 0x10]);
+        assert_eq!(node(2), [(1 << 24) | 6, 0, 4]);
+        assert_eq!(node(3), [10, 0x10000, 0x20]);
+        assert_eq!(&fst[48..], b"a.bin\0dir\0b.bin\0");
+        assert_eq!(be_u32(&disc, FST_MAX_SIZE), fst.len() as u32);
+        assert_eq!(&disc[0x8000..0x8010], &[1; 0x10]);
+        assert_eq!(&disc[0x10000..], &[2; 0x20]);
+
+        let original = read_original_fst(fst, 0).unwrap();
+        assert_eq!(original["a.bin"].offset, Some(0x8000));
+        assert_eq!(original["dir"].index, 2);
+        assert_eq!(original["dir/b.bin"].offset, Some(0x10000));
+    }
+
+    #[test]
+    fn test_keep_original_layout() {
+        let (dir, entries) = test_entries("layout", 0x10);
+        let disc = build(test_input(entries.clone()));
+
+        // Rebuilding from the extracted header and FST gives the same image
+        let mut input = test_input(entries);
+        input.raw_boot = disc[..BOOT_SIZE].into();
+        input.raw_fst = Some(fst(&disc).into());
+        assert!(build(input) == disc);
+
+        // A file that no longer fits moves after the previous file, keeping its alignment
+        let (_, entries) = test_entries("layout", 0x8010);
+        let mut input = test_input(entries);
+        input.raw_boot = disc[..BOOT_SIZE].into();
+        input.raw_fst = Some(fst(&disc).into());
+        let grown = build(input);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let original = read_original_fst(fst(&grown), 0).unwrap();
+        assert_eq!(original["a.bin"].offset, Some(0x8000));
+        assert_eq!(original["dir/b.bin"].offset, Some(0x18000));
+        assert_eq!(&grown[0x18000..], &[2; 0x20]);
+    }
+
+    #[test]
+    fn test_write_wii() {
+        use aes::cipher::BlockDecryptMut;
+
+        use crate::util::wad::tmd_contents;
+
+        let (dir, entries) = test_entries("wii", 0x10);
+        let mut input = test_input(entries);
+        input.raw_boot[0x18..0x1C].copy_from_slice(&WII_MAGIC);
+        input.raw_boot[0x1C..0x20].fill(0);
+        // TMD with a single content record
+        let mut raw_tmd = vec![0u8; 0x208];
+        raw_tmd[..4].copy_from_slice(&0x10001u32.to_be_bytes());
+        raw_tmd[0x1DE..0x1E0].copy_from_slice(&1u16.to_be_bytes());
+        input.wii = Some(WiiBuildInput {
+            raw_disc_header: input.raw_boot[..WII_DISC_HEADER_SIZE].into(),
+            raw_region: Some(vec![0x11; WII_REGION_SIZE].into()),
+            raw_ticket: vec![0; 0x2A4].into(),
+            raw_tmd: raw_tmd.into(),
+            raw_cert_chain: vec![0xCC; 0x40].into(),
+        });
+        let boot = input.raw_boot.clone();
+        let disc = build(input);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Everything fits in a single group of clusters
+        let group_size = WII_GROUP_CLUSTERS * WII_CLUSTER_SIZE;
+        assert_eq!(disc.len(), WII_PARTITION_OFFSET + WII_DATA_OFFSET + group_size);
+        assert_eq!(&disc[..WII_DISC_HEADER_SIZE], &boot[..WII_DISC_HEADER_SIZE]);
+        assert_eq!(be_u32(&disc, WII_PARTITION_INFO_OFFSET), 1);
+        assert_eq!(be_u32(&disc, WII_PARTITION_TABLE_OFFSET), (WII_PARTITION_OFFSET >> 2) as u32);
+        assert_eq!(&disc[WII_REGION_OFFSET..][..WII_REGION_SIZE], &[0x11; WII_REGION_SIZE]);
+
+        let partition = &disc[WII_PARTITION_OFFSET..];
+        assert_eq!(be_u32(partition, 0x2A4), 0x208);
+        assert_eq!(be_u32(partition, 0x2BC), (group_size >> 2) as u32);
+        assert_eq!(&partition[0x4E0..0x520], &[0xCC; 0x40]);
+        let raw_h3 = &partition[WII_H3_OFFSET..WII_H3_OFFSET + WII_H3_SIZE];
+        let tmd = &partition[WII_TMD_OFFSET..WII_TMD_OFFSET + 0x208];
+        let content = &tmd_contents(tmd).unwrap()[0];
+        assert_eq!(content.size.get(), group_size as u64);
+        assert_eq!(content.hash, HashBytes::from(Sha1::digest(raw_h3)));
+        assert_eq!(Sha1::digest(&tmd[0x140..])[0], 0);
+
+        // Decrypt the first cluster and check its hashes
+        let title_key = [0u8; 0x2A4];
+        let title_key = Ticket::ref_from_bytes(&title_key).unwrap().decrypt_title_key().unwrap();
+        let decrypt = |data: &mut [u8], iv: &[u8; 16]| {
+            let mut decryptor = cbc::Decryptor::<aes::Aes128>::new((&title_key).into(), iv.into());
+            for block in data.chunks_exact_mut(16) {
+                decryptor.decrypt_block_mut(block.into());
+            }
+        };
+        let mut data = vec![];
+        let mut h2 = vec![];
+        for (i, cluster) in partition[WII_DATA_OFFSET..].chunks_exact(WII_CLUSTER_SIZE).enumerate()
+        {
+            let mut cluster = cluster.to_vec();
+            let (hashes, cluster_data) = cluster.split_at_mut(WII_HASHES_SIZE);
+            decrypt(cluster_data, array_ref!(hashes, 0x3D0, 16));
+            decrypt(hashes, &[0; 16]);
+            if i == 0 {
+                assert_eq!(&hashes[..20], Sha1::digest(&cluster_data[..0x400]).as_slice());
+                assert_eq!(&hashes[0x280..0x294], Sha1::digest(&hashes[..0x26C]).as_slice());
+                h2 = hashes[0x340..0x3E0].to_vec();
+            }
+            data.extend_from_slice(cluster_data);
+        }
+        assert_eq!(&raw_h3[..20], Sha1::digest(&h2).as_slice());
+        assert!(raw_h3[20..].iter().all(|&b| b == 0));
+
+        // Offsets in the partition are stored divided by 4
+        assert_eq!(be_u32(&data, DOL_OFFSET), 0x3000);
+        assert_eq!(&data[0xC000..0xC000 + DOL_SIZE], &[0xDD; DOL_SIZE]);
+        assert_eq!(be_u32(&data, FST_OFFSET), 0x4000);
+        assert_eq!(be_u32(&data, FST_SIZE), 0x40 >> 2);
+        assert_eq!(be_u32(&data[0x10000..], 16), 0x18000 >> 2);
+        assert_eq!(&data[0x18000..0x18010], &[1; 0x10]);
+    }
+}
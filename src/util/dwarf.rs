@@ -2,13 +2,17 @@ use std::{
     cmp::max,
     collections::BTreeMap,
     fmt::{Display, Formatter, Write},
-    io::{BufRead, Cursor, Seek, SeekFrom},
+    io::{BufRead, Cursor, Read, Seek, SeekFrom},
     num::NonZeroU32,
 };
 
 use anyhow::{anyhow, bail, ensure, Context, Result};
 use indent::indent_all_by;
+use itertools::Itertools;
 use num_enum::{IntoPrimitive, TryFromPrimitive, TryFromPrimitiveError};
+use object::{
+    elf, Object, ObjectSection, ObjectSymbol, RelocationFlags, RelocationTarget, Section,
+};
 
 use crate::{
     array_ref,
@@ -501,6 +505,133 @@ where R: BufRead + Seek + ?Sized {
     Ok(info)
 }
 
+/// A row of a DWARF 1.1 `.line` table.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct LineEntry {
+    pub address: u32,
+    /// Source line number. 0 marks the end of a sequence.
+    pub line: u32,
+    /// Position within the line, if known.
+    pub column: Option<u16>,
+}
+
+/// The `.line` table of a compile unit.
+#[derive(Debug, Clone)]
+pub struct LineTable {
+    pub base_address: u32,
+    pub entries: Vec<LineEntry>,
+}
+
+impl LineTable {
+    /// Returns the lowest and highest source line for addresses in `start..end`.
+    pub fn line_range(&self, start: u32, end: u32) -> Option<(u32, u32)> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.line != 0 && entry.address >= start && entry.address < end)
+            .map(|entry| entry.line)
+            .minmax()
+            .into_option()
+    }
+}
+
+/// Reads the `.line` table at `offset`, as referenced by a compile unit's `AT_stmt_list`.
+pub fn read_line_table<R>(reader: &mut R, e: Endian, offset: u32) -> Result<LineTable>
+where R: Read + Seek + ?Sized {
+    reader.seek(SeekFrom::Start(offset as u64))?;
+    let size = u32::from_reader(reader, e)?;
+    ensure!(size >= 8, "Invalid .line table size {:#X} at {:#X}", size, offset);
+    let base_address = u32::from_reader(reader, e)?;
+    let count = (size - 8) / 10;
+    let mut entries = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let line = u32::from_reader(reader, e)?;
+        let column = u16::from_reader(reader, e)?;
+        let delta = u32::from_reader(reader, e)?;
+        entries.push(LineEntry {
+            address: base_address.wrapping_add(delta),
+            line,
+            // 0xFFFF refers to the whole line
+            column: (column != 0xFFFF).then_some(column),
+        });
+    }
+    Ok(LineTable { base_address, entries })
+}
+
+/// Source line information for a linked or relocatable object, from its `.line` tables.
+#[derive(Debug, Clone, Default)]
+pub struct LineInfo {
+    files: Vec<String>,
+    /// Statement address to file index and line number.
+    lines: BTreeMap<u32, (usize, u32)>,
+}
+
+impl LineInfo {
+    /// Returns the source file and line of the statement starting at `address`.
+    pub fn line_at(&self, address: u32) -> Option<(&str, u32)> {
+        self.lines.get(&address).map(|&(file, line)| (self.files[file].as_str(), line))
+    }
+}
+
+/// Returns the data of a debug section with its `R_PPC_ADDR32` relocations applied.
+pub fn relocated_section_data(obj_file: &object::File<'_>, section: &Section) -> Result<Vec<u8>> {
+    let e: Endian = obj_file.endianness().into();
+    let mut data = section.uncompressed_data()?.into_owned();
+    for (addr, reloc) in section.relocations() {
+        match reloc.flags() {
+            RelocationFlags::Elf { r_type: elf::R_PPC_ADDR32 | elf::R_PPC_UADDR32 } => {
+                let target = match reloc.target() {
+                    RelocationTarget::Symbol(symbol_idx) => {
+                        let symbol = obj_file.symbol_by_index(symbol_idx)?;
+                        (symbol.address() as i64 + reloc.addend()) as u32
+                    }
+                    _ => bail!("Invalid {} relocation target", section.name()?),
+                };
+                data[addr as usize..addr as usize + 4].copy_from_slice(&match e {
+                    Endian::Big => target.to_be_bytes(),
+                    Endian::Little => target.to_le_bytes(),
+                });
+            }
+            RelocationFlags::Elf { r_type: elf::R_PPC_NONE } => {}
+            _ => bail!("Unhandled {} relocation type {:?}", section.name()?, reloc.kind()),
+        }
+    }
+    Ok(data)
+}
+
+/// Reads the line tables of every compile unit in an ELF.
+/// Returns `None` if the ELF has no `.debug` or `.line` section.
+pub fn read_line_info(obj_file: &object::File<'_>) -> Result<Option<LineInfo>> {
+    let (Some(debug_section), Some(line_section)) =
+        (obj_file.section_by_name(".debug"), obj_file.section_by_name(".line"))
+    else {
+        return Ok(None);
+    };
+    let e: Endian = obj_file.endianness().into();
+    let debug_data = relocated_section_data(obj_file, &debug_section)?;
+    let info = read_debug_section(&mut Cursor::new(&*debug_data), e, false)?;
+    let line_data = relocated_section_data(obj_file, &line_section)?;
+    let mut line_reader = Cursor::new(&*line_data);
+
+    let mut line_info = LineInfo::default();
+    let mut tag = info.tags.first_key_value().map(|(_, tag)| tag);
+    while let Some(current) = tag {
+        if current.kind == TagKind::CompileUnit {
+            let unit = process_compile_unit(current)?;
+            if let Some(offset) = unit.line_offset {
+                let table = read_line_table(&mut line_reader, e, offset)
+                    .with_context(|| format!("Failed to read line table for {}", unit.name))?;
+                let file = line_info.files.len();
+                line_info.files.push(unit.name);
+                for entry in table.entries.iter().filter(|entry| entry.line != 0) {
+                    line_info.lines.insert(entry.address, (file, entry.line));
+                }
+            }
+        }
+        tag = current.next_sibling(&info.tags);
+    }
+    Ok(Some(line_info))
+}
+
 #[allow(unused)]
 pub fn read_aranges_section<R>(reader: &mut R, e: Endian) -> Result<()>
 where R: BufRead + Seek + ?Sized {
@@ -808,6 +939,8 @@ pub struct SubroutineType {
     pub inlines: Vec<SubroutineType>,
     pub start_address: Option<u32>,
     pub end_address: Option<u32>,
+    /// Lowest and highest source line, filled in from the `.line` table.
+    pub line_range: Option<(u32, u32)>,
 }
 
 #[derive(Debug, Clone)]
@@ -893,6 +1026,8 @@ pub struct CompileUnit {
     pub end_address: Option<u32>,
     pub gcc_srcfile_name_offset: Option<u32>,
     pub gcc_srcinfo_offset: Option<u32>,
+    /// Offset of the unit's table in the `.line` section.
+    pub line_offset: Option<u32>,
 }
 
 #[derive(Debug, Clone)]
@@ -1337,7 +1472,11 @@ pub fn subroutine_def_string(
     if is_erased {
         out.push_str("// Erased\n");
     } else if let (Some(start), Some(end)) = (t.start_address, t.end_address) {
-        writeln!(out, "// Range: {:#X} -> {:#X}", start, end)?;
+        write!(out, "// Range: {:#X} -> {:#X}", start, end)?;
+        if let Some((first, last)) = t.line_range {
+            write!(out, " (lines {} -> {})", first, last)?;
+        }
+        writeln!(out)?;
     }
     let rt = type_string(info, typedefs, &t.return_type, true)?;
     if t.local {
@@ -2362,6 +2501,7 @@ fn process_subroutine_tag(info: &DwarfInfo, tag: &Tag) -> Result<SubroutineType>
         inlines,
         start_address,
         end_address,
+        line_range: None,
     })
 }
 
@@ -2648,6 +2788,7 @@ pub fn process_compile_unit(tag: &Tag) -> Result<CompileUnit> {
     let mut end_address = None;
     let mut gcc_srcfile_name_offset = None;
     let mut gcc_srcinfo_offset = None;
+    let mut line_offset = None;
     for attr in &tag.attributes {
         match (attr.kind, &attr.value) {
             (AttributeKind::Sibling, _) => {}
@@ -2659,9 +2800,7 @@ pub fn process_compile_unit(tag: &Tag) -> Result<CompileUnit> {
             }
             (AttributeKind::LowPc, &AttributeValue::Address(addr)) => start_address = Some(addr),
             (AttributeKind::HighPc, &AttributeValue::Address(addr)) => end_address = Some(addr),
-            (AttributeKind::StmtList, AttributeValue::Data4(_value)) => {
-                // TODO .line support
-            }
+            (AttributeKind::StmtList, &AttributeValue::Data4(value)) => line_offset = Some(value),
 
             (AttributeKind::GccSfName, &AttributeValue::Data4(value)) => {
                 gcc_srcfile_name_offset = Some(value)
@@ -2685,6 +2824,7 @@ pub fn process_compile_unit(tag: &Tag) -> Result<CompileUnit> {
         end_address,
         gcc_srcfile_name_offset,
         gcc_srcinfo_offset,
+        line_offset,
     })
 }
 
@@ -0,0 +1,1368 @@
+//! DWARF 2/3 reader, for objects built with GCC (e.g. SN Systems ProDG).
+//!
+//! Entries in `.debug_info` are lowered to DWARF 1.1 tags, so that the rest of the
+//! [`dwarf`](super::dwarf) module can process and print them as it does for MWCC objects.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{Cursor, Read, Seek},
+};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+
+use crate::util::{
+    dwarf::{
+        Attribute, AttributeKind, AttributeValue, DwarfInfo, FundType, LocationOp, Modifier,
+        SubscriptFormat, Tag, TagKind, TagMap,
+    },
+    reader::{Endian, FromReader},
+};
+
+const DW_TAG_ARRAY_TYPE: u64 = 0x01;
+const DW_TAG_CLASS_TYPE: u64 = 0x02;
+const DW_TAG_ENUMERATION_TYPE: u64 = 0x04;
+const DW_TAG_FORMAL_PARAMETER: u64 = 0x05;
+const DW_TAG_LABEL: u64 = 0x0a;
+const DW_TAG_LEXICAL_BLOCK: u64 = 0x0b;
+const DW_TAG_MEMBER: u64 = 0x0d;
+const DW_TAG_POINTER_TYPE: u64 = 0x0f;
+const DW_TAG_REFERENCE_TYPE: u64 = 0x10;
+const DW_TAG_COMPILE_UNIT: u64 = 0x11;
+const DW_TAG_STRUCTURE_TYPE: u64 = 0x13;
+const DW_TAG_SUBROUTINE_TYPE: u64 = 0x15;
+const DW_TAG_TYPEDEF: u64 = 0x16;
+const DW_TAG_UNION_TYPE: u64 = 0x17;
+const DW_TAG_UNSPECIFIED_PARAMETERS: u64 = 0x18;
+const DW_TAG_INHERITANCE: u64 = 0x1c;
+const DW_TAG_INLINED_SUBROUTINE: u64 = 0x1d;
+const DW_TAG_PTR_TO_MEMBER_TYPE: u64 = 0x1f;
+const DW_TAG_SUBRANGE_TYPE: u64 = 0x21;
+const DW_TAG_BASE_TYPE: u64 = 0x24;
+const DW_TAG_CONST_TYPE: u64 = 0x26;
+const DW_TAG_ENUMERATOR: u64 = 0x28;
+const DW_TAG_SUBPROGRAM: u64 = 0x2e;
+const DW_TAG_VARIABLE: u64 = 0x34;
+const DW_TAG_VOLATILE_TYPE: u64 = 0x35;
+const DW_TAG_RESTRICT_TYPE: u64 = 0x37;
+const DW_TAG_NAMESPACE: u64 = 0x39;
+
+const DW_AT_LOCATION: u64 = 0x02;
+const DW_AT_NAME: u64 = 0x03;
+const DW_AT_BYTE_SIZE: u64 = 0x0b;
+const DW_AT_BIT_OFFSET: u64 = 0x0c;
+const DW_AT_BIT_SIZE: u64 = 0x0d;
+const DW_AT_LOW_PC: u64 = 0x11;
+const DW_AT_HIGH_PC: u64 = 0x12;
+const DW_AT_LANGUAGE: u64 = 0x13;
+const DW_AT_COMP_DIR: u64 = 0x1b;
+const DW_AT_CONST_VALUE: u64 = 0x1c;
+const DW_AT_CONTAINING_TYPE: u64 = 0x1d;
+const DW_AT_INLINE: u64 = 0x20;
+const DW_AT_LOWER_BOUND: u64 = 0x22;
+const DW_AT_PRODUCER: u64 = 0x25;
+const DW_AT_PROTOTYPED: u64 = 0x27;
+const DW_AT_UPPER_BOUND: u64 = 0x2f;
+const DW_AT_ABSTRACT_ORIGIN: u64 = 0x31;
+const DW_AT_ACCESSIBILITY: u64 = 0x32;
+const DW_AT_COUNT: u64 = 0x37;
+const DW_AT_DATA_MEMBER_LOCATION: u64 = 0x38;
+const DW_AT_ENCODING: u64 = 0x3e;
+const DW_AT_EXTERNAL: u64 = 0x3f;
+const DW_AT_FRAME_BASE: u64 = 0x40;
+const DW_AT_SPECIFICATION: u64 = 0x47;
+const DW_AT_TYPE: u64 = 0x49;
+const DW_AT_VIRTUALITY: u64 = 0x4c;
+const DW_AT_LINKAGE_NAME: u64 = 0x6e;
+const DW_AT_MIPS_LINKAGE_NAME: u64 = 0x2007;
+
+const DW_FORM_ADDR: u64 = 0x01;
+const DW_FORM_BLOCK2: u64 = 0x03;
+const DW_FORM_BLOCK4: u64 = 0x04;
+const DW_FORM_DATA2: u64 = 0x05;
+const DW_FORM_DATA4: u64 = 0x06;
+const DW_FORM_DATA8: u64 = 0x07;
+const DW_FORM_STRING: u64 = 0x08;
+const DW_FORM_BLOCK: u64 = 0x09;
+const DW_FORM_BLOCK1: u64 = 0x0a;
+const DW_FORM_DATA1: u64 = 0x0b;
+const DW_FORM_FLAG: u64 = 0x0c;
+const DW_FORM_SDATA: u64 = 0x0d;
+const DW_FORM_STRP: u64 = 0x0e;
+const DW_FORM_UDATA: u64 = 0x0f;
+const DW_FORM_REF_ADDR: u64 = 0x10;
+const DW_FORM_REF1: u64 = 0x11;
+const DW_FORM_REF2: u64 = 0x12;
+const DW_FORM_REF4: u64 = 0x13;
+const DW_FORM_REF8: u64 = 0x14;
+const DW_FORM_REF_UDATA: u64 = 0x15;
+const DW_FORM_INDIRECT: u64 = 0x16;
+const DW_FORM_SEC_OFFSET: u64 = 0x17;
+const DW_FORM_EXPRLOC: u64 = 0x18;
+const DW_FORM_FLAG_PRESENT: u64 = 0x19;
+
+const DW_ATE_COMPLEX_FLOAT: u64 = 0x03;
+const DW_ATE_BOOLEAN: u64 = 0x02;
+const DW_ATE_FLOAT: u64 = 0x04;
+const DW_ATE_SIGNED: u64 = 0x05;
+const DW_ATE_SIGNED_CHAR: u64 = 0x06;
+const DW_ATE_UNSIGNED: u64 = 0x07;
+const DW_ATE_UNSIGNED_CHAR: u64 = 0x08;
+
+const DW_OP_ADDR: u8 = 0x03;
+const DW_OP_PLUS_UCONST: u8 = 0x23;
+const DW_OP_REG0: u8 = 0x50;
+const DW_OP_REG31: u8 = 0x6f;
+const DW_OP_BREG0: u8 = 0x70;
+const DW_OP_BREG31: u8 = 0x8f;
+const DW_OP_REGX: u8 = 0x90;
+const DW_OP_FBREG: u8 = 0x91;
+
+#[derive(Debug, Clone)]
+enum Value {
+    Address(u32),
+    /// Offset of the referenced entry in `.debug_info`.
+    Reference(u32),
+    Constant(u64),
+    Signed(i64),
+    Block(Vec<u8>),
+    String(String),
+    Flag(bool),
+}
+
+#[derive(Debug)]
+struct Die {
+    tag: u64,
+    attributes: Vec<(u64, Value)>,
+    parent: Option<u32>,
+    children: Vec<u32>,
+    /// Offset of the null entry ending the children, if the entry has any.
+    children_end: Option<u32>,
+}
+
+impl Die {
+    fn attribute(&self, at: u64) -> Option<&Value> {
+        self.attributes.iter().find(|(kind, _)| *kind == at).map(|(_, value)| value)
+    }
+
+    fn reference(&self, at: u64) -> Option<u32> {
+        match self.attribute(at) {
+            Some(&Value::Reference(offset)) => Some(offset),
+            _ => None,
+        }
+    }
+
+    fn constant(&self, at: u64) -> Option<u64> {
+        match self.attribute(at) {
+            Some(&Value::Constant(value)) => Some(value),
+            Some(&Value::Signed(value)) => Some(value as u64),
+            _ => None,
+        }
+    }
+
+    fn string(&self, at: u64) -> Option<&str> {
+        match self.attribute(at) {
+            Some(Value::String(s)) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+struct Abbrev {
+    tag: u64,
+    has_children: bool,
+    /// Attribute and form pairs.
+    attributes: Vec<(u64, u64)>,
+}
+
+struct Unit {
+    /// Offset of the unit's root entry.
+    root: u32,
+    /// Offset of the end of the unit.
+    end: u32,
+}
+
+/// Reads DWARF 2/3 information from `.debug_info`, lowering it to DWARF 1.1 tags.
+///
+/// `abbrev` and `str` are the contents of `.debug_abbrev` and `.debug_str`, with relocations
+/// applied. Entries without a DWARF 1.1 equivalent are skipped, and C++ namespaces are
+/// flattened.
+pub fn read_debug_info_section(
+    info: &[u8],
+    abbrev: &[u8],
+    str: &[u8],
+    e: Endian,
+) -> Result<DwarfInfo> {
+    let (dies, units) = read_dies(info, abbrev, str, e)?;
+    let mut lowering = Lowering { dies: &dies, e, tags: TagMap::new() };
+    for (idx, unit) in units.iter().enumerate() {
+        // Units are siblings; the last one points past the end of the section
+        let sibling = units.get(idx + 1).map(|next| next.root).unwrap_or(unit.end);
+        let scope = Scope { parent: None, frame_base: None };
+        lowering.lower_die(unit.root, sibling, scope)?;
+    }
+    Ok(DwarfInfo { e, tags: lowering.tags })
+}
+
+fn read_dies(
+    info: &[u8],
+    abbrev: &[u8],
+    str: &[u8],
+    e: Endian,
+) -> Result<(BTreeMap<u32, Die>, Vec<Unit>)> {
+    let mut dies = BTreeMap::<u32, Die>::new();
+    let mut units = Vec::new();
+    let mut abbrev_cache = HashMap::<u32, HashMap<u64, Abbrev>>::new();
+    let mut reader = Cursor::new(info);
+    while reader.position() < info.len() as u64 {
+        let unit_offset = reader.position() as u32;
+        let unit_length = u32::from_reader(&mut reader, e)?;
+        ensure!(unit_length != 0xFFFFFFFF, "64-bit DWARF is not supported");
+        let unit_end = unit_offset as u64 + 4 + unit_length as u64;
+        ensure!(
+            unit_end <= info.len() as u64,
+            "Invalid unit length {:#X} at {:#X}",
+            unit_length,
+            unit_offset
+        );
+        let version = u16::from_reader(&mut reader, e)?;
+        ensure!(
+            (2..=4).contains(&version),
+            "Unsupported DWARF version {} at {:#X}",
+            version,
+            unit_offset
+        );
+        let abbrev_offset = u32::from_reader(&mut reader, e)?;
+        let address_size = u8::from_reader(&mut reader, e)?;
+        ensure!(address_size == 4, "Unsupported address size {}", address_size);
+        let abbrevs = match abbrev_cache.entry(abbrev_offset) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                entry.insert(read_abbrevs(abbrev, abbrev_offset).with_context(|| {
+                    format!("Failed to read abbreviations at {:#X}", abbrev_offset)
+                })?)
+            }
+        };
+
+        let mut root = None;
+        let mut parents = Vec::<u32>::new();
+        while reader.position() < unit_end {
+            let offset = reader.position() as u32;
+            let code = read_uleb(&mut reader)?;
+            if code == 0 {
+                // Null entry, ends the current list of children
+                if let Some(die) = parents.pop().and_then(|parent| dies.get_mut(&parent)) {
+                    die.children_end = Some(offset);
+                }
+                continue;
+            }
+            let abbrev = abbrevs
+                .get(&code)
+                .ok_or_else(|| anyhow!("Unknown abbreviation {} at {:#X}", code, offset))?;
+            let mut attributes = Vec::with_capacity(abbrev.attributes.len());
+            for &(at, form) in &abbrev.attributes {
+                let value =
+                    read_value(&mut reader, form, unit_offset, str, e).with_context(|| {
+                        format!("Failed to read attribute {:#X} at {:#X}", at, offset)
+                    })?;
+                attributes.push((at, value));
+            }
+            let parent = parents.last().copied();
+            match parent.and_then(|parent| dies.get_mut(&parent)) {
+                Some(die) => die.children.push(offset),
+                None => {
+                    root.get_or_insert(offset);
+                }
+            }
+            dies.insert(offset, Die {
+                tag: abbrev.tag,
+                attributes,
+                parent,
+                children: Vec::new(),
+                children_end: None,
+            });
+            if abbrev.has_children {
+                parents.push(offset);
+            }
+        }
+        reader.set_position(unit_end);
+        // Partial and type units have no DWARF 1.1 equivalent
+        if let Some(root) = root.filter(|root| dies[root].tag == DW_TAG_COMPILE_UNIT) {
+            units.push(Unit { root, end: unit_end as u32 });
+        }
+    }
+    Ok((dies, units))
+}
+
+fn read_abbrevs(data: &[u8], offset: u32) -> Result<HashMap<u64, Abbrev>> {
+    let mut reader = Cursor::new(data);
+    reader.set_position(offset as u64);
+    let mut abbrevs = HashMap::new();
+    loop {
+        let code = read_uleb(&mut reader)?;
+        if code == 0 {
+            break;
+        }
+        let tag = read_uleb(&mut reader)?;
+        let has_children = u8::from_reader(&mut reader, Endian::Big)? != 0;
+        let mut attributes = Vec::new();
+        loop {
+            let at = read_uleb(&mut reader)?;
+            let form = read_uleb(&mut reader)?;
+            if at == 0 && form == 0 {
+                break;
+            }
+            attributes.push((at, form));
+        }
+        abbrevs.insert(code, Abbrev { tag, has_children, attributes });
+    }
+    Ok(abbrevs)
+}
+
+fn read_value(
+    reader: &mut Cursor<&[u8]>,
+    form: u64,
+    unit_offset: u32,
+    str: &[u8],
+    e: Endian,
+) -> Result<Value> {
+    Ok(match form {
+        DW_FORM_ADDR => Value::Address(u32::from_reader(reader, e)?),
+        DW_FORM_BLOCK1 => {
+            let size = u8::from_reader(reader, e)?;
+            Value::Block(read_block(reader, size as usize)?)
+        }
+        DW_FORM_BLOCK2 => {
+            let size = u16::from_reader(reader, e)?;
+            Value::Block(read_block(reader, size as usize)?)
+        }
+        DW_FORM_BLOCK4 => {
+            let size = u32::from_reader(reader, e)?;
+            Value::Block(read_block(reader, size as usize)?)
+        }
+        DW_FORM_BLOCK | DW_FORM_EXPRLOC => {
+            let size = read_uleb(reader)?;
+            Value::Block(read_block(reader, size as usize)?)
+        }
+        DW_FORM_DATA1 => Value::Constant(u8::from_reader(reader, e)? as u64),
+        DW_FORM_DATA2 => Value::Constant(u16::from_reader(reader, e)? as u64),
+        DW_FORM_DATA4 | DW_FORM_SEC_OFFSET => Value::Constant(u32::from_reader(reader, e)? as u64),
+        DW_FORM_DATA8 => Value::Constant(u64::from_reader(reader, e)?),
+        DW_FORM_SDATA => Value::Signed(read_sleb(reader)?),
+        DW_FORM_UDATA => Value::Constant(read_uleb(reader)?),
+        DW_FORM_FLAG => Value::Flag(u8::from_reader(reader, e)? != 0),
+        DW_FORM_FLAG_PRESENT => Value::Flag(true),
+        DW_FORM_STRING => Value::String(read_string(reader)?),
+        DW_FORM_STRP => {
+            let offset = u32::from_reader(reader, e)?;
+            let data = str
+                .get(offset as usize..)
+                .ok_or_else(|| anyhow!("Invalid .debug_str offset {:#X}", offset))?;
+            Value::String(read_string(&mut Cursor::new(data))?)
+        }
+        DW_FORM_REF_ADDR => Value::Reference(u32::from_reader(reader, e)?),
+        DW_FORM_REF1 => Value::Reference(unit_offset + u8::from_reader(reader, e)? as u32),
+        DW_FORM_REF2 => Value::Reference(unit_offset + u16::from_reader(reader, e)? as u32),
+        DW_FORM_REF4 => Value::Reference(unit_offset + u32::from_reader(reader, e)?),
+        DW_FORM_REF8 => Value::Reference(unit_offset + u64::from_reader(reader, e)? as u32),
+        DW_FORM_REF_UDATA => Value::Reference(unit_offset + read_uleb(reader)? as u32),
+        DW_FORM_INDIRECT => {
+            let form = read_uleb(reader)?;
+            return read_value(reader, form, unit_offset, str, e);
+        }
+        _ => bail!("Unsupported form {:#X}", form),
+    })
+}
+
+fn read_block<R>(reader: &mut R, size: usize) -> Result<Vec<u8>>
+where R: Read + ?Sized {
+    let mut data = vec![0u8; size];
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
+
+fn read_string<R>(reader: &mut R) -> Result<String>
+where R: Read + ?Sized {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 1];
+    loop {
+        reader.read_exact(&mut buf)?;
+        if buf[0] == 0 {
+            break;
+        }
+        data.push(buf[0]);
+    }
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+fn read_uleb<R>(reader: &mut R) -> Result<u64>
+where R: Read + Seek + ?Sized {
+    let mut result = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = u8::from_reader(reader, Endian::Big)?;
+        if shift < 64 {
+            result |= ((byte & 0x7F) as u64) << shift;
+        }
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+    }
+}
+
+fn read_sleb<R>(reader: &mut R) -> Result<i64>
+where R: Read + Seek + ?Sized {
+    let mut result = 0i64;
+    let mut shift = 0;
+    loop {
+        let byte = u8::from_reader(reader, Endian::Big)?;
+        if shift < 64 {
+            result |= ((byte & 0x7F) as i64) << shift;
+        }
+        shift += 7;
+        if byte & 0x80 == 0 {
+            if shift < 64 && byte & 0x40 != 0 {
+                result |= -1i64 << shift;
+            }
+            return Ok(result);
+        }
+    }
+}
+
+fn push_u16(out: &mut Vec<u8>, value: u16, e: Endian) {
+    out.extend_from_slice(&match e {
+        Endian::Big => value.to_be_bytes(),
+        Endian::Little => value.to_le_bytes(),
+    });
+}
+
+fn push_u32(out: &mut Vec<u8>, value: u32, e: Endian) {
+    out.extend_from_slice(&match e {
+        Endian::Big => value.to_be_bytes(),
+        Endian::Little => value.to_le_bytes(),
+    });
+}
+
+/// Where an entry is being lowered.
+#[derive(Copy, Clone)]
+struct Scope {
+    parent: Option<TagKind>,
+    /// Register of the enclosing subroutine's frame base, for `DW_OP_fbreg` locations.
+    frame_base: Option<u32>,
+}
+
+impl Scope {
+    fn in_subroutine(&self) -> bool {
+        matches!(
+            self.parent,
+            Some(
+                TagKind::GlobalSubroutine
+                    | TagKind::Subroutine
+                    | TagKind::InlinedSubroutine
+                    | TagKind::LexicalBlock
+            )
+        )
+    }
+}
+
+/// A DWARF 2 type reference, flattened into DWARF 1.1 modifiers and a base type.
+struct LoweredType {
+    modifiers: Vec<Modifier>,
+    fundamental: Option<FundType>,
+    /// Offset of the user defined type, if not fundamental.
+    user_defined: u32,
+}
+
+impl LoweredType {
+    fn attribute(&self, e: Endian) -> Attribute {
+        let mut block = self.modifiers.iter().map(|&m| m as u8).collect::<Vec<_>>();
+        let (kind, value) = match (self.fundamental, self.modifiers.is_empty()) {
+            (Some(ft), true) => (AttributeKind::FundType, AttributeValue::Data2(ft as u16)),
+            (Some(ft), false) => {
+                push_u16(&mut block, ft as u16, e);
+                (AttributeKind::ModFundType, AttributeValue::Block(block))
+            }
+            (None, true) => {
+                (AttributeKind::UserDefType, AttributeValue::Reference(self.user_defined))
+            }
+            (None, false) => {
+                push_u32(&mut block, self.user_defined, e);
+                (AttributeKind::ModUDType, AttributeValue::Block(block))
+            }
+        };
+        Attribute { kind, value }
+    }
+
+    /// Writes the type as an attribute, as embedded in `AT_subscr_data`.
+    fn write(&self, out: &mut Vec<u8>, e: Endian) {
+        let attr = self.attribute(e);
+        push_u16(out, attr.kind as u16, e);
+        match attr.value {
+            AttributeValue::Data2(value) => push_u16(out, value, e),
+            AttributeValue::Reference(value) => push_u32(out, value, e),
+            AttributeValue::Block(block) => {
+                push_u16(out, block.len() as u16, e);
+                out.extend_from_slice(&block);
+            }
+            _ => {}
+        }
+    }
+}
+
+struct Lowering<'a> {
+    dies: &'a BTreeMap<u32, Die>,
+    e: Endian,
+    tags: TagMap,
+}
+
+impl<'a> Lowering<'a> {
+    fn die(&self, offset: u32) -> Result<&'a Die> {
+        self.dies.get(&offset).ok_or_else(|| anyhow!("Failed to locate entry {:#X}", offset))
+    }
+
+    /// Finds an attribute on an entry or, failing that, on its specification or abstract origin.
+    fn lookup(&self, die: &'a Die, at: u64) -> Option<&'a Value> {
+        let mut current = die;
+        for _ in 0..8 {
+            if let Some(value) = current.attribute(at) {
+                return Some(value);
+            }
+            let next = current
+                .reference(DW_AT_SPECIFICATION)
+                .or_else(|| current.reference(DW_AT_ABSTRACT_ORIGIN))?;
+            current = self.dies.get(&next)?;
+        }
+        None
+    }
+
+    fn lookup_flag(&self, die: &'a Die, at: u64) -> bool {
+        match self.lookup(die, at) {
+            Some(&Value::Flag(value)) => value,
+            Some(&Value::Constant(value)) => value != 0,
+            _ => false,
+        }
+    }
+
+    /// The DWARF 1.1 tag for an entry, or `None` if it's skipped.
+    fn tag_kind(&self, die: &'a Die, scope: Scope) -> Option<TagKind> {
+        Some(match die.tag {
+            DW_TAG_ARRAY_TYPE => TagKind::ArrayType,
+            DW_TAG_CLASS_TYPE => TagKind::ClassType,
+            DW_TAG_ENUMERATION_TYPE => TagKind::EnumerationType,
+            DW_TAG_FORMAL_PARAMETER if scope.parent != Some(TagKind::LexicalBlock) => {
+                TagKind::FormalParameter
+            }
+            // Labels optimized out have no address
+            DW_TAG_LABEL
+                if scope.parent != Some(TagKind::LexicalBlock)
+                    && die.attribute(DW_AT_NAME).is_some()
+                    && die.attribute(DW_AT_LOW_PC).is_some() =>
+            {
+                TagKind::Label
+            }
+            DW_TAG_LEXICAL_BLOCK => TagKind::LexicalBlock,
+            DW_TAG_MEMBER => TagKind::Member,
+            DW_TAG_COMPILE_UNIT => TagKind::CompileUnit,
+            DW_TAG_STRUCTURE_TYPE => TagKind::StructureType,
+            DW_TAG_SUBROUTINE_TYPE => TagKind::SubroutineType,
+            DW_TAG_TYPEDEF if scope.parent != Some(TagKind::LexicalBlock) => TagKind::Typedef,
+            DW_TAG_UNION_TYPE => TagKind::UnionType,
+            DW_TAG_UNSPECIFIED_PARAMETERS => TagKind::UnspecifiedParameters,
+            DW_TAG_INHERITANCE => TagKind::Inheritance,
+            DW_TAG_INLINED_SUBROUTINE => TagKind::InlinedSubroutine,
+            DW_TAG_PTR_TO_MEMBER_TYPE => TagKind::PtrToMemberType,
+            DW_TAG_SUBPROGRAM if !scope.in_subroutine() => {
+                if self.lookup_flag(die, DW_AT_EXTERNAL) {
+                    TagKind::GlobalSubroutine
+                } else {
+                    TagKind::Subroutine
+                }
+            }
+            DW_TAG_VARIABLE => {
+                if scope.in_subroutine() {
+                    TagKind::LocalVariable
+                } else if self.lookup_flag(die, DW_AT_EXTERNAL)
+                    || matches!(
+                        scope.parent,
+                        Some(TagKind::StructureType | TagKind::ClassType | TagKind::UnionType)
+                    )
+                {
+                    TagKind::GlobalVariable
+                } else {
+                    // File-scope static
+                    TagKind::LocalVariable
+                }
+            }
+            _ => return None,
+        })
+    }
+
+    /// Collects the entries that get a tag, flattening namespaces.
+    fn collect(&self, offsets: &[u32], scope: Scope, out: &mut Vec<u32>) -> Result<()> {
+        for &offset in offsets {
+            let die = self.die(offset)?;
+            if die.tag == DW_TAG_NAMESPACE {
+                self.collect(&die.children, scope, out)?;
+            } else if self.tag_kind(die, scope).is_some() {
+                out.push(offset);
+            }
+        }
+        Ok(())
+    }
+
+    /// Lowers an entry and its children. `sibling` is the key of the tag following it.
+    fn lower_die(&mut self, offset: u32, sibling: u32, scope: Scope) -> Result<()> {
+        let die = self.die(offset)?;
+        let kind = self
+            .tag_kind(die, scope)
+            .ok_or_else(|| anyhow!("Unexpected entry {:#X} at {:#X}", die.tag, offset))?;
+        let mut attributes = vec![Attribute {
+            kind: AttributeKind::Sibling,
+            value: AttributeValue::Reference(sibling),
+        }];
+        self.lower_attributes(die, kind, scope, &mut attributes)
+            .with_context(|| format!("Failed to process entry at {:#X}", offset))?;
+        self.tags.insert(offset, Tag {
+            key: offset,
+            kind,
+            is_erased: false,
+            is_erased_root: false,
+            attributes,
+        });
+
+        let Some(children_end) = die.children_end else {
+            return Ok(());
+        };
+        // Subranges and enumerators are folded into their parent's attributes
+        if !matches!(kind, TagKind::ArrayType | TagKind::EnumerationType) {
+            let frame_base = match die.attribute(DW_AT_FRAME_BASE) {
+                Some(Value::Block(block)) => match block.as_slice() {
+                    &[op @ DW_OP_REG0..=DW_OP_REG31] => Some((op - DW_OP_REG0) as u32),
+                    &[op @ DW_OP_BREG0..=DW_OP_BREG31, 0] => Some((op - DW_OP_BREG0) as u32),
+                    _ => None,
+                },
+                _ => scope.frame_base,
+            };
+            let child_scope = Scope { parent: Some(kind), frame_base };
+            let mut children = Vec::new();
+            self.collect(&die.children, child_scope, &mut children)?;
+            for (idx, &child) in children.iter().enumerate() {
+                let next = children.get(idx + 1).copied().unwrap_or(children_end);
+                self.lower_die(child, next, child_scope)?;
+            }
+        }
+        self.tags.insert(children_end, Tag {
+            key: children_end,
+            kind: TagKind::Padding,
+            is_erased: false,
+            is_erased_root: false,
+            attributes: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn lower_attributes(
+        &self,
+        die: &'a Die,
+        kind: TagKind,
+        scope: Scope,
+        out: &mut Vec<Attribute>,
+    ) -> Result<()> {
+        let e = self.e;
+        let flag =
+            |kind: AttributeKind| Attribute { kind, value: AttributeValue::String(String::new()) };
+        let named = !matches!(kind, TagKind::ArrayType | TagKind::PtrToMemberType);
+        if let (true, Some(Value::String(name))) = (named, self.lookup(die, DW_AT_NAME)) {
+            out.push(Attribute {
+                kind: AttributeKind::Name,
+                value: AttributeValue::String(name.clone()),
+            });
+        }
+        match kind {
+            TagKind::CompileUnit => {
+                if let Some(producer) = die.string(DW_AT_PRODUCER) {
+                    out.push(Attribute {
+                        kind: AttributeKind::Producer,
+                        value: AttributeValue::String(producer.to_string()),
+                    });
+                }
+                if let Some(comp_dir) = die.string(DW_AT_COMP_DIR) {
+                    out.push(Attribute {
+                        kind: AttributeKind::CompDir,
+                        value: AttributeValue::String(comp_dir.to_string()),
+                    });
+                }
+                let language = match die.constant(DW_AT_LANGUAGE) {
+                    Some(value @ 0x1..=0xa) => Some(value as u32),
+                    // C99
+                    Some(0xc) => Some(0x2),
+                    _ => None,
+                };
+                if let Some(language) = language {
+                    out.push(Attribute {
+                        kind: AttributeKind::Language,
+                        value: AttributeValue::Data4(language),
+                    });
+                }
+                self.push_pc_range(die, out);
+            }
+            TagKind::StructureType | TagKind::ClassType | TagKind::UnionType => {
+                self.push_byte_size(die, out);
+            }
+            TagKind::EnumerationType => {
+                self.push_byte_size(die, out);
+                let mut elements = Vec::new();
+                for &child in &die.children {
+                    let child = self.die(child)?;
+                    if child.tag != DW_TAG_ENUMERATOR {
+                        continue;
+                    }
+                    let value = child
+                        .constant(DW_AT_CONST_VALUE)
+                        .ok_or_else(|| anyhow!("Enumerator without value"))?;
+                    push_u32(&mut elements, value as u32, e);
+                    elements
+                        .extend_from_slice(child.string(DW_AT_NAME).unwrap_or_default().as_bytes());
+                    elements.push(0);
+                }
+                out.push(Attribute {
+                    kind: AttributeKind::ElementList,
+                    value: AttributeValue::Block(elements),
+                });
+            }
+            TagKind::ArrayType => {
+                let mut data = Vec::new();
+                for &child in &die.children {
+                    let child = self.die(child)?;
+                    if child.tag != DW_TAG_SUBRANGE_TYPE {
+                        continue;
+                    }
+                    let low_bound = child.constant(DW_AT_LOWER_BOUND).unwrap_or(0);
+                    // u32::MAX marks an unbounded dimension
+                    let high_bound =
+                        match (child.constant(DW_AT_UPPER_BOUND), child.constant(DW_AT_COUNT)) {
+                            (Some(upper), _) => upper,
+                            (None, Some(count)) => low_bound.wrapping_add(count).wrapping_sub(1),
+                            (None, None) => u32::MAX as u64,
+                        };
+                    data.push(SubscriptFormat::FundTypeConstConst as u8);
+                    push_u16(&mut data, FundType::Long as u16, e);
+                    push_u32(&mut data, low_bound as u32, e);
+                    push_u32(&mut data, high_bound as u32, e);
+                }
+                data.push(SubscriptFormat::ElementType as u8);
+                self.lower_type(die.reference(DW_AT_TYPE))?.write(&mut data, e);
+                out.push(Attribute {
+                    kind: AttributeKind::SubscrData,
+                    value: AttributeValue::Block(data),
+                });
+            }
+            TagKind::Member | TagKind::Inheritance => {
+                out.push(self.lower_type(self.lookup_reference(die, DW_AT_TYPE))?.attribute(e));
+                // Union members may omit their offset
+                let offset = match die.attribute(DW_AT_DATA_MEMBER_LOCATION) {
+                    None => Some(0),
+                    Some(Value::Constant(value)) => Some(*value as u32),
+                    Some(Value::Block(block)) => match block.split_first() {
+                        Some((&DW_OP_PLUS_UCONST, rest)) => {
+                            let mut reader = Cursor::new(rest);
+                            let value = read_uleb(&mut reader)?;
+                            (reader.position() == rest.len() as u64).then_some(value as u32)
+                        }
+                        _ => None,
+                    },
+                    Some(_) => None,
+                };
+                if let Some(offset) = offset {
+                    let mut block = vec![LocationOp::Const as u8];
+                    push_u32(&mut block, offset, e);
+                    block.push(LocationOp::Add as u8);
+                    out.push(Attribute {
+                        kind: AttributeKind::Location,
+                        value: AttributeValue::Block(block),
+                    });
+                }
+                if let Some(bit_size) = die.constant(DW_AT_BIT_SIZE) {
+                    if let Some(byte_size) = die.constant(DW_AT_BYTE_SIZE) {
+                        out.push(Attribute {
+                            kind: AttributeKind::ByteSize,
+                            value: AttributeValue::Data4(byte_size as u32),
+                        });
+                    }
+                    out.push(Attribute {
+                        kind: AttributeKind::BitSize,
+                        value: AttributeValue::Data4(bit_size as u32),
+                    });
+                    out.push(Attribute {
+                        kind: AttributeKind::BitOffset,
+                        value: AttributeValue::Data2(
+                            die.constant(DW_AT_BIT_OFFSET).unwrap_or(0) as u16
+                        ),
+                    });
+                }
+                match die.constant(DW_AT_ACCESSIBILITY) {
+                    Some(1) => out.push(flag(AttributeKind::Public)),
+                    Some(2) => out.push(flag(AttributeKind::Protected)),
+                    Some(3) => out.push(flag(AttributeKind::Private)),
+                    _ => {}
+                }
+                if kind == TagKind::Inheritance && die.constant(DW_AT_VIRTUALITY).unwrap_or(0) != 0
+                {
+                    out.push(flag(AttributeKind::Virtual));
+                }
+            }
+            TagKind::Typedef | TagKind::FormalParameter => {
+                out.push(self.lower_type(self.lookup_reference(die, DW_AT_TYPE))?.attribute(e));
+                if kind == TagKind::FormalParameter {
+                    self.push_location(die, scope, false, out);
+                }
+            }
+            TagKind::GlobalVariable | TagKind::LocalVariable => {
+                self.push_mangled_name(die, out);
+                out.push(self.lower_type(self.lookup_reference(die, DW_AT_TYPE))?.attribute(e));
+                // Global variables only have addresses
+                let address_only = !scope.in_subroutine();
+                self.push_location(die, scope, address_only, out);
+            }
+            TagKind::SubroutineType
+            | TagKind::GlobalSubroutine
+            | TagKind::Subroutine
+            | TagKind::InlinedSubroutine => {
+                self.push_mangled_name(die, out);
+                // A missing return type means void
+                if let Some(offset) = self.lookup_reference(die, DW_AT_TYPE) {
+                    out.push(self.lower_type(Some(offset))?.attribute(e));
+                }
+                if self.lookup_flag(die, DW_AT_PROTOTYPED) {
+                    out.push(flag(AttributeKind::Prototyped));
+                }
+                if self.lookup_flag(die, DW_AT_INLINE) {
+                    out.push(flag(AttributeKind::Inline));
+                }
+                if self.lookup_flag(die, DW_AT_VIRTUALITY) {
+                    out.push(flag(AttributeKind::Virtual));
+                }
+                if let Some(class) = self.containing_class(die) {
+                    out.push(Attribute {
+                        kind: AttributeKind::Member,
+                        value: AttributeValue::Reference(class),
+                    });
+                }
+                self.push_pc_range(die, out);
+            }
+            TagKind::LexicalBlock => self.push_pc_range(die, out),
+            TagKind::Label => {
+                if let Some(&Value::Address(addr)) = die.attribute(DW_AT_LOW_PC) {
+                    out.push(Attribute {
+                        kind: AttributeKind::LowPc,
+                        value: AttributeValue::Address(addr),
+                    });
+                }
+            }
+            TagKind::PtrToMemberType => {
+                out.push(self.lower_type(die.reference(DW_AT_TYPE))?.attribute(e));
+                if let Some(containing_type) = die.reference(DW_AT_CONTAINING_TYPE) {
+                    out.push(Attribute {
+                        kind: AttributeKind::ContainingType,
+                        value: AttributeValue::Reference(containing_type),
+                    });
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn lookup_reference(&self, die: &'a Die, at: u64) -> Option<u32> {
+        match self.lookup(die, at) {
+            Some(&Value::Reference(offset)) => Some(offset),
+            _ => None,
+        }
+    }
+
+    /// The class a member function belongs to: the parent of its declaration.
+    fn containing_class(&self, die: &'a Die) -> Option<u32> {
+        let mut current = die;
+        for _ in 0..8 {
+            if let Some(parent) = current.parent {
+                let parent_die = self.dies.get(&parent)?;
+                if matches!(
+                    parent_die.tag,
+                    DW_TAG_STRUCTURE_TYPE | DW_TAG_CLASS_TYPE | DW_TAG_UNION_TYPE
+                ) {
+                    return Some(parent);
+                }
+            }
+            let next = current
+                .reference(DW_AT_SPECIFICATION)
+                .or_else(|| current.reference(DW_AT_ABSTRACT_ORIGIN))?;
+            current = self.dies.get(&next)?;
+        }
+        None
+    }
+
+    fn push_byte_size(&self, die: &Die, out: &mut Vec<Attribute>) {
+        if let Some(byte_size) = die.constant(DW_AT_BYTE_SIZE) {
+            out.push(Attribute {
+                kind: AttributeKind::ByteSize,
+                value: AttributeValue::Data4(byte_size as u32),
+            });
+        }
+    }
+
+    fn push_mangled_name(&self, die: &'a Die, out: &mut Vec<Attribute>) {
+        let name = self
+            .lookup(die, DW_AT_MIPS_LINKAGE_NAME)
+            .or_else(|| self.lookup(die, DW_AT_LINKAGE_NAME));
+        if let Some(Value::String(name)) = name {
+            out.push(Attribute {
+                kind: AttributeKind::MwMangled,
+                value: AttributeValue::String(name.clone()),
+            });
+        }
+    }
+
+    fn push_pc_range(&self, die: &Die, out: &mut Vec<Attribute>) {
+        let Some(&Value::Address(low_pc)) = die.attribute(DW_AT_LOW_PC) else {
+            return;
+        };
+        out.push(Attribute { kind: AttributeKind::LowPc, value: AttributeValue::Address(low_pc) });
+        let high_pc = match die.attribute(DW_AT_HIGH_PC) {
+            Some(&Value::Address(addr)) => addr,
+            // DWARF 4 stores the size
+            Some(&Value::Constant(size)) => low_pc.wrapping_add(size as u32),
+            _ => return,
+        };
+        out.push(Attribute {
+            kind: AttributeKind::HighPc,
+            value: AttributeValue::Address(high_pc),
+        });
+    }
+
+    /// Converts a location expression to its DWARF 1.1 equivalent, if there is one. Location
+    /// lists and complex expressions are skipped.
+    fn push_location(&self, die: &Die, scope: Scope, address_only: bool, out: &mut Vec<Attribute>) {
+        let Some(Value::Block(expr)) = die.attribute(DW_AT_LOCATION) else {
+            return;
+        };
+        let Some((&op, rest)) = expr.split_first() else {
+            return;
+        };
+        let mut reader = Cursor::new(rest);
+        let (base_op, value, offset) = match op {
+            DW_OP_ADDR if rest.len() == 4 => {
+                let Ok(addr) = u32::from_reader(&mut reader, self.e) else { return };
+                (LocationOp::Address, addr, None)
+            }
+            _ if address_only => return,
+            DW_OP_REG0..=DW_OP_REG31 => (LocationOp::Register, (op - DW_OP_REG0) as u32, None),
+            DW_OP_REGX => {
+                let Ok(reg) = read_uleb(&mut reader) else { return };
+                (LocationOp::Register, reg as u32, None)
+            }
+            DW_OP_BREG0..=DW_OP_BREG31 => {
+                let Ok(offset) = read_sleb(&mut reader) else { return };
+                (LocationOp::BaseRegister, (op - DW_OP_BREG0) as u32, Some(offset))
+            }
+            DW_OP_FBREG => {
+                let (Some(reg), Ok(offset)) = (scope.frame_base, read_sleb(&mut reader)) else {
+                    return;
+                };
+                (LocationOp::BaseRegister, reg, Some(offset))
+            }
+            _ => return,
+        };
+        if reader.position() != rest.len() as u64 {
+            return;
+        }
+        let mut block = vec![base_op as u8];
+        push_u32(&mut block, value, self.e);
+        if let Some(offset) = offset {
+            block.push(LocationOp::Const as u8);
+            push_u32(&mut block, offset as u32, self.e);
+            block.push(LocationOp::Add as u8);
+        }
+        out.push(Attribute { kind: AttributeKind::Location, value: AttributeValue::Block(block) });
+    }
+
+    /// Follows a chain of pointer, reference, const, volatile and typedef entries down to a
+    /// base type or user defined type. Modifiers without a DWARF 1.1 equivalent, such as
+    /// `restrict`, are skipped.
+    fn lower_type(&self, offset: Option<u32>) -> Result<LoweredType> {
+        let mut modifiers = Vec::new();
+        let mut current = offset;
+        for _ in 0..64 {
+            let Some(offset) = current else {
+                return Ok(LoweredType {
+                    modifiers,
+                    fundamental: Some(FundType::Void),
+                    user_defined: 0,
+                });
+            };
+            let die = self.die(offset)?;
+            match die.tag {
+                DW_TAG_POINTER_TYPE => modifiers.push(Modifier::PointerTo),
+                DW_TAG_REFERENCE_TYPE => modifiers.push(Modifier::ReferenceTo),
+                DW_TAG_CONST_TYPE => modifiers.push(Modifier::Const),
+                DW_TAG_VOLATILE_TYPE => modifiers.push(Modifier::Volatile),
+                DW_TAG_TYPEDEF | DW_TAG_RESTRICT_TYPE => {}
+                DW_TAG_BASE_TYPE => {
+                    return Ok(LoweredType {
+                        modifiers,
+                        fundamental: Some(fund_type(die)),
+                        user_defined: 0,
+                    });
+                }
+                DW_TAG_ARRAY_TYPE
+                | DW_TAG_CLASS_TYPE
+                | DW_TAG_ENUMERATION_TYPE
+                | DW_TAG_STRUCTURE_TYPE
+                | DW_TAG_SUBROUTINE_TYPE
+                | DW_TAG_UNION_TYPE
+                | DW_TAG_PTR_TO_MEMBER_TYPE => {
+                    return Ok(LoweredType { modifiers, fundamental: None, user_defined: offset });
+                }
+                tag => log::warn!("Skipping unsupported type entry {:#X} at {:#X}", tag, offset),
+            }
+            current = die.reference(DW_AT_TYPE);
+        }
+        bail!("Type chain too long at {:#X}", offset.unwrap_or_default())
+    }
+}
+
+fn fund_type(die: &Die) -> FundType {
+    let name = die.string(DW_AT_NAME).unwrap_or_default();
+    let size = die.constant(DW_AT_BYTE_SIZE).unwrap_or(0);
+    let encoding = die.constant(DW_AT_ENCODING).unwrap_or(0);
+    match (encoding, size) {
+        _ if name == "void" => FundType::Void,
+        (_, 2) if name == "wchar_t" => FundType::WideChar,
+        (DW_ATE_BOOLEAN, _) => FundType::Boolean,
+        (DW_ATE_FLOAT, 4) => FundType::Float,
+        (DW_ATE_FLOAT, 8) => FundType::DblPrecFloat,
+        (DW_ATE_FLOAT, _) => FundType::ExtPrecFloat,
+        (DW_ATE_COMPLEX_FLOAT, 8) => FundType::Complex,
+        (DW_ATE_COMPLEX_FLOAT, 16) => FundType::DblPrecComplex,
+        (DW_ATE_COMPLEX_FLOAT, _) => FundType::ExtPrecComplex,
+        (DW_ATE_SIGNED_CHAR | DW_ATE_UNSIGNED_CHAR, 1) if name == "char" => FundType::Char,
+        (DW_ATE_SIGNED | DW_ATE_SIGNED_CHAR, 1) => FundType::SignedChar,
+        (DW_ATE_UNSIGNED | DW_ATE_UNSIGNED_CHAR, 1) => FundType::UnsignedChar,
+        (DW_ATE_SIGNED, 2) => FundType::Short,
+        (DW_ATE_UNSIGNED, 2) => FundType::UnsignedShort,
+        (DW_ATE_SIGNED, 4) if name.contains("long") => FundType::Long,
+        (DW_ATE_SIGNED, 4) => FundType::Integer,
+        (DW_ATE_UNSIGNED, 4) if name.contains("long") => FundType::UnsignedLong,
+        (DW_ATE_UNSIGNED, 4) => FundType::UnsignedInteger,
+        (DW_ATE_SIGNED, 8) => FundType::LongLong,
+        (DW_ATE_UNSIGNED, 8) => FundType::UnsignedLongLong,
+        (DW_ATE_SIGNED | DW_ATE_UNSIGNED, 16) => FundType::Int128,
+        _ => {
+            // Substitute a type of the same size, so that layouts stay correct
+            let fallback = match size {
+                1 => FundType::UnsignedChar,
+                2 => FundType::UnsignedShort,
+                4 => FundType::UnsignedInteger,
+                8 => FundType::UnsignedLongLong,
+                16 => FundType::Int128,
+                _ => FundType::Void,
+            };
+            log::warn!(
+                "Unsupported base type '{}' (encoding {:#X}, size {}), using {:?}",
+                name,
+                encoding,
+                size,
+                fallback
+            );
+            fallback
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ABBREV: &[u8] = &[
+        1, 0x11, 1, 0x03, 0x08, 0x25, 0x08, 0x11, 0x01, 0x12, 0x01, 0, 0, // compile_unit
+        2, 0x24, 0, 0x03, 0x08, 0x3e, 0x0b, 0x0b, 0x0b, 0, 0, // base_type
+        3, 0x13, 1, 0x03, 0x0e, 0x0b, 0x0b, 0, 0, // structure_type
+        4, 0x0d, 0, 0x03, 0x08, 0x49, 0x13, 0x38, 0x0a, 0, 0, // member
+        5, 0x34, 0, 0x03, 0x08, 0x49, 0x13, 0x3f, 0x0c, 0x02, 0x0a, 0, 0, // variable
+        6, 0x0f, 0, 0x49, 0x13, 0x0b, 0x0b, 0, 0, // pointer_type
+        7, 0x37, 0, 0x49, 0x13, 0, 0, // restrict_type
+        8, 0x47, 0, 0x49, 0x13, 0, 0, // atomic_type (DWARF 5)
+        9, 0x2e, 1, 0x03, 0x08, 0x3f, 0x19, 0x11, 0x01, 0x12, 0x01, 0x40, 0x0a, 0,
+        0, // subprogram
+        10, 0x05, 0, 0x03, 0x08, 0x49, 0x15, 0x02, 0x0a, 0, 0, // formal_parameter
+        0,
+    ];
+    const STR: &[u8] = b"\0Foo\0";
+
+    /// Offsets of the entries in [`debug_info`].
+    struct Offsets {
+        int: u32,
+        char32: u32,
+        foo: u32,
+        ptr: u32,
+        restrict: u32,
+        atomic: u32,
+        var_foo: u32,
+        var_bar: u32,
+        var_baz: u32,
+        func: u32,
+        param_p: u32,
+        param_q: u32,
+    }
+
+    fn push_die(info: &mut Vec<u8>, parts: &[&[u8]]) -> u32 {
+        let offset = info.len() as u32;
+        for part in parts {
+            info.extend_from_slice(part);
+        }
+        offset
+    }
+
+    /// A single unit declaring `struct Foo { int a; int b; }`, variables of types that lower
+    /// through `restrict`, an unknown modifier and an unknown base type, and a function.
+    fn debug_info() -> (Vec<u8>, Offsets) {
+        // Header: unit length (patched below), version, abbreviation offset, address size
+        let mut info = vec![0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 4];
+        let be = u32::to_be_bytes;
+        push_die(&mut info, &[
+            &[1],
+            b"test.c\0",
+            b"GNU C 2.95.2\0",
+            &be(0x80003000),
+            &be(0x80003010),
+        ]);
+        let int = push_die(&mut info, &[&[2], b"int\0", &[0x05, 4]]);
+        let char32 = push_die(&mut info, &[&[2], b"char32_t\0", &[0x10, 4]]);
+        let foo = push_die(&mut info, &[&[3], &be(1), &[8]]);
+        push_die(&mut info, &[&[4], b"a\0", &be(int), &[2, DW_OP_PLUS_UCONST, 0]]);
+        push_die(&mut info, &[&[4], b"b\0", &be(int), &[2, DW_OP_PLUS_UCONST, 4]]);
+        push_die(&mut info, &[&[0]]);
+        let ptr = push_die(&mut info, &[&[6], &be(foo), &[4]]);
+        let restrict = push_die(&mut info, &[&[7], &be(ptr)]);
+        let atomic = push_die(&mut info, &[&[8], &be(int)]);
+        let var_foo = push_die(&mut info, &[
+            &[5],
+            b"foo\0",
+            &be(restrict),
+            &[1, 5, DW_OP_ADDR],
+            &be(0x80001000),
+        ]);
+        let var_bar = push_die(&mut info, &[
+            &[5],
+            b"bar\0",
+            &be(atomic),
+            &[1, 5, DW_OP_ADDR],
+            &be(0x80001008),
+        ]);
+        let var_baz = push_die(&mut info, &[
+            &[5],
+            b"baz\0",
+            &be(char32),
+            &[1, 5, DW_OP_ADDR],
+            &be(0x8000100C),
+        ]);
+        let func = push_die(&mut info, &[&[9], b"func\0", &be(0x80003000), &be(0x80003010), &[
+            1,
+            DW_OP_REG0 + 1,
+        ]]);
+        let param_p = push_die(&mut info, &[&[10], b"p\0", &[int as u8], &[2, DW_OP_FBREG, 0x78]]);
+        let param_q = push_die(&mut info, &[&[10], b"q\0", &[int as u8], &[1, DW_OP_REG0 + 3]]);
+        push_die(&mut info, &[&[0]]);
+        push_die(&mut info, &[&[0]]);
+        let length = info.len() as u32 - 4;
+        info[..4].copy_from_slice(&be(length));
+        (info, Offsets {
+            int,
+            char32,
+            foo,
+            ptr,
+            restrict,
+            atomic,
+            var_foo,
+            var_bar,
+            var_baz,
+            func,
+            param_p,
+            param_q,
+        })
+    }
+
+    fn attribute(tag: &Tag, kind: AttributeKind) -> &AttributeValue {
+        &tag.attribute(kind).unwrap_or_else(|| panic!("Missing {:?} on {:?}", kind, tag)).value
+    }
+
+    #[test]
+    fn test_read_abbrevs() {
+        let abbrevs = read_abbrevs(ABBREV, 0).unwrap();
+        assert_eq!(abbrevs.len(), 10);
+        let cu = &abbrevs[&1];
+        assert_eq!(cu.tag, DW_TAG_COMPILE_UNIT);
+        assert!(cu.has_children);
+        assert_eq!(cu.attributes, [
+            (DW_AT_NAME, DW_FORM_STRING),
+            (DW_AT_PRODUCER, DW_FORM_STRING),
+            (DW_AT_LOW_PC, DW_FORM_ADDR),
+            (DW_AT_HIGH_PC, DW_FORM_ADDR)
+        ]);
+        let member = &abbrevs[&4];
+        assert_eq!(member.tag, DW_TAG_MEMBER);
+        assert!(!member.has_children);
+        assert_eq!(member.attributes.len(), 3);
+    }
+
+    #[test]
+    fn test_read_value() {
+        let read = |data: &[u8], form: u64| {
+            let mut reader = Cursor::new(data);
+            let value = read_value(&mut reader, form, 0x100, STR, Endian::Big).unwrap();
+            assert_eq!(reader.position(), data.len() as u64, "form {:#X}", form);
+            value
+        };
+        assert!(matches!(read(&[0x12, 0x34], DW_FORM_DATA2), Value::Constant(0x1234)));
+        assert!(matches!(read(&[0xE5, 0x8E, 0x26], DW_FORM_UDATA), Value::Constant(624485)));
+        assert!(matches!(read(&[0x7F], DW_FORM_SDATA), Value::Signed(-1)));
+        assert!(matches!(read(&[], DW_FORM_FLAG_PRESENT), Value::Flag(true)));
+        assert!(matches!(read(&[0, 0, 0, 1], DW_FORM_STRP), Value::String(s) if s == "Foo"));
+        assert!(matches!(read(b"bar\0", DW_FORM_STRING), Value::String(s) if s == "bar"));
+        assert!(
+            matches!(read(&[2, 0xAA, 0xBB], DW_FORM_BLOCK1), Value::Block(b) if b == [0xAA, 0xBB])
+        );
+        // References are relative to the unit, except for `DW_FORM_ref_addr`
+        assert!(matches!(read(&[0, 0, 0, 0x10], DW_FORM_REF4), Value::Reference(0x110)));
+        assert!(matches!(read(&[0, 0, 0, 0x10], DW_FORM_REF_ADDR), Value::Reference(0x10)));
+        assert!(matches!(read(&[DW_FORM_DATA1 as u8, 7], DW_FORM_INDIRECT), Value::Constant(7)));
+        assert!(read_value(&mut Cursor::new(&[0u8][..]), 0x7F, 0, STR, Endian::Big).is_err());
+    }
+
+    #[test]
+    fn test_read_dies() {
+        let (info, offsets) = debug_info();
+        let (dies, units) = read_dies(&info, ABBREV, STR, Endian::Big).unwrap();
+        assert_eq!(units.len(), 1);
+        assert_eq!(units[0].root, 11);
+        assert_eq!(units[0].end, info.len() as u32);
+
+        let cu = &dies[&11];
+        assert_eq!(cu.tag, DW_TAG_COMPILE_UNIT);
+        assert_eq!(cu.string(DW_AT_PRODUCER), Some("GNU C 2.95.2"));
+        assert_eq!(cu.children, [
+            offsets.int,
+            offsets.char32,
+            offsets.foo,
+            offsets.ptr,
+            offsets.restrict,
+            offsets.atomic,
+            offsets.var_foo,
+            offsets.var_bar,
+            offsets.var_baz,
+            offsets.func
+        ]);
+        assert_eq!(cu.children_end, Some(info.len() as u32 - 1));
+
+        let foo = &dies[&offsets.foo];
+        assert_eq!(foo.string(DW_AT_NAME), Some("Foo"));
+        assert_eq!(foo.constant(DW_AT_BYTE_SIZE), Some(8));
+        assert_eq!(foo.children.len(), 2);
+        assert_eq!(dies[&foo.children[1]].parent, Some(offsets.foo));
+        assert_eq!(dies[&offsets.param_p].reference(DW_AT_TYPE), Some(offsets.int));
+        assert_eq!(dies[&offsets.func].children, [offsets.param_p, offsets.param_q]);
+    }
+
+    #[test]
+    fn test_lower_type() {
+        let (info, offsets) = debug_info();
+        let (dies, _) = read_dies(&info, ABBREV, STR, Endian::Big).unwrap();
+        let lowering = Lowering { dies: &dies, e: Endian::Big, tags: TagMap::new() };
+
+        let t = lowering.lower_type(None).unwrap();
+        assert_eq!(t.fundamental, Some(FundType::Void));
+        let t = lowering.lower_type(Some(offsets.int)).unwrap();
+        assert_eq!(t.fundamental, Some(FundType::Integer));
+        // `restrict` and unknown modifiers are transparent
+        let t = lowering.lower_type(Some(offsets.restrict)).unwrap();
+        assert_eq!(t.modifiers, [Modifier::PointerTo]);
+        assert_eq!((t.fundamental, t.user_defined), (None, offsets.foo));
+        let t = lowering.lower_type(Some(offsets.atomic)).unwrap();
+        assert_eq!((t.modifiers.len(), t.fundamental), (0, Some(FundType::Integer)));
+        // Unknown base types are replaced by one of the same size
+        let t = lowering.lower_type(Some(offsets.char32)).unwrap();
+        assert_eq!(t.fundamental, Some(FundType::UnsignedInteger));
+
+        let t = lowering.lower_type(Some(offsets.ptr)).unwrap();
+        let mut data = Vec::new();
+        t.write(&mut data, Endian::Big);
+        assert_eq!(data[..2], (AttributeKind::ModUDType as u16).to_be_bytes());
+        assert_eq!(data[2..], [0, 5, Modifier::PointerTo as u8, 0, 0, 0, offsets.foo as u8]);
+    }
+
+    #[test]
+    fn test_push_location() {
+        let (info, offsets) = debug_info();
+        let (dies, _) = read_dies(&info, ABBREV, STR, Endian::Big).unwrap();
+        let lowering = Lowering { dies: &dies, e: Endian::Big, tags: TagMap::new() };
+        let location = |offset: u32, scope: Scope, address_only: bool| {
+            let mut out = Vec::new();
+            lowering.push_location(&dies[&offset], scope, address_only, &mut out);
+            match out.as_slice() {
+                [Attribute { kind: AttributeKind::Location, value: AttributeValue::Block(b) }] => {
+                    Some(b.clone())
+                }
+                [] => None,
+                out => panic!("Unexpected attributes {:?}", out),
+            }
+        };
+        let unit = Scope { parent: Some(TagKind::CompileUnit), frame_base: None };
+        let func = Scope { parent: Some(TagKind::GlobalSubroutine), frame_base: Some(1) };
+
+        assert_eq!(location(offsets.var_foo, unit, true), Some(vec![3, 0x80, 0, 0x10, 0]));
+        // r1-8
+        assert_eq!(
+            location(offsets.param_p, func, false),
+            Some(vec![2, 0, 0, 0, 1, 4, 0xFF, 0xFF, 0xFF, 0xF8, 7])
+        );
+        assert_eq!(location(offsets.param_q, func, false), Some(vec![1, 0, 0, 0, 3]));
+        // Registers aren't addresses, and `DW_OP_fbreg` needs a frame base
+        assert_eq!(location(offsets.param_q, func, true), None);
+        assert_eq!(location(offsets.param_p, unit, false), None);
+    }
+
+    #[test]
+    fn test_read_debug_info_section() {
+        let (info, offsets) = debug_info();
+        let info = read_debug_info_section(&info, ABBREV, STR, Endian::Big).unwrap();
+        // Types and modifiers are folded into the entries referencing them
+        assert!(!info.tags.contains_key(&offsets.int));
+        assert!(!info.tags.contains_key(&offsets.restrict));
+        assert_eq!(info.tags[&11].kind, TagKind::CompileUnit);
+        assert_eq!(info.tags[&offsets.foo].kind, TagKind::StructureType);
+
+        let var_foo = &info.tags[&offsets.var_foo];
+        assert_eq!(var_foo.kind, TagKind::GlobalVariable);
+        assert!(matches!(
+            attribute(var_foo, AttributeKind::ModUDType),
+            AttributeValue::Block(b) if b[..] == [Modifier::PointerTo as u8, 0, 0, 0, offsets.foo as u8]
+        ));
+        assert!(matches!(
+            attribute(&info.tags[&offsets.var_bar], AttributeKind::FundType),
+            &AttributeValue::Data2(ft) if ft == FundType::Integer as u16
+        ));
+        assert!(matches!(
+            attribute(&info.tags[&offsets.var_baz], AttributeKind::FundType),
+            &AttributeValue::Data2(ft) if ft == FundType::UnsignedInteger as u16
+        ));
+
+        let func = &info.tags[&offsets.func];
+        assert_eq!(func.kind, TagKind::GlobalSubroutine);
+        assert_eq!(func.address_attribute(AttributeKind::HighPc), Some(0x80003010));
+        let param_p = &info.tags[&offsets.param_p];
+        assert_eq!(param_p.kind, TagKind::FormalParameter);
+        assert_eq!(
+            param_p.block_attribute(AttributeKind::Location),
+            Some(&[2, 0, 0, 0, 1, 4, 0xFF, 0xFF, 0xFF, 0xF8, 7][..])
+        );
+    }
+}
@@ -31,6 +31,7 @@ use crate::{
     },
     util::{
         comment::{CommentSym, MWComment},
+        dwarf::{read_line_info, LineInfo},
         reader::{Endian, FromReader, ToWriter},
     },
     vfs::open_file,
@@ -49,7 +50,21 @@ enum BoundaryState {
 
 pub fn process_elf(path: &Utf8NativePath) -> Result<ObjInfo> {
     let mut file = open_file(path, true)?;
-    let obj_file = File::parse(file.map()?)?;
+    process_elf_data(file.map()?)
+}
+
+/// Reads the DWARF 1.1 line tables of an ELF, if it has any.
+pub fn process_elf_lines(path: &Utf8NativePath) -> Result<Option<LineInfo>> {
+    let read = || -> Result<Option<LineInfo>> {
+        let mut file = open_file(path, true)?;
+        let obj_file = File::parse(file.map()?)?;
+        read_line_info(&obj_file)
+    };
+    read().with_context(|| format!("Failed to read line info from {}", path))
+}
+
+pub fn process_elf_data(data: &[u8]) -> Result<ObjInfo> {
+    let obj_file = File::parse(data)?;
     let architecture = match obj_file.architecture() {
         Architecture::PowerPc => ObjArchitecture::PowerPc,
         arch => bail!("Unexpected architecture: {arch:?}"),
@@ -608,7 +623,7 @@ pub fn write_elf(obj: &ObjInfo, export_all: bool) -> Result<Vec<u8>> {
                 0
             } else if symbol.flags.is_common() {
                 elf::SHN_COMMON
-            } else if symbol.address != 0 {
+            } else if !symbol.flags.is_undefined() && symbol.address != 0 {
                 elf::SHN_ABS
             } else {
                 elf::SHN_UNDEF
@@ -891,6 +906,9 @@ fn to_obj_symbol(
     if symbol.scope() == SymbolScope::Linkage {
         flags = ObjSymbolFlagSet(flags.0 | ObjSymbolFlags::Hidden);
     }
+    if symbol.is_undefined() {
+        flags = ObjSymbolFlagSet(flags.0 | ObjSymbolFlags::Undefined);
+    }
     let section_idx = section.as_ref().and_then(|section| section_indexes[section.index().0]);
     Ok(ObjSymbol {
         name: name.to_string(),
@@ -104,3 +104,11 @@ pub fn obj_path_for_unit(unit: &str) -> Utf8NativePathBuf {
 pub fn asm_path_for_unit(unit: &str) -> Utf8NativePathBuf {
     Utf8UnixPath::new(unit).with_encoding().with_extension("s")
 }
+
+pub fn c_asm_path_for_unit(unit: &str) -> Utf8NativePathBuf {
+    let path = Utf8UnixPath::new(unit);
+    match path.extension() {
+        Some("c" | "cp" | "cpp" | "cxx" | "cc") => path.with_encoding(),
+        _ => path.with_encoding().with_extension("c"),
+    }
+}
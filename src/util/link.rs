@@ -0,0 +1,1347 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use indexmap::IndexMap;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::{
+    obj::{
+        ObjArchitecture, ObjInfo, ObjKind, ObjReloc, ObjRelocKind, ObjSection, ObjSectionKind,
+        ObjSymbol, ObjSymbolFlagSet, ObjSymbolFlags, ObjSymbolKind, SectionIndex, SymbolIndex,
+    },
+    util::config::parse_u32,
+};
+
+/// The subset of an mwld linker script used by [`link`].
+#[derive(Debug, Clone, Default)]
+pub struct LinkerScript {
+    /// Origin of the `text` memory region.
+    pub origin: u32,
+    /// Output sections, in order.
+    pub sections: Vec<ScriptSection>,
+    /// Symbol assignments following the section group, in order.
+    pub assignments: Vec<(String, String)>,
+    /// Symbols listed in the `FORCEACTIVE` block.
+    pub force_active: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScriptSection {
+    pub name: String,
+    pub align: u32,
+    /// Input section names. Empty when the output section only
+    /// collects input sections with the same name.
+    pub inputs: Vec<String>,
+}
+
+impl ScriptSection {
+    fn matches(&self, name: &str) -> bool {
+        if self.inputs.is_empty() {
+            self.name == name
+        } else {
+            self.inputs.iter().any(|i| i == name)
+        }
+    }
+}
+
+pub fn parse_linker_script(text: &str) -> Result<LinkerScript> {
+    static COMMENT: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)/\*.*?\*/|//[^\n]*").unwrap());
+    static MEMORY: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"MEMORY\s*\{[^}]*origin\s*=\s*(\w+)").unwrap());
+    static GROUP: Lazy<Regex> = Lazy::new(|| Regex::new(r"GROUP\s*:\s*\{").unwrap());
+    static SECTION: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"([^\s:{}]+)\s*(?:ALIGN\s*\(\s*(\w+)\s*\))?\s*:\s*\{([^}]*)\}").unwrap()
+    });
+    static INPUT: Lazy<Regex> = Lazy::new(|| Regex::new(r"\*\s*\(\s*([^)\s]+)\s*\)").unwrap());
+    static ASSIGNMENT: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"([A-Za-z_$][\w$]*)\s*=\s*([^;=]+);").unwrap());
+    static FORCE_ACTIVE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"FORCEACTIVE\s*\{([^}]*)\}").unwrap());
+
+    let text = COMMENT.replace_all(text, "");
+    let mut script = LinkerScript::default();
+
+    let captures = MEMORY.captures(&text).ok_or_else(|| anyhow!("Missing MEMORY origin"))?;
+    script.origin = parse_u32(&captures[1])
+        .with_context(|| format!("Invalid MEMORY origin '{}'", &captures[1]))?;
+
+    let group = GROUP.find(&text).ok_or_else(|| anyhow!("Missing SECTIONS GROUP"))?;
+    let mut depth = 1;
+    let mut group_end = None;
+    for (pos, c) in text[group.end()..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    group_end = Some(group.end() + pos);
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    let group_end = group_end.ok_or_else(|| anyhow!("Unterminated SECTIONS GROUP"))?;
+    for captures in SECTION.captures_iter(&text[group.end()..group_end]) {
+        let name = captures[1].to_string();
+        let align = match captures.get(2) {
+            Some(m) => parse_u32(m.as_str())
+                .with_context(|| format!("Invalid alignment for section {name}"))?,
+            None => 1,
+        };
+        let inputs = INPUT.captures_iter(&captures[3]).map(|c| c[1].to_string()).collect();
+        script.sections.push(ScriptSection { name, align, inputs });
+    }
+
+    let rest = &text[group_end..];
+    let assignments_end = rest.find("FORCEACTIVE").unwrap_or(rest.len());
+    for captures in ASSIGNMENT.captures_iter(&rest[..assignments_end]) {
+        script.assignments.push((captures[1].to_string(), captures[2].trim().to_string()));
+    }
+    if let Some(captures) = FORCE_ACTIVE.captures(rest) {
+        script.force_active = captures[1].split_whitespace().map(str::to_string).collect();
+    }
+    Ok(script)
+}
+
+/// Evaluation context for linker script expressions.
+pub trait ExprContext {
+    fn symbol(&self, name: &str) -> Result<u32>;
+
+    /// Evaluates `SIZEOF(section)` or `ADDR(section)`.
+    fn function(&self, function: &str, arg: &str) -> Result<u32>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(u32),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = expr.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$') {
+            let mut end = start;
+            while let Some(&(pos, c)) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$')) {
+                    break;
+                }
+                end = pos + c.len_utf8();
+                chars.next();
+            }
+            let word = &expr[start..end];
+            if c.is_ascii_digit() {
+                tokens.push(Token::Number(
+                    parse_u32(word).with_context(|| format!("Invalid number '{word}'"))?,
+                ));
+            } else {
+                tokens.push(Token::Ident(word.to_string()));
+            }
+        } else {
+            chars.next();
+            let token = match c {
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                '<' | '>' => {
+                    ensure!(
+                        chars.next().map(|(_, n)| n) == Some(c),
+                        "Unsupported operator '{c}' in expression '{expr}'"
+                    );
+                    Token::Op(if c == '<' { "<<" } else { ">>" })
+                }
+                '+' => Token::Op("+"),
+                '-' => Token::Op("-"),
+                '*' => Token::Op("*"),
+                '/' => Token::Op("/"),
+                '%' => Token::Op("%"),
+                '&' => Token::Op("&"),
+                '|' => Token::Op("|"),
+                '^' => Token::Op("^"),
+                '~' => Token::Op("~"),
+                _ => bail!("Unexpected character '{c}' in expression '{expr}'"),
+            };
+            tokens.push(token);
+        }
+    }
+    Ok(tokens)
+}
+
+const BINARY_OPS: [&[&str]; 6] =
+    [&["|"], &["^"], &["&"], &["<<", ">>"], &["+", "-"], &["*", "/", "%"]];
+
+struct ExprParser<'a, C: ExprContext + ?Sized> {
+    tokens: &'a [Token],
+    pos: usize,
+    ctx: &'a C,
+}
+
+impl<C: ExprContext + ?Sized> ExprParser<'_, C> {
+    fn advance(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, token: Token) -> Result<()> {
+        match self.advance() {
+            Some(t) if *t == token => Ok(()),
+            t => bail!("Expected {:?}, found {:?}", token, t),
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<u32> {
+        let Some(ops) = BINARY_OPS.get(level) else {
+            return self.unary();
+        };
+        let mut value = self.binary(level + 1)?;
+        while let Some(&Token::Op(op)) = self.tokens.get(self.pos) {
+            if !ops.contains(&op) {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+            value = match op {
+                "|" => value | rhs,
+                "^" => value ^ rhs,
+                "&" => value & rhs,
+                "<<" => value.wrapping_shl(rhs),
+                ">>" => value.wrapping_shr(rhs),
+                "+" => value.wrapping_add(rhs),
+                "-" => value.wrapping_sub(rhs),
+                "*" => value.wrapping_mul(rhs),
+                "/" => value.checked_div(rhs).ok_or_else(|| anyhow!("Division by zero"))?,
+                "%" => value.checked_rem(rhs).ok_or_else(|| anyhow!("Division by zero"))?,
+                _ => unreachable!(),
+            };
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<u32> {
+        match self.advance().cloned() {
+            Some(Token::Op("~")) => Ok(!self.unary()?),
+            Some(Token::Op("-")) => Ok(self.unary()?.wrapping_neg()),
+            Some(Token::Op("+")) => self.unary(),
+            Some(Token::Number(value)) => Ok(value),
+            Some(Token::LParen) => {
+                let value = self.binary(0)?;
+                self.expect(Token::RParen)?;
+                Ok(value)
+            }
+            Some(Token::Ident(name)) => {
+                if self.tokens.get(self.pos) == Some(&Token::LParen) {
+                    self.pos += 1;
+                    let arg = match self.advance() {
+                        Some(Token::Ident(arg)) => arg.clone(),
+                        t => bail!("Expected section name for {}, found {:?}", name, t),
+                    };
+                    self.expect(Token::RParen)?;
+                    self.ctx.function(&name, &arg)
+                } else {
+                    self.ctx.symbol(&name)
+                }
+            }
+            t => bail!("Unexpected token {:?}", t),
+        }
+    }
+}
+
+/// Evaluates a linker script expression.
+pub fn eval_expr<C>(expr: &str, ctx: &C) -> Result<u32>
+where C: ExprContext + ?Sized {
+    let tokens = tokenize(expr)?;
+    let mut parser = ExprParser { tokens: &tokens, pos: 0, ctx };
+    let value = parser.binary(0).with_context(|| format!("While evaluating '{expr}'"))?;
+    ensure!(parser.pos == tokens.len(), "Trailing tokens in expression '{expr}'");
+    Ok(value)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Placement {
+    section: usize,
+    offset: u32,
+}
+
+/// A part of an input section that is kept or stripped as a whole: a function or object
+/// together with any padding that follows it. The first atom of a section starts at 0 and
+/// holds any data before the first symbol.
+#[derive(Debug, Clone, Copy)]
+struct Atom {
+    start: u32,
+    end: u32,
+    align: u32,
+    live: bool,
+    /// Offset in the output section, once placed.
+    offset: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+struct InputSection {
+    /// Output section index, if matched by the linker script.
+    output: Option<usize>,
+    atoms: Vec<Atom>,
+}
+
+impl InputSection {
+    /// Index of the atom containing `address`.
+    fn atom_index(&self, address: u32) -> usize {
+        self.atoms.partition_point(|atom| atom.start <= address).saturating_sub(1)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct OutputSection {
+    name: String,
+    kind: ObjSectionKind,
+    align: u32,
+    address: u32,
+    size: u32,
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Definition {
+    Symbol { object: usize, symbol: SymbolIndex, weak: bool },
+    Common { size: u32, align: u32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Section(usize, u32),
+    Absolute(u32),
+    Linker,
+}
+
+/// A linker-generated table reserved at the end of an output section.
+#[derive(Debug, Clone, Copy)]
+struct Table {
+    section: usize,
+    offset: u32,
+}
+
+struct Linker<'a> {
+    objects: &'a [ObjInfo],
+    inputs: Vec<Vec<InputSection>>,
+    sections: Vec<OutputSection>,
+    globals: IndexMap<String, Definition>,
+    commons: HashMap<String, Placement>,
+    linker_symbols: IndexMap<String, u32>,
+}
+
+impl ExprContext for Linker<'_> {
+    fn symbol(&self, name: &str) -> Result<u32> { self.global_address(name) }
+
+    fn function(&self, function: &str, arg: &str) -> Result<u32> {
+        let section = self
+            .sections
+            .iter()
+            .find(|s| s.name == arg)
+            .ok_or_else(|| anyhow!("Unknown section {arg}"))?;
+        match function {
+            "SIZEOF" => Ok(section.size),
+            "ADDR" => Ok(section.address),
+            _ => bail!("Unsupported function {function}"),
+        }
+    }
+}
+
+impl Linker<'_> {
+    fn locate(&self, object: usize, symbol: SymbolIndex) -> Result<Target> {
+        let obj = &self.objects[object];
+        let sym = &obj.symbols[symbol];
+        if let Some(section) = sym.section {
+            return self
+                .locate_address(object, section, sym.address as u32)
+                .with_context(|| format!("While locating symbol {} in {}", sym.name, obj.name));
+        }
+        if sym.flags.is_local() {
+            return Ok(Target::Absolute(sym.address as u32));
+        }
+        self.locate_global(&sym.name)
+    }
+
+    /// Maps an address in an input section to its output location.
+    fn locate_address(&self, object: usize, section: SectionIndex, address: u32) -> Result<Target> {
+        let input = &self.inputs[object][section as usize];
+        let output = input.output.ok_or_else(|| anyhow!("Section {} is discarded", section))?;
+        let atom = &input.atoms[input.atom_index(address)];
+        let offset =
+            atom.offset.ok_or_else(|| anyhow!("Address {:#X} was dead-stripped", address))?;
+        Ok(Target::Section(output, offset + address - atom.start))
+    }
+
+    /// Locates the target of a relocation, returning the addend that still has to be applied.
+    /// Section symbol references are mapped with their addend, since the atom they point into
+    /// may have moved.
+    fn locate_reloc(&self, object: usize, reloc: &ObjReloc) -> Result<(Target, i64)> {
+        let sym = &self.objects[object].symbols[reloc.target_symbol];
+        match sym.section {
+            Some(section) if sym.kind == ObjSymbolKind::Section => {
+                let address = (sym.address as i64 + reloc.addend) as u32;
+                Ok((self.locate_address(object, section, address)?, 0))
+            }
+            _ => Ok((self.locate(object, reloc.target_symbol)?, reloc.addend)),
+        }
+    }
+
+    /// Finds the input atom a relocation points to, for dead-stripping.
+    fn reloc_atom(&self, object: usize, reloc: &ObjReloc) -> Option<(usize, usize, usize)> {
+        let sym = &self.objects[object].symbols[reloc.target_symbol];
+        let (object, section, address) = match sym.section {
+            Some(section) if sym.kind == ObjSymbolKind::Section => {
+                (object, section, (sym.address as i64 + reloc.addend) as u32)
+            }
+            Some(section) => (object, section, sym.address as u32),
+            None if sym.flags.is_local() => return None,
+            None => return self.symbol_atom(&sym.name),
+        };
+        let input = &self.inputs[object][section as usize];
+        Some((object, section as usize, input.atom_index(address)))
+    }
+
+    /// Finds the input atom defining a global symbol.
+    fn symbol_atom(&self, name: &str) -> Option<(usize, usize, usize)> {
+        let Some(&Definition::Symbol { object, symbol, .. }) = self.globals.get(name) else {
+            return None;
+        };
+        let sym = &self.objects[object].symbols[symbol];
+        let section = sym.section?;
+        let input = &self.inputs[object][section as usize];
+        Some((object, section as usize, input.atom_index(sym.address as u32)))
+    }
+
+    /// Whether a symbol was kept. Symbols outside of sections are always kept.
+    fn is_live(&self, object: usize, symbol: SymbolIndex) -> bool {
+        let sym = &self.objects[object].symbols[symbol];
+        match sym.section {
+            Some(section) => {
+                let input = &self.inputs[object][section as usize];
+                input.output.is_some() && input.atoms[input.atom_index(sym.address as u32)].live
+            }
+            None => true,
+        }
+    }
+
+    fn locate_global(&self, name: &str) -> Result<Target> {
+        match self.globals.get(name) {
+            Some(&Definition::Symbol { object, symbol, .. }) => {
+                let sym = &self.objects[object].symbols[symbol];
+                if sym.section.is_none() {
+                    Ok(Target::Absolute(sym.address as u32))
+                } else {
+                    self.locate(object, symbol)
+                }
+            }
+            Some(Definition::Common { .. }) => {
+                let placement = self.commons[name];
+                Ok(Target::Section(placement.section, placement.offset))
+            }
+            None => Ok(Target::Linker),
+        }
+    }
+
+    fn target_address(&self, target: Target, name: &str) -> Result<u32> {
+        match target {
+            Target::Section(section, offset) => Ok(self.sections[section].address + offset),
+            Target::Absolute(address) => Ok(address),
+            Target::Linker => self
+                .linker_symbols
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow!("Undefined symbol {name}")),
+        }
+    }
+
+    fn global_address(&self, name: &str) -> Result<u32> {
+        self.target_address(self.locate_global(name)?, name)
+    }
+
+    fn section_index(&self, name: &str) -> Option<usize> {
+        self.sections.iter().position(|s| s.name == name)
+    }
+
+    /// Reserves space at the end of an output section.
+    fn reserve(&mut self, section: usize, size: u32, align: u32) -> Table {
+        let out = &mut self.sections[section];
+        let offset = align_up(out.size, align);
+        out.size = offset + size;
+        out.align = out.align.max(align);
+        Table { section, offset }
+    }
+}
+
+#[inline]
+fn align_up(value: u32, align: u32) -> u32 {
+    let align = align.max(1);
+    value.div_ceil(align) * align
+}
+
+/// Writes a zero-terminated table reserved with [`Linker::reserve`].
+fn write_table(sections: &mut [OutputSection], table: Table, words: &[u32]) {
+    let data = &mut sections[table.section].data;
+    for (i, word) in words.iter().enumerate() {
+        let offset = table.offset as usize + i * 4;
+        data[offset..offset + 4].copy_from_slice(&word.to_be_bytes());
+    }
+}
+
+#[inline]
+fn section_kind_for_name(name: &str) -> ObjSectionKind {
+    match name {
+        ".init" | ".text" => ObjSectionKind::Code,
+        ".bss" | ".sbss" | ".sbss2" | ".stack" => ObjSectionKind::Bss,
+        _ => ObjSectionKind::Data,
+    }
+}
+
+/// Linker-generated `_f_`/`_e_` symbol prefix for a section, e.g. `_f_text` or `_fextab`.
+#[inline]
+fn section_symbol_name(prefix: &str, section: &str) -> String {
+    format!("{}{}", prefix, section.replace('.', "_"))
+}
+
+/// Splits an input section into atoms at each function and object symbol. Symbols nested
+/// inside another symbol stay part of it.
+fn split_atoms(obj: &ObjInfo, section_index: SectionIndex, section: &ObjSection) -> Vec<Atom> {
+    let section_align = (section.align as u32).max(1);
+    let mut starts = vec![0u32];
+    let mut covered_end = 0;
+    for (_, sym) in obj.symbols.for_section(section_index) {
+        if !matches!(sym.kind, ObjSymbolKind::Function | ObjSymbolKind::Object) || sym.size == 0 {
+            continue;
+        }
+        let address = sym.address as u32;
+        if address >= covered_end && address > *starts.last().unwrap() {
+            starts.push(address);
+        }
+        covered_end = covered_end.max(address + sym.size as u32);
+    }
+    let size = section.size as u32;
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, &start)| Atom {
+            start,
+            end: starts.get(i + 1).cloned().unwrap_or(size).max(start),
+            // Keep the alignment the atom had in the input section
+            align: if start == 0 {
+                section_align
+            } else {
+                section_align.min(1 << start.trailing_zeros())
+            },
+            live: false,
+            offset: None,
+        })
+        .collect()
+}
+
+/// Marks the atoms reachable from the entry point, `FORCEACTIVE` symbols and the `.init`,
+/// `.ctors` and `.dtors` sections. An `extabindex` entry is kept when the function it
+/// describes is kept.
+fn mark_live(linker: &mut Linker<'_>, script: &LinkerScript) {
+    let mut queue = vec![];
+    for name in std::iter::once("__start").chain(script.force_active.iter().map(String::as_str)) {
+        if let Some(atom) = linker.symbol_atom(name) {
+            queue.push(atom);
+        }
+    }
+    for (object, inputs) in linker.inputs.iter().enumerate() {
+        for (section, input) in inputs.iter().enumerate() {
+            let Some(output) = input.output else { continue };
+            if matches!(linker.sections[output].name.as_str(), ".init" | ".ctors" | ".dtors") {
+                queue.extend((0..input.atoms.len()).map(|atom| (object, section, atom)));
+            }
+        }
+    }
+
+    loop {
+        while let Some((object, section, atom)) = queue.pop() {
+            let input = &mut linker.inputs[object][section];
+            if input.atoms[atom].live {
+                continue;
+            }
+            input.atoms[atom].live = true;
+            let Atom { start, end, .. } = input.atoms[atom];
+            let relocations = &linker.objects[object].sections[section as SectionIndex].relocations;
+            for (_, reloc) in relocations.range(start..end) {
+                if let Some(target) = linker.reloc_atom(object, reloc) {
+                    queue.push(target);
+                }
+            }
+        }
+
+        // Keep extabindex entries for live functions
+        for (object, obj) in linker.objects.iter().enumerate() {
+            for (section_index, section) in obj.sections.iter() {
+                let input = &linker.inputs[object][section_index as usize];
+                if !input.output.is_some_and(|o| linker.sections[o].name == "extabindex") {
+                    continue;
+                }
+                for (atom_index, atom) in input.atoms.iter().enumerate() {
+                    if atom.live {
+                        continue;
+                    }
+                    let keep = section
+                        .relocations
+                        .range(atom.start..atom.end)
+                        .filter(|&(address, _)| address % 12 == 0)
+                        .filter_map(|(_, reloc)| linker.reloc_atom(object, reloc))
+                        .any(|(o, s, a)| linker.inputs[o][s].atoms[a].live);
+                    if keep {
+                        queue.push((object, section_index as usize, atom_index));
+                    }
+                }
+            }
+        }
+        if queue.is_empty() {
+            break;
+        }
+    }
+}
+
+/// Links relocatable objects into an executable, following the output
+/// section layout of the linker script.
+///
+/// Like mwld, this appends null terminators to `.ctors` and `.dtors` and
+/// generates `_rom_copy_info`, `_bss_init_info` and `_eti_init_info` when
+/// they are referenced. With `dead_strip`, functions and objects that are not
+/// reachable from `__start` or a `FORCEACTIVE` symbol are removed.
+pub fn link(script: &LinkerScript, objects: &[ObjInfo], dead_strip: bool) -> Result<ObjInfo> {
+    let mut linker = Linker {
+        objects,
+        inputs: Vec::with_capacity(objects.len()),
+        sections: script
+            .sections
+            .iter()
+            .map(|s| OutputSection {
+                name: s.name.clone(),
+                kind: section_kind_for_name(&s.name),
+                align: s.align.max(1),
+                address: 0,
+                size: 0,
+                data: vec![],
+            })
+            .collect(),
+        globals: IndexMap::new(),
+        commons: HashMap::new(),
+        linker_symbols: IndexMap::new(),
+    };
+
+    // Resolve global symbols
+    let mut referenced = HashSet::new();
+    for (object, obj) in objects.iter().enumerate() {
+        for (symbol, sym) in obj.symbols.iter() {
+            if sym.flags.is_local() || sym.kind == ObjSymbolKind::Section {
+                continue;
+            }
+            if sym.flags.is_common() {
+                let (size, align) = (sym.size as u32, sym.align.unwrap_or(sym.address as u32));
+                match linker.globals.get_mut(&sym.name) {
+                    Some(Definition::Common { size: s, align: a }) => {
+                        *s = (*s).max(size);
+                        *a = (*a).max(align);
+                    }
+                    Some(_) => {}
+                    None => {
+                        linker.globals.insert(sym.name.clone(), Definition::Common { size, align });
+                    }
+                }
+                continue;
+            }
+            if sym.flags.is_undefined() {
+                referenced.insert(sym.name.clone());
+                continue;
+            }
+            let weak = sym.flags.is_weak();
+            let definition = Definition::Symbol { object, symbol, weak };
+            match linker.globals.get(&sym.name) {
+                Some(Definition::Symbol { object: other, weak: false, .. }) if !weak => {
+                    bail!(
+                        "Duplicate symbol {} (defined in {} and {})",
+                        sym.name,
+                        objects[*other].name,
+                        obj.name
+                    );
+                }
+                Some(Definition::Symbol { weak: false, .. }) => {}
+                Some(Definition::Symbol { weak: true, .. }) if weak => {}
+                _ => {
+                    linker.globals.insert(sym.name.clone(), definition);
+                }
+            }
+        }
+    }
+
+    // Match input sections to output sections
+    for obj in objects {
+        let mut inputs = Vec::with_capacity(obj.sections.len() as usize);
+        for (section_index, section) in obj.sections.iter() {
+            let output = script.sections.iter().position(|s| s.matches(&section.name));
+            if output.is_none() && section.size > 0 {
+                bail!(
+                    "Section {} in {} is not placed by the linker script",
+                    section.name,
+                    obj.name
+                );
+            }
+            let mut atoms = split_atoms(obj, section_index, section);
+            if !dead_strip {
+                atoms.iter_mut().for_each(|atom| atom.live = true);
+            }
+            inputs.push(InputSection { output, atoms });
+        }
+        linker.inputs.push(inputs);
+    }
+    if dead_strip {
+        mark_live(&mut linker, script);
+    }
+
+    // Place live atoms
+    for (object, obj) in objects.iter().enumerate() {
+        for (section_index, section) in obj.sections.iter() {
+            let input = &mut linker.inputs[object][section_index as usize];
+            let Some(index) = input.output else { continue };
+            let out = &mut linker.sections[index];
+            for atom in input.atoms.iter_mut().filter(|atom| atom.live) {
+                if out.size == 0 {
+                    out.kind = section.kind;
+                } else if out.kind != section.kind && section.kind == ObjSectionKind::Code {
+                    out.kind = ObjSectionKind::Code;
+                }
+                let offset = align_up(out.size, atom.align);
+                out.size = offset + atom.end - atom.start;
+                out.align = out.align.max(atom.align);
+                atom.offset = Some(offset);
+            }
+        }
+    }
+
+    // Allocate common symbols, small ones in .sbss when available
+    let bss = linker.section_index(".bss");
+    let sbss = linker.section_index(".sbss");
+    let commons = linker
+        .globals
+        .iter()
+        .filter_map(|(name, def)| match *def {
+            Definition::Common { size, align } => Some((name.clone(), size, align)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    for (name, size, align) in commons {
+        let section = if size <= 8 { sbss.or(bss) } else { bss.or(sbss) }
+            .ok_or_else(|| anyhow!("No .bss section for common symbol {name}"))?;
+        let table = linker.reserve(section, size, align);
+        linker.commons.insert(name, Placement { section, offset: table.offset });
+    }
+
+    // Null-terminate .ctors and .dtors
+    for name in [".ctors", ".dtors"] {
+        if let Some(section) = linker.section_index(name) {
+            linker.reserve(section, 4, 4);
+        }
+    }
+
+    // Group extabindex entries by the code section they describe
+    let mut eti_ranges = BTreeMap::<usize, (u32, u32)>::new();
+    let mut eti_init_info = None;
+    if referenced.contains("_eti_init_info") {
+        let extabindex = linker
+            .section_index("extabindex")
+            .ok_or_else(|| anyhow!("_eti_init_info referenced without an extabindex section"))?;
+        for (object, obj) in objects.iter().enumerate() {
+            for (section_index, section) in obj.sections.iter() {
+                let input = &linker.inputs[object][section_index as usize];
+                if input.output != Some(extabindex) {
+                    continue;
+                }
+                for (address, reloc) in section.relocations.iter() {
+                    if address % 12 != 0 || reloc.kind != ObjRelocKind::Absolute {
+                        continue;
+                    }
+                    let atom = &input.atoms[input.atom_index(address)];
+                    let Some(atom_offset) = atom.offset else { continue };
+                    let Target::Section(code, _) = linker.locate_reloc(object, reloc)?.0 else {
+                        continue;
+                    };
+                    let start = atom_offset + address - atom.start;
+                    let range = eti_ranges.entry(code).or_insert((start, start + 12));
+                    range.0 = range.0.min(start);
+                    range.1 = range.1.max(start + 12);
+                }
+            }
+        }
+        let size = (eti_ranges.len() as u32 + 1) * 16;
+        eti_init_info = Some(linker.reserve(extabindex, size, 4));
+    }
+
+    // Reserve _rom_copy_info and _bss_init_info at the end of .init
+    let (mut rom_copy_info, mut bss_init_info) = (None, None);
+    let wants_rom_copy_info = referenced.contains("_rom_copy_info");
+    let wants_bss_init_info = referenced.contains("_bss_init_info");
+    if wants_rom_copy_info || wants_bss_init_info {
+        let init = linker
+            .section_index(".init")
+            .ok_or_else(|| anyhow!("_rom_copy_info referenced without an .init section"))?;
+        let count = |bss: bool| {
+            linker
+                .sections
+                .iter()
+                .enumerate()
+                .filter(|&(i, s)| {
+                    (s.kind == ObjSectionKind::Bss) == bss && (s.size > 0 || (!bss && i == init))
+                })
+                .count() as u32
+        };
+        let (rom_count, bss_count) = (count(false), count(true));
+        if wants_rom_copy_info {
+            rom_copy_info = Some(linker.reserve(init, (rom_count + 1) * 12, 4));
+        }
+        if wants_bss_init_info {
+            bss_init_info = Some(linker.reserve(init, (bss_count + 1) * 8, 4));
+        }
+    }
+
+    // Assign addresses
+    let mut address = script.origin;
+    for section in &mut linker.sections {
+        address = align_up(address, section.align);
+        section.address = address;
+        address += section.size;
+        if section.kind != ObjSectionKind::Bss {
+            section.data = vec![0; section.size as usize];
+        }
+    }
+
+    // Linker-generated symbols
+    let mut linker_symbols = IndexMap::new();
+    for section in &linker.sections {
+        let (start, end) = (section.address, section.address + section.size);
+        linker_symbols.insert(section_symbol_name("_f", &section.name), start);
+        linker_symbols.insert(section_symbol_name("_f", &section.name) + "_rom", start);
+        linker_symbols.insert(section_symbol_name("_e", &section.name), end);
+        linker_symbols.insert(section_symbol_name("_e", &section.name) + "_rom", end);
+        match section.name.as_str() {
+            ".ctors" => {
+                linker_symbols.insert("_ctors".to_string(), start);
+            }
+            ".dtors" => {
+                linker_symbols.insert("_dtors".to_string(), start);
+            }
+            ".sdata" => {
+                linker_symbols.insert("_SDA_BASE_".to_string(), start + 0x8000);
+            }
+            ".sdata2" => {
+                linker_symbols.insert("_SDA2_BASE_".to_string(), start + 0x8000);
+            }
+            _ => {}
+        }
+    }
+    for (name, table) in [
+        ("_rom_copy_info", rom_copy_info),
+        ("_bss_init_info", bss_init_info),
+        ("_eti_init_info", eti_init_info),
+    ] {
+        if let Some(table) = table {
+            linker_symbols
+                .insert(name.to_string(), linker.sections[table.section].address + table.offset);
+        }
+    }
+    linker.linker_symbols = linker_symbols;
+    for (name, expr) in &script.assignments {
+        let value = eval_expr(expr, &linker)?;
+        linker.linker_symbols.insert(name.clone(), value);
+    }
+    for name in &script.force_active {
+        if !linker.globals.contains_key(name) {
+            log::warn!("FORCEACTIVE symbol {} is not defined", name);
+        }
+    }
+    if dead_strip {
+        let stripped = linker
+            .inputs
+            .iter()
+            .flatten()
+            .filter(|input| input.output.is_some())
+            .flat_map(|input| &input.atoms)
+            .filter(|atom| !atom.live)
+            .count();
+        log::debug!("Dead-stripped {} functions and objects", stripped);
+    }
+
+    // Copy section data and apply relocations
+    for (object, obj) in objects.iter().enumerate() {
+        for (section_index, section) in obj.sections.iter() {
+            let input = &linker.inputs[object][section_index as usize];
+            let Some(output) = input.output else { continue };
+            let out_address = linker.sections[output].address;
+            if section.kind != ObjSectionKind::Bss {
+                let data = &mut linker.sections[output].data;
+                for atom in &input.atoms {
+                    let Some(offset) = atom.offset else { continue };
+                    let end = (atom.end as usize).min(section.data.len());
+                    let start = (atom.start as usize).min(end);
+                    let out_start = offset as usize;
+                    data[out_start..out_start + end - start]
+                        .copy_from_slice(&section.data[start..end]);
+                }
+            }
+            for (address, reloc) in section.relocations.iter() {
+                let atom = &input.atoms[input.atom_index(address)];
+                let Some(atom_offset) = atom.offset else { continue };
+                let name = &obj.symbols[reloc.target_symbol].name;
+                let (target, addend) = linker.locate_reloc(object, reloc)?;
+                let target_address = (linker.target_address(target, name)? as i64 + addend) as u32;
+                let out_offset = atom_offset + address - atom.start;
+                let offset = out_offset as usize;
+                let source_address = out_address + out_offset;
+                let sda_base = match (reloc.kind, target) {
+                    (ObjRelocKind::PpcEmbSda21, Target::Section(section, _)) => {
+                        match linker.sections[section].name.as_str() {
+                            ".sdata" | ".sbss" => {
+                                Some((13, linker.global_address("_SDA_BASE_")?))
+                            }
+                            ".sdata2" | ".sbss2" => {
+                                Some((2, linker.global_address("_SDA2_BASE_")?))
+                            }
+                            name => bail!(
+                                "SDA21 relocation at {:#010X} against {} in non-small data section {}",
+                                source_address,
+                                obj.symbols[reloc.target_symbol].name,
+                                name
+                            ),
+                        }
+                    }
+                    (ObjRelocKind::PpcEmbSda21, _) => Some((0, 0)),
+                    _ => None,
+                };
+                let data = &mut linker.sections[output].data;
+                ensure!(
+                    offset + 4 <= data.len(),
+                    "Relocation at {:#010X} out of bounds",
+                    source_address
+                );
+                let ins_ref: &mut [u8; 4] = (&mut data[offset..offset + 4]).try_into()?;
+                let mut ins = u32::from_be_bytes(*ins_ref);
+                match reloc.kind {
+                    ObjRelocKind::Absolute => {
+                        ins = target_address;
+                    }
+                    ObjRelocKind::PpcAddr16Hi => {
+                        ins = (ins & 0xffff0000) | ((target_address >> 16) & 0xffff);
+                    }
+                    ObjRelocKind::PpcAddr16Ha => {
+                        ins = (ins & 0xffff0000)
+                            | ((target_address.wrapping_add(0x8000) >> 16) & 0xffff);
+                    }
+                    ObjRelocKind::PpcAddr16Lo => {
+                        ins = (ins & 0xffff0000) | (target_address & 0xffff);
+                    }
+                    ObjRelocKind::PpcRel24 => {
+                        let diff = target_address as i32 - source_address as i32;
+                        ensure!(
+                            (-0x2000000..0x2000000).contains(&diff),
+                            "R_PPC_REL24 relocation out of range at {:#010X}",
+                            source_address
+                        );
+                        ins = (ins & !0x3fffffc) | (diff as u32 & 0x3fffffc);
+                    }
+                    ObjRelocKind::PpcRel14 => {
+                        let diff = target_address as i32 - source_address as i32;
+                        ensure!(
+                            (-0x8000..0x8000).contains(&diff),
+                            "R_PPC_REL14 relocation out of range at {:#010X}",
+                            source_address
+                        );
+                        ins = (ins & !0xfffc) | (diff as u32 & 0xfffc);
+                    }
+                    ObjRelocKind::PpcEmbSda21 => {
+                        let (register, base) = sda_base.unwrap();
+                        let diff = target_address.wrapping_sub(base) as i32;
+                        ensure!(
+                            (-0x8000..0x8000).contains(&diff),
+                            "R_PPC_EMB_SDA21 relocation out of range at {:#010X}",
+                            source_address
+                        );
+                        ins = (ins & !0x1fffff) | (register << 16) | (diff as u32 & 0xffff);
+                    }
+                }
+                *ins_ref = ins.to_be_bytes();
+            }
+        }
+    }
+
+    // Fill linker-generated tables
+    if let Some(table) = rom_copy_info {
+        let words = linker
+            .sections
+            .iter()
+            .filter(|s| s.kind != ObjSectionKind::Bss && s.size > 0)
+            .flat_map(|s| [s.address, s.address, s.size])
+            .collect::<Vec<_>>();
+        write_table(&mut linker.sections, table, &words);
+    }
+    if let Some(table) = bss_init_info {
+        let words = linker
+            .sections
+            .iter()
+            .filter(|s| s.kind == ObjSectionKind::Bss && s.size > 0)
+            .flat_map(|s| [s.address, s.size])
+            .collect::<Vec<_>>();
+        write_table(&mut linker.sections, table, &words);
+    }
+    if let Some(table) = eti_init_info {
+        let base = linker.sections[table.section].address;
+        let words = eti_ranges
+            .iter()
+            .flat_map(|(&code, &(start, end))| {
+                let code = &linker.sections[code];
+                [base + start, base + end, code.address, code.size]
+            })
+            .collect::<Vec<_>>();
+        write_table(&mut linker.sections, table, &words);
+    }
+
+    // Build output sections
+    let mut section_map = vec![None; linker.sections.len()];
+    let mut out_sections = vec![];
+    for (index, section) in linker.sections.iter().enumerate() {
+        if section.size == 0 {
+            continue;
+        }
+        section_map[index] = Some(out_sections.len() as SectionIndex);
+        out_sections.push(ObjSection {
+            name: section.name.clone(),
+            kind: section.kind,
+            address: section.address as u64,
+            size: section.size as u64,
+            data: section.data.clone(),
+            align: section.align as u64,
+            elf_index: out_sections.len() as SectionIndex + 1,
+            relocations: Default::default(),
+            virtual_address: None,
+            file_offset: 0,
+            section_known: true,
+            splits: Default::default(),
+        });
+    }
+
+    // Build output symbols
+    let mut out_symbols = vec![];
+    let mut written_commons = HashSet::new();
+    for (object, obj) in objects.iter().enumerate() {
+        for (symbol, sym) in obj.symbols.iter() {
+            if sym.kind == ObjSymbolKind::Section
+                || sym.flags.is_undefined()
+                || !linker.is_live(object, symbol)
+            {
+                continue;
+            }
+            let mut flags = sym.flags;
+            if !sym.flags.is_local() {
+                match linker.globals.get(&sym.name) {
+                    Some(&Definition::Symbol { object: o, symbol: s, .. })
+                        if o == object && s == symbol => {}
+                    Some(Definition::Common { .. }) if sym.flags.is_common() => {
+                        if !written_commons.insert(sym.name.clone()) {
+                            continue;
+                        }
+                        flags = ObjSymbolFlagSet(
+                            (flags.0 & !ObjSymbolFlags::Common) | ObjSymbolFlags::Global,
+                        );
+                    }
+                    _ => continue,
+                }
+            }
+            let target = linker.locate(object, symbol)?;
+            let address = linker.target_address(target, &sym.name)?;
+            let section = match target {
+                Target::Section(section, _) => section_map[section],
+                _ => None,
+            };
+            out_symbols.push(ObjSymbol {
+                name: sym.name.clone(),
+                demangled_name: sym.demangled_name.clone(),
+                address: address as u64,
+                section,
+                size: sym.size,
+                size_known: sym.size_known,
+                flags,
+                kind: sym.kind,
+                align: sym.align,
+                data_kind: sym.data_kind,
+                ..Default::default()
+            });
+        }
+    }
+    for (name, &address) in &linker.linker_symbols {
+        if linker.globals.contains_key(name) {
+            continue;
+        }
+        out_symbols.push(ObjSymbol {
+            name: name.clone(),
+            address: address as u64,
+            size_known: true,
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+            ..Default::default()
+        });
+    }
+
+    let entry = match linker.globals.get("__start") {
+        Some(_) => Some(linker.global_address("__start")? as u64),
+        None => {
+            log::warn!("Entry point __start is not defined");
+            None
+        }
+    };
+    let mut obj = ObjInfo::new(
+        ObjKind::Executable,
+        ObjArchitecture::PowerPc,
+        String::new(),
+        out_symbols,
+        out_sections,
+    );
+    obj.entry = entry;
+    obj.sda_base = linker.linker_symbols.get("_SDA_BASE_").cloned();
+    obj.sda2_base = linker.linker_symbols.get("_SDA2_BASE_").cloned();
+    obj.stack_address = linker.linker_symbols.get("_stack_addr").cloned();
+    obj.stack_end = linker.linker_symbols.get("_stack_end").cloned();
+    obj.db_stack_addr = linker.linker_symbols.get("_db_stack_addr").cloned();
+    obj.arena_lo = linker.linker_symbols.get("__ArenaLo").cloned();
+    obj.arena_hi = linker.linker_symbols.get("__ArenaHi").cloned();
+    Ok(obj)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::ObjRelocations;
+
+    struct Symbols;
+
+    impl ExprContext for Symbols {
+        fn symbol(&self, name: &str) -> Result<u32> {
+            match name {
+                "_f_sbss2" => Ok(0x80400000),
+                _ => bail!("Undefined symbol {name}"),
+            }
+        }
+
+        fn function(&self, function: &str, arg: &str) -> Result<u32> {
+            match (function, arg) {
+                ("SIZEOF", ".sbss2") => Ok(0x14),
+                _ => bail!("Unsupported {function}({arg})"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_linker_script() {
+        let script = parse_linker_script(
+            r#"
+MEMORY
+{
+    text : origin = 0x80003100
+}
+
+SECTIONS
+{
+    GROUP:
+    {
+        .init ALIGN(0x20):{}
+        .data ALIGN(0x20):{ *(.data) *(extabindex) }
+        .stack ALIGN(0x100):{}
+    } > text
+
+    _stack_end = _f_stack + SIZEOF(.stack);
+    __ArenaHi = 0x81700000;
+}
+
+FORCEACTIVE
+{
+    /* comment */
+    __start
+}
+"#,
+        )
+        .unwrap();
+        assert_eq!(script.origin, 0x80003100);
+        assert_eq!(script.sections.len(), 3);
+        assert_eq!(script.sections[1].inputs, vec![".data", "extabindex"]);
+        assert_eq!(script.sections[2].align, 0x100);
+        assert_eq!(script.assignments.len(), 2);
+        assert_eq!(script.assignments[0].1, "_f_stack + SIZEOF(.stack)");
+        assert_eq!(script.force_active, vec!["__start"]);
+    }
+
+    fn test_symbol(
+        name: &str,
+        section: Option<SectionIndex>,
+        address: u64,
+        size: u64,
+        kind: ObjSymbolKind,
+    ) -> ObjSymbol {
+        let mut flags = ObjSymbolFlagSet(ObjSymbolFlags::Global.into());
+        if section.is_none() {
+            flags.0 |= ObjSymbolFlags::Undefined;
+        }
+        ObjSymbol {
+            name: name.to_string(),
+            address,
+            section,
+            size,
+            size_known: true,
+            flags,
+            kind,
+            ..Default::default()
+        }
+    }
+
+    fn test_object(
+        name: &str,
+        sections: Vec<(&str, ObjSectionKind, Vec<u32>, Vec<(u32, ObjRelocKind, SymbolIndex)>)>,
+        symbols: Vec<ObjSymbol>,
+    ) -> ObjInfo {
+        let sections = sections
+            .into_iter()
+            .enumerate()
+            .map(|(index, (name, kind, words, relocations))| ObjSection {
+                name: name.to_string(),
+                kind,
+                address: 0,
+                size: words.len() as u64 * 4,
+                data: words.iter().flat_map(|w| w.to_be_bytes()).collect(),
+                align: 4,
+                elf_index: index as SectionIndex + 1,
+                relocations: ObjRelocations::new(
+                    relocations
+                        .into_iter()
+                        .map(|(address, kind, target_symbol)| {
+                            (address, ObjReloc { kind, target_symbol, addend: 0, module: None })
+                        })
+                        .collect(),
+                )
+                .unwrap(),
+                virtual_address: None,
+                file_offset: 0,
+                section_known: true,
+                splits: Default::default(),
+            })
+            .collect();
+        ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            name.to_string(),
+            symbols,
+            sections,
+        )
+    }
+
+    #[test]
+    fn test_link() {
+        let script = parse_linker_script(
+            r#"
+MEMORY
+{
+    text : origin = 0x80003100
+}
+
+SECTIONS
+{
+    GROUP:
+    {
+        .text ALIGN(0x20):{}
+        .data ALIGN(0x20):{}
+    } > text
+}
+
+FORCEACTIVE
+{
+    table
+}
+"#,
+        )
+        .unwrap();
+        let objects = [
+            // __start: bl foo; blr  unused: blr
+            test_object(
+                "a.o",
+                vec![(
+                    ".text",
+                    ObjSectionKind::Code,
+                    vec![0x48000001, 0x4E800020, 0x4E800020],
+                    vec![(0, ObjRelocKind::PpcRel24, 2)],
+                )],
+                vec![
+                    test_symbol("__start", Some(0), 0, 8, ObjSymbolKind::Function),
+                    test_symbol("unused", Some(0), 8, 4, ObjSymbolKind::Function),
+                    test_symbol("foo", None, 0, 0, ObjSymbolKind::Unknown),
+                ],
+            ),
+            // foo: blr  bar: blr  table: .4byte bar  unused_data: .4byte 0
+            test_object(
+                "b.o",
+                vec![
+                    (".text", ObjSectionKind::Code, vec![0x4E800020, 0x4E800020], vec![]),
+                    (".data", ObjSectionKind::Data, vec![0, 0], vec![(
+                        0,
+                        ObjRelocKind::Absolute,
+                        1,
+                    )]),
+                ],
+                vec![
+                    test_symbol("foo", Some(0), 0, 4, ObjSymbolKind::Function),
+                    test_symbol("bar", Some(0), 4, 4, ObjSymbolKind::Function),
+                    test_symbol("table", Some(1), 0, 4, ObjSymbolKind::Object),
+                    test_symbol("unused_data", Some(1), 4, 4, ObjSymbolKind::Object),
+                ],
+            ),
+        ];
+        let address = |obj: &ObjInfo, name: &str| {
+            obj.symbols.by_name(name).unwrap().map(|(_, symbol)| symbol.address as u32)
+        };
+
+        let obj = link(&script, &objects, true).unwrap();
+        assert_eq!(obj.entry, Some(0x80003100));
+        assert_eq!(obj.sections.len(), 2);
+        let text = &obj.sections[0];
+        assert_eq!((text.name.as_str(), text.address, text.size), (".text", 0x80003100, 0x10));
+        // bl foo
+        assert_eq!(text.data[0..4], 0x48000009u32.to_be_bytes());
+        let data = &obj.sections[1];
+        assert_eq!((data.name.as_str(), data.address, data.size), (".data", 0x80003120, 4));
+        assert_eq!(data.data, 0x8000310Cu32.to_be_bytes());
+        assert_eq!(address(&obj, "foo"), Some(0x80003108));
+        assert_eq!(address(&obj, "bar"), Some(0x8000310C));
+        assert_eq!(address(&obj, "unused"), None);
+        assert_eq!(address(&obj, "unused_data"), None);
+        assert_eq!(address(&obj, "_f_data"), Some(0x80003120));
+        assert_eq!(address(&obj, "_e_data"), Some(0x80003124));
+
+        let obj = link(&script, &objects, false).unwrap();
+        let text = &obj.sections[0];
+        assert_eq!((text.address, text.size), (0x80003100, 0x14));
+        assert_eq!(text.data[0..4], 0x4800000Du32.to_be_bytes());
+        let data = &obj.sections[1];
+        assert_eq!((data.address, data.size), (0x80003120, 8));
+        assert_eq!(data.data[0..4], 0x80003110u32.to_be_bytes());
+        assert_eq!(address(&obj, "unused"), Some(0x80003108));
+    }
+
+    #[test]
+    fn test_eval_expr() {
+        assert_eq!(eval_expr("_f_sbss2 + SIZEOF(.sbss2)", &Symbols).unwrap(), 0x80400014);
+        assert_eq!(
+            eval_expr("((_f_sbss2 + SIZEOF(.sbss2)) + 0x10000 + 0x7) & ~0x7", &Symbols).unwrap(),
+            0x80410018
+        );
+        assert_eq!(eval_expr("1 + 2 * 3 << 1", &Symbols).unwrap(), 14);
+    }
+}
@@ -3,15 +3,19 @@ use std::{borrow::Cow, ops::Deref};
 pub mod alf;
 pub mod asm;
 pub mod bin2c;
+pub mod c_asm;
 pub mod comment;
 pub mod config;
 pub mod dep;
 pub mod diff;
+pub mod disc;
 pub mod dol;
 pub mod dwarf;
+pub mod dwarf2;
 pub mod elf;
 pub mod file;
 pub mod lcf;
+pub mod link;
 pub mod map;
 pub mod ncompress;
 pub mod nested;
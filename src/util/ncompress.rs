@@ -1,9 +1,53 @@
+use std::{fmt, str::FromStr};
+
 use anyhow::{anyhow, Result};
 use orthrus_ncompress::{yay0::Yay0, yaz0::Yaz0};
 
 pub const YAZ0_MAGIC: [u8; 4] = *b"Yaz0";
 pub const YAY0_MAGIC: [u8; 4] = *b"Yay0";
 
+/// The encoder used for Yaz0 and Yay0 compression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompressionAlgo {
+    /// Reproduces the encoder used by Nintendo's SDK tools. Slow, but byte-identical to
+    /// original files.
+    Nintendo,
+    /// Fast encoder. (orthrus-ncompress)
+    #[default]
+    Fast,
+    /// Optimal parsing, for the smallest output.
+    Best,
+}
+
+impl FromStr for CompressionAlgo {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nintendo" => Ok(Self::Nintendo),
+            "fast" => Ok(Self::Fast),
+            "best" => Ok(Self::Best),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for CompressionAlgo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Nintendo => write!(f, "nintendo"),
+            Self::Fast => write!(f, "fast"),
+            Self::Best => write!(f, "best"),
+        }
+    }
+}
+
+/// Parses a [`CompressionAlgo`] from a command line argument.
+pub fn compression_algo(value: &str) -> Result<CompressionAlgo, String> {
+    CompressionAlgo::from_str(value)
+        .map_err(|()| format!("Invalid algorithm '{}' (nintendo, fast or best)", value))
+}
+
 /// Compresses the data into a new allocated buffer using Yaz0 compression.
 pub fn compress_yaz0(input: &[u8]) -> Box<[u8]> {
     let mut output = vec![0u8; Yaz0::worst_possible_size(input.len())];
@@ -12,6 +56,15 @@ pub fn compress_yaz0(input: &[u8]) -> Box<[u8]> {
     output.into_boxed_slice()
 }
 
+/// Compresses the data into a new allocated buffer using Yaz0 compression and the given
+/// encoder.
+pub fn compress_yaz0_with(input: &[u8], algo: CompressionAlgo) -> Box<[u8]> {
+    match algo {
+        CompressionAlgo::Fast => compress_yaz0(input),
+        _ => encode_yaz0(input.len(), &tokenize(input, algo)).into_boxed_slice(),
+    }
+}
+
 /// Decompresses the data into a new allocated buffer. Assumes a Yaz0 header followed by
 /// compressed data.
 pub fn decompress_yaz0(input: &[u8]) -> Result<Box<[u8]>> {
@@ -26,8 +79,284 @@ pub fn compress_yay0(input: &[u8]) -> Box<[u8]> {
     output.into_boxed_slice()
 }
 
+/// Compresses the data into a new allocated buffer using Yay0 compression and the given
+/// encoder.
+pub fn compress_yay0_with(input: &[u8], algo: CompressionAlgo) -> Box<[u8]> {
+    match algo {
+        CompressionAlgo::Fast => compress_yay0(input),
+        _ => encode_yay0(input.len(), &tokenize(input, algo)).into_boxed_slice(),
+    }
+}
+
 /// Decompresses the data into a new allocated buffer. Assumes a Yay0 header followed by
 /// compressed data.
 pub fn decompress_yay0(input: &[u8]) -> Result<Box<[u8]>> {
     Yay0::decompress_from(input).map_err(|e| anyhow!(e))
 }
+
+/// Checks whether recompressing Yaz0 or Yay0 data with the given encoder reproduces it exactly.
+/// Returns `None` if the data is not compressed.
+pub fn check_roundtrip(input: &[u8], algo: CompressionAlgo) -> Result<Option<bool>> {
+    Ok(match input.get(0..4) {
+        Some(magic) if magic == YAZ0_MAGIC => {
+            let data = decompress_yaz0(input)?;
+            Some(*compress_yaz0_with(&data, algo) == *input)
+        }
+        Some(magic) if magic == YAY0_MAGIC => {
+            let data = decompress_yay0(input)?;
+            Some(*compress_yay0_with(&data, algo) == *input)
+        }
+        _ => None,
+    })
+}
+
+const WINDOW_SIZE: usize = 0x1000;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 0xFF + 0x12;
+
+#[derive(Debug, Clone, Copy)]
+enum Token {
+    Literal(u8),
+    /// Back-reference: `distance` is the offset from the current position, minus one.
+    Match {
+        distance: usize,
+        length: usize,
+    },
+}
+
+fn tokenize(input: &[u8], algo: CompressionAlgo) -> Vec<Token> {
+    match algo {
+        CompressionAlgo::Best => tokenize_optimal(input),
+        _ => tokenize_nintendo(input),
+    }
+}
+
+/// Finds the longest match for `pos`, preferring the furthest one. Matches of length 2 are
+/// discarded. This is the lookback search used by Nintendo's encoder: the length is not
+/// limited to [`MAX_MATCH`] here, that only happens when the match is emitted.
+fn search_nintendo(input: &[u8], pos: usize) -> (usize, usize) {
+    let start = pos.saturating_sub(WINDOW_SIZE);
+    let end = input.len() - pos;
+    let mut length = 1;
+    let mut match_pos = 0;
+    for i in start..pos {
+        if length >= end {
+            break;
+        }
+        // A candidate can only be longer if it also matches at `length`
+        if input[i + length] != input[pos + length] {
+            continue;
+        }
+        let j = input[i..].iter().zip(&input[pos..pos + end]).take_while(|(a, b)| a == b).count();
+        if j > length {
+            length = j;
+            match_pos = i;
+        }
+    }
+    if length == 2 {
+        length = 1;
+    }
+    (length, match_pos)
+}
+
+/// Greedy parsing with one byte of lookahead: if the match at the next position is at least
+/// two bytes longer, a literal is emitted first. Both lengths are compared before clamping.
+fn tokenize_nintendo(input: &[u8]) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut pos = 0;
+    let mut lookahead = None;
+    while pos < input.len() {
+        let (length, match_pos) = match lookahead.take() {
+            Some(result) => result,
+            None => {
+                let (length, match_pos) = search_nintendo(input, pos);
+                if length >= MIN_MATCH {
+                    let next = search_nintendo(input, pos + 1);
+                    if next.0 >= length + 2 {
+                        lookahead = Some(next);
+                        (1, match_pos)
+                    } else {
+                        (length, match_pos)
+                    }
+                } else {
+                    (length, match_pos)
+                }
+            }
+        };
+        if length < MIN_MATCH {
+            tokens.push(Token::Literal(input[pos]));
+            pos += 1;
+        } else {
+            let length = length.min(MAX_MATCH);
+            tokens.push(Token::Match { distance: pos - match_pos - 1, length });
+            pos += length;
+        }
+    }
+    tokens
+}
+
+/// Parsing that minimizes the output size, using the longest match at each position.
+fn tokenize_optimal(input: &[u8]) -> Vec<Token> {
+    let len = input.len();
+    let hash = |pos: usize| -> usize {
+        (((input[pos] as usize) << 8) ^ ((input[pos + 1] as usize) << 4) ^ input[pos + 2] as usize)
+            & 0xFFFF
+    };
+
+    // Longest match at each position, using hash chains
+    let mut head = vec![usize::MAX; 1 << 16];
+    let mut prev = vec![usize::MAX; len];
+    let mut matches = vec![(0usize, 0usize); len];
+    for pos in 0..len.saturating_sub(MIN_MATCH - 1) {
+        let h = hash(pos);
+        let end = (len - pos).min(MAX_MATCH);
+        let mut best = (0, 0);
+        let mut candidate = head[h];
+        while candidate != usize::MAX && pos - candidate <= WINDOW_SIZE && best.0 < end {
+            if input[candidate + best.0] == input[pos + best.0] {
+                let length = input[candidate..]
+                    .iter()
+                    .zip(&input[pos..pos + end])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best.0 {
+                    best = (length, pos - candidate - 1);
+                }
+            }
+            candidate = prev[candidate];
+        }
+        if best.0 >= MIN_MATCH {
+            matches[pos] = best;
+        }
+        prev[pos] = head[h];
+        head[h] = pos;
+    }
+
+    // Cost in bits to encode the rest of the input from each position
+    let mut cost = vec![0u32; len + 1];
+    let mut choice = vec![1usize; len];
+    for pos in (0..len).rev() {
+        cost[pos] = cost[pos + 1] + 9;
+        for length in MIN_MATCH..=matches[pos].0 {
+            let bits = if length < 0x12 { 17 } else { 25 };
+            if cost[pos + length] + bits < cost[pos] {
+                cost[pos] = cost[pos + length] + bits;
+                choice[pos] = length;
+            }
+        }
+    }
+
+    let mut tokens = vec![];
+    let mut pos = 0;
+    while pos < len {
+        let length = choice[pos];
+        if length == 1 {
+            tokens.push(Token::Literal(input[pos]));
+        } else {
+            tokens.push(Token::Match { distance: matches[pos].1, length });
+        }
+        pos += length;
+    }
+    tokens
+}
+
+fn encode_yaz0(size: usize, tokens: &[Token]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(size / 2 + 16);
+    out.extend_from_slice(&YAZ0_MAGIC);
+    out.extend_from_slice(&(size as u32).to_be_bytes());
+    out.extend_from_slice(&[0u8; 8]);
+    for group in tokens.chunks(8) {
+        let flags_pos = out.len();
+        let mut flags = 0u8;
+        out.push(0);
+        for (i, token) in group.iter().enumerate() {
+            match *token {
+                Token::Literal(byte) => {
+                    flags |= 0x80 >> i;
+                    out.push(byte);
+                }
+                Token::Match { distance, length } if length >= 0x12 => {
+                    out.extend_from_slice(&[
+                        (distance >> 8) as u8,
+                        distance as u8,
+                        (length - 0x12) as u8,
+                    ]);
+                }
+                Token::Match { distance, length } => {
+                    out.extend_from_slice(&[
+                        (((length - 2) << 4) | (distance >> 8)) as u8,
+                        distance as u8,
+                    ]);
+                }
+            }
+        }
+        out[flags_pos] = flags;
+    }
+    out
+}
+
+fn encode_yay0(size: usize, tokens: &[Token]) -> Vec<u8> {
+    let mut masks = Vec::with_capacity(tokens.len().div_ceil(32));
+    let mut links = vec![];
+    let mut chunks = vec![];
+    for group in tokens.chunks(32) {
+        let mut mask = 0u32;
+        for (i, token) in group.iter().enumerate() {
+            match *token {
+                Token::Literal(byte) => {
+                    mask |= 0x80000000 >> i;
+                    chunks.push(byte);
+                }
+                Token::Match { distance, length } if length >= 0x12 => {
+                    links.extend_from_slice(&(distance as u16).to_be_bytes());
+                    chunks.push((length - 0x12) as u8);
+                }
+                Token::Match { distance, length } => {
+                    let link = ((length - 2) << 12) | distance;
+                    links.extend_from_slice(&(link as u16).to_be_bytes());
+                }
+            }
+        }
+        masks.push(mask);
+    }
+    let link_offset = 16 + masks.len() * 4;
+    let chunk_offset = link_offset + links.len();
+    let mut out = Vec::with_capacity(chunk_offset + chunks.len());
+    out.extend_from_slice(&YAY0_MAGIC);
+    out.extend_from_slice(&(size as u32).to_be_bytes());
+    out.extend_from_slice(&(link_offset as u32).to_be_bytes());
+    out.extend_from_slice(&(chunk_offset as u32).to_be_bytes());
+    for mask in masks {
+        out.extend_from_slice(&mask.to_be_bytes());
+    }
+    out.extend_from_slice(&links);
+    out.extend_from_slice(&chunks);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nintendo_yaz0() {
+        // Matches longer than the maximum length and a lookahead literal. The expected output
+        // is from the reference encoder (`nintendoEnc`).
+        let pattern = (0..0x120u32).map(|i| b'a' + (i % 16) as u8).collect::<Vec<_>>();
+        let mut input = pattern[..0x118].to_vec();
+        input.push(b'Q');
+        input.extend_from_slice(&pattern);
+        input.extend_from_slice(&pattern);
+        input.extend_from_slice(b"Nintendo Ninja Nintendo Ninja");
+        let expected = [
+            0x59, 0x61, 0x7A, 0x30, 0x00, 0x00, 0x03, 0x76, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0xFF, 0x61, 0x62, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0xFF, 0x69, 0x6A,
+            0x6B, 0x6C, 0x6D, 0x6E, 0x6F, 0x70, 0x47, 0x00, 0x0F, 0xF6, 0x51, 0x01, 0x18, 0xFF,
+            0x01, 0x0F, 0xFF, 0x03, 0x38, 0x0C, 0x4E, 0x69, 0x6E, 0xFD, 0x74, 0x65, 0x6E, 0x64,
+            0x6F, 0x20, 0x10, 0x08, 0x6A, 0xC0, 0x61, 0x20, 0xC0, 0x0E,
+        ];
+        let compressed = compress_yaz0_with(&input, CompressionAlgo::Nintendo);
+        assert_eq!(*compressed, expected);
+        assert_eq!(*decompress_yaz0(&compressed).unwrap(), *input);
+    }
+}
@@ -26,17 +26,29 @@
 //
 // Source: https://gitlab.com/DarkKirb/nintendo-lz
 // Modified to compile with latest edition, use anyhow::Error, and fix various issues.
+// Rewritten compressor, added RLE and Huffman decoding.
 
-use std::io::{Cursor, Read, Write};
+use std::{
+    io,
+    io::{Cursor, Read, Write},
+};
 
-use anyhow::{bail, ensure, Result};
+use anyhow::{anyhow, bail, ensure, Result};
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
-/// Decompresses an LZ10/LZ11 compressed file. It returns an error when:
+/// Magic preceding the LZ10 header in some Wii files. (e.g. `banner.bin`)
+pub const LZ77_MAGIC: [u8; 4] = *b"LZ77";
+
+/// Maximum match length for LZ11.
+pub const LZ11_MAX_LENGTH: u32 = 0x111 + 0xFFFF;
+
+/// Decompresses an LZ10/LZ11, Huffman or RLE compressed file. It returns an error when:
 ///
-/// - The file is not a valid LZ10/LZ11 file
+/// - The file is not a valid LZ10/LZ11, Huffman or RLE file
 /// - The file is truncated (More data was expected than present)
 ///
+/// An `LZ77` magic before the LZ10 header is skipped.
+///
 /// # Example
 ///
 /// ```rust,ignore
@@ -45,16 +57,32 @@ use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 /// ```
 pub fn decompress<R>(inp: &mut R) -> Result<Vec<u8>>
 where R: Read + ?Sized {
-    let mut length = inp.read_u32::<LittleEndian>()? as usize;
-    let ver = match length & 0xFF {
-        0x10 => 0,
-        0x11 => 1,
-        _ => bail!("Invalid magic number"),
-    };
-    length >>= 8;
-    if length == 0 && ver == 1 {
+    let mut header = [0u8; 4];
+    inp.read_exact(&mut header)?;
+    if header == LZ77_MAGIC {
+        inp.read_exact(&mut header)?;
+    }
+    let header = u32::from_le_bytes(header);
+    let kind = header & 0xFF;
+    let mut length = (header >> 8) as usize;
+    if length == 0 && kind == 0x11 {
         length = inp.read_u32::<LittleEndian>()? as usize;
     }
+    match kind {
+        0x10 => decompress_lz(inp, length, false),
+        0x11 => decompress_lz(inp, length, true),
+        0x24 | 0x28 => {
+            let mut data = vec![];
+            inp.read_to_end(&mut data)?;
+            decompress_huffman(&data, length, (kind & 0xF) as u8)
+        }
+        0x30 => decompress_rle(inp, length),
+        _ => bail!("Invalid magic number"),
+    }
+}
+
+fn decompress_lz<R>(inp: &mut R, length: usize, lz11: bool) -> Result<Vec<u8>>
+where R: Read + ?Sized {
     let mut out = Vec::<u8>::with_capacity(length);
     while out.len() < length {
         let byte = inp.read_u8()?;
@@ -70,7 +98,7 @@ where R: Read + ?Sized {
                 let lsb = inp.read_u8()? as usize;
                 let mut length: usize = lenmsb >> 4;
                 let mut disp: usize = ((lenmsb & 15) << 8) + lsb;
-                if ver == 0 {
+                if !lz11 {
                     length += 3;
                 } else if length > 1 {
                     length += 1;
@@ -89,8 +117,10 @@ where R: Read + ?Sized {
                     length += 0x111;
                     disp = ((byte1 & 15) << 8) + byte2;
                 }
-                let start: usize = out.len() - disp - 1;
-
+                let start = out
+                    .len()
+                    .checked_sub(disp + 1)
+                    .ok_or_else(|| anyhow!("Invalid back-reference at offset {:#X}", out.len()))?;
                 for i in 0..length {
                     let val = out[start + i];
                     out.push(val);
@@ -98,6 +128,68 @@ where R: Read + ?Sized {
             }
         }
     }
+    out.truncate(length);
+    Ok(out)
+}
+
+fn decompress_rle<R>(inp: &mut R, length: usize) -> Result<Vec<u8>>
+where R: Read + ?Sized {
+    let mut out = Vec::<u8>::with_capacity(length);
+    while out.len() < length {
+        let flag = inp.read_u8()?;
+        if flag & 0x80 != 0 {
+            let count = (flag & 0x7F) as usize + 3;
+            let data = inp.read_u8()?;
+            out.resize(out.len() + count, data);
+        } else {
+            let count = (flag & 0x7F) as usize + 1;
+            let start = out.len();
+            out.resize(start + count, 0);
+            inp.read_exact(&mut out[start..])?;
+        }
+    }
+    out.truncate(length);
+    Ok(out)
+}
+
+fn decompress_huffman(data: &[u8], length: usize, bits: u8) -> Result<Vec<u8>> {
+    ensure!(bits == 4 || bits == 8, "Invalid Huffman data size {}", bits);
+    let tree_size =
+        (*data.first().ok_or_else(|| anyhow!("Missing Huffman tree"))? as usize + 1) * 2;
+    let tree = data.get(..tree_size).ok_or_else(|| anyhow!("Huffman tree truncated"))?;
+    let stream = &data[tree_size..];
+
+    let mut out = Vec::<u8>::with_capacity(length);
+    // Nodes are addressed by their offset in the tree table. The root is at offset 1.
+    let mut node = 1usize;
+    let mut pending: Option<u8> = None;
+    'outer: for word in stream.chunks_exact(4) {
+        let word = u32::from_le_bytes(word.try_into().unwrap());
+        for bit in (0..32).rev() {
+            let value = *tree.get(node).ok_or_else(|| anyhow!("Invalid Huffman tree"))?;
+            let right = (word >> bit) & 1 != 0;
+            let child = (node & !1) + ((value & 0x3F) as usize) * 2 + 2 + right as usize;
+            let is_leaf = value & if right { 0x40 } else { 0x80 } != 0;
+            if !is_leaf {
+                node = child;
+                continue;
+            }
+            let symbol = *tree.get(child).ok_or_else(|| anyhow!("Invalid Huffman tree"))?;
+            node = 1;
+            if bits == 8 {
+                out.push(symbol);
+            } else if let Some(low) = pending.take() {
+                out.push(low | (symbol << 4));
+            } else {
+                pending = Some(symbol & 0xF);
+                continue;
+            }
+            if out.len() >= length {
+                break 'outer;
+            }
+        }
+    }
+    ensure!(out.len() >= length, "Huffman data truncated");
     Ok(out)
 }
 
@@ -112,142 +204,174 @@ pub fn decompress_arr(input: &[u8]) -> Result<Vec<u8>> {
 pub enum CompressionLevel {
     /// LZ10 compression. Maximum repeat size: 18 bytes
     LZ10,
-    /// LZ11 compression. Maximum repeat size: 65809 bytes
+    /// LZ11 compression. Maximum repeat size: 65808 bytes
     ///
-    /// Argument: Maximum repeat size (0..65810), lower means worse compression but higher speed.
+    /// Argument: Maximum repeat size (0..=65808), lower means worse compression but higher speed.
     /// for values < 3 compression is disabled
     LZ11(u32),
 }
 
-fn find_longest_match(data: &[u8], off: usize, max: usize) -> Option<(usize, usize)> {
-    if off < 4 || data.len() - off < 4 {
-        return None;
+const WINDOW_SIZE: usize = 0x1000;
+const MIN_MATCH: usize = 3;
+
+/// Finds matches using hash chains over 3-byte prefixes.
+struct MatchFinder<'a> {
+    data: &'a [u8],
+    head: Vec<usize>,
+    prev: Vec<usize>,
+}
+
+impl<'a> MatchFinder<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, head: vec![usize::MAX; 1 << 16], prev: vec![usize::MAX; data.len()] }
     }
-    let mut longest_pos: usize = 0;
-    let mut longest_len: usize = 0;
-    let mut start = 0;
-    if off > 0x1000 {
-        start = off - 0x1000;
+
+    fn hash(&self, pos: usize) -> Option<usize> {
+        let bytes = self.data.get(pos..pos + MIN_MATCH)?;
+        Some((((bytes[0] as usize) << 8) ^ ((bytes[1] as usize) << 4) ^ bytes[2] as usize) & 0xFFFF)
     }
-    for pos in search(&data[start..off + 2], &data[off..off + 3]) {
-        let mut length = 0;
-        for (i, p) in (off..data.len()).enumerate() {
-            if length == max {
-                return Some((start + pos, length));
-            }
-            if data[p] != data[start + pos + i] {
-                break;
-            }
-            length += 1;
-        }
-        if length > longest_len {
-            longest_pos = pos;
-            longest_len = length;
+
+    fn insert(&mut self, pos: usize) {
+        if let Some(hash) = self.hash(pos) {
+            self.prev[pos] = self.head[hash];
+            self.head[hash] = pos;
         }
     }
-    if longest_len < 3 {
-        return None;
+
+    /// Returns the position and length of the longest match for `off`, preferring the closest.
+    fn longest_match(&self, off: usize, max: usize) -> Option<(usize, usize)> {
+        let hash = self.hash(off)?;
+        let max = max.min(self.data.len() - off);
+        let mut best: Option<(usize, usize)> = None;
+        let mut pos = self.head[hash];
+        while pos != usize::MAX && off - pos <= WINDOW_SIZE {
+            let length = self.data[pos..]
+                .iter()
+                .zip(&self.data[off..off + max])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if length >= MIN_MATCH && best.map_or(true, |(_, best_len)| length > best_len) {
+                best = Some((pos, length));
+                if length == max {
+                    break;
+                }
+            }
+            pos = self.prev[pos];
+        }
+        best
     }
-    Some((start + longest_pos, longest_len))
 }
 
 /// Compresses data to LZ10/LZ11. It returns an error when:
 ///
 /// - The input is too large for the selected LZ version (LZ10 supports at most 16MiB)
-/// - The maximum repeat length is out of range (for LZ11, has to be in the range (0..65810)
+/// - The maximum repeat length is out of range (for LZ11, has to be in the range (0..=65808)
 /// - Writing to the output file failed
 ///
+/// The output is padded to a multiple of 4 bytes.
+///
 /// # Example
 ///
 /// ```rust,ignore
 /// let mut f = File::create("Archive.bin.cmp");
 /// let data = b"This is an example text. This is an example text";
-/// nintendo_lz::compress(&data, &mut f, nintendo_lz::CompressionLevel::LZ11(65809)).unwrap();
+/// nintendo_lz::compress(&data, &mut f, nintendo_lz::CompressionLevel::LZ11(65808)).unwrap();
 /// ```
 pub fn compress<W>(inp: &[u8], out: &mut W, level: CompressionLevel) -> Result<()>
 where W: Write + ?Sized {
-    let ver = match level {
-        CompressionLevel::LZ10 => 0,
-        CompressionLevel::LZ11(_) => 1,
-    };
-    if ver == 0 && inp.len() > 16777216 {
+    let lz11 = matches!(level, CompressionLevel::LZ11(_));
+    if !lz11 && inp.len() >= 0x1000000 {
         bail!("Input data too large for LZ10");
     }
-    if ver == 1 && inp.len() as u64 > 0xFFFFFFFF {
+    if lz11 && inp.len() as u64 > 0xFFFFFFFF {
         bail!("Input data too large for LZ11");
     }
     let repeat_size = match level {
         CompressionLevel::LZ10 => 18,
         CompressionLevel::LZ11(max) => max,
     };
-    ensure!(repeat_size < 65810, "Maximum repeat size out of range. (0..65810)");
+    ensure!(
+        repeat_size <= LZ11_MAX_LENGTH,
+        "Maximum repeat size out of range. (0..={})",
+        LZ11_MAX_LENGTH
+    );
 
     let size: usize = inp.len();
-
-    if size < 16777216 && (size != 0 || ver == 0) {
-        let header = 0x10 + ver + ((size as u32) << 8);
+    let mut written = 4;
+    if size < 0x1000000 && (size != 0 || !lz11) {
+        let header = 0x10 + lz11 as u32 + ((size as u32) << 8);
         out.write_u32::<LittleEndian>(header)?;
     } else {
         out.write_u32::<LittleEndian>(0x11)?;
         out.write_u32::<LittleEndian>(size as u32)?;
+        written += 4;
     }
 
+    let mut finder = MatchFinder::new(inp);
     let mut off: usize = 0;
     let mut byte: u8 = 0;
     let mut index = 7;
-    let mut cmpbuf: Vec<u8> = Vec::new();
-
+    let mut cmpbuf: Vec<u8> = Vec::with_capacity(32);
     while off < size {
-        match find_longest_match(inp, off, repeat_size as usize) {
+        let found = if repeat_size as usize >= MIN_MATCH {
+            finder.longest_match(off, repeat_size as usize)
+        } else {
+            None
+        };
+        match found {
             None => {
-                index -= 1;
                 cmpbuf.push(inp[off]);
+                finder.insert(off);
                 off += 1;
             }
             Some((pos, len)) => {
                 let lz_off: usize = off - pos - 1;
                 byte |= 1 << index;
-                index -= 1;
-                if ver == 0 {
+                if !lz11 {
                     let l = len - 3;
-                    let cmp: [u8; 2] = [((lz_off >> 8) as u8) + ((l << 4) as u8), lz_off as u8];
-                    cmpbuf.extend_from_slice(&cmp);
+                    cmpbuf.extend_from_slice(&[((lz_off >> 8) | (l << 4)) as u8, lz_off as u8]);
                 } else if len < 0x11 {
                     let l = len - 1;
-                    let cmp: [u8; 2] = [((lz_off >> 8) as u8) + ((l << 4) as u8), lz_off as u8];
-                    cmpbuf.extend_from_slice(&cmp);
+                    cmpbuf.extend_from_slice(&[((lz_off >> 8) | (l << 4)) as u8, lz_off as u8]);
                 } else if len < 0x111 {
                     let l = len - 0x11;
-                    let cmp: [u8; 3] =
-                        [(l >> 4) as u8, ((lz_off >> 8) as u8) + ((l << 4) as u8), lz_off as u8];
-                    cmpbuf.extend_from_slice(&cmp);
+                    cmpbuf.extend_from_slice(&[
+                        (l >> 4) as u8,
+                        ((lz_off >> 8) | (l << 4)) as u8,
+                        lz_off as u8,
+                    ]);
                 } else {
                     let l = len - 0x111;
-                    let cmp: [u8; 4] = [
-                        (l >> 12) as u8 + 0x10,
+                    cmpbuf.extend_from_slice(&[
+                        ((l >> 12) | 0x10) as u8,
                         (l >> 4) as u8,
-                        ((lz_off >> 8) as u8) + ((l << 4) as u8),
+                        ((lz_off >> 8) | (l << 4)) as u8,
                         lz_off as u8,
-                    ];
-                    cmpbuf.extend_from_slice(&cmp);
+                    ]);
+                }
+                for pos in off..off + len {
+                    finder.insert(pos);
                 }
                 off += len;
             }
         };
-        if index < 0 {
+        if index == 0 {
             out.write_u8(byte)?;
             out.write_all(&cmpbuf)?;
+            written += 1 + cmpbuf.len();
             byte = 0;
             index = 7;
             cmpbuf.clear();
+        } else {
+            index -= 1;
         }
     }
-    if !cmpbuf.is_empty() {
+    if index != 7 {
         out.write_u8(byte)?;
         out.write_all(&cmpbuf)?;
+        written += 1 + cmpbuf.len();
     }
-    out.write_u8(0xFF)?;
-
+    out.write_all(&[0u8; 3][..written.next_multiple_of(4) - written])?;
     Ok(())
 }
 
@@ -255,51 +379,112 @@ where W: Write + ?Sized {
 /// Additionally, it uses LZ11 as compression algorithm by default.
 pub fn compress_arr(input: &[u8]) -> Result<Vec<u8>> {
     let mut out: Vec<u8> = Vec::new();
-    {
-        let mut writer = Cursor::new(&mut out);
-        compress(input, &mut writer, CompressionLevel::LZ11(65809))?;
+    compress(input, &mut out, CompressionLevel::LZ11(LZ11_MAX_LENGTH))?;
+    Ok(out)
+}
+
+/// Compresses data to RLE (type 0x30). The output is padded to a multiple of 4 bytes.
+pub fn compress_rle(inp: &[u8]) -> Result<Vec<u8>> {
+    ensure!(inp.len() < 0x1000000, "Input data too large for RLE");
+    let mut out = Vec::with_capacity(inp.len() + inp.len() / 128 + 8);
+    out.extend_from_slice(&(0x30 | ((inp.len() as u32) << 8)).to_le_bytes());
+    let mut literal_start = 0;
+    let mut off = 0;
+    let flush_literals = |out: &mut Vec<u8>, literals: &[u8]| {
+        for chunk in literals.chunks(128) {
+            out.push((chunk.len() - 1) as u8);
+            out.extend_from_slice(chunk);
+        }
+    };
+    while off < inp.len() {
+        let run = inp[off..].iter().take(130).take_while(|&&b| b == inp[off]).count();
+        if run >= 3 {
+            flush_literals(&mut out, &inp[literal_start..off]);
+            out.push(0x80 | (run - 3) as u8);
+            out.push(inp[off]);
+            off += run;
+            literal_start = off;
+        } else {
+            off += 1;
+        }
     }
+    flush_literals(&mut out, &inp[literal_start..]);
+    out.resize(out.len().next_multiple_of(4), 0);
     Ok(out)
 }
 
-fn get_needle_table(needle: &[u8]) -> [usize; 256] {
-    let mut needle_table = [needle.len(); 256];
-    for (i, c) in needle.iter().enumerate() {
-        needle_table[*c as usize] = needle.len() - i;
+/// Checks whether `data` looks like the start of an LZ10/LZ11 file of `file_len` bytes.
+/// Returns the compression type (0x10 or 0x11).
+///
+/// These formats have no real magic, so the decompressed size and the first flag byte are
+/// checked for plausibility. (The first token can never be a back-reference.)
+pub fn detect_lz(data: &[u8; 8], file_len: u64) -> Option<u8> {
+    let kind = data[0];
+    if kind != 0x10 && kind != 0x11 {
+        return None;
+    }
+    let mut size = u32::from_le_bytes([data[1], data[2], data[3], 0]) as u64;
+    let mut flags = data[4];
+    if size == 0 && kind == 0x11 {
+        size = u32::from_le_bytes(data[4..8].try_into().unwrap()) as u64;
+        flags = 0; // Not available
     }
-    needle_table
+    let max_ratio = if kind == 0x10 { 9 } else { 0x4000 };
+    let plausible = size > 0
+        && flags & 0x80 == 0
+        // Compressed data is at most 9/8 of the input, plus the header and padding
+        && file_len <= size + size / 8 + 16
+        && size <= file_len.saturating_mul(max_ratio);
+    plausible.then_some(kind)
 }
 
-pub fn search_one(haystack: &[u8], needle: &[u8], needle_table: &[usize; 256]) -> Option<usize> {
-    let mut cur = 0;
-    while haystack.len() - cur >= needle.len() {
-        let mut output = None;
-        for i in (0..needle.len()).rev() {
-            if haystack[cur + i] == needle[i] {
-                output = Some(cur);
-                break;
-            }
-        }
-        if output.is_some() {
-            return output;
+/// Number of bytes read from the start of a file to confirm an LZ10/LZ11 header.
+pub const LZ_VERIFY_PREFIX: u64 = 0x10000;
+
+/// Confirms an LZ10/LZ11 header found by [`detect_lz`] by decoding `data`, the first bytes of
+/// a file of `file_len` bytes. The data must decode without errors (such as back-references
+/// before the start of the output) and end within the padding at the end of the file. Running
+/// out of data is only accepted if `data` doesn't hold the whole file.
+pub fn verify_lz(data: &[u8], file_len: u64) -> bool {
+    let mut reader = Cursor::new(data);
+    match decompress(&mut reader) {
+        Ok(_) => file_len - reader.position() < 0x20,
+        Err(e) => {
+            (data.len() as u64) < file_len
+                && e.downcast_ref::<io::Error>()
+                    .is_some_and(|e| e.kind() == io::ErrorKind::UnexpectedEof)
         }
-        cur += needle_table[haystack[cur + needle.len() - 1] as usize];
     }
-    None
 }
 
-fn search(haystack: &[u8], needle: &[u8]) -> Vec<usize> {
-    let needle_table = get_needle_table(needle);
-    let mut cur = 0usize;
-    let mut positions = Vec::new();
-    while cur + needle.len() < haystack.len() {
-        let found_pos = search_one(&haystack[cur..], needle, &needle_table);
-        if let Some(pos) = found_pos {
-            positions.push(pos);
-            cur += pos + needle.len() + 1;
-        } else {
-            return positions;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_lz() {
+        let input = b"Nintendo LZ sample. Nintendo LZ sample. Nintendo LZ sample.".repeat(8);
+        for level in [CompressionLevel::LZ10, CompressionLevel::LZ11(LZ11_MAX_LENGTH)] {
+            let mut data = vec![];
+            compress(&input, &mut data, level).unwrap();
+            let kind = detect_lz(data[..8].try_into().unwrap(), data.len() as u64);
+            assert!(kind.is_some());
+            assert!(verify_lz(&data, data.len() as u64));
+            // Padded to 0x20
+            data.resize(data.len().next_multiple_of(0x20), 0);
+            assert!(verify_lz(&data, data.len() as u64));
+            // Only a prefix of the file was read
+            assert!(verify_lz(&data[..data.len() / 2], data.len() as u64));
+            // Trailing data that isn't padding
+            assert!(!verify_lz(&data, data.len() as u64 + 0x20));
+            assert_eq!(decompress(&mut Cursor::new(&data)).unwrap(), input);
         }
+
+        // A raw file with a plausible LZ10 header
+        let mut data = vec![0x10, 0x00, 0x01, 0x00];
+        data.extend((0..0xFC).map(|i| i as u8));
+        assert_eq!(detect_lz(data[..8].try_into().unwrap(), data.len() as u64), Some(0x10));
+        assert!(!verify_lz(&data, data.len() as u64));
+        assert!(!verify_lz(&data[..0x80], data.len() as u64));
     }
-    positions
 }
@@ -1,5 +1,7 @@
-use std::{borrow::Cow, ffi::CStr};
+use std::{borrow::Cow, collections::HashMap, ffi::CStr};
 
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize};
 use typed_path::Utf8UnixPath;
 use zerocopy::{big_endian::*, FromBytes, Immutable, IntoBytes, KnownLayout};
 
@@ -103,6 +105,18 @@ impl RarcNode {
     /// Files: Length of the data.
     /// Directories: Unknown. Always 16.
     pub fn data_length(&self) -> u32 { self.data_length.get() }
+
+    /// Where the file is loaded when the archive is mounted.
+    pub fn preload(&self) -> RarcPreload {
+        let attributes = (self._unk0.get() >> 8) as u8;
+        if attributes & RarcPreload::Aram.attribute() != 0 {
+            RarcPreload::Aram
+        } else if attributes & RarcPreload::Dvd.attribute() != 0 {
+            RarcPreload::Dvd
+        } else {
+            RarcPreload::Mram
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, FromBytes, IntoBytes, Immutable, KnownLayout)]
@@ -268,6 +282,14 @@ impl<'a> RarcView<'a> {
         None
     }
 
+    /// Whether file IDs match node indices.
+    pub fn has_synced_ids(&self) -> bool {
+        self.nodes
+            .iter()
+            .enumerate()
+            .all(|(i, node)| node.is_dir() || node.index.get() as usize == i)
+    }
+
     /// Get the children of a directory.
     pub fn children(&self, dir: RarcDirectory) -> &[RarcNode] {
         let start = dir.node_index() as usize;
@@ -281,3 +303,271 @@ pub enum RarcNodeKind {
     File(usize, RarcNode),
     Directory(usize, RarcDirectory),
 }
+
+/// Node attribute: file.
+const ATTR_FILE: u8 = 0x01;
+/// Node attribute: directory.
+const ATTR_DIRECTORY: u8 = 0x02;
+/// Node attribute: Yaz0 compressed (together with [`ATTR_COMPRESSED`]).
+const ATTR_YAZ0: u8 = 0x04;
+/// Node attribute: compressed.
+const ATTR_COMPRESSED: u8 = 0x80;
+
+/// Where a file is loaded when the archive is mounted.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RarcPreload {
+    /// Preloaded to main RAM.
+    #[default]
+    Mram,
+    /// Preloaded to auxiliary RAM.
+    Aram,
+    /// Read from disc on demand.
+    Dvd,
+}
+
+impl RarcPreload {
+    fn attribute(self) -> u8 {
+        match self {
+            RarcPreload::Mram => 0x10,
+            RarcPreload::Aram => 0x20,
+            RarcPreload::Dvd => 0x40,
+        }
+    }
+}
+
+/// A file to be written to a RARC archive.
+#[derive(Clone, Debug)]
+pub struct RarcFileEntry {
+    pub name: String,
+    pub data: Vec<u8>,
+    pub preload: RarcPreload,
+}
+
+/// A directory to be written to a RARC archive. Entries are written in order.
+#[derive(Clone, Debug, Default)]
+pub struct RarcDirEntry {
+    pub name: String,
+    pub entries: Vec<RarcEntry>,
+}
+
+/// A file or directory to be written to a RARC archive.
+#[derive(Clone, Debug)]
+pub enum RarcEntry {
+    File(RarcFileEntry),
+    Dir(RarcDirEntry),
+}
+
+impl RarcDirEntry {
+    fn dirs(&self) -> impl Iterator<Item = &RarcDirEntry> {
+        self.entries.iter().filter_map(|entry| match entry {
+            RarcEntry::Dir(dir) => Some(dir),
+            RarcEntry::File(_) => None,
+        })
+    }
+}
+
+/// Hash of a node name, as used by JKRArchive.
+pub fn rarc_name_hash(name: &str) -> u16 {
+    name.bytes().fold(0u16, |hash, c| hash.wrapping_mul(3).wrapping_add(c as u16))
+}
+
+#[inline]
+fn align32(n: usize) -> usize { (n + 31) & !31 }
+
+#[derive(Default)]
+struct StringTable {
+    data: Vec<u8>,
+    offsets: HashMap<String, u32>,
+}
+
+impl StringTable {
+    fn add(&mut self, name: &str) -> u32 {
+        if let Some(&offset) = self.offsets.get(name) {
+            return offset;
+        }
+        let offset = self.data.len() as u32;
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.push(0);
+        self.offsets.insert(name.to_string(), offset);
+        offset
+    }
+}
+
+fn directory_node(name_offset: u32, name_hash: u16, directory: u32) -> RarcNode {
+    RarcNode {
+        index: U16::new(0xFFFF),
+        name_hash: U16::new(name_hash),
+        _unk0: U16::new((ATTR_DIRECTORY as u16) << 8),
+        name_offset: U16::new(name_offset as u16),
+        data_offset: U32::new(directory),
+        data_length: U32::new(size_of::<RarcDirectory>() as u32),
+        _unk1: U32::new(0),
+    }
+}
+
+/// Writes a RARC archive.
+///
+/// Directories are laid out breadth-first. Each directory lists its entries in order,
+/// then the `.` and `..` entries. File data is grouped by preload type
+/// (MRAM, ARAM, then DVD) with each file aligned to 32 bytes. When `sync_ids` is set,
+/// file IDs match node indices.
+pub fn write_rarc(root: &RarcDirEntry, sync_ids: bool) -> Result<Vec<u8>> {
+    // Assign directory indices breadth-first
+    let mut dirs: Vec<(&RarcDirEntry, Option<usize>)> = vec![(root, None)];
+    let mut children: Vec<Vec<usize>> = vec![];
+    let mut i = 0;
+    while i < dirs.len() {
+        let dir = dirs[i].0;
+        let mut indices = vec![];
+        for sub in dir.dirs() {
+            indices.push(dirs.len());
+            dirs.push((sub, Some(i)));
+        }
+        children.push(indices);
+        i += 1;
+    }
+
+    let mut strings = StringTable::default();
+    strings.add(".");
+    strings.add("..");
+
+    let mut directories = Vec::with_capacity(dirs.len());
+    let mut nodes = Vec::<RarcNode>::new();
+    // (node index, data) for each preload type
+    let mut file_data: [Vec<(usize, &[u8])>; 3] = Default::default();
+    let mut next_file_id = 0u16;
+    for (dir_index, &(dir, parent)) in dirs.iter().enumerate() {
+        let identifier = if dir_index == 0 {
+            *b"ROOT"
+        } else {
+            let mut identifier = *b"    ";
+            for (dst, c) in identifier.iter_mut().zip(dir.name.bytes()) {
+                *dst = c.to_ascii_uppercase();
+            }
+            identifier
+        };
+        let count = dir.entries.len() + 2;
+        ensure!(count <= u16::MAX as usize, "Too many entries in directory {}", dir.name);
+        directories.push(RarcDirectory {
+            identifier,
+            name_offset: U32::new(strings.add(&dir.name)),
+            name_hash: U16::new(rarc_name_hash(&dir.name)),
+            count: U16::new(count as u16),
+            index: U32::new(nodes.len() as u32),
+        });
+
+        let mut sub_indices = children[dir_index].iter();
+        for entry in &dir.entries {
+            let file = match entry {
+                RarcEntry::File(file) => file,
+                RarcEntry::Dir(sub) => {
+                    let sub_index = *sub_indices.next().unwrap();
+                    let name_offset = strings.add(&sub.name);
+                    nodes.push(directory_node(
+                        name_offset,
+                        rarc_name_hash(&sub.name),
+                        sub_index as u32,
+                    ));
+                    continue;
+                }
+            };
+            let node_index = nodes.len();
+            let id = if sync_ids { node_index as u16 } else { next_file_id };
+            next_file_id += 1;
+            let mut attributes = ATTR_FILE | file.preload.attribute();
+            if file.data.starts_with(b"Yaz0") {
+                attributes |= ATTR_COMPRESSED | ATTR_YAZ0;
+            } else if file.data.starts_with(b"Yay0") {
+                attributes |= ATTR_COMPRESSED;
+            }
+            nodes.push(RarcNode {
+                index: U16::new(id),
+                name_hash: U16::new(rarc_name_hash(&file.name)),
+                _unk0: U16::new((attributes as u16) << 8),
+                name_offset: U16::new(strings.add(&file.name) as u16),
+                data_offset: U32::new(0), // Set below
+                data_length: U32::new(file.data.len() as u32),
+                _unk1: U32::new(0),
+            });
+            let group = match file.preload {
+                RarcPreload::Mram => 0,
+                RarcPreload::Aram => 1,
+                RarcPreload::Dvd => 2,
+            };
+            file_data[group].push((node_index, &file.data));
+        }
+        nodes.push(directory_node(0, rarc_name_hash("."), dir_index as u32));
+        nodes.push(directory_node(
+            2,
+            rarc_name_hash(".."),
+            parent.map(|p| p as u32).unwrap_or(u32::MAX),
+        ));
+    }
+    ensure!(strings.data.len() <= u16::MAX as usize, "RARC string table too large");
+    if sync_ids {
+        ensure!(nodes.len() < 0xFFFF, "Too many nodes for synced file IDs");
+        next_file_id = nodes.len() as u16;
+    }
+
+    // Lay out file data
+    let mut group_sizes = [0u32; 3];
+    let mut data_len = 0usize;
+    for (group, files) in file_data.iter().enumerate() {
+        let start = data_len;
+        for &(node_index, data) in files {
+            nodes[node_index].data_offset = U32::new(data_len as u32);
+            data_len = align32(data_len + data.len());
+        }
+        group_sizes[group] = (data_len - start) as u32;
+    }
+
+    // Offsets are relative to the end of the header
+    let header_len = size_of::<RarcHeader>();
+    let directory_offset = size_of::<RarcInfo>();
+    let node_offset = align32(directory_offset + directories.len() * size_of::<RarcDirectory>());
+    let string_table_offset = align32(node_offset + nodes.len() * size_of::<RarcNode>());
+    let string_table_len = align32(strings.data.len());
+    let data_offset = string_table_offset + string_table_len;
+    let file_len = header_len + data_offset + data_len;
+
+    let header = RarcHeader {
+        magic: RARC_MAGIC,
+        file_len: U32::new(file_len as u32),
+        header_len: U32::new(header_len as u32),
+        data_offset: U32::new(data_offset as u32),
+        data_len: U32::new(data_len as u32),
+        _unk1: U32::new(group_sizes[0]),
+        _unk2: U32::new(group_sizes[1]),
+        _unk3: U32::new(0),
+    };
+    let info = RarcInfo {
+        directory_count: U32::new(directories.len() as u32),
+        directory_offset: U32::new(directory_offset as u32),
+        node_count: U32::new(nodes.len() as u32),
+        node_offset: U32::new(node_offset as u32),
+        string_table_len: U32::new(string_table_len as u32),
+        string_table_offset: U32::new(string_table_offset as u32),
+        _file_count: U16::new(next_file_id),
+        _unk4: U16::new((sync_ids as u16) << 8),
+        _unk5: U32::new(0),
+    };
+
+    let mut out = Vec::with_capacity(file_len);
+    out.extend_from_slice(header.as_bytes());
+    out.extend_from_slice(info.as_bytes());
+    out.extend_from_slice(directories.as_bytes());
+    out.resize(header_len + node_offset, 0);
+    out.extend_from_slice(nodes.as_bytes());
+    out.resize(header_len + string_table_offset, 0);
+    out.extend_from_slice(&strings.data);
+    out.resize(header_len + data_offset, 0);
+    for files in &file_data {
+        for &(_, data) in files {
+            out.extend_from_slice(data);
+            out.resize(align32(out.len()), 0);
+        }
+    }
+    ensure!(out.len() == file_len, "RARC size mismatch");
+    Ok(out)
+}
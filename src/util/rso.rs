@@ -3,17 +3,21 @@ use std::{
     io::{Read, Seek, SeekFrom, Write},
 };
 
-use anyhow::{anyhow, ensure, Result};
+use anyhow::{anyhow, bail, ensure, Result};
 use cwdemangle::{demangle, DemangleOptions};
+use object::elf;
 
 use crate::{
     obj::{
-        ObjArchitecture, ObjInfo, ObjKind, ObjSection, ObjSectionKind, ObjSymbol, ObjSymbolFlagSet,
-        ObjSymbolFlags, ObjSymbolKind, SectionIndex,
+        ObjArchitecture, ObjInfo, ObjKind, ObjRelocKind, ObjSection, ObjSectionKind, ObjSymbol,
+        ObjSymbolFlagSet, ObjSymbolFlags, ObjSymbolKind, SectionIndex,
     },
     util::{
+        align_up,
         file::{read_c_string, read_string},
         reader::{struct_size, Endian, FromReader, ToWriter, DYNAMIC_SIZE},
+        rel::RelReloc,
+        split::default_section_align,
     },
 };
 
@@ -412,12 +416,66 @@ impl ToWriter for RsoSymbol {
     fn write_size(&self) -> usize { Self::STATIC_SIZE }
 }
 
+/// An exported symbol, resolved by name (and [`symbol_hash`]) at link time.
+#[derive(Debug, Clone)]
+pub struct RsoExport {
+    /// Symbol name.
+    pub name: String,
+    /// Hash of the symbol name.
+    pub hash: u32,
+    /// Raw section index. For SEL files, this is an index into [`DOL_SECTION_NAMES`]
+    /// (or [`DOL_SECTION_ETI`] / [`DOL_SECTION_ABS`]).
+    pub section: u32,
+    /// Section-relative offset of the symbol.
+    pub offset: u32,
+}
+
+/// A relocation against an imported symbol.
+#[derive(Debug, Clone)]
+pub struct RsoImportReloc {
+    /// Relocation kind.
+    pub kind: ObjRelocKind,
+    /// Source section index.
+    pub section: u8,
+    /// Source address.
+    pub address: u32,
+    /// Name of the imported symbol.
+    pub symbol: String,
+    /// Offset from the imported symbol.
+    pub addend: u32,
+}
+
+/// Symbol tables used to link RSO modules together by name.
+#[derive(Debug, Clone, Default)]
+pub struct RsoLinkInfo {
+    pub exports: Vec<RsoExport>,
+    pub imports: Vec<RsoImportReloc>,
+}
+
 pub fn process_rso<R>(reader: &mut R) -> Result<ObjInfo>
 where R: Read + Seek + ?Sized {
+    process_rso_module(reader, "", 0).map(|(_, obj, _)| obj)
+}
+
+/// Reads an RSO (or SEL) file.
+///
+/// Internal relocations are added to [`ObjInfo::unresolved_relocations`] using the given module
+/// ID. Since RSO modules are linked by symbol name, relocations against imported symbols are
+/// returned separately and must be resolved against the export tables of the other modules.
+/// If `name` is empty, the module name stored in the file is used.
+pub fn process_rso_module<R>(
+    reader: &mut R,
+    name: &str,
+    module_id: u32,
+) -> Result<(RsoHeader, ObjInfo, RsoLinkInfo)>
+where
+    R: Read + Seek + ?Sized,
+{
     let header = RsoHeader::from_reader(reader, Endian::Big)?;
     let mut sections = Vec::with_capacity(header.num_sections as usize);
     reader.seek(SeekFrom::Start(header.section_info_offset as u64))?;
     let mut total_bss_size = 0;
+    let mut text_section = None;
     for idx in 0..header.num_sections {
         let section = RsoSectionHeader::from_reader(reader, Endian::Big)?;
         let offset = section.offset();
@@ -437,17 +495,31 @@ where R: Read + Seek + ?Sized {
             data
         };
 
-        // println!("Section {} offset {:#X} size {:#X}", idx, offset, size);
-
-        sections.push(ObjSection {
-            name: format!(".section{}", idx),
-            kind: if offset == 0 {
-                ObjSectionKind::Bss
-            } else if section.exec() {
-                ObjSectionKind::Code
+        // RSO files don't always mark executable sections, but the sections
+        // containing _prolog, _epilog and _unresolved are always code.
+        let exec = section.exec()
+            || (idx > 0
+                && [header.prolog_section, header.epilog_section, header.unresolved_section]
+                    .contains(&(idx as u8)));
+        let (name, kind, section_known) = if offset == 0 {
+            if total_bss_size == 0 {
+                (".bss".to_string(), ObjSectionKind::Bss, true)
             } else {
-                ObjSectionKind::Data
-            },
+                (format!(".section{}", idx), ObjSectionKind::Bss, false)
+            }
+        } else if exec {
+            if text_section.is_none() {
+                text_section = Some(idx);
+                (".text".to_string(), ObjSectionKind::Code, true)
+            } else {
+                (format!(".section{}", idx), ObjSectionKind::Code, false)
+            }
+        } else {
+            (format!(".section{}", idx), ObjSectionKind::Data, false)
+        };
+        sections.push(ObjSection {
+            name,
+            kind,
             address: 0,
             size: size as u64,
             data,
@@ -456,7 +528,7 @@ where R: Read + Seek + ?Sized {
             relocations: Default::default(),
             virtual_address: None, // TODO option to set?
             file_offset: offset as u64,
-            section_known: false,
+            section_known,
             splits: Default::default(),
         });
         if offset == 0 {
@@ -479,11 +551,13 @@ where R: Read + Seek + ?Sized {
                 .find(|&(_, section)| section.elf_index == rel_section_idx as SectionIndex)
                 .ok_or_else(|| anyhow!("Failed to locate {name} section {rel_section_idx}"))?;
             log::debug!("Adding {name} section {rel_section_idx} offset {offset:#X}");
+            let mut flags = ObjSymbolFlagSet(ObjSymbolFlags::Global.into());
+            flags.set_force_active(true);
             symbols.push(ObjSymbol {
                 name: name.to_string(),
                 address: offset as u64,
                 section: Some(section_index as SectionIndex),
-                flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+                flags,
                 kind: ObjSymbolKind::Function,
                 ..Default::default()
             });
@@ -494,19 +568,7 @@ where R: Read + Seek + ?Sized {
     add_symbol(header.epilog_section, header.epilog_offset, "_epilog")?;
     add_symbol(header.unresolved_section, header.unresolved_offset, "_unresolved")?;
 
-    reader.seek(SeekFrom::Start(header.external_rel_offset as u64))?;
-    while reader.stream_position()? < (header.external_rel_offset + header.external_rel_size) as u64
-    {
-        let reloc = RsoRelocation::from_reader(reader, Endian::Big)?;
-        log::debug!(
-            "Reloc offset: {:#X}, id: {}, type: {}, sym offset: {:#X}",
-            reloc.offset(),
-            reloc.id(),
-            reloc.rel_type(),
-            reloc.sym_offset()
-        );
-    }
-
+    let mut link_info = RsoLinkInfo::default();
     reader.seek(SeekFrom::Start(header.export_table_offset as u64))?;
     while reader.stream_position()? < (header.export_table_offset + header.export_table_size) as u64
     {
@@ -523,13 +585,20 @@ where R: Read + Seek + ?Sized {
             calc
         );
         let demangled_name = demangle(&name, &DemangleOptions::default());
-        let section = sections
+        let section = match sections
             .iter()
-            .enumerate()
-            .find(|&(_, section)| section.elf_index == symbol.section_index as SectionIndex)
-            .map(|(idx, _)| idx)
+            .position(|section| section.elf_index == symbol.section_index as SectionIndex)
+        {
+            Some(idx) => Some(idx as SectionIndex),
             // HACK: selfiles won't have any sections
-            .unwrap_or(symbol.section_index as usize);
+            None if sections.is_empty() => Some(symbol.section_index as SectionIndex),
+            None if symbol.section_index == DOL_SECTION_ABS => None,
+            None => bail!(
+                "Failed to locate section {} for exported symbol {}",
+                symbol.section_index,
+                name
+            ),
+        };
         log::debug!(
             "Export: {}, sym off: {:#X}, section: {}, ELF hash: {:#X}",
             demangled_name.as_deref().unwrap_or(&name),
@@ -537,14 +606,27 @@ where R: Read + Seek + ?Sized {
             symbol.section_index,
             hash_n
         );
+        link_info.exports.push(RsoExport {
+            name: name.clone(),
+            hash: hash_n,
+            section: symbol.section_index,
+            offset: symbol.offset,
+        });
         symbols.push(ObjSymbol {
             name,
             demangled_name,
             address: symbol.offset as u64,
-            section: Some(section as SectionIndex),
+            section,
+            flags: if sections.is_empty() {
+                Default::default()
+            } else {
+                ObjSymbolFlagSet(ObjSymbolFlags::Global | ObjSymbolFlags::Exported)
+            },
             ..Default::default()
         });
     }
+
+    let mut import_names = Vec::new();
     reader.seek(SeekFrom::Start(header.import_table_offset as u64))?;
     while reader.stream_position()? < (header.import_table_offset + header.import_table_size) as u64
     {
@@ -557,15 +639,146 @@ where R: Read + Seek + ?Sized {
             symbol.offset,
             symbol.section_index
         );
+        import_names.push(name);
+    }
+
+    let mut unresolved_relocations = Vec::new();
+    reader.seek(SeekFrom::Start(header.internal_rel_offset as u64))?;
+    while reader.stream_position()? < (header.internal_rel_offset + header.internal_rel_size) as u64
+    {
+        let reloc = RsoRelocation::from_reader(reader, Endian::Big)?;
+        let Some(kind) = rso_reloc_kind(reloc.rel_type())? else {
+            continue;
+        };
+        let (section, address) = reloc_source(&sections, reloc.offset())?;
+        let target_section = reloc.id() as u8;
+        unresolved_relocations.push(RelReloc {
+            kind,
+            section,
+            address: address & !3,
+            module_id,
+            target_section,
+            addend: reloc.sym_offset(),
+            original_section: section,
+            original_target_section: target_section,
+        });
+    }
+
+    reader.seek(SeekFrom::Start(header.external_rel_offset as u64))?;
+    while reader.stream_position()? < (header.external_rel_offset + header.external_rel_size) as u64
+    {
+        let reloc = RsoRelocation::from_reader(reader, Endian::Big)?;
+        log::debug!(
+            "Reloc offset: {:#X}, id: {}, type: {}, sym offset: {:#X}",
+            reloc.offset(),
+            reloc.id(),
+            reloc.rel_type(),
+            reloc.sym_offset()
+        );
+        let Some(kind) = rso_reloc_kind(reloc.rel_type())? else {
+            continue;
+        };
+        let symbol = import_names.get(reloc.id() as usize).ok_or_else(|| {
+            anyhow!("Invalid import symbol index {} at {:#X}", reloc.id(), reloc.offset())
+        })?;
+        let (section, address) = reloc_source(&sections, reloc.offset())?;
+        link_info.imports.push(RsoImportReloc {
+            kind,
+            section,
+            address: address & !3,
+            symbol: symbol.clone(),
+            addend: reloc.sym_offset(),
+        });
     }
 
     let name = match header.name_offset {
+        _ if !name.is_empty() => name.to_string(),
         0 => String::new(),
         _ => read_string(reader, header.name_offset as u64, header.name_size as usize)?,
     };
 
-    let obj = ObjInfo::new(ObjKind::Relocatable, ObjArchitecture::PowerPc, name, symbols, sections);
-    Ok(obj)
+    let mut obj =
+        ObjInfo::new(ObjKind::Relocatable, ObjArchitecture::PowerPc, name, symbols, sections);
+    obj.module_id = module_id;
+    obj.unresolved_relocations = unresolved_relocations;
+    Ok((header, obj, link_info))
+}
+
+fn rso_reloc_kind(r_type: u8) -> Result<Option<ObjRelocKind>> {
+    Ok(Some(match r_type as u32 {
+        elf::R_PPC_NONE => return Ok(None),
+        elf::R_PPC_ADDR32 | elf::R_PPC_UADDR32 => ObjRelocKind::Absolute,
+        elf::R_PPC_ADDR16_LO => ObjRelocKind::PpcAddr16Lo,
+        elf::R_PPC_ADDR16_HI => ObjRelocKind::PpcAddr16Hi,
+        elf::R_PPC_ADDR16_HA => ObjRelocKind::PpcAddr16Ha,
+        elf::R_PPC_REL24 => ObjRelocKind::PpcRel24,
+        elf::R_PPC_REL14 => ObjRelocKind::PpcRel14,
+        reloc_type => bail!("Unhandled RSO relocation type {reloc_type}"),
+    }))
+}
+
+/// Converts an absolute file offset into a section index and section-relative address.
+fn reloc_source(sections: &[ObjSection], offset: u32) -> Result<(u8, u32)> {
+    let section = sections
+        .iter()
+        .find(|section| {
+            section.file_offset != 0
+                && (section.file_offset..section.file_offset + section.size)
+                    .contains(&(offset as u64))
+        })
+        .ok_or_else(|| anyhow!("Failed to locate section for relocation at {:#X}", offset))?;
+    Ok((section.elf_index as u8, offset - section.file_offset as u32))
+}
+
+/// Determines RSO section alignment based on its file offset.
+pub fn update_rso_section_alignment(obj: &mut ObjInfo, header: &RsoHeader) -> Result<()> {
+    let mut last_offset = header.section_info_offset + header.num_sections * 8;
+    for (_, section) in obj.sections.iter_mut() {
+        if section.kind == ObjSectionKind::Bss {
+            // BSS sections aren't stored in the file
+            if section.align == 0 {
+                section.align = default_section_align(section);
+            }
+            continue;
+        }
+
+        let prev_offset = last_offset;
+        last_offset = (section.file_offset + section.size) as u32;
+
+        if section.align > 0 {
+            // Already set
+            continue;
+        }
+
+        if section.section_known {
+            // Try the default section alignment for known sections
+            let default_align = default_section_align(section);
+            if align_up(prev_offset, default_align as u32) == section.file_offset as u32 {
+                section.align = default_align;
+                continue;
+            }
+        }
+
+        // Work our way down from the maximum alignment
+        let mut align = 32;
+        while align >= 4 {
+            if align_up(prev_offset, align) == section.file_offset as u32 {
+                section.align = align as u64;
+                break;
+            }
+            align /= 2;
+        }
+
+        if section.align == 0 {
+            bail!(
+                "Failed to determine alignment for RSO section {}: {:#X} -> {:#X}",
+                section.name,
+                prev_offset,
+                section.file_offset
+            );
+        }
+    }
+    Ok(())
 }
 
 pub fn symbol_hash(s: &str) -> u32 {
@@ -1,11 +1,15 @@
-use std::collections::{btree_map, BTreeMap};
+use std::{
+    collections::{btree_map, BTreeMap},
+    fs,
+};
 
-use anyhow::{anyhow, bail, ensure, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use base64::{engine::general_purpose::STANDARD, Engine};
 use cwdemangle::{demangle, DemangleOptions};
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use sha1::{Digest, Sha1};
-use typed_path::Utf8NativePath;
+use typed_path::{Utf8NativePath, Utf8NativePathBuf};
 
 use crate::{
     analysis::{
@@ -18,7 +22,7 @@ use crate::{
         ObjInfo, ObjKind, ObjReloc, ObjRelocKind, ObjSection, ObjSymbol, ObjSymbolFlagSet,
         ObjSymbolKind, SectionIndex, SymbolIndex,
     },
-    util::elf::process_elf,
+    util::{elf::process_elf, path::check_path_buf},
 };
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
@@ -73,6 +77,38 @@ pub fn parse_signatures(sig_str: &str) -> Result<Vec<FunctionSignature>> {
     Ok(serde_yaml::from_str(sig_str)?)
 }
 
+/// Reads signatures from a YAML file (as written by `elf sigs`), or from every `.yml` / `.yaml`
+/// file in a directory. Returns the signatures along with the files that were read.
+pub fn read_signatures(
+    path: &Utf8NativePath,
+) -> Result<(Vec<FunctionSignature>, Vec<Utf8NativePathBuf>)> {
+    let mut files = vec![];
+    if fs::metadata(path).with_context(|| format!("Failed to open '{}'", path))?.is_dir() {
+        for entry in fs::read_dir(path).with_context(|| format!("Failed to read '{}'", path))? {
+            let entry = entry.with_context(|| format!("Reading entry in directory {}", path))?;
+            let Ok(entry_path) = check_path_buf(entry.path()) else {
+                log::warn!("Path is not valid UTF-8: {:?}", entry.path());
+                continue;
+            };
+            if matches!(entry_path.extension(), Some("yml" | "yaml")) {
+                files.push(entry_path);
+            }
+        }
+        files.sort();
+    } else {
+        files.push(path.to_path_buf());
+    }
+    let mut signatures = vec![];
+    for file in &files {
+        let sig_str =
+            fs::read_to_string(file).with_context(|| format!("Failed to read '{}'", file))?;
+        signatures.extend(
+            parse_signatures(&sig_str).with_context(|| format!("Failed to parse '{}'", file))?,
+        );
+    }
+    Ok((signatures, files))
+}
+
 pub fn check_signatures_str(
     section: &ObjSection,
     addr: u32,
@@ -84,7 +120,7 @@ pub fn check_signatures_str(
 pub fn check_signatures(
     section: &ObjSection,
     addr: u32,
-    signatures: &Vec<FunctionSignature>,
+    signatures: &[FunctionSignature],
 ) -> Result<Option<FunctionSignature>> {
     let data = section.data_range(addr, 0)?;
     let mut name = None;
@@ -248,10 +284,6 @@ pub fn generate_signature(
     path: &Utf8NativePath,
     symbol_name: &str,
 ) -> Result<Option<FunctionSignature>> {
-    let mut out_symbols: Vec<OutSymbol> = Vec::new();
-    let mut out_relocs: Vec<OutReloc> = Vec::new();
-    let mut symbol_map: BTreeMap<SymbolIndex, u32> = BTreeMap::new();
-
     let mut obj = process_elf(path)?;
     if obj.kind == ObjKind::Executable
         && (obj.sda2_base.is_none()
@@ -281,110 +313,164 @@ pub fn generate_signature(
         tracker.process_function(&obj, symbol)?;
     }
     tracker.apply(&mut obj, true)?; // true
-    for (_, symbol) in obj.symbols.by_kind(ObjSymbolKind::Function) {
+    for (symbol_idx, symbol) in obj.symbols.by_kind(ObjSymbolKind::Function) {
         if symbol.name != symbol_name && symbol.name != symbol_name.replace("TRK", "TRK_") {
             continue;
         }
-        let section_idx = symbol.section.unwrap();
-        let section = &obj.sections[section_idx];
-        // let out_symbol_idx = out_symbols.len();
-        out_symbols.push(OutSymbol {
-            kind: symbol.kind,
-            name: symbol.name.clone(),
-            size: symbol.size as u32,
-            flags: symbol.flags,
-            section: Some(section.name.clone()),
-        });
-        // println!(
-        //     "Building signature for {} ({:#010X}-{:#010X})",
-        //     symbol.name,
-        //     symbol.address,
-        //     symbol.address + symbol.size
-        // );
-        let mut instructions = section.data[(symbol.address - section.address) as usize
-            ..(symbol.address - section.address + symbol.size) as usize]
-            .chunks_exact(4)
-            .map(|c| (u32::from_be_bytes(c.try_into().unwrap()), !0u32))
-            .collect::<Vec<(u32, u32)>>();
-        for (idx, (ins, pat)) in instructions.iter_mut().enumerate() {
-            let addr = (symbol.address as usize + idx * 4) as u32;
-            if let Some(reloc) = section.relocations.at(addr) {
-                let symbol_idx = match symbol_map.entry(reloc.target_symbol) {
-                    btree_map::Entry::Vacant(e) => {
-                        let target = &obj.symbols[reloc.target_symbol];
-                        let symbol_idx = out_symbols.len() as u32;
-                        e.insert(symbol_idx);
-                        out_symbols.push(OutSymbol {
-                            kind: target.kind,
-                            name: target.name.clone(),
-                            size: if target.kind == ObjSymbolKind::Function {
-                                0
-                            } else {
-                                target.size as u32
-                            },
-                            flags: target.flags,
-                            section: target
-                                .section
-                                .and_then(|idx| obj.sections.get(idx))
-                                .map(|section| section.name.clone()),
-                        });
-                        symbol_idx
-                    }
-                    btree_map::Entry::Occupied(e) => *e.get(),
-                };
-                match reloc.kind {
-                    ObjRelocKind::Absolute => {
-                        *ins = 0;
-                        *pat = 0;
-                    }
-                    ObjRelocKind::PpcAddr16Hi
-                    | ObjRelocKind::PpcAddr16Ha
-                    | ObjRelocKind::PpcAddr16Lo => {
-                        *ins &= !0xFFFF;
-                        *pat = !0xFFFF;
-                    }
-                    ObjRelocKind::PpcRel24 => {
-                        *ins &= !0x3FFFFFC;
-                        *pat = !0x3FFFFFC;
-                    }
-                    ObjRelocKind::PpcRel14 => {
-                        *ins &= !0xFFFC;
-                        *pat = !0xFFFC;
-                    }
-                    ObjRelocKind::PpcEmbSda21 => {
-                        *ins &= !0x1FFFFF;
-                        *pat = !0x1FFFFF;
-                    }
-                }
-                out_relocs.push(OutReloc {
-                    offset: addr - (symbol.address as u32),
-                    kind: reloc.kind,
-                    symbol: symbol_idx,
-                    addend: reloc.addend as i32,
-                });
-            }
+        return build_signature(&obj, symbol_idx).map(Some);
+    }
+    Ok(None)
+}
+
+/// Generates signatures for every function in a relocatable object, using its relocations.
+/// Functions smaller than `min_size` bytes are skipped.
+pub fn generate_signatures(obj: &ObjInfo, min_size: u32) -> Result<Vec<FunctionSignature>> {
+    ensure!(obj.kind == ObjKind::Relocatable, "Expected relocatable object");
+    let mut out = vec![];
+    for (symbol_idx, symbol) in obj.symbols.by_kind(ObjSymbolKind::Function) {
+        if symbol.section.is_none() || symbol.size < min_size as u64 {
+            continue;
         }
+        out.push(build_signature(obj, symbol_idx)?);
+    }
+    Ok(out)
+}
+
+fn build_signature(obj: &ObjInfo, symbol_idx: SymbolIndex) -> Result<FunctionSignature> {
+    let mut out_symbols: Vec<OutSymbol> = Vec::new();
+    let mut out_relocs: Vec<OutReloc> = Vec::new();
+    let mut symbol_map: BTreeMap<SymbolIndex, u32> = BTreeMap::new();
 
-        let mut data = vec![0u8; instructions.len() * 8];
-        for (idx, &(ins, pat)) in instructions.iter().enumerate() {
-            data[idx * 8..idx * 8 + 4].copy_from_slice(&ins.to_be_bytes());
-            data[idx * 8 + 4..idx * 8 + 8].copy_from_slice(&pat.to_be_bytes());
+    let symbol = &obj.symbols[symbol_idx];
+    let section_idx =
+        symbol.section.ok_or_else(|| anyhow!("Missing section for symbol {}", symbol.name))?;
+    let section = &obj.sections[section_idx];
+    out_symbols.push(OutSymbol {
+        kind: symbol.kind,
+        name: symbol.name.clone(),
+        size: symbol.size as u32,
+        flags: symbol.flags,
+        section: Some(section.name.clone()),
+    });
+    // println!(
+    //     "Building signature for {} ({:#010X}-{:#010X})",
+    //     symbol.name,
+    //     symbol.address,
+    //     symbol.address + symbol.size
+    // );
+    let mut instructions = section.data[(symbol.address - section.address) as usize
+        ..(symbol.address - section.address + symbol.size) as usize]
+        .chunks_exact(4)
+        .map(|c| (u32::from_be_bytes(c.try_into().unwrap()), !0u32))
+        .collect::<Vec<(u32, u32)>>();
+    for (idx, (ins, pat)) in instructions.iter_mut().enumerate() {
+        let addr = (symbol.address as usize + idx * 4) as u32;
+        if let Some(reloc) = section.relocations.at(addr) {
+            match reloc.kind {
+                ObjRelocKind::Absolute => {
+                    *ins = 0;
+                    *pat = 0;
+                }
+                ObjRelocKind::PpcAddr16Hi
+                | ObjRelocKind::PpcAddr16Ha
+                | ObjRelocKind::PpcAddr16Lo => {
+                    *ins &= !0xFFFF;
+                    *pat = !0xFFFF;
+                }
+                ObjRelocKind::PpcRel24 => {
+                    *ins &= !0x3FFFFFC;
+                    *pat = !0x3FFFFFC;
+                }
+                ObjRelocKind::PpcRel14 => {
+                    *ins &= !0xFFFC;
+                    *pat = !0xFFFC;
+                }
+                ObjRelocKind::PpcEmbSda21 => {
+                    *ins &= !0x1FFFFF;
+                    *pat = !0x1FFFFF;
+                }
+            }
+            // Relocations against section symbols (in relocatable objects) are mapped to the
+            // symbol at the target address. If there isn't one, the instruction is still masked,
+            // but the relocation is left out of the signature.
+            let (target_symbol, addend) = match obj.symbols[reloc.target_symbol].kind {
+                ObjSymbolKind::Section => {
+                    let target_section = obj.symbols[reloc.target_symbol]
+                        .section
+                        .ok_or_else(|| anyhow!("Section symbol without section"))?;
+                    let target_addr =
+                        (obj.symbols[reloc.target_symbol].address as i64 + reloc.addend) as u32;
+                    // Prefer a symbol starting at the target, then one containing it
+                    let candidates = obj
+                        .symbols
+                        .for_section(target_section)
+                        .filter(|(_, sym)| sym.kind != ObjSymbolKind::Section)
+                        .collect_vec();
+                    match candidates
+                        .iter()
+                        .find(|(_, sym)| sym.address == target_addr as u64)
+                        .or_else(|| {
+                            candidates.iter().find(|(_, sym)| {
+                                (sym.address..sym.address + sym.size)
+                                    .contains(&(target_addr as u64))
+                            })
+                        }) {
+                        Some(&(idx, target)) => (idx, target_addr as i64 - target.address as i64),
+                        None => continue,
+                    }
+                }
+                _ => (reloc.target_symbol, reloc.addend),
+            };
+            let out_symbol_idx = match symbol_map.entry(target_symbol) {
+                btree_map::Entry::Vacant(e) => {
+                    let target = &obj.symbols[target_symbol];
+                    let out_symbol_idx = out_symbols.len() as u32;
+                    e.insert(out_symbol_idx);
+                    out_symbols.push(OutSymbol {
+                        kind: target.kind,
+                        name: target.name.clone(),
+                        size: if target.kind == ObjSymbolKind::Function {
+                            0
+                        } else {
+                            target.size as u32
+                        },
+                        flags: target.flags,
+                        section: target
+                            .section
+                            .and_then(|idx| obj.sections.get(idx))
+                            .map(|section| section.name.clone()),
+                    });
+                    out_symbol_idx
+                }
+                btree_map::Entry::Occupied(e) => *e.get(),
+            };
+            out_relocs.push(OutReloc {
+                offset: addr - (symbol.address as u32),
+                kind: reloc.kind,
+                symbol: out_symbol_idx,
+                addend: addend as i32,
+            });
         }
+    }
 
-        let encoded = STANDARD.encode(&data);
-        let mut hasher = Sha1::new();
-        hasher.update(&data);
-        let hash = hasher.finalize();
-        let mut hash_buf = [0u8; 40];
-        let hash_str = base16ct::lower::encode_str(&hash, &mut hash_buf)
-            .map_err(|e| anyhow!("Failed to encode hash: {e}"))?;
-        return Ok(Some(FunctionSignature {
-            symbol: 0,
-            hash: hash_str.to_string(),
-            signature: encoded,
-            symbols: out_symbols,
-            relocations: out_relocs,
-        }));
+    let mut data = vec![0u8; instructions.len() * 8];
+    for (idx, &(ins, pat)) in instructions.iter().enumerate() {
+        data[idx * 8..idx * 8 + 4].copy_from_slice(&ins.to_be_bytes());
+        data[idx * 8 + 4..idx * 8 + 8].copy_from_slice(&pat.to_be_bytes());
     }
-    Ok(None)
+
+    let encoded = STANDARD.encode(&data);
+    let mut hasher = Sha1::new();
+    hasher.update(&data);
+    let hash = hasher.finalize();
+    let mut hash_buf = [0u8; 40];
+    let hash_str = base16ct::lower::encode_str(&hash, &mut hash_buf)
+        .map_err(|e| anyhow!("Failed to encode hash: {e}"))?;
+    Ok(FunctionSignature {
+        symbol: 0,
+        hash: hash_str.to_string(),
+        signature: encoded,
+        symbols: out_symbols,
+        relocations: out_relocs,
+    })
 }
@@ -305,7 +305,7 @@ fn split_extabindex(obj: &mut ObjInfo, start: SectionAddress) -> Result<()> {
 }
 
 /// Create splits for gaps between existing splits.
-fn create_gap_splits(obj: &mut ObjInfo) -> Result<()> {
+pub fn create_gap_splits(obj: &mut ObjInfo) -> Result<()> {
     let mut new_splits = BTreeMap::<SectionAddress, ObjSplit>::new();
 
     for (section_index, section) in obj.sections.iter() {
@@ -809,23 +809,19 @@ fn trim_linker_generated_symbols(obj: &mut ObjInfo) -> Result<()> {
     Ok(())
 }
 
-/// Perform any necessary adjustments to allow relinking.
-/// This includes:
-/// - Ensuring .ctors & .dtors entries are split with their associated function
-/// - Ensuring extab & extabindex entries are split with their associated function
-/// - Creating splits for gaps between existing splits
-/// - Resolving a new object link order
-#[instrument(level = "debug", skip(obj))]
-pub fn update_splits(obj: &mut ObjInfo, common_start: Option<u32>, fill_gaps: bool) -> Result<()> {
-    // Create splits for extab and extabindex entries
+/// Create splits for extab and extabindex entries with their associated functions.
+pub fn split_extabindex_section(obj: &mut ObjInfo) -> Result<()> {
     if let Some((section_index, section)) = obj.sections.by_name("extabindex")? {
         if !section.data.is_empty() {
             let start = SectionAddress::new(section_index, section.address as u32);
             split_extabindex(obj, start)?;
         }
     }
+    Ok(())
+}
 
-    // Create splits for .ctors entries
+/// Create splits for .ctors and .dtors entries with their associated functions.
+pub fn split_ctors_dtors_sections(obj: &mut ObjInfo) -> Result<()> {
     if let Some((section_index, section)) = obj.sections.by_name(".ctors")? {
         if !section.data.is_empty() {
             let start = SectionAddress::new(section_index, section.address as u32);
@@ -833,8 +829,6 @@ pub fn update_splits(obj: &mut ObjInfo, common_start: Option<u32>, fill_gaps: bo
             split_ctors_dtors(obj, start, end)?;
         }
     }
-
-    // Create splits for .dtors entries
     if let Some((section_index, section)) = obj.sections.by_name(".dtors")? {
         if !section.data.is_empty() {
             let mut start = SectionAddress::new(section_index, section.address as u32);
@@ -846,6 +840,22 @@ pub fn update_splits(obj: &mut ObjInfo, common_start: Option<u32>, fill_gaps: bo
             split_ctors_dtors(obj, start, end)?;
         }
     }
+    Ok(())
+}
+
+/// Perform any necessary adjustments to allow relinking.
+/// This includes:
+/// - Ensuring .ctors & .dtors entries are split with their associated function
+/// - Ensuring extab & extabindex entries are split with their associated function
+/// - Creating splits for gaps between existing splits
+/// - Resolving a new object link order
+#[instrument(level = "debug", skip(obj))]
+pub fn update_splits(obj: &mut ObjInfo, common_start: Option<u32>, fill_gaps: bool) -> Result<()> {
+    // Create splits for extab and extabindex entries
+    split_extabindex_section(obj)?;
+
+    // Create splits for .ctors and .dtors entries
+    split_ctors_dtors_sections(obj)?;
 
     // Remove linker generated symbols from splits
     trim_linker_generated_symbols(obj)?;
@@ -1,10 +1,14 @@
 use std::{borrow::Cow, ffi::CStr, mem::size_of};
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, ensure, Result};
+use md5::{Digest, Md5};
 use typed_path::Utf8UnixPath;
-use zerocopy::{big_endian::U32, FromBytes, Immutable, IntoBytes, KnownLayout};
+use zerocopy::{
+    big_endian::{U16, U32},
+    FromBytes, FromZeros, Immutable, IntoBytes, KnownLayout,
+};
 
-use crate::{static_assert, vfs::next_non_empty};
+use crate::{static_assert, util::align_up, vfs::next_non_empty};
 
 pub const U8_MAGIC: [u8; 4] = [0x55, 0xAA, 0x38, 0x2D];
 
@@ -196,3 +200,311 @@ impl<'a> Iterator for U8Iter<'a> {
         Some((idx, node, name))
     }
 }
+
+/// An entry to be written to a U8 archive.
+#[derive(Clone, Debug)]
+pub enum U8Entry {
+    File { name: String, data: Vec<u8> },
+    Directory { name: String, entries: Vec<U8Entry> },
+}
+
+fn collect_u8_nodes<'a>(
+    entries: &'a [U8Entry],
+    parent: usize,
+    nodes: &mut Vec<U8Node>,
+    strings: &mut Vec<u8>,
+    files: &mut Vec<(usize, &'a [u8])>,
+) -> Result<()> {
+    for entry in entries {
+        let (name, is_dir) = match entry {
+            U8Entry::File { name, .. } => (name, false),
+            U8Entry::Directory { name, .. } => (name, true),
+        };
+        let name_offset = strings.len() as u32;
+        ensure!(name_offset < 0x1000000, "U8 string table too large");
+        strings.extend_from_slice(name.as_bytes());
+        strings.push(0);
+        let index = nodes.len();
+        nodes.push(U8Node {
+            kind: is_dir as u8,
+            name_offset: name_offset.to_be_bytes()[1..].try_into().unwrap(),
+            offset: U32::new(if is_dir { parent as u32 } else { 0 }),
+            length: U32::new(0),
+        });
+        match entry {
+            U8Entry::File { data, .. } => {
+                nodes[index].length = U32::new(data.len() as u32);
+                files.push((index, data));
+            }
+            U8Entry::Directory { entries, .. } => {
+                collect_u8_nodes(entries, index, nodes, strings, files)?;
+                nodes[index].length = U32::new(nodes.len() as u32);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes a U8 archive containing `entries` in the root directory.
+///
+/// Nodes are written depth-first in the given order, followed by the string table. The data
+/// area starts on a 64-byte boundary and each file is aligned to 32 bytes.
+pub fn write_u8(entries: &[U8Entry]) -> Result<Vec<u8>> {
+    let mut nodes =
+        vec![U8Node { kind: 1, name_offset: [0; 3], offset: U32::new(0), length: U32::new(0) }];
+    let mut strings = vec![0u8];
+    let mut files = vec![];
+    collect_u8_nodes(entries, 0, &mut nodes, &mut strings, &mut files)?;
+    nodes[0].length = U32::new(nodes.len() as u32);
+
+    let node_table_offset = size_of::<U8Header>() as u32;
+    let node_table_size = (nodes.len() * size_of::<U8Node>() + strings.len()) as u32;
+    let data_offset = align_up(node_table_offset + node_table_size, 0x40);
+    let mut offset = data_offset;
+    for &(index, data) in &files {
+        offset = align_up(offset, 0x20);
+        nodes[index].offset = U32::new(offset);
+        offset =
+            offset.checked_add(data.len() as u32).ok_or_else(|| anyhow!("U8 archive too large"))?;
+    }
+
+    let header = U8Header {
+        magic: U8_MAGIC,
+        node_table_offset: U32::new(node_table_offset),
+        node_table_size: U32::new(node_table_size),
+        data_offset: U32::new(data_offset),
+        _pad: [0; 16],
+    };
+    let mut out = Vec::with_capacity(offset as usize);
+    out.extend_from_slice(header.as_bytes());
+    out.extend_from_slice(nodes.as_bytes());
+    out.extend_from_slice(&strings);
+    for (index, data) in files {
+        out.resize(nodes[index].offset() as usize, 0);
+        out.extend_from_slice(data);
+    }
+    out.resize(offset as usize, 0);
+    Ok(out)
+}
+
+fn md5(data: &[u8]) -> [u8; 16] { Md5::digest(data).into() }
+
+pub const IMD5_MAGIC: [u8; 4] = *b"IMD5";
+pub const IMET_MAGIC: [u8; 4] = *b"IMET";
+
+/// Header of `banner.bin`, `icon.bin` and `sound.bin` in a channel banner.
+#[derive(Clone, Debug, PartialEq, FromBytes, IntoBytes, Immutable, KnownLayout)]
+#[repr(C, align(4))]
+pub struct Imd5Header {
+    /// Magic identifier. (Always "IMD5")
+    magic: [u8; 4],
+    /// Length of the data following the header.
+    size: U32,
+    _pad: [u8; 8],
+    /// MD5 digest of the data following the header.
+    md5: [u8; 16],
+}
+
+static_assert!(size_of::<Imd5Header>() == 0x20);
+
+/// Number of languages in an IMET header.
+pub const IMET_LANGUAGES: usize = 10;
+/// Length of each title in an IMET header, in UTF-16 code units.
+pub const IMET_TITLE_LEN: usize = 42;
+
+/// Header of `opening.bnr`, preceding the U8 archive.
+///
+/// The header inside a channel's content 0 is preceded by 0x40 more bytes, which are not
+/// covered by the digest.
+#[derive(Clone, Debug, PartialEq, FromBytes, IntoBytes, Immutable, KnownLayout)]
+#[repr(C, align(4))]
+pub struct ImetHeader {
+    _zeroes: [u8; 0x40],
+    /// Magic identifier. (Always "IMET")
+    magic: [u8; 4],
+    /// Length of the header covered by the digest. (Always 0x600)
+    hash_size: U32,
+    /// Unknown. (Always 3)
+    _unk: U32,
+    /// Sizes of `icon.bin`, `banner.bin` and `sound.bin`.
+    sizes: [U32; 3],
+    /// Unknown flags.
+    flags: U32,
+    /// Channel titles: Japanese, English, German, French, Spanish, Italian, Dutch,
+    /// Simplified Chinese, Traditional Chinese and Korean.
+    titles: [[U16; IMET_TITLE_LEN]; IMET_LANGUAGES],
+    _pad: [u8; 0x24C],
+    /// MD5 digest of the header, computed with this field zeroed.
+    md5: [u8; 16],
+}
+
+static_assert!(size_of::<ImetHeader>() == 0x600);
+
+impl ImetHeader {
+    /// Creates a header with the given titles, in language order.
+    pub fn new(titles: &[String]) -> Result<Self> {
+        ensure!(titles.len() <= IMET_LANGUAGES, "Too many IMET titles (max {})", IMET_LANGUAGES);
+        let mut header = Self::new_zeroed();
+        header.magic = IMET_MAGIC;
+        header.hash_size = U32::new(size_of::<Self>() as u32);
+        header._unk = U32::new(3);
+        for (dst, title) in header.titles.iter_mut().zip(titles) {
+            let units = title.encode_utf16().collect::<Vec<_>>();
+            ensure!(
+                units.len() < IMET_TITLE_LEN,
+                "IMET title '{}' too long (max {} characters)",
+                title,
+                IMET_TITLE_LEN - 1
+            );
+            for (dst, unit) in dst.iter_mut().zip(units) {
+                *dst = U16::new(unit);
+            }
+        }
+        Ok(header)
+    }
+
+    /// Channel titles in language order.
+    pub fn titles(&self) -> Vec<String> {
+        self.titles
+            .iter()
+            .map(|title| {
+                let units = title.iter().map(|u| u.get()).take_while(|&u| u != 0);
+                char::decode_utf16(units)
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Sets the sizes of `icon.bin`, `banner.bin` and `sound.bin`.
+    pub fn set_sizes(&mut self, sizes: [u32; 3]) { self.sizes = sizes.map(U32::new); }
+
+    fn digest(&self) -> [u8; 16] {
+        let mut header = self.clone();
+        header.md5 = [0; 16];
+        md5(header.as_bytes())
+    }
+}
+
+/// Prepends an IMD5 header to `data`.
+pub fn add_imd5(data: &[u8]) -> Vec<u8> {
+    let header = Imd5Header {
+        magic: IMD5_MAGIC,
+        size: U32::new(data.len() as u32),
+        _pad: [0; 8],
+        md5: md5(data),
+    };
+    let mut out = Vec::with_capacity(size_of::<Imd5Header>() + data.len());
+    out.extend_from_slice(header.as_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+/// Prepends an IMET header to `data`, updating its digest. `prefix` is written before the
+/// header, as in a channel's content 0.
+pub fn add_imet(mut header: ImetHeader, prefix: &[u8], data: &[u8]) -> Vec<u8> {
+    header.md5 = header.digest();
+    let mut out = Vec::with_capacity(prefix.len() + size_of::<ImetHeader>() + data.len());
+    out.extend_from_slice(prefix);
+    out.extend_from_slice(header.as_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+/// A header removed by [`strip_header`].
+pub enum StrippedHeader<'a> {
+    Imd5(&'a Imd5Header),
+    /// IMET header, with the bytes preceding it in a channel's content 0.
+    Imet {
+        header: &'a ImetHeader,
+        prefix: &'a [u8],
+    },
+}
+
+/// Removes an IMET or IMD5 header from `data`, verifying its digest.
+pub fn strip_header(data: &[u8]) -> Result<(StrippedHeader, &[u8])> {
+    if data.starts_with(&IMD5_MAGIC) {
+        let (header, rest) =
+            Imd5Header::ref_from_prefix(data).map_err(|_| anyhow!("IMD5 header truncated"))?;
+        let payload = rest
+            .get(..header.size.get() as usize)
+            .ok_or_else(|| anyhow!("IMD5 size {:#X} out of bounds", header.size.get()))?;
+        ensure!(md5(payload) == header.md5, "IMD5 digest mismatch");
+        return Ok((StrippedHeader::Imd5(header), payload));
+    }
+    for prefix_len in [0, 0x40] {
+        let Some((prefix, buf)) = data.split_at_checked(prefix_len) else { continue };
+        let Ok((header, rest)) = ImetHeader::ref_from_prefix(buf) else { continue };
+        if header.magic != IMET_MAGIC {
+            continue;
+        }
+        ensure!(header.digest() == header.md5, "IMET digest mismatch");
+        return Ok((StrippedHeader::Imet { header, prefix }, rest));
+    }
+    bail!("No IMET or IMD5 header found")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_u8() {
+        let entries = vec![
+            U8Entry::File { name: "b.bin".to_string(), data: vec![1, 2, 3] },
+            U8Entry::Directory {
+                name: "dir".to_string(),
+                entries: vec![
+                    U8Entry::File { name: "c.bin".to_string(), data: vec![4; 0x21] },
+                    U8Entry::Directory { name: "empty".to_string(), entries: vec![] },
+                ],
+            },
+            U8Entry::File { name: "a.bin".to_string(), data: vec![] },
+        ];
+        let data = write_u8(&entries).unwrap();
+        let view = U8View::new(&data).unwrap();
+        assert_eq!(view.header.data_offset.get() % 0x40, 0);
+
+        // Nodes are read back depth-first in the order given
+        let nodes = view
+            .iter()
+            .map(|(idx, node, name)| {
+                let name = name.unwrap().into_owned();
+                let contents = if node.is_file() {
+                    assert_eq!(node.offset() % 0x20, 0);
+                    data[node.offset() as usize..(node.offset() + node.length()) as usize].to_vec()
+                } else {
+                    vec![]
+                };
+                (idx, name, node.is_dir(), node.offset(), node.length(), contents)
+            })
+            .collect::<Vec<_>>();
+        let files = nodes
+            .iter()
+            .map(|(idx, name, is_dir, _, _, contents)| (*idx, name.as_str(), *is_dir, contents))
+            .collect::<Vec<_>>();
+        assert_eq!(files, [
+            (1, "b.bin", false, &vec![1, 2, 3]),
+            (2, "dir", true, &vec![]),
+            (3, "c.bin", false, &vec![4; 0x21]),
+            (4, "empty", true, &vec![]),
+            (5, "a.bin", false, &vec![]),
+        ]);
+        // Directories hold their parent index and the index past their last child
+        assert_eq!((nodes[1].3, nodes[1].4), (0, 5));
+        assert_eq!((nodes[3].3, nodes[3].4), (2, 5));
+
+        let (idx, node) = view.find(Utf8UnixPath::new("dir/c.bin")).unwrap();
+        assert_eq!((idx, node.length()), (3, 0x21));
+        assert!(view.find(Utf8UnixPath::new("dir/a.bin")).is_none());
+    }
+
+    #[test]
+    fn test_imd5() {
+        let data = add_imd5(b"banner");
+        assert_eq!(&data[0x10..0x20], hex::decode("12df53fea8b3adfa6c2ec456dd22e204").unwrap());
+        let (header, payload) = strip_header(&data).unwrap();
+        assert!(matches!(header, StrippedHeader::Imd5(_)));
+        assert_eq!(payload, b"banner");
+    }
+}
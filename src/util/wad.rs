@@ -1,22 +1,62 @@
 use std::{
-    io,
-    io::{BufRead, Read, Seek},
+    fmt, io,
+    io::{BufRead, Read, Seek, Write},
 };
 
-use aes::cipher::{BlockDecryptMut, KeyIvInit};
-use anyhow::{bail, Result};
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use anyhow::{anyhow, bail, ensure, Result};
 use nodtool::nod::{Ticket, TmdHeader};
 use sha1::{Digest, Sha1};
 use size::Size;
 use zerocopy::{big_endian::*, FromBytes, FromZeros, Immutable, IntoBytes, KnownLayout};
 
 use crate::{
-    array_ref_mut, static_assert,
+    array_ref, array_ref_mut, static_assert,
     util::read::{read_box_slice, read_from},
 };
 
-// TODO: other WAD types?
+/// Installable WAD (`Is`)
 pub const WAD_MAGIC: [u8; 8] = [0x00, 0x00, 0x00, 0x20, 0x49, 0x73, 0x00, 0x00];
+/// Boot2 WAD (`ib`)
+pub const WAD_BOOT2_MAGIC: [u8; 8] = [0x00, 0x00, 0x00, 0x20, 0x69, 0x62, 0x00, 0x00];
+/// Backup WAD (`Bk`), as written to the SD card for DLC
+pub const WAD_BACKUP_MAGIC: [u8; 8] = [0x00, 0x00, 0x00, 0x70, 0x42, 0x6B, 0x00, 0x01];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WadKind {
+    Installable,
+    Boot2,
+    Backup,
+}
+
+impl WadKind {
+    pub fn from_type(wad_type: [u8; 2]) -> Option<Self> {
+        match &wad_type {
+            b"Is" => Some(Self::Installable),
+            b"ib" => Some(Self::Boot2),
+            b"Bk" => Some(Self::Backup),
+            _ => None,
+        }
+    }
+
+    pub fn wad_type(self) -> [u8; 2] {
+        match self {
+            Self::Installable => *b"Is",
+            Self::Boot2 => *b"ib",
+            Self::Backup => *b"Bk",
+        }
+    }
+}
+
+impl fmt::Display for WadKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Installable => write!(f, "Installable (Is)"),
+            Self::Boot2 => write!(f, "Boot2 (ib)"),
+            Self::Backup => write!(f, "Backup (Bk)"),
+        }
+    }
+}
 
 #[derive(Clone, Debug, PartialEq, FromBytes, IntoBytes, Immutable, KnownLayout)]
 #[repr(C, align(4))]
@@ -34,6 +74,28 @@ pub struct WadHeader {
 
 static_assert!(size_of::<WadHeader>() == 0x20);
 
+/// Header of a backup (`Bk`) WAD. These have no certificate chain or ticket, and only
+/// contain the contents marked in `included_contents`.
+#[derive(Clone, Debug, PartialEq, FromBytes, IntoBytes, Immutable, KnownLayout)]
+#[repr(C, align(4))]
+pub struct BackupWadHeader {
+    pub header_size: U32,
+    pub wad_type: [u8; 0x2],
+    pub wad_version: U16,
+    pub console_id: U32,
+    pub save_file_count: U32,
+    pub save_file_data_size: U32,
+    pub tmd_size: U32,
+    pub data_size: U32,
+    pub backup_area_size: U32,
+    pub included_contents: [u8; 0x40],
+    pub title_id: [u8; 0x8],
+    pub mac_address: [u8; 0x6],
+    pub _pad: [u8; 0x2],
+}
+
+static_assert!(size_of::<BackupWadHeader>() == 0x70);
+
 #[derive(Clone, Debug, PartialEq, FromBytes, IntoBytes, Immutable, KnownLayout)]
 #[repr(C, align(4))]
 pub struct ContentMetadata {
@@ -64,10 +126,23 @@ pub type HashBytes = [u8; 20];
 pub type KeyBytes = [u8; 16];
 
 type Aes128Cbc = cbc::Decryptor<aes::Aes128>;
+type Aes128CbcEnc = cbc::Encryptor<aes::Aes128>;
+
+/// Offset of the title ID in the TMD
+const TMD_TITLE_ID_OFFSET: usize = 0x18C;
+/// Start of the signed area of tickets and TMDs (RSA-2048 signatures)
+const SIGNED_DATA_OFFSET: usize = 0x140;
+/// Unused TMD bytes modified when fakesigning
+const TMD_FAKESIGN_OFFSET: usize = 0x1D4;
+/// Unused ticket bytes modified when fakesigning
+const TICKET_FAKESIGN_OFFSET: usize = 0x21E;
 
 #[derive(Debug, Clone)]
 pub struct WadFile {
+    pub kind: WadKind,
     pub header: WadHeader,
+    /// Only set for backup WADs
+    pub backup_header: Option<BackupWadHeader>,
     pub title_key: KeyBytes,
     pub fake_signed: bool,
     pub raw_cert_chain: Box<[u8]>,
@@ -77,10 +152,39 @@ pub struct WadFile {
 }
 
 impl WadFile {
+    /// Backup WADs don't contain a ticket, so this must not be called for them.
     pub fn ticket(&self) -> &Ticket {
         Ticket::ref_from_bytes(&self.raw_ticket).expect("Invalid ticket alignment")
     }
 
+    pub fn has_ticket(&self) -> bool { !self.raw_ticket.is_empty() }
+
+    pub fn title_id(&self) -> [u8; 8] {
+        match &self.backup_header {
+            Some(header) => header.title_id,
+            None => *array_ref!(self.raw_tmd, TMD_TITLE_ID_OFFSET, 8),
+        }
+    }
+
+    pub fn footer_size(&self) -> u64 {
+        match self.kind {
+            WadKind::Backup => 0,
+            _ => self.header.footer_size.get() as u64,
+        }
+    }
+
+    /// Whether the content at the given position is stored in the WAD.
+    /// Backup WADs only contain a subset of the title's contents.
+    pub fn is_included(&self, content_index: u16) -> bool {
+        match &self.backup_header {
+            Some(header) => header
+                .included_contents
+                .get(content_index as usize / 8)
+                .is_some_and(|&b| b & (1 << (content_index % 8)) != 0),
+            None => true,
+        }
+    }
+
     pub fn tmd(&self) -> &TmdHeader {
         TmdHeader::ref_from_prefix(&self.raw_tmd).expect("Invalid TMD alignment").0
     }
@@ -94,7 +198,10 @@ impl WadFile {
     pub fn content_offset(&self, content_index: u16) -> u64 {
         let contents = self.contents();
         let mut offset = self.content_offset;
-        for content in contents.iter().take(content_index as usize) {
+        for (i, content) in contents.iter().enumerate().take(content_index as usize) {
+            if !self.is_included(i as u16) {
+                continue;
+            }
             offset = align_up(offset + content.size.get(), ALIGNMENT as u64);
         }
         offset
@@ -103,7 +210,10 @@ impl WadFile {
     pub fn trailer_offset(&self) -> u64 {
         let contents = self.contents();
         let mut offset = self.content_offset;
-        for content in contents.iter() {
+        for (i, content) in contents.iter().enumerate() {
+            if !self.is_included(i as u16) {
+                continue;
+            }
             offset = align_up(offset + content.size.get(), ALIGNMENT as u64);
         }
         offset
@@ -113,6 +223,12 @@ impl WadFile {
 pub fn process_wad<R>(reader: &mut R) -> Result<WadFile>
 where R: BufRead + Seek + ?Sized {
     let header: WadHeader = read_from(reader)?;
+    let Some(kind) = WadKind::from_type(header.wad_type) else {
+        bail!("Unsupported WAD type {:?}", String::from_utf8_lossy(&header.wad_type));
+    };
+    if kind == WadKind::Backup {
+        return process_backup_wad(reader, header);
+    }
     let mut offset = align_up(header.header_size.get() as u64, ALIGNMENT as u64);
 
     reader.seek(io::SeekFrom::Start(offset))?;
@@ -129,7 +245,9 @@ where R: BufRead + Seek + ?Sized {
 
     let content_offset = offset;
     let mut file = WadFile {
+        kind,
         header,
+        backup_header: None,
         title_key: [0; 16],
         fake_signed: false,
         raw_cert_chain,
@@ -169,8 +287,41 @@ where R: BufRead + Seek + ?Sized {
     Ok(file)
 }
 
+fn process_backup_wad<R>(reader: &mut R, header: WadHeader) -> Result<WadFile>
+where R: BufRead + Seek + ?Sized {
+    reader.seek(io::SeekFrom::Start(0))?;
+    let backup_header: BackupWadHeader = read_from(reader)?;
+    ensure!(
+        backup_header.save_file_count.get() == 0 && backup_header.save_file_data_size.get() == 0,
+        "Backup WADs containing save files are not supported"
+    );
+    let mut offset = align_up(backup_header.header_size.get() as u64, ALIGNMENT as u64);
+
+    reader.seek(io::SeekFrom::Start(offset))?;
+    let raw_tmd: Box<[u8]> = read_box_slice(reader, backup_header.tmd_size.get() as usize)?;
+    offset = align_up(offset + backup_header.tmd_size.get() as u64, ALIGNMENT as u64);
+
+    // Contents are encrypted with a console-specific key, so no title key is available
+    Ok(WadFile {
+        kind: WadKind::Backup,
+        header,
+        backup_header: Some(backup_header),
+        title_key: [0; 16],
+        fake_signed: false,
+        raw_cert_chain: Box::default(),
+        raw_ticket: Box::default(),
+        raw_tmd,
+        content_offset: offset,
+    })
+}
+
 pub fn verify_wad<R>(file: &WadFile, reader: &mut R) -> Result<()>
 where R: Read + Seek + ?Sized {
+    if file.kind == WadKind::Backup {
+        bail!(
+            "Backup WAD contents are encrypted with a console-specific key and cannot be verified"
+        );
+    }
     for content in file.contents() {
         let content_index = content.content_index.get();
         println!(
@@ -218,3 +369,201 @@ where
     }
     Ok(HashBytes::from(digest.finalize()) == content.hash)
 }
+
+/// Parses the content records following a raw TMD header.
+pub fn tmd_contents(raw_tmd: &[u8]) -> Result<&[ContentMetadata]> {
+    let cmd_data = raw_tmd.get(size_of::<TmdHeader>()..).ok_or_else(|| anyhow!("TMD too small"))?;
+    <[ContentMetadata]>::ref_from_bytes(cmd_data)
+        .map_err(|_| anyhow!("Invalid TMD content records"))
+}
+
+/// Parses the content records following a raw TMD header, for updating them.
+pub fn tmd_contents_mut(raw_tmd: &mut [u8]) -> Result<&mut [ContentMetadata]> {
+    let cmd_data =
+        raw_tmd.get_mut(size_of::<TmdHeader>()..).ok_or_else(|| anyhow!("TMD too small"))?;
+    <[ContentMetadata]>::mut_from_bytes(cmd_data)
+        .map_err(|_| anyhow!("Invalid TMD content records"))
+}
+
+/// Fakesigns a TMD, e.g. after updating its content records.
+pub fn fakesign_tmd(raw_tmd: &mut [u8]) -> Result<()> {
+    fakesign_signed_data(raw_tmd, TMD_FAKESIGN_OFFSET)
+}
+
+/// Inputs for building an installable or boot2 WAD.
+pub struct WadPackInput {
+    pub kind: WadKind,
+    pub raw_cert_chain: Box<[u8]>,
+    pub raw_ticket: Box<[u8]>,
+    pub raw_tmd: Box<[u8]>,
+    /// Decrypted contents, in TMD order
+    pub contents: Vec<Box<[u8]>>,
+    pub footer: Box<[u8]>,
+}
+
+/// Builds a WAD, encrypting contents with the title key from the ticket.
+///
+/// Content sizes and hashes in the TMD are updated to match the provided contents. If the
+/// TMD changes, the original signature becomes invalid, so `fakesign` should be set.
+pub fn write_wad<W>(w: &mut W, mut input: WadPackInput, fakesign: bool) -> Result<()>
+where W: Write + ?Sized {
+    ensure!(input.kind != WadKind::Backup, "Writing backup WADs is not supported");
+    let ticket = Ticket::ref_from_bytes(&input.raw_ticket)
+        .map_err(|_| anyhow!("Invalid ticket size {:#X}", input.raw_ticket.len()))?;
+    let title_key = ticket.decrypt_title_key()?;
+
+    let contents = tmd_contents_mut(&mut input.raw_tmd)?;
+    ensure!(
+        contents.len() == input.contents.len(),
+        "TMD lists {} contents, but {} were provided",
+        contents.len(),
+        input.contents.len()
+    );
+
+    let mut tmd_changed = false;
+    let mut encrypted = Vec::with_capacity(contents.len());
+    for (content, data) in contents.iter_mut().zip(&input.contents) {
+        let hash = HashBytes::from(Sha1::digest(data));
+        if content.size.get() != data.len() as u64 || content.hash != hash {
+            log::info!("Updating content {:08x}", content.content_index.get());
+            content.size.set(data.len() as u64);
+            content.hash = hash;
+            tmd_changed = true;
+        }
+        let mut buf = data.to_vec();
+        buf.resize(align_up(data.len() as u64, 0x10) as usize, 0);
+        let mut encryptor = Aes128CbcEnc::new((&title_key).into(), (&content.iv()).into());
+        for block in buf.chunks_exact_mut(0x10) {
+            encryptor.encrypt_block_mut(block.into());
+        }
+        encrypted.push(buf);
+    }
+
+    if fakesign {
+        fakesign_signed_data(&mut input.raw_ticket, TICKET_FAKESIGN_OFFSET)?;
+        fakesign_signed_data(&mut input.raw_tmd, TMD_FAKESIGN_OFFSET)?;
+    } else if tmd_changed {
+        log::warn!("TMD was modified without fakesigning; the signature is no longer valid");
+    }
+
+    let data_size =
+        encrypted.iter().map(|c| align_up(c.len() as u64, ALIGNMENT as u64)).sum::<u64>();
+    let header = WadHeader {
+        header_size: U32::new(size_of::<WadHeader>() as u32),
+        wad_type: input.kind.wad_type(),
+        wad_version: U16::new(0),
+        cert_chain_size: U32::new(input.raw_cert_chain.len() as u32),
+        _reserved1: [0; 4],
+        ticket_size: U32::new(input.raw_ticket.len() as u32),
+        tmd_size: U32::new(input.raw_tmd.len() as u32),
+        data_size: U32::new(
+            u32::try_from(data_size).map_err(|_| anyhow!("WAD contents too large"))?,
+        ),
+        footer_size: U32::new(input.footer.len() as u32),
+    };
+    write_aligned(w, header.as_bytes())?;
+    write_aligned(w, &input.raw_cert_chain)?;
+    write_aligned(w, &input.raw_ticket)?;
+    write_aligned(w, &input.raw_tmd)?;
+    for data in &encrypted {
+        write_aligned(w, data)?;
+    }
+    write_aligned(w, &input.footer)?;
+    Ok(())
+}
+
+fn write_aligned<W>(w: &mut W, data: &[u8]) -> io::Result<()>
+where W: Write + ?Sized {
+    w.write_all(data)?;
+    let padding = align_up(data.len() as u64, ALIGNMENT as u64) as usize - data.len();
+    w.write_all(&[0u8; ALIGNMENT][..padding])
+}
+
+/// Fakesigns a ticket or TMD: the signature is zeroed, and an unused field is modified until
+/// the SHA-1 of the signed data starts with a zero byte.
+fn fakesign_signed_data(data: &mut [u8], counter_offset: usize) -> Result<()> {
+    ensure!(data.len() > counter_offset + 4, "Signed data too small");
+    let sig_type = u32::from_be_bytes(*array_ref!(data, 0, 4));
+    ensure!(sig_type == 0x10001, "Unsupported signature type {:#X}", sig_type);
+    data[4..0x104].fill(0);
+    for counter in 0..=u32::MAX {
+        *array_ref_mut!(data, counter_offset, 4) = counter.to_be_bytes();
+        if Sha1::digest(&data[SIGNED_DATA_OFFSET..])[0] == 0 {
+            return Ok(());
+        }
+    }
+    bail!("Failed to fakesign data")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICKET_TITLE_KEY_OFFSET: usize = 0x1BF;
+
+    fn test_input() -> WadPackInput {
+        let mut raw_ticket = vec![0u8; size_of::<Ticket>()];
+        raw_ticket[..4].copy_from_slice(&0x10001u32.to_be_bytes());
+        raw_ticket[TICKET_TITLE_KEY_OFFSET..TICKET_TITLE_KEY_OFFSET + 16]
+            .copy_from_slice(&[0x5A; 16]);
+        // Two content records with outdated sizes and hashes
+        let mut raw_tmd = vec![0u8; size_of::<TmdHeader>() + 2 * size_of::<ContentMetadata>()];
+        raw_tmd[..4].copy_from_slice(&0x10001u32.to_be_bytes());
+        raw_tmd[TMD_TITLE_ID_OFFSET..TMD_TITLE_ID_OFFSET + 8]
+            .copy_from_slice(&[0, 1, 0, 1, b'T', b'E', b'S', b'T']);
+        for (i, content) in tmd_contents_mut(&mut raw_tmd).unwrap().iter_mut().enumerate() {
+            content.content_id.set(i as u32);
+            content.content_index.set(i as u16);
+            content.size.set(0x10);
+        }
+        WadPackInput {
+            kind: WadKind::Installable,
+            raw_cert_chain: vec![0xCC; 0x50].into(),
+            raw_ticket: raw_ticket.into(),
+            raw_tmd: raw_tmd.into(),
+            contents: vec![vec![1; 0x30].into(), vec![2; 0x25].into()],
+            footer: b"footer".as_slice().into(),
+        }
+    }
+
+    #[test]
+    fn test_write_wad() {
+        let mut data = vec![];
+        write_wad(&mut data, test_input(), true).unwrap();
+
+        let mut reader = io::Cursor::new(data);
+        let wad = process_wad(&mut reader).unwrap();
+        assert_eq!(wad.kind, WadKind::Installable);
+        assert!(wad.fake_signed);
+        assert_eq!(wad.title_id(), [0, 1, 0, 1, b'T', b'E', b'S', b'T']);
+        assert_eq!(&*wad.raw_cert_chain, &[0xCC; 0x50]);
+        let sizes = wad.contents().iter().map(|c| c.size.get()).collect::<Vec<_>>();
+        assert_eq!(sizes, [0x30, 0x25]);
+        assert_eq!(wad.contents()[1].hash, HashBytes::from(Sha1::digest([2u8; 0x25])));
+        verify_wad(&wad, &mut reader).unwrap();
+
+        // Fakesigned: zeroed signatures, and signed data hashes starting with 0
+        for raw in [&wad.raw_ticket, &wad.raw_tmd] {
+            assert!(raw[4..0x104].iter().all(|&b| b == 0));
+            assert_eq!(Sha1::digest(&raw[SIGNED_DATA_OFFSET..])[0], 0);
+        }
+
+        let mut footer = vec![0; 6];
+        reader.seek(io::SeekFrom::Start(wad.trailer_offset())).unwrap();
+        reader.read_exact(&mut footer).unwrap();
+        assert_eq!(footer, b"footer");
+    }
+
+    #[test]
+    fn test_fakesign_signed_data() {
+        let mut raw_tmd = test_input().raw_tmd;
+        raw_tmd[4..0x104].fill(0xFF);
+        fakesign_tmd(&mut raw_tmd).unwrap();
+        assert!(raw_tmd[4..0x104].iter().all(|&b| b == 0));
+        assert_eq!(Sha1::digest(&raw_tmd[SIGNED_DATA_OFFSET..])[0], 0);
+
+        // Only RSA-2048 signatures are supported
+        raw_tmd[..4].copy_from_slice(&0x10000u32.to_be_bytes());
+        assert!(fakesign_tmd(&mut raw_tmd).is_err());
+    }
+}
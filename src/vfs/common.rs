@@ -43,6 +43,7 @@ impl VfsFile for StaticFile {
             file_type: VfsFileType::File,
             len: self.inner.get_ref().len() as u64,
             mtime: self.mtime,
+            offset: None,
         })
     }
 
@@ -123,7 +124,12 @@ impl VfsFile for WindowedFile {
 
     fn metadata(&mut self) -> io::Result<VfsMetadata> {
         let metadata = self.base.metadata()?;
-        Ok(VfsMetadata { file_type: VfsFileType::File, len: self.len(), mtime: metadata.mtime })
+        Ok(VfsMetadata {
+            file_type: VfsFileType::File,
+            len: self.len(),
+            mtime: metadata.mtime,
+            offset: None,
+        })
     }
 
     fn into_disc_stream(self: Box<Self>) -> Box<dyn DiscStream> { self }
@@ -215,21 +215,29 @@ impl Vfs for DiscFs {
     fn metadata(&mut self, path: &Utf8UnixPath) -> VfsResult<VfsMetadata> {
         match self.find(path)? {
             DiscNode::None => Err(VfsError::NotFound),
-            DiscNode::Special(_) => {
-                Ok(VfsMetadata { file_type: VfsFileType::Directory, len: 0, mtime: self.mtime })
-            }
+            DiscNode::Special(_) => Ok(VfsMetadata {
+                file_type: VfsFileType::Directory,
+                len: 0,
+                mtime: self.mtime,
+                offset: None,
+            }),
             DiscNode::Node(_, _, node) => {
                 let (file_type, len) = match node.kind() {
                     NodeKind::File => (VfsFileType::File, node.length()),
                     NodeKind::Directory => (VfsFileType::Directory, 0),
                     NodeKind::Invalid => return Err(VfsError::from("FST: Invalid node kind")),
                 };
-                Ok(VfsMetadata { file_type, len, mtime: self.mtime })
+                // Wii file offsets are relative to the encrypted partition data
+                let is_wii = self.disc.header().is_wii();
+                let offset =
+                    (file_type == VfsFileType::File && !is_wii).then(|| node.offset(false));
+                Ok(VfsMetadata { file_type, len, mtime: self.mtime, offset })
             }
             DiscNode::Static(data) => Ok(VfsMetadata {
                 file_type: VfsFileType::File,
                 len: data.len() as u64,
                 mtime: self.mtime,
+                offset: None,
             }),
         }
     }
@@ -319,11 +327,13 @@ impl VfsFile for DiscFile {
                 file_type: VfsFileType::File,
                 len: stream.len(),
                 mtime: self.mtime,
+                offset: None,
             }),
             DiscFileInner::Mapped(data) => Ok(VfsMetadata {
                 file_type: VfsFileType::File,
                 len: data.get_ref().len() as u64,
                 mtime: self.mtime,
+                offset: None,
             }),
         }
     }
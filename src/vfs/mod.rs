@@ -1,6 +1,8 @@
 mod common;
 mod disc;
+mod overlay;
 mod rarc;
+mod repack;
 mod std_fs;
 mod u8_arc;
 mod wad;
@@ -19,7 +21,9 @@ use disc::{nod_to_io_error, DiscFs};
 use dyn_clone::DynClone;
 use filetime::FileTime;
 use nodtool::{nod, nod::DiscStream};
+pub use overlay::OverlayFs;
 use rarc::RarcFs;
+pub use repack::{repack, RepackOptions, RepackTree};
 pub use std_fs::StdFs;
 use typed_path::{Utf8NativePath, Utf8UnixPath, Utf8UnixPathBuf};
 use u8_arc::U8Fs;
@@ -28,9 +32,10 @@ use wad::WadFs;
 use crate::util::{
     ncompress::{YAY0_MAGIC, YAZ0_MAGIC},
     nlzss,
+    nlzss::LZ77_MAGIC,
     rarc::RARC_MAGIC,
     u8_arc::U8_MAGIC,
-    wad::WAD_MAGIC,
+    wad::{WAD_BACKUP_MAGIC, WAD_BOOT2_MAGIC, WAD_MAGIC},
 };
 
 pub trait Vfs: DynClone + Send + Sync {
@@ -63,6 +68,8 @@ pub struct VfsMetadata {
     pub file_type: VfsFileType,
     pub len: u64,
     pub mtime: Option<FileTime>,
+    /// Offset of the file data in the containing file, if stored as-is.
+    pub offset: Option<u64>,
 }
 
 impl VfsMetadata {
@@ -139,7 +146,10 @@ impl Display for FileFormat {
 pub enum CompressionKind {
     Yay0,
     Yaz0,
+    /// Any NLZSS variant, detected from the header when decompressing.
     Nlzss,
+    Lz10,
+    Lz11,
 }
 
 impl Display for CompressionKind {
@@ -148,6 +158,8 @@ impl Display for CompressionKind {
             CompressionKind::Yay0 => write!(f, "Yay0"),
             CompressionKind::Yaz0 => write!(f, "Yaz0"),
             CompressionKind::Nlzss => write!(f, "NLZSS"),
+            CompressionKind::Lz10 => write!(f, "LZ10"),
+            CompressionKind::Lz11 => write!(f, "LZ11"),
         }
     }
 }
@@ -181,12 +193,36 @@ where R: Read + Seek + ?Sized {
         Err(e) => return Err(e),
     }
     file.seek_relative(-8)?;
+    // LZ10/LZ11 have no magic, so check the header against the file length, then confirm
+    // by decoding the start of the file
+    let mut lz_kind = None;
+    if matches!(magic[0], 0x10 | 0x11) {
+        let len = file.seek(SeekFrom::End(0))?;
+        file.seek(SeekFrom::Start(0))?;
+        lz_kind = nlzss::detect_lz(&magic, len);
+        if lz_kind.is_some() {
+            let mut data = vec![];
+            file.by_ref().take(nlzss::LZ_VERIFY_PREFIX).read_to_end(&mut data)?;
+            file.seek(SeekFrom::Start(0))?;
+            if !nlzss::verify_lz(&data, len) {
+                lz_kind = None;
+            }
+        }
+    }
     match magic {
         _ if magic.starts_with(&YAY0_MAGIC) => Ok(FileFormat::Compressed(CompressionKind::Yay0)),
         _ if magic.starts_with(&YAZ0_MAGIC) => Ok(FileFormat::Compressed(CompressionKind::Yaz0)),
+        _ if magic.starts_with(&LZ77_MAGIC) && magic[4] == 0x10 => {
+            Ok(FileFormat::Compressed(CompressionKind::Lz10))
+        }
+        _ if magic.starts_with(&LZ77_MAGIC) && magic[4] == 0x11 => {
+            Ok(FileFormat::Compressed(CompressionKind::Lz11))
+        }
         _ if magic.starts_with(&RARC_MAGIC) => Ok(FileFormat::Archive(ArchiveKind::Rarc)),
         _ if magic.starts_with(&U8_MAGIC) => Ok(FileFormat::Archive(ArchiveKind::U8)),
-        WAD_MAGIC => Ok(FileFormat::Archive(ArchiveKind::Wad)),
+        WAD_MAGIC | WAD_BOOT2_MAGIC | WAD_BACKUP_MAGIC => Ok(FileFormat::Archive(ArchiveKind::Wad)),
+        _ if lz_kind == Some(0x10) => Ok(FileFormat::Compressed(CompressionKind::Lz10)),
+        _ if lz_kind == Some(0x11) => Ok(FileFormat::Compressed(CompressionKind::Lz11)),
         _ => {
             let format = nod::Disc::detect(file)?;
             file.seek(SeekFrom::Start(0))?;
@@ -360,7 +396,7 @@ pub fn decompress_file(
                 .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
             Ok(Box::new(StaticFile::new(Arc::from(result), metadata.mtime)))
         }
-        CompressionKind::Nlzss => {
+        CompressionKind::Nlzss | CompressionKind::Lz10 | CompressionKind::Lz11 => {
             let result = nlzss::decompress(file)
                 .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
             Ok(Box::new(StaticFile::new(Arc::from(result.as_slice()), metadata.mtime)))
@@ -0,0 +1,136 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use filetime::FileTime;
+use typed_path::Utf8UnixPath;
+
+use super::{common::StaticFile, Vfs, VfsError, VfsFile, VfsFileType, VfsMetadata, VfsResult};
+
+/// A writable layer over a read-only [`Vfs`].
+///
+/// Written files shadow files in the base file system. New files are only created with
+/// [`add`](Self::add). Paths are matched case-insensitively, like in the archive formats.
+#[derive(Clone)]
+pub struct OverlayFs {
+    base: Box<dyn Vfs>,
+    /// Written files, keyed by lowercase path. Values hold the path as written.
+    files: BTreeMap<String, (String, Arc<[u8]>)>,
+    mtime: Option<FileTime>,
+}
+
+fn normalize(path: &str) -> String {
+    path.split('/').filter(|s| !s.is_empty()).collect::<Vec<_>>().join("/")
+}
+
+impl OverlayFs {
+    pub fn new(base: Box<dyn Vfs>) -> Self {
+        Self { base, files: BTreeMap::new(), mtime: Some(FileTime::now()) }
+    }
+
+    /// Replaces an existing file. Fails with [`VfsError::NotFound`] if the file doesn't exist.
+    pub fn write(&mut self, path: &Utf8UnixPath, data: Arc<[u8]>) -> VfsResult<()> {
+        self.insert(path, data, false)
+    }
+
+    /// Writes a file, replacing any existing file at the same path or adding a new one.
+    pub fn add(&mut self, path: &Utf8UnixPath, data: Arc<[u8]>) -> VfsResult<()> {
+        self.insert(path, data, true)
+    }
+
+    fn insert(&mut self, path: &Utf8UnixPath, data: Arc<[u8]>, add: bool) -> VfsResult<()> {
+        let path = normalize(path.as_str());
+        if path.is_empty() {
+            return Err(VfsError::IsADirectory);
+        }
+        match self.base.metadata(Utf8UnixPath::new(&path)) {
+            Ok(m) if m.is_dir() => return Err(VfsError::IsADirectory),
+            Ok(_) => {}
+            Err(VfsError::NotFound) if add || self.find(&path).is_some() => {}
+            Err(e) => return Err(e),
+        }
+        self.files.insert(path.to_ascii_lowercase(), (path, data));
+        Ok(())
+    }
+
+    fn find(&self, path: &str) -> Option<&Arc<[u8]>> {
+        self.files.get(&path.to_ascii_lowercase()).map(|(_, data)| data)
+    }
+
+    /// Whether the overlay adds files below the given directory.
+    fn has_children(&self, path: &str) -> bool {
+        let prefix =
+            if path.is_empty() { String::new() } else { format!("{}/", path.to_ascii_lowercase()) };
+        self.files.range(prefix.clone()..).next().is_some_and(|(key, _)| key.starts_with(&prefix))
+    }
+}
+
+impl Vfs for OverlayFs {
+    fn open(&mut self, path: &Utf8UnixPath) -> VfsResult<Box<dyn VfsFile>> {
+        let normalized = normalize(path.as_str());
+        if let Some(data) = self.find(&normalized) {
+            return Ok(Box::new(StaticFile::new(data.clone(), self.mtime)));
+        }
+        if self.has_children(&normalized) {
+            return Err(VfsError::IsADirectory);
+        }
+        self.base.open(path)
+    }
+
+    fn exists(&mut self, path: &Utf8UnixPath) -> VfsResult<bool> {
+        let normalized = normalize(path.as_str());
+        if self.find(&normalized).is_some() || self.has_children(&normalized) {
+            return Ok(true);
+        }
+        self.base.exists(path)
+    }
+
+    fn read_dir(&mut self, path: &Utf8UnixPath) -> VfsResult<Vec<String>> {
+        let normalized = normalize(path.as_str());
+        if self.find(&normalized).is_some() {
+            return Err(VfsError::NotADirectory);
+        }
+        let mut entries = match self.base.read_dir(path) {
+            Ok(entries) => entries,
+            Err(VfsError::NotFound) if self.has_children(&normalized) => vec![],
+            Err(e) => return Err(e),
+        };
+        // Append new entries, keeping the original order of existing ones
+        let prefix = if normalized.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", normalized.to_ascii_lowercase())
+        };
+        for (key, (written_path, _)) in &self.files {
+            if !key.starts_with(&prefix) {
+                continue;
+            }
+            // Lowercasing keeps byte offsets, so the prefix length applies to both
+            let rest = &written_path[prefix.len()..];
+            let name = rest.split('/').next().unwrap_or(rest);
+            if !entries.iter().any(|e| e.eq_ignore_ascii_case(name)) {
+                entries.push(name.to_string());
+            }
+        }
+        Ok(entries)
+    }
+
+    fn metadata(&mut self, path: &Utf8UnixPath) -> VfsResult<VfsMetadata> {
+        let normalized = normalize(path.as_str());
+        if let Some(data) = self.find(&normalized) {
+            return Ok(VfsMetadata {
+                file_type: VfsFileType::File,
+                len: data.len() as u64,
+                mtime: self.mtime,
+                offset: None,
+            });
+        }
+        match self.base.metadata(path) {
+            Err(VfsError::NotFound) if self.has_children(&normalized) => Ok(VfsMetadata {
+                file_type: VfsFileType::Directory,
+                len: 0,
+                mtime: self.mtime,
+                offset: None,
+            }),
+            result => result,
+        }
+    }
+}
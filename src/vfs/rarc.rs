@@ -68,10 +68,18 @@ impl Vfs for RarcFs {
                 file_type: VfsFileType::File,
                 len: node.data_length() as u64,
                 mtime: metadata.mtime,
+                offset: Some(
+                    view.header.header_len() as u64
+                        + view.header.data_offset() as u64
+                        + node.data_offset() as u64,
+                ),
+            }),
+            Some(RarcNodeKind::Directory(_, _)) => Ok(VfsMetadata {
+                file_type: VfsFileType::Directory,
+                len: 0,
+                mtime: metadata.mtime,
+                offset: None,
             }),
-            Some(RarcNodeKind::Directory(_, _)) => {
-                Ok(VfsMetadata { file_type: VfsFileType::Directory, len: 0, mtime: metadata.mtime })
-            }
             None => Err(VfsError::NotFound),
         }
     }
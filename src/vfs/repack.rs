@@ -0,0 +1,461 @@
+use std::{collections::BTreeMap, io::Write, sync::Arc};
+
+use anyhow::{anyhow, bail, Context, Result};
+use typed_path::{Utf8UnixPath, Utf8UnixPathBuf};
+
+use super::{
+    decompress_file, detect, open_fs, ArchiveKind, CompressionKind, FileFormat, OverlayFs, Vfs,
+    VfsFile, VfsFileType,
+};
+use crate::util::{
+    disc::{is_wii_boot, write_disc, DiscBuildInput, DiscEntry, DiscSource, WiiBuildInput},
+    ncompress::{compress_yay0_with, compress_yaz0_with, CompressionAlgo},
+    nlzss,
+    nlzss::{CompressionLevel, LZ11_MAX_LENGTH, LZ77_MAGIC},
+    rarc::{write_rarc, RarcDirEntry, RarcEntry, RarcFileEntry, RarcNodeKind, RarcView},
+    u8_arc::{write_u8, U8Entry},
+    wad::{process_wad, tmd_contents, write_wad, WadPackInput},
+};
+
+/// Files to replace inside a container.
+///
+/// Paths may cross nested containers with `:`, as in [`open_path`](super::open_path),
+/// e.g. `files/Stage/x.arc:dat/y.bmd`.
+#[derive(Clone, Debug, Default)]
+pub struct RepackTree {
+    entries: BTreeMap<String, RepackEntry>,
+}
+
+#[derive(Clone, Debug)]
+enum RepackEntry {
+    /// Replacement data for an existing file.
+    Replace(Arc<[u8]>),
+    /// Data for a file that's added if it doesn't exist.
+    Add(Arc<[u8]>),
+    /// Replacements inside a nested container.
+    Nested(RepackTree),
+}
+
+impl RepackTree {
+    /// Records a replacement. The data is stored as-is; to keep a file compressed, replace a
+    /// path inside it instead. The file must exist, unless `add` is set.
+    pub fn insert(&mut self, path: &str, data: Arc<[u8]>, add: bool) -> Result<()> {
+        let (segment, rest) = match path.split_once(':') {
+            Some((segment, rest)) => (segment, Some(rest)),
+            None => (path, None),
+        };
+        let key = segment.split('/').filter(|s| !s.is_empty()).collect::<Vec<_>>().join("/");
+        if key.is_empty() {
+            bail!("Empty path segment in {}", path);
+        }
+        match rest {
+            Some(rest) => {
+                if matches!(rest.split(':').next(), Some("nlzss" | "yay0" | "yaz0")) {
+                    bail!("Compression segments are not supported in {}", path);
+                }
+                match self
+                    .entries
+                    .entry(key)
+                    .or_insert_with(|| RepackEntry::Nested(RepackTree::default()))
+                {
+                    RepackEntry::Nested(tree) => tree.insert(rest, data, add),
+                    RepackEntry::Replace(_) | RepackEntry::Add(_) => {
+                        bail!("{} is replaced, so files inside it can't be replaced", segment)
+                    }
+                }
+            }
+            None => {
+                if matches!(self.entries.get(&key), Some(RepackEntry::Nested(_))) {
+                    bail!("{} contains replacements, so it can't be replaced", segment);
+                }
+                let entry = if add { RepackEntry::Add(data) } else { RepackEntry::Replace(data) };
+                self.entries.insert(key, entry);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RepackOptions {
+    /// Algorithm used to recompress Yaz0 and Yay0 files.
+    pub algo: CompressionAlgo,
+    /// Fakesign rebuilt WADs.
+    pub fakesign: bool,
+}
+
+/// Rebuilds a container with the replacements in `tree` applied, writing it to `w`.
+///
+/// Nested containers are rebuilt first, and compressed files are recompressed with their
+/// original format. Disc images are written as ISO images, whatever their original format.
+pub fn repack<W>(
+    mut file: Box<dyn VfsFile>,
+    tree: &RepackTree,
+    options: &RepackOptions,
+    w: &mut W,
+) -> Result<()>
+where
+    W: Write + ?Sized,
+{
+    let data = match detect(file.as_mut())? {
+        FileFormat::Regular => bail!("Not a container"),
+        FileFormat::Compressed(kind) => {
+            let header = file.map()?.get(..8).map(|b| b.to_vec()).unwrap_or_default();
+            let decompressed = decompress_file(file.as_mut(), kind)?;
+            let mut data = vec![];
+            repack(decompressed, tree, options, &mut data)?;
+            recompress(kind, &header, &data, options)?
+        }
+        FileFormat::Archive(kind) => {
+            let root_name = match kind {
+                ArchiveKind::Rarc => Some(rarc_root_name(file.as_mut())?),
+                _ => None,
+            };
+            let mut overlay = OverlayFs::new(open_fs(file.clone(), kind)?);
+            for (path, entry) in &tree.entries {
+                // RARC paths may start with the root directory name, as in `RarcView::find`
+                let path = Utf8UnixPath::new(match &root_name {
+                    Some(root_name) => strip_root_name(path, root_name),
+                    None => path.as_str(),
+                });
+                let result = match entry {
+                    RepackEntry::Replace(data) => overlay.write(path, data.clone()),
+                    RepackEntry::Add(data) => overlay.add(path, data.clone()),
+                    RepackEntry::Nested(tree) => {
+                        let inner = overlay
+                            .open(path)
+                            .with_context(|| format!("Failed to open {}", path))?;
+                        let mut data = vec![];
+                        repack(inner, tree, options, &mut data)
+                            .with_context(|| format!("Failed to repack {}", path))?;
+                        overlay.write(path, Arc::from(data))
+                    }
+                };
+                result.with_context(|| format!("Failed to write {}", path))?;
+            }
+            match kind {
+                ArchiveKind::Rarc => rebuild_rarc(file.as_mut(), &mut overlay)?,
+                ArchiveKind::U8 => write_u8(&read_u8_dir(&mut overlay, Utf8UnixPath::new(""))?)?,
+                ArchiveKind::Wad => rebuild_wad(file.as_mut(), &mut overlay, options)?,
+                // Written directly, since disc images are too large to buffer
+                ArchiveKind::Disc(_) => return rebuild_disc(&mut overlay, w),
+            }
+        }
+    };
+    w.write_all(&data)?;
+    Ok(())
+}
+
+fn recompress(
+    kind: CompressionKind,
+    header: &[u8],
+    data: &[u8],
+    options: &RepackOptions,
+) -> Result<Vec<u8>> {
+    Ok(match kind {
+        CompressionKind::Yaz0 => compress_yaz0_with(data, options.algo).into_vec(),
+        CompressionKind::Yay0 => compress_yay0_with(data, options.algo).into_vec(),
+        CompressionKind::Nlzss | CompressionKind::Lz10 | CompressionKind::Lz11 => {
+            let mut out = vec![];
+            let header = match header.strip_prefix(&LZ77_MAGIC) {
+                Some(rest) => {
+                    out.extend_from_slice(&LZ77_MAGIC);
+                    rest
+                }
+                None => header,
+            };
+            let level = match header.first() {
+                Some(0x10) => CompressionLevel::LZ10,
+                Some(0x11) => CompressionLevel::LZ11(LZ11_MAX_LENGTH),
+                _ => bail!("Unsupported NLZSS type for recompression"),
+            };
+            nlzss::compress(data, &mut out, level)?;
+            out
+        }
+    })
+}
+
+fn read_file(fs: &mut dyn Vfs, path: &Utf8UnixPath) -> Result<Vec<u8>> {
+    let mut file = fs.open(path).with_context(|| format!("Failed to open {}", path))?;
+    Ok(file.map().with_context(|| format!("Failed to read {}", path))?.to_vec())
+}
+
+/// Lists a directory, returning the path and type of each entry.
+fn read_dir_entries(
+    fs: &mut dyn Vfs,
+    path: &Utf8UnixPath,
+) -> Result<Vec<(String, Utf8UnixPathBuf, VfsFileType)>> {
+    let mut entries = vec![];
+    for name in fs.read_dir(path).with_context(|| format!("Failed to read {}", path))? {
+        let entry_path = path.join(&name);
+        let metadata = fs
+            .metadata(&entry_path)
+            .with_context(|| format!("Failed to fetch metadata for {}", entry_path))?;
+        entries.push((name, entry_path, metadata.file_type));
+    }
+    Ok(entries)
+}
+
+fn rarc_root_name(file: &mut dyn VfsFile) -> Result<String> {
+    let view = RarcView::new(file.map()?).map_err(|e| anyhow!(e))?;
+    let root_name = view.get_string(view.directories[0].name_offset()).map_err(|e| anyhow!(e))?;
+    Ok(root_name.into_owned())
+}
+
+/// Strips a leading RARC root directory name from a path.
+fn strip_root_name<'a>(path: &'a str, root_name: &str) -> &'a str {
+    match path.split_once('/') {
+        Some((first, rest)) if first.eq_ignore_ascii_case(root_name) => rest,
+        _ => path,
+    }
+}
+
+fn rebuild_rarc(file: &mut dyn VfsFile, overlay: &mut OverlayFs) -> Result<Vec<u8>> {
+    let data = file.map()?;
+    let view = RarcView::new(data).map_err(|e| anyhow!(e))?;
+    let root_name = view.get_string(view.directories[0].name_offset()).map_err(|e| anyhow!(e))?;
+    let mut root = RarcDirEntry { name: root_name.to_string(), ..Default::default() };
+    read_rarc_dir(overlay, &view, Utf8UnixPath::new(""), &mut root)?;
+    write_rarc(&root, view.has_synced_ids())
+}
+
+fn read_rarc_dir(
+    fs: &mut dyn Vfs,
+    view: &RarcView,
+    path: &Utf8UnixPath,
+    dir: &mut RarcDirEntry,
+) -> Result<()> {
+    for (name, entry_path, file_type) in read_dir_entries(fs, path)? {
+        match file_type {
+            VfsFileType::File => {
+                // Keep the preload type of existing files
+                let preload = match view.find(&entry_path) {
+                    Some(RarcNodeKind::File(_, node)) => node.preload(),
+                    _ => Default::default(),
+                };
+                let data = read_file(fs, &entry_path)?;
+                dir.entries.push(RarcEntry::File(RarcFileEntry { name, data, preload }));
+            }
+            VfsFileType::Directory => {
+                let mut sub = RarcDirEntry { name, ..Default::default() };
+                read_rarc_dir(fs, view, &entry_path, &mut sub)?;
+                dir.entries.push(RarcEntry::Dir(sub));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_u8_dir(fs: &mut dyn Vfs, path: &Utf8UnixPath) -> Result<Vec<U8Entry>> {
+    let mut entries = vec![];
+    for (name, entry_path, file_type) in read_dir_entries(fs, path)? {
+        entries.push(match file_type {
+            VfsFileType::File => U8Entry::File { name, data: read_file(fs, &entry_path)? },
+            VfsFileType::Directory => {
+                U8Entry::Directory { name, entries: read_u8_dir(fs, &entry_path)? }
+            }
+        });
+    }
+    Ok(entries)
+}
+
+fn rebuild_disc<W>(overlay: &mut OverlayFs, w: &mut W) -> Result<()>
+where W: Write + ?Sized {
+    // Wii discs without a region setting have no disc/region.bin
+    let has_region = overlay.exists(Utf8UnixPath::new("disc/region.bin"))?;
+    let mut read = |name: &str| -> Result<Box<[u8]>> {
+        Ok(read_file(&mut *overlay, Utf8UnixPath::new(name))?.into_boxed_slice())
+    };
+    let raw_boot = read("sys/boot.bin")?;
+    let wii = if is_wii_boot(&raw_boot) {
+        Some(WiiBuildInput {
+            raw_disc_header: read("disc/header.bin")?,
+            raw_region: if has_region { Some(read("disc/region.bin")?) } else { None },
+            raw_ticket: read("ticket.bin")?,
+            raw_tmd: read("tmd.bin")?,
+            raw_cert_chain: read("cert.bin")?,
+        })
+    } else {
+        None
+    };
+    let input = DiscBuildInput {
+        raw_boot,
+        raw_bi2: read("sys/bi2.bin")?,
+        raw_apploader: read("sys/apploader.img")?,
+        raw_dol: read("sys/main.dol")?,
+        raw_fst: Some(read("sys/fst.bin")?),
+        files: read_disc_dir(overlay, Utf8UnixPath::new("files"))?,
+        wii,
+    };
+    write_disc(w, input)
+}
+
+/// Lists the files below a directory of the disc. File data is read while writing the disc.
+fn read_disc_dir(fs: &mut dyn Vfs, path: &Utf8UnixPath) -> Result<Vec<DiscEntry>> {
+    let mut entries = vec![];
+    for (name, entry_path, file_type) in read_dir_entries(fs, path)? {
+        entries.push(match file_type {
+            VfsFileType::File => {
+                let mut file = fs
+                    .open(&entry_path)
+                    .with_context(|| format!("Failed to open {}", entry_path))?;
+                let size = file.metadata()?.len;
+                DiscEntry::File { name, source: DiscSource::File(file), size }
+            }
+            VfsFileType::Directory => {
+                DiscEntry::Directory { name, entries: read_disc_dir(fs, &entry_path)? }
+            }
+        });
+    }
+    Ok(entries)
+}
+
+fn rebuild_wad(
+    file: &mut dyn VfsFile,
+    overlay: &mut OverlayFs,
+    options: &RepackOptions,
+) -> Result<Vec<u8>> {
+    let wad = process_wad(file)?;
+    let title_id = hex::encode(wad.title_id());
+    let mut read = |name: String| -> Result<Box<[u8]>> {
+        Ok(read_file(&mut *overlay, Utf8UnixPath::new(&name))?.into_boxed_slice())
+    };
+    let raw_tmd = read(format!("{}.tmd", title_id))?;
+    let raw_ticket = read(format!("{}.tik", title_id))?;
+    let raw_cert_chain = read(format!("{}.cert", title_id))?;
+    let footer =
+        if wad.footer_size() > 0 { read(format!("{}.trailer", title_id))? } else { Box::default() };
+    let contents = tmd_contents(&raw_tmd)?
+        .iter()
+        .map(|content| read(format!("{:08x}.app", content.content_index.get())))
+        .collect::<Result<Vec<_>>>()?;
+
+    let input =
+        WadPackInput { kind: wad.kind, raw_cert_chain, raw_ticket, raw_tmd, contents, footer };
+    let mut out = vec![];
+    write_wad(&mut out, input, options.fakesign)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::common::StaticFile;
+
+    fn test_rarc() -> Box<dyn VfsFile> {
+        let file = RarcFileEntry {
+            name: "a.bin".to_string(),
+            data: vec![1; 4],
+            preload: Default::default(),
+        };
+        let root = RarcDirEntry {
+            name: "stage".to_string(),
+            entries: vec![RarcEntry::Dir(RarcDirEntry {
+                name: "dat".to_string(),
+                entries: vec![RarcEntry::File(file)],
+            })],
+        };
+        Box::new(StaticFile::new(write_rarc(&root, true).unwrap().into(), None))
+    }
+
+    fn repack_rarc(tree: &RepackTree) -> Result<Box<dyn Vfs>> {
+        let mut data = vec![];
+        repack(test_rarc(), tree, &RepackOptions::default(), &mut data)?;
+        Ok(open_fs(Box::new(StaticFile::new(data.into(), None)), ArchiveKind::Rarc)?)
+    }
+
+    fn static_file(data: &[u8]) -> Box<dyn VfsFile> {
+        Box::new(StaticFile::new(Arc::from(data), None))
+    }
+
+    /// Builds a GameCube disc image containing `a.bin` and `dir/b.bin`.
+    fn test_disc() -> Box<dyn VfsFile> {
+        let mut boot = vec![0u8; 0x440];
+        boot[..6].copy_from_slice(b"GTEST0");
+        boot[0x1C..0x20].copy_from_slice(&[0xC2, 0x33, 0x9F, 0x3D]);
+        boot[0x20..0x24].copy_from_slice(b"Test");
+        // Apploader header with a 0x20 byte body and no trailer
+        let mut apploader = vec![0u8; 0x40];
+        apploader[0x14..0x18].copy_from_slice(&0x20u32.to_be_bytes());
+        // DOL with a single 0x20 byte text section
+        let mut dol = vec![0u8; 0x120];
+        dol[..4].copy_from_slice(&0x100u32.to_be_bytes());
+        dol[0x90..0x94].copy_from_slice(&0x20u32.to_be_bytes());
+        let file = |name: &str, data: &[u8]| DiscEntry::File {
+            name: name.to_string(),
+            source: DiscSource::File(static_file(data)),
+            size: data.len() as u64,
+        };
+        let input = DiscBuildInput {
+            raw_boot: boot.into(),
+            raw_bi2: vec![0; 0x2000].into(),
+            raw_apploader: apploader.into(),
+            raw_dol: dol.into(),
+            raw_fst: None,
+            files: vec![file("a.bin", &[1; 0x10]), DiscEntry::Directory {
+                name: "dir".to_string(),
+                entries: vec![file("b.bin", &[2; 0x20])],
+            }],
+            wii: None,
+        };
+        let mut out = vec![];
+        write_disc(&mut out, input).unwrap();
+        static_file(&out)
+    }
+
+    #[test]
+    fn test_repack_disc() {
+        let mut tree = RepackTree::default();
+        tree.insert("files/a.bin", Arc::from([3u8; 0x9000].as_slice()), false).unwrap();
+        let mut disc = test_disc();
+        let kind = match detect(disc.as_mut()).unwrap() {
+            FileFormat::Archive(kind @ ArchiveKind::Disc(_)) => kind,
+            format => panic!("Detected as {}", format),
+        };
+        let mut original = open_fs(disc.clone(), kind).unwrap();
+        let original_dol = read_file(original.as_mut(), Utf8UnixPath::new("sys/main.dol")).unwrap();
+
+        let mut data = vec![];
+        repack(disc, &tree, &RepackOptions::default(), &mut data).unwrap();
+        let mut fs = open_fs(static_file(&data), kind).unwrap();
+        assert_eq!(read_file(fs.as_mut(), Utf8UnixPath::new("files/a.bin")).unwrap(), vec![
+            3;
+            0x9000
+        ]);
+        assert_eq!(read_file(fs.as_mut(), Utf8UnixPath::new("files/dir/b.bin")).unwrap(), vec![
+            2;
+            0x20
+        ]);
+        assert_eq!(
+            read_file(fs.as_mut(), Utf8UnixPath::new("sys/main.dol")).unwrap(),
+            original_dol
+        );
+    }
+
+    #[test]
+    fn test_repack_rarc_root_name() {
+        let mut tree = RepackTree::default();
+        tree.insert("stage/dat/a.bin", Arc::from([2u8; 8].as_slice()), false).unwrap();
+        let mut fs = repack_rarc(&tree).unwrap();
+        assert_eq!(fs.read_dir(Utf8UnixPath::new("")).unwrap(), vec!["dat".to_string()]);
+        let data = read_file(fs.as_mut(), Utf8UnixPath::new("dat/a.bin")).unwrap();
+        assert_eq!(data, vec![2; 8]);
+    }
+
+    #[test]
+    fn test_repack_add() {
+        let mut tree = RepackTree::default();
+        tree.insert("dat/new.bin", Arc::from([3u8; 4].as_slice()), false).unwrap();
+        assert!(repack_rarc(&tree).is_err());
+
+        let mut tree = RepackTree::default();
+        tree.insert("dat/new.bin", Arc::from([3u8; 4].as_slice()), true).unwrap();
+        let mut fs = repack_rarc(&tree).unwrap();
+        let entries = fs.read_dir(Utf8UnixPath::new("dat")).unwrap();
+        assert_eq!(entries, vec!["a.bin".to_string(), "new.bin".to_string()]);
+        let data = read_file(fs.as_mut(), Utf8UnixPath::new("dat/new.bin")).unwrap();
+        assert_eq!(data, vec![3; 4]);
+    }
+}
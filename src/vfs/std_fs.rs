@@ -35,6 +35,7 @@ impl Vfs for StdFs {
             file_type: if metadata.is_dir() { VfsFileType::Directory } else { VfsFileType::File },
             len: metadata.len(),
             mtime: Some(FileTime::from_last_modification_time(&metadata)),
+            offset: None,
         })
     }
 }
@@ -103,6 +104,7 @@ impl VfsFile for StdFile {
             file_type: if metadata.is_dir() { VfsFileType::Directory } else { VfsFileType::File },
             len: metadata.len(),
             mtime: Some(FileTime::from_last_modification_time(&metadata)),
+            offset: None,
         })
     }
 
@@ -77,11 +77,13 @@ impl Vfs for U8Fs {
                     file_type: VfsFileType::File,
                     len: node.length() as u64,
                     mtime: metdata.mtime,
+                    offset: Some(node.offset() as u64),
                 }),
                 U8NodeKind::Directory => Ok(VfsMetadata {
                     file_type: VfsFileType::Directory,
                     len: 0,
                     mtime: metdata.mtime,
+                    offset: None,
                 }),
                 U8NodeKind::Invalid => Err(VfsError::from("U8: Invalid node kind")),
             },
@@ -12,7 +12,7 @@ use zerocopy::FromZeros;
 
 use crate::{
     array_ref,
-    util::wad::{align_up, process_wad, ContentMetadata, WadFile},
+    util::wad::{align_up, process_wad, ContentMetadata, WadFile, WadKind},
     vfs::{
         common::{StaticFile, WindowedFile},
         Vfs, VfsError, VfsFile, VfsFileType, VfsMetadata, VfsResult,
@@ -50,7 +50,23 @@ impl WadFs {
             return Some(WadFindResult::Root);
         }
         let filename = filename.to_ascii_lowercase();
-        if let Some(id) = filename.strip_suffix(".app") {
+        if self.wad.kind == WadKind::Backup {
+            // Backup WAD contents are encrypted with a console-specific key,
+            // so they're exposed as-is.
+            if let Some(id) = filename.strip_suffix(".app.enc") {
+                if let Ok(content_index) = u16::from_str_radix(id, 16) {
+                    if let Some(content) = self.wad.contents().get(content_index as usize) {
+                        if self.wad.is_included(content_index) {
+                            return Some(WadFindResult::Window(
+                                self.wad.content_offset(content_index),
+                                align_up(content.size.get(), 0x10),
+                            ));
+                        }
+                    }
+                }
+                return None;
+            }
+        } else if let Some(id) = filename.strip_suffix(".app") {
             if let Ok(content_index) = u16::from_str_radix(id, 16) {
                 if let Some(content) = self.wad.contents().get(content_index as usize) {
                     return Some(WadFindResult::Content(content_index, content));
@@ -58,19 +74,20 @@ impl WadFs {
             }
             return None;
         }
-        let title_id = hex::encode(self.wad.ticket().title_id);
+        let title_id = hex::encode(self.wad.title_id());
         match filename.strip_prefix(&title_id) {
-            Some(".tik") => Some(WadFindResult::Static(&self.wad.raw_ticket)),
+            Some(".tik") if self.wad.has_ticket() => {
+                Some(WadFindResult::Static(&self.wad.raw_ticket))
+            }
             Some(".tmd") => Some(WadFindResult::Static(&self.wad.raw_tmd)),
-            Some(".cert") => Some(WadFindResult::Static(&self.wad.raw_cert_chain)),
+            Some(".cert") if !self.wad.raw_cert_chain.is_empty() => {
+                Some(WadFindResult::Static(&self.wad.raw_cert_chain))
+            }
             Some(".trailer") => {
-                if self.wad.header.footer_size.get() == 0 {
+                if self.wad.footer_size() == 0 {
                     return None;
                 }
-                Some(WadFindResult::Window(
-                    self.wad.trailer_offset(),
-                    self.wad.header.footer_size.get() as u64,
-                ))
+                Some(WadFindResult::Window(self.wad.trailer_offset(), self.wad.footer_size()))
             }
             _ => None,
         }
@@ -116,16 +133,26 @@ impl Vfs for WadFs {
         if !path.is_empty() {
             return Err(VfsError::NotFound);
         }
-        let title_id = hex::encode(self.wad.ticket().title_id);
+        let title_id = hex::encode(self.wad.title_id());
         let mut entries = Vec::new();
-        entries.push(format!("{}.tik", title_id));
+        if self.wad.has_ticket() {
+            entries.push(format!("{}.tik", title_id));
+        }
         entries.push(format!("{}.tmd", title_id));
-        entries.push(format!("{}.cert", title_id));
-        if self.wad.header.footer_size.get() > 0 {
+        if !self.wad.raw_cert_chain.is_empty() {
+            entries.push(format!("{}.cert", title_id));
+        }
+        if self.wad.footer_size() > 0 {
             entries.push(format!("{}.trailer", title_id));
         }
-        for content in self.wad.contents() {
-            entries.push(format!("{:08x}.app", content.content_index.get()));
+        for (i, content) in self.wad.contents().iter().enumerate() {
+            if self.wad.kind == WadKind::Backup {
+                if self.wad.is_included(i as u16) {
+                    entries.push(format!("{:08x}.app.enc", content.content_index.get()));
+                }
+            } else {
+                entries.push(format!("{:08x}.app", content.content_index.get()));
+            }
         }
         Ok(entries)
     }
@@ -133,22 +160,31 @@ impl Vfs for WadFs {
     fn metadata(&mut self, path: &Utf8UnixPath) -> VfsResult<VfsMetadata> {
         if let Some(result) = self.find(path.as_str()) {
             match result {
-                WadFindResult::Root => {
-                    Ok(VfsMetadata { file_type: VfsFileType::Directory, len: 0, mtime: self.mtime })
-                }
+                WadFindResult::Root => Ok(VfsMetadata {
+                    file_type: VfsFileType::Directory,
+                    len: 0,
+                    mtime: self.mtime,
+                    offset: None,
+                }),
                 WadFindResult::Static(data) => Ok(VfsMetadata {
                     file_type: VfsFileType::File,
                     len: data.len() as u64,
                     mtime: self.mtime,
+                    offset: None,
                 }),
-                WadFindResult::Content(_, content) => Ok(VfsMetadata {
+                WadFindResult::Content(content_index, content) => Ok(VfsMetadata {
                     file_type: VfsFileType::File,
                     len: content.size.get(),
                     mtime: self.mtime,
+                    // Encrypted data
+                    offset: Some(self.wad.content_offset(content_index)),
+                }),
+                WadFindResult::Window(offset, len) => Ok(VfsMetadata {
+                    file_type: VfsFileType::File,
+                    len,
+                    mtime: self.mtime,
+                    offset: Some(offset),
                 }),
-                WadFindResult::Window(_, len) => {
-                    Ok(VfsMetadata { file_type: VfsFileType::File, len, mtime: self.mtime })
-                }
             }
         } else {
             Err(VfsError::NotFound)
@@ -240,11 +276,13 @@ impl VfsFile for WadContent {
                 file_type: VfsFileType::File,
                 len: stream.len(),
                 mtime: self.mtime,
+                offset: None,
             }),
             WadContentInner::Mapped(data) => Ok(VfsMetadata {
                 file_type: VfsFileType::File,
                 len: data.get_ref().len() as u64,
                 mtime: self.mtime,
+                offset: None,
             }),
         }
     }